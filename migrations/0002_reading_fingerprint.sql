@@ -0,0 +1,6 @@
+-- Question fingerprint + detected language, for caching and "popular questions" analytics.
+
+ALTER TABLE readings ADD COLUMN IF NOT EXISTS language TEXT NOT NULL DEFAULT 'other';
+ALTER TABLE readings ADD COLUMN IF NOT EXISTS fingerprint TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_readings_fingerprint ON readings(fingerprint);
@@ -0,0 +1,11 @@
+-- Public share links for readings. A token is valid until expires_at or
+-- until the owner revokes it (row deleted).
+
+CREATE TABLE IF NOT EXISTS reading_shares (
+    token TEXT PRIMARY KEY,
+    reading_id BIGINT NOT NULL REFERENCES readings(id) ON DELETE CASCADE,
+    expires_at TIMESTAMPTZ NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_reading_shares_reading_id ON reading_shares(reading_id);
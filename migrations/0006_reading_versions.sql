@@ -0,0 +1,6 @@
+-- Regenerated readings: a new row per version, linked to the original
+-- reading (never to another version) through parent_id.
+
+ALTER TABLE readings ADD COLUMN IF NOT EXISTS parent_id BIGINT REFERENCES readings(id);
+
+CREATE INDEX IF NOT EXISTS idx_readings_parent_id ON readings(parent_id);
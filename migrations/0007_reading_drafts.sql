@@ -0,0 +1,12 @@
+-- Questions saved for later: no reading is generated and nothing is
+-- charged until the draft is submitted.
+
+CREATE TABLE IF NOT EXISTS reading_drafts (
+    id BIGSERIAL PRIMARY KEY,
+    user_id BIGINT NOT NULL REFERENCES users(id),
+    question TEXT NOT NULL,
+    spread TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_reading_drafts_user_id ON reading_drafts(user_id);
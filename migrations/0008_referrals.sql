@@ -0,0 +1,16 @@
+-- Referral codes and the sign-ups they brought in. A referral is `pending`
+-- until the referrer is rewarded, then `rewarded` with the credits granted.
+
+ALTER TABLE users ADD COLUMN IF NOT EXISTS referral_code TEXT UNIQUE;
+
+CREATE TABLE IF NOT EXISTS referrals (
+    id BIGSERIAL PRIMARY KEY,
+    referrer_id BIGINT NOT NULL REFERENCES users(id),
+    referred_id BIGINT NOT NULL UNIQUE REFERENCES users(id),
+    status TEXT NOT NULL DEFAULT 'pending',
+    credits_awarded BIGINT NOT NULL DEFAULT 0,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    rewarded_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_referrals_referrer_id ON referrals(referrer_id);
@@ -0,0 +1,7 @@
+-- Latest re-moderation verdict of a reading (admin safety sweeps). A
+-- flagged reading is kept for review, not deleted.
+
+ALTER TABLE readings ADD COLUMN IF NOT EXISTS moderation_flagged BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE readings ADD COLUMN IF NOT EXISTS moderation_reason TEXT;
+ALTER TABLE readings ADD COLUMN IF NOT EXISTS moderated_by BIGINT REFERENCES users(id);
+ALTER TABLE readings ADD COLUMN IF NOT EXISTS moderated_at TIMESTAMPTZ;
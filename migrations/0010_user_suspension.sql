@@ -0,0 +1,5 @@
+-- Automatic suspension after repeated QuestionFilter rejections. A suspended
+-- user gets 403 until an admin lifts it.
+
+ALTER TABLE users ADD COLUMN IF NOT EXISTS suspended_at TIMESTAMPTZ;
+ALTER TABLE users ADD COLUMN IF NOT EXISTS suspension_reason TEXT;
@@ -0,0 +1,4 @@
+-- The completion JSON behind a reading, as much of it as STORE_RAW_LLM keeps
+-- (NULL when it keeps none).
+
+ALTER TABLE readings ADD COLUMN IF NOT EXISTS raw_llm JSONB;
@@ -0,0 +1,7 @@
+-- A payment passed as `payment_id` to `POST /readings` pays for that one
+-- reading: `consumed_at` is set when a reading claims it, `reading_id` once
+-- the reading is stored.
+
+ALTER TABLE payments ADD COLUMN IF NOT EXISTS consumed_at TIMESTAMPTZ;
+ALTER TABLE payments ADD COLUMN IF NOT EXISTS reading_id BIGINT
+    REFERENCES readings(id) ON DELETE SET NULL;
@@ -0,0 +1,10 @@
+-- LINE webhook events already processed, so a redelivered event is not
+-- acted on twice. Kept for a week; the cleanup job removes older ones.
+
+CREATE TABLE IF NOT EXISTS line_events (
+    event_id TEXT PRIMARY KEY,
+    received_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    expires_at TIMESTAMPTZ NOT NULL DEFAULT NOW() + INTERVAL '7 days'
+);
+
+CREATE INDEX IF NOT EXISTS idx_line_events_expires_at ON line_events(expires_at);
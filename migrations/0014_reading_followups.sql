@@ -0,0 +1,7 @@
+-- Follow-up readings: a new question asked after a reading, linked to the
+-- first reading of the conversation (never to another follow-up) through
+-- followup_of.
+
+ALTER TABLE readings ADD COLUMN IF NOT EXISTS followup_of BIGINT REFERENCES readings(id);
+
+CREATE INDEX IF NOT EXISTS idx_readings_followup_of ON readings(followup_of);
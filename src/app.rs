@@ -0,0 +1,47 @@
+//! The actix `App`: routes plus the middleware stack, shared by the server
+//! binary and the HTTP tests so both run the same pipeline.
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceFactory, ServiceRequest, ServiceResponse};
+use actix_web::middleware::{Condition, Logger, from_fn};
+use actix_web::{App, Error, web};
+
+use crate::handlers;
+use crate::middleware::{
+    assign_request_id, catch_panics, enforce_rate_limits, enforce_timeouts, json_error,
+    limit_concurrent_readings, localize_errors, log_request_bodies, reject_suspended,
+    resolve_origin,
+};
+use crate::state::AppState;
+
+/// actix's default access-log format plus the request id.
+const LOG_FORMAT: &str = r#"%a "%r" %s %b "%{Referer}i" "%{User-Agent}i" %T %{x-request-id}o"#;
+
+/// Every route behind the full middleware stack, outermost last.
+pub fn build_app(
+    state: web::Data<AppState>,
+) -> App<
+    impl ServiceFactory<
+        ServiceRequest,
+        Config = (),
+        Response = ServiceResponse<impl MessageBody>,
+        Error = Error,
+        InitError = (),
+    >,
+> {
+    let log_bodies = state.config().log_request_bodies;
+    App::new()
+        .wrap(from_fn(enforce_timeouts))
+        .wrap(from_fn(limit_concurrent_readings))
+        .wrap(from_fn(reject_suspended))
+        .wrap(from_fn(enforce_rate_limits))
+        .wrap(Condition::new(log_bodies, from_fn(log_request_bodies)))
+        .wrap(from_fn(catch_panics))
+        .wrap(from_fn(localize_errors))
+        .wrap(from_fn(resolve_origin))
+        .wrap(from_fn(assign_request_id))
+        .wrap(Logger::new(LOG_FORMAT))
+        .app_data(state)
+        .app_data(web::JsonConfig::default().error_handler(json_error))
+        .configure(handlers::configure)
+}
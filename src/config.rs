@@ -0,0 +1,448 @@
+//! Runtime configuration loaded from environment variables.
+
+use std::env;
+
+use thiserror::Error;
+
+use crate::db::RawLlmStorage;
+use crate::services::Language;
+
+/// The `JWT_SECRET` used under `DEV_MODE` when none is set; also the
+/// placeholder in `.env.example`, so it is refused outside `DEV_MODE`.
+pub const DEV_JWT_SECRET: &str = "change-me";
+
+/// Why `Config::from_env` refused the environment.
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("{0} must be set (DEV_MODE=true allows a development default)")]
+    Missing(&'static str),
+}
+
+/// Application configuration. Optional services (Redis, Postgres) are `None`
+/// when their env vars are unset so the server can still boot locally.
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub host: String,
+    pub port: u16,
+    pub database_url: Option<String>,
+    /// Run without Postgres even when `DATABASE_URL` is set: the in-memory
+    /// `Datastore`, and no credits, payments or shares.
+    pub mock_db: bool,
+    /// Developer conveniences such as `?pretty=true`; off in production.
+    pub dev_mode: bool,
+    /// Postgres `statement_timeout` applied to every pooled connection.
+    pub db_statement_timeout_ms: u64,
+    pub redis_url: Option<String>,
+    pub jwt_secret: String,
+    /// Seconds a token is still accepted past its `exp`, to absorb clock
+    /// skew between the issuer and this instance.
+    pub jwt_leeway_secs: u64,
+    /// HMAC key for pagination cursors (defaults to the JWT secret, so it is
+    /// never a public value outside `DEV_MODE`).
+    pub cursor_secret: String,
+    pub frontend_url: String,
+    pub openai_api_key: Option<String>,
+    pub openai_base_url: String,
+    pub openai_model: String,
+    /// Raw `OPENAI_ALLOWED_MODELS`: comma-separated `id|Display name|tier`
+    /// entries; see `ModelCatalog`.
+    pub openai_allowed_models: Option<String>,
+    /// Models tried in order when `openai_model` is missing or overloaded.
+    pub openai_model_fallbacks: Vec<String>,
+    pub openai_timeout_secs: u64,
+    /// Wall-clock cap across all LLM retries and fallbacks for one request.
+    pub llm_total_deadline_secs: u64,
+    /// Largest LLM response body read into memory; 0 for no cap.
+    pub llm_max_response_bytes: usize,
+    /// How much of each reading's raw completion JSON is stored.
+    pub store_raw_llm: RawLlmStorage,
+    /// Sent as `OpenAI-Organization` for billing attribution when set.
+    pub openai_org_id: Option<String>,
+    /// Sent as `OpenAI-Project` for billing attribution when set.
+    pub openai_project_id: Option<String>,
+    /// Send a `prompt_cache_key` per system prompt so repeated prompts land
+    /// on the same provider cache.
+    pub openai_prompt_cache: bool,
+    /// Share of successful LLM exchanges logged in full (0.0-1.0); failures
+    /// are always logged.
+    pub llm_log_sample_rate: f64,
+    /// Share (0.0-1.0) of OpenAI's remaining request or token budget below
+    /// which outbound calls are slowed; 0 disables throttling.
+    pub llm_throttle_threshold: f64,
+    /// Longest a single call is held back by the throttle.
+    pub llm_throttle_max_delay_ms: u64,
+    /// Raw `MODEL_PRICING` entries (`model=input/output`, USD per million
+    /// tokens); see `PricingTable`.
+    pub model_pricing: Vec<String>,
+    /// Raw `MODEL_TOKEN_LIMITS` entries (`model=context/max_output`); see
+    /// `TokenLimits`.
+    pub model_token_limits: Vec<String>,
+    /// Mock mode for local dev/CI: health checks skip the real provider call.
+    pub llm_mock: bool,
+    /// Save every successful completion as a cassette in `llm_cassette_dir`.
+    pub llm_record: bool,
+    /// Answer requests from recorded cassettes instead of the provider.
+    pub llm_replay: bool,
+    pub llm_cassette_dir: String,
+    /// Connect to the LLM provider at boot so the first request skips the handshake.
+    pub warmup_llm: bool,
+    /// Readings remembered per user for `use_memory`.
+    pub memory_max_turns: usize,
+    pub memory_ttl_secs: i64,
+    /// Cap on memory context injected into a reading prompt, in characters.
+    pub memory_max_context_chars: usize,
+    /// Estimated-token cap on a reading prompt (system plus user); memory
+    /// and card keywords are trimmed to fit. 0 disables trimming.
+    pub max_prompt_tokens: usize,
+    /// Sampling for the question filter (QuestionAnalysis); deterministic by default.
+    pub filter_temperature: f32,
+    pub filter_max_tokens: Option<u32>,
+    pub filter_top_p: Option<f32>,
+    /// Sampling for the ReadingAgent; slightly creative by default.
+    pub reading_temperature: f32,
+    pub reading_max_tokens: Option<u32>,
+    pub reading_top_p: Option<f32>,
+    /// `stripe` (default) or `mock`.
+    pub payment_provider: String,
+    /// Force the mock payment provider regardless of `payment_provider`.
+    pub mock_payments: bool,
+    pub stripe_secret_key: Option<String>,
+    pub stripe_webhook_secret: Option<String>,
+    /// Verifies LINE webhook signatures; the webhook is off without it.
+    pub line_channel_secret: Option<String>,
+    /// `BAHT_PER_CREDIT`: a payment buys `amount_baht / baht_per_credit`
+    /// credits, fixed when the charge is created and granted once it's paid.
+    pub baht_per_credit: u32,
+    /// Redraw cards once when the ReadingAgent keeps failing validation.
+    pub reshuffle_on_failure: bool,
+    /// Store a reproducible draw audit on every reading.
+    pub audit_draws: bool,
+    /// Send each reading's draw seed to the LLM as `seed`, and record it with
+    /// the `system_fingerprint`, so the wording can be reproduced.
+    pub seed_completions: bool,
+    /// Cap on ReadingAgent calls per reading.
+    pub reading_max_attempts: u32,
+    /// Retries shared across analysis and reading stages of one request.
+    pub pipeline_retry_budget: u32,
+    /// Request deadline for routes without an entry in `route_timeouts`.
+    pub default_route_timeout_secs: u64,
+    /// Raw `ROUTE_TIMEOUTS` entries (`pattern=secs`); see `RouteTimeouts`.
+    pub route_timeouts: Vec<String>,
+    /// Raw `RATE_LIMIT_FREE` / `RATE_LIMIT_PAID` (`30/min`, `off`); see
+    /// `RateLimiter`.
+    pub rate_limit_free: Option<String>,
+    pub rate_limit_paid: Option<String>,
+    /// Readings one user may have in flight at once; 0 disables the cap.
+    pub max_concurrent_readings: u32,
+    /// QuestionFilter rejections within `abuse_window_secs` that suspend a
+    /// user; 0 disables auto-suspension.
+    pub abuse_rejection_threshold: u32,
+    pub abuse_window_secs: u64,
+    /// Log request bodies (PII redacted) for debugging; off by default.
+    pub log_request_bodies: bool,
+    /// Raw `TRUSTED_PROXIES` entries (IPs or CIDR ranges) whose forwarded
+    /// headers are believed; see `TrustedProxies`.
+    pub trusted_proxies: Vec<String>,
+    /// Redirect plain http requests to https.
+    pub force_https: bool,
+    /// Seconds between purges of expired rows; 0 disables the cleanup task.
+    pub cleanup_interval_secs: u64,
+    /// Lifetime of a reading share link.
+    pub share_ttl_secs: i64,
+    /// Readings a device may get without an account, ever; 0 disables
+    /// guest readings.
+    pub guest_readings_per_device: u32,
+    /// How long a guest reading stays claimable by an account.
+    pub guest_claim_ttl_secs: u64,
+    /// How long a queued reading job may wait for a worker before it
+    /// expires and is refunded; 0 disables the deadline.
+    pub job_deadline_secs: i64,
+    /// Queued jobs at which `POST /readings/async` is refused (503); 0
+    /// disables the cap.
+    pub max_queue_depth: u64,
+    /// Credits charged per reading.
+    pub reading_credit_cost: i64,
+    /// Credits charged for a multi-question `/readings/session`.
+    pub session_credit_cost: i64,
+    /// Credits charged by `/readings/{id}/regenerate`.
+    pub regenerate_credit_cost: i64,
+    /// Credits granted once, when a LINE login creates the user; 0 grants
+    /// none.
+    pub signup_bonus_credits: i64,
+    /// Regenerated versions allowed per original reading.
+    pub max_regenerations: i64,
+    /// Saved draft questions allowed per user.
+    pub max_drafts: i64,
+    /// Most questions one `/readings/session` may ask.
+    pub max_session_questions: usize,
+    /// Follow-up questions allowed per original reading.
+    pub max_followups_per_reading: i64,
+    /// Answer 404 rather than 403 for another user's reading, so a reading
+    /// id's existence isn't revealed.
+    pub hide_ownership: bool,
+    /// Largest spread (in cards) free and paid users may draw; admins are
+    /// unlimited.
+    pub free_max_cards: usize,
+    pub paid_max_cards: usize,
+    /// Longest question accepted by `/ask`, in characters.
+    pub ask_max_question_chars: usize,
+    /// Language codes (`Language::code`) readings accept; others get 422.
+    pub supported_languages: Vec<String>,
+    /// Language for questions too short to classify (`DEFAULT_LANGUAGE`).
+    pub default_language: Language,
+    /// Topic names (`Topic::as_str`) whose readings get a disclaimer.
+    pub sensitive_topics: Vec<String>,
+    /// How long QuestionAnalysis results are reused for the same question
+    /// (in Redis); 0 limits reuse to one request.
+    pub analysis_cache_secs: u64,
+    /// Raw `TOPIC_SUIT_WEIGHTS` entries (`topic:suit=weight`); see
+    /// `TopicSuitWeights`.
+    pub topic_suit_weights: Vec<String>,
+    /// Readings are refused (422) when QuestionAnalysis confidence is below this.
+    pub analysis_min_confidence: f32,
+    /// Raw `*_GUARDRAILS` lists per agent; `None` when unset (agent
+    /// defaults). See `GuardrailPolicy`.
+    pub reading_guardrails: Option<Vec<String>>,
+    pub analysis_guardrails: Option<Vec<String>>,
+    pub ask_guardrails: Option<Vec<String>>,
+}
+
+impl Config {
+    /// Build the configuration from the process environment (after `dotenv`).
+    pub fn from_env() -> Result<Self, ConfigError> {
+        Config::from_vars(|key| env::var(key).ok())
+    }
+
+    /// Build the configuration from `lookup` instead of the environment,
+    /// e.g. a fixed set of values in tests.
+    ///
+    /// `JWT_SECRET` is required: it signs tokens and (unless
+    /// `CURSOR_SECRET` is set) pagination cursors, so a guessable default
+    /// would let anyone mint them. Only under `DEV_MODE` does a missing or
+    /// placeholder secret fall back to `DEV_JWT_SECRET`.
+    pub fn from_vars(lookup: impl Fn(&str) -> Option<String>) -> Result<Self, ConfigError> {
+        let vars = Vars(lookup);
+        let dev_mode = vars.parse_var("DEV_MODE").unwrap_or(false);
+        let jwt_secret = match vars.var("JWT_SECRET").filter(|s| s != DEV_JWT_SECRET) {
+            Some(secret) => secret,
+            None if dev_mode => {
+                log::warn!("JWT_SECRET is not set; using the development secret");
+                DEV_JWT_SECRET.to_string()
+            }
+            None => return Err(ConfigError::Missing("JWT_SECRET")),
+        };
+        Ok(Config {
+            host: vars.var("HOST").unwrap_or_else(|| "0.0.0.0".to_string()),
+            port: vars.parse_var("PORT").unwrap_or(8080),
+            database_url: vars.var("DATABASE_URL"),
+            mock_db: vars.parse_var("MOCK_DB").unwrap_or(false),
+            dev_mode,
+            db_statement_timeout_ms: vars.parse_var("DB_STATEMENT_TIMEOUT_MS").unwrap_or(5000),
+            redis_url: vars.var("UPSTASH_REDIS_URL"),
+            cursor_secret: vars
+                .var("CURSOR_SECRET")
+                .unwrap_or_else(|| jwt_secret.clone()),
+            jwt_secret,
+            jwt_leeway_secs: vars.parse_var("JWT_LEEWAY_SECS").unwrap_or(60),
+            frontend_url: vars
+                .var("FRONTEND_URL")
+                .unwrap_or_else(|| "http://localhost:3000".to_string()),
+            openai_api_key: vars.var("OPENAI_API_KEY"),
+            openai_base_url: vars
+                .var("OPENAI_BASE_URL")
+                .unwrap_or_else(|| "https://api.openai.com/v1".to_string()),
+            openai_model: vars
+                .var("OPENAI_MODEL")
+                .unwrap_or_else(|| "gpt-4o-mini".to_string()),
+            openai_allowed_models: vars.var("OPENAI_ALLOWED_MODELS"),
+            openai_model_fallbacks: vars.list_var("OPENAI_MODEL_FALLBACKS"),
+            openai_timeout_secs: vars.parse_var("OPENAI_TIMEOUT_SECS").unwrap_or(30),
+            llm_total_deadline_secs: vars.parse_var("LLM_TOTAL_DEADLINE_SECS").unwrap_or(60),
+            llm_max_response_bytes: vars
+                .parse_var("LLM_MAX_RESPONSE_BYTES")
+                .unwrap_or(4 * 1024 * 1024),
+            store_raw_llm: vars
+                .var("STORE_RAW_LLM")
+                .and_then(|mode| RawLlmStorage::from_name(&mode))
+                .unwrap_or_default(),
+            openai_org_id: vars.var("OPENAI_ORG_ID"),
+            openai_project_id: vars.var("OPENAI_PROJECT_ID"),
+            openai_prompt_cache: vars.parse_var("OPENAI_PROMPT_CACHE").unwrap_or(true),
+            llm_log_sample_rate: vars.parse_var("LLM_LOG_SAMPLE_RATE").unwrap_or(0.0),
+            llm_throttle_threshold: vars.parse_var("LLM_THROTTLE_THRESHOLD").unwrap_or(0.1),
+            llm_throttle_max_delay_ms: vars.parse_var("LLM_THROTTLE_MAX_DELAY_MS").unwrap_or(5000),
+            model_pricing: vars.list_var("MODEL_PRICING"),
+            model_token_limits: vars.list_var("MODEL_TOKEN_LIMITS"),
+            llm_mock: vars.parse_var("LLM_MOCK").unwrap_or(false),
+            llm_record: vars.parse_var("LLM_RECORD").unwrap_or(false),
+            llm_replay: vars.parse_var("LLM_REPLAY").unwrap_or(false),
+            llm_cassette_dir: vars
+                .var("LLM_CASSETTE_DIR")
+                .unwrap_or_else(|| "tests/cassettes".to_string()),
+            warmup_llm: vars.parse_var("WARMUP_LLM").unwrap_or(false),
+            memory_max_turns: vars.parse_var("MEMORY_MAX_TURNS").unwrap_or(5),
+            memory_ttl_secs: vars
+                .parse_var("MEMORY_TTL_SECS")
+                .unwrap_or(7 * 24 * 60 * 60),
+            memory_max_context_chars: vars.parse_var("MEMORY_MAX_CONTEXT_CHARS").unwrap_or(1000),
+            max_prompt_tokens: vars.parse_var("MAX_PROMPT_TOKENS").unwrap_or(8000),
+            filter_temperature: vars.parse_var("FILTER_TEMPERATURE").unwrap_or(0.0),
+            filter_max_tokens: vars.parse_var("FILTER_MAX_TOKENS"),
+            filter_top_p: vars.parse_var("FILTER_TOP_P"),
+            reading_temperature: vars.parse_var("READING_TEMPERATURE").unwrap_or(0.7),
+            reading_max_tokens: vars.parse_var("READING_MAX_TOKENS"),
+            reading_top_p: vars.parse_var("READING_TOP_P"),
+            payment_provider: vars
+                .var("PAYMENT_PROVIDER")
+                .unwrap_or_else(|| "stripe".to_string()),
+            mock_payments: vars.parse_var("MOCK_PAYMENTS").unwrap_or(false),
+            stripe_secret_key: vars.var("STRIPE_API_KEY"),
+            stripe_webhook_secret: vars.var("STRIPE_WEBHOOK_SECRET"),
+            line_channel_secret: vars.var("LINE_CHANNEL_SECRET"),
+            // `CREDIT_PRICE_BAHT` is the older name.
+            baht_per_credit: vars
+                .parse_var("BAHT_PER_CREDIT")
+                .or_else(|| vars.parse_var("CREDIT_PRICE_BAHT"))
+                .unwrap_or(10),
+            reshuffle_on_failure: vars.parse_var("RESHUFFLE_ON_FAILURE").unwrap_or(false),
+            audit_draws: vars.parse_var("AUDIT_DRAWS").unwrap_or(false),
+            seed_completions: vars.parse_var("SEED_COMPLETIONS").unwrap_or(false),
+            reading_max_attempts: vars.parse_var("READING_MAX_ATTEMPTS").unwrap_or(4),
+            pipeline_retry_budget: vars.parse_var("PIPELINE_RETRY_BUDGET").unwrap_or(3),
+            default_route_timeout_secs: vars.parse_var("DEFAULT_ROUTE_TIMEOUT_SECS").unwrap_or(30),
+            route_timeouts: vars.list_var("ROUTE_TIMEOUTS"),
+            rate_limit_free: vars.var("RATE_LIMIT_FREE"),
+            rate_limit_paid: vars.var("RATE_LIMIT_PAID"),
+            max_concurrent_readings: vars.parse_var("MAX_CONCURRENT_READINGS").unwrap_or(2),
+            abuse_rejection_threshold: vars.parse_var("ABUSE_REJECTION_THRESHOLD").unwrap_or(20),
+            abuse_window_secs: vars.parse_var("ABUSE_WINDOW_SECS").unwrap_or(3600),
+            log_request_bodies: vars.parse_var("LOG_REQUEST_BODIES").unwrap_or(false),
+            trusted_proxies: vars.list_var("TRUSTED_PROXIES"),
+            force_https: vars.parse_var("FORCE_HTTPS").unwrap_or(false),
+            cleanup_interval_secs: vars.parse_var("CLEANUP_INTERVAL_SECS").unwrap_or(3600),
+            share_ttl_secs: vars.parse_var("SHARE_TTL_SECS").unwrap_or(7 * 24 * 60 * 60),
+            guest_readings_per_device: vars.parse_var("GUEST_READINGS_PER_DEVICE").unwrap_or(1),
+            guest_claim_ttl_secs: vars
+                .parse_var("GUEST_CLAIM_TTL_SECS")
+                .unwrap_or(7 * 24 * 60 * 60),
+            job_deadline_secs: vars.parse_var("JOB_DEADLINE_SECS").unwrap_or(300),
+            max_queue_depth: vars.parse_var("MAX_QUEUE_DEPTH").unwrap_or(500),
+            reading_credit_cost: vars.parse_var("READING_CREDIT_COST").unwrap_or(1),
+            session_credit_cost: vars.parse_var("SESSION_CREDIT_COST").unwrap_or(3),
+            regenerate_credit_cost: vars.parse_var("REGENERATE_CREDIT_COST").unwrap_or(1),
+            signup_bonus_credits: vars.parse_var("SIGNUP_BONUS_CREDITS").unwrap_or(0),
+            max_regenerations: vars.parse_var("MAX_REGENERATIONS").unwrap_or(3),
+            max_drafts: vars.parse_var("MAX_DRAFTS").unwrap_or(20),
+            max_session_questions: vars.parse_var("MAX_SESSION_QUESTIONS").unwrap_or(5),
+            max_followups_per_reading: vars.parse_var("MAX_FOLLOWUPS_PER_READING").unwrap_or(3),
+            hide_ownership: vars.parse_var("HIDE_OWNERSHIP").unwrap_or(true),
+            free_max_cards: vars.parse_var("FREE_MAX_CARDS").unwrap_or(3),
+            paid_max_cards: vars.parse_var("PAID_MAX_CARDS").unwrap_or(10),
+            ask_max_question_chars: vars.parse_var("ASK_MAX_QUESTION_CHARS").unwrap_or(500),
+            supported_languages: match vars.list_var("SUPPORTED_LANGUAGES") {
+                codes if codes.is_empty() => vec!["th".to_string(), "en".to_string()],
+                codes => codes,
+            },
+            default_language: vars
+                .var("DEFAULT_LANGUAGE")
+                .and_then(|code| Language::from_code(&code))
+                .unwrap_or(Language::Thai),
+            sensitive_topics: match vars.list_var("SENSITIVE_TOPICS") {
+                topics if topics.is_empty() => {
+                    ["health", "legal", "finance"].map(str::to_string).to_vec()
+                }
+                topics => topics,
+            },
+            topic_suit_weights: vars.list_var("TOPIC_SUIT_WEIGHTS"),
+            analysis_cache_secs: vars.parse_var("ANALYSIS_CACHE_SECS").unwrap_or(3600),
+            analysis_min_confidence: vars.parse_var("ANALYSIS_MIN_CONFIDENCE").unwrap_or(0.4),
+            reading_guardrails: vars
+                .var("READING_GUARDRAILS")
+                .map(|_| vars.list_var("READING_GUARDRAILS")),
+            analysis_guardrails: vars
+                .var("ANALYSIS_GUARDRAILS")
+                .map(|_| vars.list_var("ANALYSIS_GUARDRAILS")),
+            ask_guardrails: vars
+                .var("ASK_GUARDRAILS")
+                .map(|_| vars.list_var("ASK_GUARDRAILS")),
+        })
+    }
+}
+
+/// Environment lookups for `Config::from_vars`.
+struct Vars<F>(F);
+
+impl<F: Fn(&str) -> Option<String>> Vars<F> {
+    /// Read a variable, treating empty values as unset.
+    fn var(&self, key: &str) -> Option<String> {
+        (self.0)(key).filter(|v| !v.trim().is_empty())
+    }
+
+    /// Read a comma-separated variable, dropping empty entries.
+    fn list_var(&self, key: &str) -> Vec<String> {
+        self.var(key)
+            .map(|v| {
+                v.split(',')
+                    .map(str::trim)
+                    .filter(|s| !s.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Read and parse a variable, ignoring values that fail to parse.
+    fn parse_var<T: std::str::FromStr>(&self, key: &str) -> Option<T> {
+        self.var(key).and_then(|v| v.trim().parse().ok())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn from_pairs(pairs: &[(&str, &str)]) -> Result<Config, ConfigError> {
+        Config::from_vars(|key| {
+            pairs
+                .iter()
+                .find(|(k, _)| *k == key)
+                .map(|(_, v)| v.to_string())
+        })
+    }
+
+    #[test]
+    fn requires_jwt_secret() {
+        assert!(matches!(
+            from_pairs(&[]),
+            Err(ConfigError::Missing("JWT_SECRET"))
+        ));
+        assert!(matches!(
+            from_pairs(&[("JWT_SECRET", DEV_JWT_SECRET)]),
+            Err(ConfigError::Missing("JWT_SECRET"))
+        ));
+        let config = from_pairs(&[("JWT_SECRET", "s3cret")]).unwrap();
+        assert_eq!(config.jwt_secret, "s3cret");
+        assert_eq!(config.cursor_secret, "s3cret");
+    }
+
+    #[test]
+    fn dev_mode_falls_back_to_the_development_secret() {
+        let config = from_pairs(&[("DEV_MODE", "true")]).unwrap();
+        assert_eq!(config.jwt_secret, DEV_JWT_SECRET);
+    }
+
+    #[test]
+    fn baht_per_credit_falls_back_to_the_older_name() {
+        let config = |pairs: &[(&str, &str)]| {
+            let mut pairs = pairs.to_vec();
+            pairs.push(("JWT_SECRET", "s3cret"));
+            from_pairs(&pairs).unwrap().baht_per_credit
+        };
+        assert_eq!(config(&[]), 10);
+        assert_eq!(config(&[("CREDIT_PRICE_BAHT", "20")]), 20);
+        assert_eq!(
+            config(&[("BAHT_PER_CREDIT", "25"), ("CREDIT_PRICE_BAHT", "20")]),
+            25
+        );
+    }
+}
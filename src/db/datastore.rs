@@ -0,0 +1,850 @@
+//! `Datastore`: the user, reading, and payment queries handlers use, behind
+//! a trait so they can run against Postgres or an in-memory store.
+//!
+//! The in-memory store is picked when `DATABASE_URL` is unset or
+//! `MOCK_DB=true`, so local runs and CI work without Postgres. Multi-step
+//! transactional writes (payment webhooks, credit changes) still need a real
+//! `PgPool` and stay unavailable without one.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+
+use super::drafts::{delete_draft, get_draft, insert_draft, list_drafts};
+use super::error::DbError;
+use super::payments::{consume_payment, get_payment, has_settled_payment, set_payment_reading};
+use super::readings::{
+    NewReading, RawLlmStorage, ReadingSummary, SIMILAR_FINGERPRINT_PREFIX, count_followups,
+    count_reading_versions, find_similar, get_reading_result, insert_followup, insert_many,
+    insert_reading, insert_reading_version, list_readings, set_reading_moderation, stored_result,
+};
+use super::referrals::{new_referral_code, referral_stats};
+use super::users::{get_user, is_suspended, suspend_user, unsuspend_user, upsert_user, user_stats};
+use crate::config::Config;
+use crate::models::{
+    ModerationVerdict, Payment, ReadingDraft, ReferralStats, Spread, TarotReading, Topic, User,
+    UserStats,
+};
+use crate::services::Language;
+
+#[async_trait]
+pub trait Datastore: Send + Sync {
+    /// Find or create the user for a LINE login. Creating one grants the
+    /// signup bonus (`SIGNUP_BONUS_CREDITS`) where credits are kept.
+    async fn upsert_user(&self, line_id: &str, name: Option<&str>) -> Result<User, DbError>;
+
+    async fn get_user(&self, user_id: i64) -> Result<Option<User>, DbError>;
+
+    /// Reading and spend aggregates; readings this month count from
+    /// `month_start`.
+    async fn user_stats(
+        &self,
+        user_id: i64,
+        month_start: DateTime<Utc>,
+    ) -> Result<Option<UserStats>, DbError>;
+
+    /// The user's referral code (generated on first use) and referral
+    /// counts; `None` if there is no such user.
+    async fn referral_stats(&self, user_id: i64) -> Result<Option<ReferralStats>, DbError>;
+
+    /// Suspend a user pending review; an existing suspension keeps its
+    /// reason. Returns whether the user exists.
+    async fn suspend_user(&self, user_id: i64, reason: &str) -> Result<bool, DbError>;
+
+    /// Lift a suspension. Returns whether the user was suspended.
+    async fn unsuspend_user(&self, user_id: i64) -> Result<bool, DbError>;
+
+    async fn is_suspended(&self, user_id: i64) -> Result<bool, DbError>;
+
+    /// Store a generated reading and return its id.
+    async fn insert_reading(
+        &self,
+        user_id: i64,
+        reading: &TarotReading,
+        language: Language,
+        fingerprint: &str,
+    ) -> Result<i64, DbError>;
+
+    /// Store a regenerated version of `source_id`; returns its id and the
+    /// original reading it is linked to.
+    async fn insert_reading_version(
+        &self,
+        user_id: i64,
+        source_id: i64,
+        reading: &TarotReading,
+        language: Language,
+        fingerprint: &str,
+    ) -> Result<(i64, i64), DbError>;
+
+    /// Store a follow-up question asked after `source_id`; returns its id
+    /// and the first reading of the conversation it is linked to.
+    async fn insert_followup(
+        &self,
+        user_id: i64,
+        source_id: i64,
+        reading: &TarotReading,
+        language: Language,
+        fingerprint: &str,
+    ) -> Result<(i64, i64), DbError>;
+
+    /// Store historical readings (imports) with their original times, all
+    /// or none; returns their ids in the order of `rows`.
+    async fn insert_readings(&self, rows: &[NewReading]) -> Result<Vec<i64>, DbError>;
+
+    /// Versions already regenerated from the original of `reading_id`.
+    async fn count_reading_versions(&self, reading_id: i64) -> Result<i64, DbError>;
+
+    /// Follow-ups already asked in the conversation of `reading_id`.
+    async fn count_followups(&self, reading_id: i64) -> Result<i64, DbError>;
+
+    /// A user's readings, newest first, keyset-paginated on `id`.
+    async fn list_readings(
+        &self,
+        user_id: i64,
+        before_id: Option<i64>,
+        limit: i64,
+    ) -> Result<Vec<ReadingSummary>, DbError>;
+
+    /// The user's readings related to `reading_id` by question
+    /// `fingerprint` prefix or `topic`, fingerprint matches first.
+    async fn find_similar(
+        &self,
+        user_id: i64,
+        reading_id: i64,
+        fingerprint: &str,
+        topic: Option<Topic>,
+        limit: i64,
+    ) -> Result<Vec<ReadingSummary>, DbError>;
+
+    /// Owner and stored result JSON of a reading.
+    async fn get_reading_result(
+        &self,
+        reading_id: i64,
+    ) -> Result<Option<(i64, serde_json::Value)>, DbError>;
+
+    /// Record a re-moderation verdict on its reading; `false` if the
+    /// reading doesn't exist.
+    async fn set_reading_moderation(&self, verdict: &ModerationVerdict) -> Result<bool, DbError>;
+
+    /// Save a draft question unless the user already has `max_drafts`;
+    /// `None` when at the cap.
+    async fn insert_draft(
+        &self,
+        user_id: i64,
+        question: &str,
+        spread: Spread,
+        max_drafts: i64,
+    ) -> Result<Option<ReadingDraft>, DbError>;
+
+    /// A user's drafts, newest first.
+    async fn list_drafts(&self, user_id: i64) -> Result<Vec<ReadingDraft>, DbError>;
+
+    /// One of the user's drafts; `None` if missing or someone else's.
+    async fn get_draft(&self, user_id: i64, draft_id: i64)
+    -> Result<Option<ReadingDraft>, DbError>;
+
+    /// Delete one of the user's drafts. Returns whether it existed.
+    async fn delete_draft(&self, user_id: i64, draft_id: i64) -> Result<bool, DbError>;
+
+    async fn get_payment(&self, payment_id: i64) -> Result<Option<Payment>, DbError>;
+
+    /// Whether the user has ever completed a payment.
+    async fn has_settled_payment(&self, user_id: i64) -> Result<bool, DbError>;
+
+    /// Use the user's succeeded payment to pay for one reading. `false` if
+    /// it isn't theirs, isn't paid, or has already been used.
+    async fn consume_payment(&self, payment_id: i64, user_id: i64) -> Result<bool, DbError>;
+
+    /// Record the reading a consumed payment paid for.
+    async fn set_payment_reading(&self, payment_id: i64, reading_id: i64) -> Result<(), DbError>;
+}
+
+/// Postgres-backed store; delegates to the query functions in `db`.
+pub struct PgDatastore {
+    pool: PgPool,
+    raw_llm: RawLlmStorage,
+    /// Credits granted to each user `upsert_user` creates.
+    signup_bonus: i64,
+}
+
+impl PgDatastore {
+    pub fn new(pool: PgPool) -> Self {
+        PgDatastore {
+            pool,
+            raw_llm: RawLlmStorage::default(),
+            signup_bonus: 0,
+        }
+    }
+
+    /// Grant `credits` to every user created from now on.
+    pub fn with_signup_bonus(mut self, credits: i64) -> Self {
+        self.signup_bonus = credits;
+        self
+    }
+
+    /// Keep `raw_llm` of each stored reading's raw completion.
+    pub fn with_raw_llm(mut self, raw_llm: RawLlmStorage) -> Self {
+        self.raw_llm = raw_llm;
+        self
+    }
+}
+
+#[async_trait]
+impl Datastore for PgDatastore {
+    async fn upsert_user(&self, line_id: &str, name: Option<&str>) -> Result<User, DbError> {
+        upsert_user(&self.pool, line_id, name, self.signup_bonus).await
+    }
+
+    async fn get_user(&self, user_id: i64) -> Result<Option<User>, DbError> {
+        get_user(&self.pool, user_id).await
+    }
+
+    async fn user_stats(
+        &self,
+        user_id: i64,
+        month_start: DateTime<Utc>,
+    ) -> Result<Option<UserStats>, DbError> {
+        user_stats(&self.pool, user_id, month_start).await
+    }
+
+    async fn referral_stats(&self, user_id: i64) -> Result<Option<ReferralStats>, DbError> {
+        referral_stats(&self.pool, user_id).await
+    }
+
+    async fn suspend_user(&self, user_id: i64, reason: &str) -> Result<bool, DbError> {
+        suspend_user(&self.pool, user_id, reason).await
+    }
+
+    async fn unsuspend_user(&self, user_id: i64) -> Result<bool, DbError> {
+        unsuspend_user(&self.pool, user_id).await
+    }
+
+    async fn is_suspended(&self, user_id: i64) -> Result<bool, DbError> {
+        is_suspended(&self.pool, user_id).await
+    }
+
+    async fn insert_reading(
+        &self,
+        user_id: i64,
+        reading: &TarotReading,
+        language: Language,
+        fingerprint: &str,
+    ) -> Result<i64, DbError> {
+        insert_reading(
+            &self.pool,
+            user_id,
+            reading,
+            language,
+            fingerprint,
+            self.raw_llm,
+        )
+        .await
+    }
+
+    async fn insert_reading_version(
+        &self,
+        user_id: i64,
+        source_id: i64,
+        reading: &TarotReading,
+        language: Language,
+        fingerprint: &str,
+    ) -> Result<(i64, i64), DbError> {
+        insert_reading_version(
+            &self.pool,
+            user_id,
+            source_id,
+            reading,
+            language,
+            fingerprint,
+            self.raw_llm,
+        )
+        .await
+    }
+
+    async fn insert_followup(
+        &self,
+        user_id: i64,
+        source_id: i64,
+        reading: &TarotReading,
+        language: Language,
+        fingerprint: &str,
+    ) -> Result<(i64, i64), DbError> {
+        insert_followup(
+            &self.pool,
+            user_id,
+            source_id,
+            reading,
+            language,
+            fingerprint,
+            self.raw_llm,
+        )
+        .await
+    }
+
+    async fn insert_readings(&self, rows: &[NewReading]) -> Result<Vec<i64>, DbError> {
+        insert_many(&self.pool, rows).await
+    }
+
+    async fn count_reading_versions(&self, reading_id: i64) -> Result<i64, DbError> {
+        count_reading_versions(&self.pool, reading_id).await
+    }
+
+    async fn count_followups(&self, reading_id: i64) -> Result<i64, DbError> {
+        count_followups(&self.pool, reading_id).await
+    }
+
+    async fn list_readings(
+        &self,
+        user_id: i64,
+        before_id: Option<i64>,
+        limit: i64,
+    ) -> Result<Vec<ReadingSummary>, DbError> {
+        list_readings(&self.pool, user_id, before_id, limit).await
+    }
+
+    async fn find_similar(
+        &self,
+        user_id: i64,
+        reading_id: i64,
+        fingerprint: &str,
+        topic: Option<Topic>,
+        limit: i64,
+    ) -> Result<Vec<ReadingSummary>, DbError> {
+        find_similar(&self.pool, user_id, reading_id, fingerprint, topic, limit).await
+    }
+
+    async fn get_reading_result(
+        &self,
+        reading_id: i64,
+    ) -> Result<Option<(i64, serde_json::Value)>, DbError> {
+        get_reading_result(&self.pool, reading_id).await
+    }
+
+    async fn set_reading_moderation(&self, verdict: &ModerationVerdict) -> Result<bool, DbError> {
+        set_reading_moderation(&self.pool, verdict).await
+    }
+
+    async fn insert_draft(
+        &self,
+        user_id: i64,
+        question: &str,
+        spread: Spread,
+        max_drafts: i64,
+    ) -> Result<Option<ReadingDraft>, DbError> {
+        insert_draft(&self.pool, user_id, question, spread, max_drafts).await
+    }
+
+    async fn list_drafts(&self, user_id: i64) -> Result<Vec<ReadingDraft>, DbError> {
+        list_drafts(&self.pool, user_id).await
+    }
+
+    async fn get_draft(
+        &self,
+        user_id: i64,
+        draft_id: i64,
+    ) -> Result<Option<ReadingDraft>, DbError> {
+        get_draft(&self.pool, user_id, draft_id).await
+    }
+
+    async fn delete_draft(&self, user_id: i64, draft_id: i64) -> Result<bool, DbError> {
+        delete_draft(&self.pool, user_id, draft_id).await
+    }
+
+    async fn get_payment(&self, payment_id: i64) -> Result<Option<Payment>, DbError> {
+        get_payment(&self.pool, payment_id).await
+    }
+
+    async fn has_settled_payment(&self, user_id: i64) -> Result<bool, DbError> {
+        has_settled_payment(&self.pool, user_id).await
+    }
+
+    async fn consume_payment(&self, payment_id: i64, user_id: i64) -> Result<bool, DbError> {
+        consume_payment(&self.pool, payment_id, user_id).await
+    }
+
+    async fn set_payment_reading(&self, payment_id: i64, reading_id: i64) -> Result<(), DbError> {
+        set_payment_reading(&self.pool, payment_id, reading_id).await
+    }
+}
+
+struct StoredReading {
+    user_id: i64,
+    parent_id: Option<i64>,
+    followup_of: Option<i64>,
+    fingerprint: String,
+    summary: ReadingSummary,
+    result: serde_json::Value,
+    moderation: Option<ModerationVerdict>,
+}
+
+impl MemoryState {
+    fn insert_reading(
+        &mut self,
+        user_id: i64,
+        parent_id: Option<i64>,
+        reading: &TarotReading,
+        fingerprint: &str,
+        created_at: DateTime<Utc>,
+    ) -> i64 {
+        let id = self.readings.keys().next_back().map_or(1, |id| id + 1);
+        let summary = ReadingSummary {
+            id,
+            question: reading.question.clone(),
+            spread: reading.spread.as_str().to_string(),
+            created_at,
+        };
+        let result = stored_result(reading);
+        self.readings.insert(
+            id,
+            StoredReading {
+                user_id,
+                parent_id,
+                followup_of: None,
+                fingerprint: fingerprint.to_string(),
+                summary,
+                result,
+                moderation: None,
+            },
+        );
+        id
+    }
+
+    /// The original a reading is a version of (itself if it is one).
+    fn original_of(&self, reading_id: i64) -> Option<i64> {
+        self.readings
+            .get(&reading_id)
+            .map(|r| r.parent_id.unwrap_or(reading_id))
+    }
+
+    /// The first reading of the conversation a reading belongs to (itself
+    /// unless it is a follow-up).
+    fn conversation_of(&self, reading_id: i64) -> Option<i64> {
+        self.readings
+            .get(&reading_id)
+            .map(|r| r.followup_of.unwrap_or(reading_id))
+    }
+}
+
+#[derive(Default)]
+struct MemoryState {
+    users: BTreeMap<i64, User>,
+    joined: HashMap<i64, DateTime<Utc>>,
+    referral_codes: HashMap<i64, String>,
+    /// Suspended user id → reason. Keyed by id alone, so tokens for users
+    /// never seen here can still be suspended.
+    suspensions: HashMap<i64, String>,
+    readings: BTreeMap<i64, StoredReading>,
+    /// Drafts with their owner.
+    drafts: BTreeMap<i64, (i64, ReadingDraft)>,
+    payments: HashMap<i64, Payment>,
+    /// Consumed payment id → the reading it paid for, once stored.
+    consumed_payments: HashMap<i64, Option<i64>>,
+}
+
+/// `HashMap`-backed store for dev and tests. Ids are assigned in insertion
+/// order, like `BIGSERIAL`.
+#[derive(Default)]
+pub struct MemoryDatastore {
+    state: Mutex<MemoryState>,
+}
+
+impl MemoryDatastore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Seed a payment, since payments are only created through `PgPool`.
+    pub fn insert_payment(&self, payment: Payment) {
+        let mut state = self.state.lock().unwrap();
+        state.payments.insert(payment.id, payment);
+    }
+
+    /// The re-moderation verdict recorded on a reading, if any.
+    pub fn reading_moderation(&self, reading_id: i64) -> Option<ModerationVerdict> {
+        let state = self.state.lock().unwrap();
+        state.readings.get(&reading_id)?.moderation.clone()
+    }
+}
+
+#[async_trait]
+impl Datastore for MemoryDatastore {
+    /// No credits are kept in memory, so there is no signup bonus.
+    async fn upsert_user(&self, line_id: &str, name: Option<&str>) -> Result<User, DbError> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(user) = state
+            .users
+            .values_mut()
+            .find(|u| u.line_id.as_deref() == Some(line_id))
+        {
+            if let Some(name) = name {
+                user.name = Some(name.to_string());
+            }
+            return Ok(user.clone());
+        }
+        let id = state.users.keys().next_back().map_or(1, |id| id + 1);
+        let user = User {
+            id,
+            line_id: Some(line_id.to_string()),
+            name: name.map(str::to_string),
+        };
+        state.users.insert(id, user.clone());
+        state.joined.insert(id, Utc::now());
+        Ok(user)
+    }
+
+    async fn get_user(&self, user_id: i64) -> Result<Option<User>, DbError> {
+        Ok(self.state.lock().unwrap().users.get(&user_id).cloned())
+    }
+
+    /// There is no credit ledger in memory, so `credits_spent` is always 0.
+    async fn user_stats(
+        &self,
+        user_id: i64,
+        month_start: DateTime<Utc>,
+    ) -> Result<Option<UserStats>, DbError> {
+        let state = self.state.lock().unwrap();
+        let Some(joined_at) = state.joined.get(&user_id).copied() else {
+            return Ok(None);
+        };
+        let originals: Vec<&StoredReading> = state
+            .readings
+            .values()
+            .filter(|r| r.user_id == user_id && r.parent_id.is_none())
+            .collect();
+        let mut topics: BTreeMap<&str, i64> = BTreeMap::new();
+        for reading in &originals {
+            if let Some(topic) = reading.result["analysis"]["topic"].as_str() {
+                *topics.entry(topic).or_default() += 1;
+            }
+        }
+        // Ties go to the alphabetically first topic, as in Postgres.
+        let top_topic = topics
+            .iter()
+            .max_by(|a, b| a.1.cmp(b.1).then(b.0.cmp(a.0)))
+            .map(|(topic, _)| Topic::from_name(topic));
+        Ok(Some(UserStats {
+            total_readings: originals.len() as i64,
+            readings_this_month: originals
+                .iter()
+                .filter(|r| r.summary.created_at >= month_start)
+                .count() as i64,
+            credits_spent: 0,
+            top_topic,
+            joined_at,
+        }))
+    }
+
+    /// Referrals are only recorded in Postgres, so the counts are always 0.
+    async fn referral_stats(&self, user_id: i64) -> Result<Option<ReferralStats>, DbError> {
+        let mut state = self.state.lock().unwrap();
+        if !state.users.contains_key(&user_id) {
+            return Ok(None);
+        }
+        let referral_code = state
+            .referral_codes
+            .entry(user_id)
+            .or_insert_with(new_referral_code)
+            .clone();
+        Ok(Some(ReferralStats {
+            referral_code,
+            successful_referrals: 0,
+            pending_referrals: 0,
+            credits_earned: 0,
+        }))
+    }
+
+    async fn suspend_user(&self, user_id: i64, reason: &str) -> Result<bool, DbError> {
+        let mut state = self.state.lock().unwrap();
+        state
+            .suspensions
+            .entry(user_id)
+            .or_insert_with(|| reason.to_string());
+        Ok(true)
+    }
+
+    async fn unsuspend_user(&self, user_id: i64) -> Result<bool, DbError> {
+        let mut state = self.state.lock().unwrap();
+        Ok(state.suspensions.remove(&user_id).is_some())
+    }
+
+    async fn is_suspended(&self, user_id: i64) -> Result<bool, DbError> {
+        let state = self.state.lock().unwrap();
+        Ok(state.suspensions.contains_key(&user_id))
+    }
+
+    async fn insert_reading(
+        &self,
+        user_id: i64,
+        reading: &TarotReading,
+        _language: Language,
+        fingerprint: &str,
+    ) -> Result<i64, DbError> {
+        let mut state = self.state.lock().unwrap();
+        Ok(state.insert_reading(user_id, None, reading, fingerprint, Utc::now()))
+    }
+
+    async fn insert_reading_version(
+        &self,
+        user_id: i64,
+        source_id: i64,
+        reading: &TarotReading,
+        _language: Language,
+        fingerprint: &str,
+    ) -> Result<(i64, i64), DbError> {
+        let mut state = self.state.lock().unwrap();
+        let parent_id = state.original_of(source_id).ok_or(DbError::NotFound)?;
+        let id = state.insert_reading(user_id, Some(parent_id), reading, fingerprint, Utc::now());
+        Ok((id, parent_id))
+    }
+
+    async fn insert_followup(
+        &self,
+        user_id: i64,
+        source_id: i64,
+        reading: &TarotReading,
+        _language: Language,
+        fingerprint: &str,
+    ) -> Result<(i64, i64), DbError> {
+        let mut state = self.state.lock().unwrap();
+        let followup_of = state.conversation_of(source_id).ok_or(DbError::NotFound)?;
+        let id = state.insert_reading(user_id, None, reading, fingerprint, Utc::now());
+        if let Some(stored) = state.readings.get_mut(&id) {
+            stored.followup_of = Some(followup_of);
+        }
+        Ok((id, followup_of))
+    }
+
+    async fn insert_readings(&self, rows: &[NewReading]) -> Result<Vec<i64>, DbError> {
+        let mut state = self.state.lock().unwrap();
+        Ok(rows
+            .iter()
+            .map(|row| {
+                state.insert_reading(
+                    row.user_id,
+                    None,
+                    &row.reading,
+                    &row.fingerprint,
+                    row.created_at,
+                )
+            })
+            .collect())
+    }
+
+    async fn count_reading_versions(&self, reading_id: i64) -> Result<i64, DbError> {
+        let state = self.state.lock().unwrap();
+        let Some(original) = state.original_of(reading_id) else {
+            return Ok(0);
+        };
+        Ok(state
+            .readings
+            .values()
+            .filter(|r| r.parent_id == Some(original))
+            .count() as i64)
+    }
+
+    async fn count_followups(&self, reading_id: i64) -> Result<i64, DbError> {
+        let state = self.state.lock().unwrap();
+        let Some(first) = state.conversation_of(reading_id) else {
+            return Ok(0);
+        };
+        Ok(state
+            .readings
+            .values()
+            .filter(|r| r.followup_of == Some(first))
+            .count() as i64)
+    }
+
+    async fn list_readings(
+        &self,
+        user_id: i64,
+        before_id: Option<i64>,
+        limit: i64,
+    ) -> Result<Vec<ReadingSummary>, DbError> {
+        let state = self.state.lock().unwrap();
+        let upper = before_id.unwrap_or(i64::MAX);
+        Ok(state
+            .readings
+            .range(..upper)
+            .rev()
+            .filter(|(_, r)| r.user_id == user_id)
+            .take(limit.max(0) as usize)
+            .map(|(_, r)| r.summary.clone())
+            .collect())
+    }
+
+    async fn find_similar(
+        &self,
+        user_id: i64,
+        reading_id: i64,
+        fingerprint: &str,
+        topic: Option<Topic>,
+        limit: i64,
+    ) -> Result<Vec<ReadingSummary>, DbError> {
+        let state = self.state.lock().unwrap();
+        let family = state.original_of(reading_id).unwrap_or(reading_id);
+        let topic = topic.map(|t| t.as_str());
+        let mut related: Vec<(bool, &StoredReading)> = state
+            .readings
+            .iter()
+            .filter(|(id, r)| r.user_id == user_id && r.parent_id.unwrap_or(**id) != family)
+            .filter_map(|(_, r)| {
+                let same_question = r
+                    .fingerprint
+                    .chars()
+                    .take(SIMILAR_FINGERPRINT_PREFIX)
+                    .eq(fingerprint.chars().take(SIMILAR_FINGERPRINT_PREFIX));
+                let same_topic = topic.is_some() && r.result["analysis"]["topic"].as_str() == topic;
+                (same_question || same_topic).then_some((same_question, r))
+            })
+            .collect();
+        // Same question first, then newest first; ids grow with time.
+        related.sort_by(|(a_same, a), (b_same, b)| {
+            b_same.cmp(a_same).then(b.summary.id.cmp(&a.summary.id))
+        });
+        Ok(related
+            .into_iter()
+            .take(limit.max(0) as usize)
+            .map(|(_, r)| r.summary.clone())
+            .collect())
+    }
+
+    async fn get_reading_result(
+        &self,
+        reading_id: i64,
+    ) -> Result<Option<(i64, serde_json::Value)>, DbError> {
+        let state = self.state.lock().unwrap();
+        Ok(state
+            .readings
+            .get(&reading_id)
+            .map(|r| (r.user_id, r.result.clone())))
+    }
+
+    async fn set_reading_moderation(&self, verdict: &ModerationVerdict) -> Result<bool, DbError> {
+        let mut state = self.state.lock().unwrap();
+        Ok(match state.readings.get_mut(&verdict.reading_id) {
+            Some(reading) => {
+                reading.moderation = Some(verdict.clone());
+                true
+            }
+            None => false,
+        })
+    }
+
+    async fn insert_draft(
+        &self,
+        user_id: i64,
+        question: &str,
+        spread: Spread,
+        max_drafts: i64,
+    ) -> Result<Option<ReadingDraft>, DbError> {
+        let mut state = self.state.lock().unwrap();
+        let count = state
+            .drafts
+            .values()
+            .filter(|(owner, _)| *owner == user_id)
+            .count() as i64;
+        if count >= max_drafts {
+            return Ok(None);
+        }
+        let id = state.drafts.keys().next_back().map_or(1, |id| id + 1);
+        let draft = ReadingDraft {
+            id,
+            question: question.to_string(),
+            spread,
+            created_at: Utc::now(),
+        };
+        state.drafts.insert(id, (user_id, draft.clone()));
+        Ok(Some(draft))
+    }
+
+    async fn list_drafts(&self, user_id: i64) -> Result<Vec<ReadingDraft>, DbError> {
+        let state = self.state.lock().unwrap();
+        Ok(state
+            .drafts
+            .values()
+            .rev()
+            .filter(|(owner, _)| *owner == user_id)
+            .map(|(_, draft)| draft.clone())
+            .collect())
+    }
+
+    async fn get_draft(
+        &self,
+        user_id: i64,
+        draft_id: i64,
+    ) -> Result<Option<ReadingDraft>, DbError> {
+        let state = self.state.lock().unwrap();
+        Ok(state
+            .drafts
+            .get(&draft_id)
+            .filter(|(owner, _)| *owner == user_id)
+            .map(|(_, draft)| draft.clone()))
+    }
+
+    async fn delete_draft(&self, user_id: i64, draft_id: i64) -> Result<bool, DbError> {
+        let mut state = self.state.lock().unwrap();
+        match state.drafts.get(&draft_id) {
+            Some((owner, _)) if *owner == user_id => {
+                state.drafts.remove(&draft_id);
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn get_payment(&self, payment_id: i64) -> Result<Option<Payment>, DbError> {
+        Ok(self
+            .state
+            .lock()
+            .unwrap()
+            .payments
+            .get(&payment_id)
+            .cloned())
+    }
+
+    async fn has_settled_payment(&self, user_id: i64) -> Result<bool, DbError> {
+        let state = self.state.lock().unwrap();
+        Ok(state
+            .payments
+            .values()
+            .any(|p| p.user_id == user_id && p.status == "succeeded"))
+    }
+    async fn consume_payment(&self, payment_id: i64, user_id: i64) -> Result<bool, DbError> {
+        let mut state = self.state.lock().unwrap();
+        let usable = state
+            .payments
+            .get(&payment_id)
+            .is_some_and(|p| p.user_id == user_id && p.status == "succeeded")
+            && !state.consumed_payments.contains_key(&payment_id);
+        if usable {
+            state.consumed_payments.insert(payment_id, None);
+        }
+        Ok(usable)
+    }
+
+    async fn set_payment_reading(&self, payment_id: i64, reading_id: i64) -> Result<(), DbError> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(reading) = state.consumed_payments.get_mut(&payment_id) {
+            *reading = Some(reading_id);
+        }
+        Ok(())
+    }
+}
+
+/// Postgres when a pool is available and `MOCK_DB` is off, else in-memory.
+pub fn datastore_from_config(config: &Config, pool: Option<&PgPool>) -> Arc<dyn Datastore> {
+    match pool {
+        Some(pool) if !config.mock_db => Arc::new(
+            PgDatastore::new(pool.clone())
+                .with_raw_llm(config.store_raw_llm)
+                .with_signup_bonus(config.signup_bonus_credits),
+        ),
+        _ => {
+            log::warn!("using the in-memory datastore; data is lost on restart");
+            Arc::new(MemoryDatastore::new())
+        }
+    }
+}
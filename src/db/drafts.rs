@@ -0,0 +1,89 @@
+//! Saved draft questions.
+
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+
+use super::error::DbError;
+use crate::models::{ReadingDraft, Spread};
+
+#[derive(sqlx::FromRow)]
+struct DraftRow {
+    id: i64,
+    question: String,
+    spread: String,
+    created_at: DateTime<Utc>,
+}
+
+impl From<DraftRow> for ReadingDraft {
+    fn from(row: DraftRow) -> Self {
+        ReadingDraft {
+            id: row.id,
+            question: row.question,
+            spread: row.spread.parse().unwrap_or_default(),
+            created_at: row.created_at,
+        }
+    }
+}
+
+/// Save a draft unless the user already has `max_drafts`; `None` when at
+/// the cap.
+pub async fn insert_draft(
+    pool: &PgPool,
+    user_id: i64,
+    question: &str,
+    spread: Spread,
+    max_drafts: i64,
+) -> Result<Option<ReadingDraft>, DbError> {
+    let row = sqlx::query_as::<_, DraftRow>(
+        "INSERT INTO reading_drafts (user_id, question, spread) \
+         SELECT $1, $2, $3 \
+         WHERE (SELECT COUNT(*) FROM reading_drafts WHERE user_id = $1) < $4 \
+         RETURNING id, question, spread, created_at",
+    )
+    .bind(user_id)
+    .bind(question)
+    .bind(spread.as_str())
+    .bind(max_drafts)
+    .fetch_optional(pool)
+    .await?;
+    Ok(row.map(ReadingDraft::from))
+}
+
+/// A user's drafts, newest first.
+pub async fn list_drafts(pool: &PgPool, user_id: i64) -> Result<Vec<ReadingDraft>, DbError> {
+    let rows = sqlx::query_as::<_, DraftRow>(
+        "SELECT id, question, spread, created_at FROM reading_drafts \
+         WHERE user_id = $1 ORDER BY id DESC",
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows.into_iter().map(ReadingDraft::from).collect())
+}
+
+/// One of the user's drafts; `None` if missing or someone else's.
+pub async fn get_draft(
+    pool: &PgPool,
+    user_id: i64,
+    draft_id: i64,
+) -> Result<Option<ReadingDraft>, DbError> {
+    let row = sqlx::query_as::<_, DraftRow>(
+        "SELECT id, question, spread, created_at FROM reading_drafts \
+         WHERE id = $1 AND user_id = $2",
+    )
+    .bind(draft_id)
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?;
+    Ok(row.map(ReadingDraft::from))
+}
+
+/// Delete one of the user's drafts. Returns whether it existed.
+pub async fn delete_draft(pool: &PgPool, user_id: i64, draft_id: i64) -> Result<bool, DbError> {
+    let result = sqlx::query("DELETE FROM reading_drafts WHERE id = $1 AND user_id = $2")
+        .bind(draft_id)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
@@ -0,0 +1,150 @@
+//! Typed database errors.
+
+use thiserror::Error;
+
+/// Postgres SQLSTATE for a statement cancelled by `statement_timeout`.
+const QUERY_CANCELED: &str = "57014";
+/// Postgres SQLSTATE for a unique constraint violation.
+const UNIQUE_VIOLATION: &str = "23505";
+/// Postgres SQLSTATE for a foreign key violation.
+const FOREIGN_KEY_VIOLATION: &str = "23503";
+
+#[derive(Debug, Error)]
+pub enum DbError {
+    /// A `fetch_one` matched no row.
+    #[error("Record not found")]
+    NotFound,
+    /// An insert or update hit a unique constraint (named when Postgres says which).
+    #[error("Record already exists")]
+    UniqueViolation { constraint: Option<String> },
+    /// A write referenced a row that doesn't exist (e.g. an unknown user).
+    #[error("Referenced record does not exist")]
+    ForeignKeyViolation { constraint: Option<String> },
+    /// The statement ran past `statement_timeout` or no pooled connection
+    /// became available in time.
+    #[error("Database query timed out")]
+    Timeout,
+    #[error("Database error: {0}")]
+    Other(sqlx::Error),
+}
+
+impl From<sqlx::Error> for DbError {
+    fn from(err: sqlx::Error) -> Self {
+        match &err {
+            sqlx::Error::RowNotFound => DbError::NotFound,
+            sqlx::Error::PoolTimedOut => DbError::Timeout,
+            sqlx::Error::Database(db) if db.code().as_deref() == Some(UNIQUE_VIOLATION) => {
+                DbError::UniqueViolation {
+                    constraint: db.constraint().map(str::to_string),
+                }
+            }
+            sqlx::Error::Database(db) if db.code().as_deref() == Some(FOREIGN_KEY_VIOLATION) => {
+                DbError::ForeignKeyViolation {
+                    constraint: db.constraint().map(str::to_string),
+                }
+            }
+            sqlx::Error::Database(db) if db.code().as_deref() == Some(QUERY_CANCELED) => {
+                DbError::Timeout
+            }
+            _ => DbError::Other(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+    use std::fmt;
+
+    use actix_web::ResponseError;
+    use sqlx::error::{DatabaseError, ErrorKind};
+
+    use super::*;
+    use crate::middleware::ApiError;
+
+    /// A Postgres error reduced to its SQLSTATE and constraint.
+    #[derive(Debug)]
+    struct PgFailure {
+        code: &'static str,
+        constraint: Option<&'static str>,
+    }
+
+    impl fmt::Display for PgFailure {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "SQLSTATE {}", self.code)
+        }
+    }
+
+    impl std::error::Error for PgFailure {}
+
+    impl DatabaseError for PgFailure {
+        fn message(&self) -> &str {
+            "simulated"
+        }
+
+        fn code(&self) -> Option<Cow<'_, str>> {
+            Some(Cow::Borrowed(self.code))
+        }
+
+        fn constraint(&self) -> Option<&str> {
+            self.constraint
+        }
+
+        fn as_error(&self) -> &(dyn std::error::Error + Send + Sync + 'static) {
+            self
+        }
+
+        fn as_error_mut(&mut self) -> &mut (dyn std::error::Error + Send + Sync + 'static) {
+            self
+        }
+
+        fn into_error(self: Box<Self>) -> Box<dyn std::error::Error + Send + Sync + 'static> {
+            self
+        }
+
+        fn kind(&self) -> ErrorKind {
+            ErrorKind::Other
+        }
+    }
+
+    fn pg(code: &'static str, constraint: Option<&'static str>) -> sqlx::Error {
+        sqlx::Error::Database(Box::new(PgFailure { code, constraint }))
+    }
+
+    fn status(err: DbError) -> u16 {
+        ApiError::from(err).status_code().as_u16()
+    }
+
+    #[test]
+    fn maps_sqlx_errors_to_variants_and_statuses() {
+        let err = DbError::from(sqlx::Error::RowNotFound);
+        assert!(matches!(err, DbError::NotFound));
+        assert_eq!(status(err), 404);
+
+        let err = DbError::from(pg(UNIQUE_VIOLATION, Some("users_line_id_key")));
+        assert!(matches!(
+            &err,
+            DbError::UniqueViolation { constraint: Some(c) } if c == "users_line_id_key"
+        ));
+        assert_eq!(status(err), 409);
+
+        let err = DbError::from(pg(FOREIGN_KEY_VIOLATION, None));
+        assert!(matches!(
+            err,
+            DbError::ForeignKeyViolation { constraint: None }
+        ));
+        assert_eq!(status(err), 422);
+
+        for timeout in [sqlx::Error::PoolTimedOut, pg(QUERY_CANCELED, None)] {
+            let err = DbError::from(timeout);
+            assert!(matches!(err, DbError::Timeout));
+            assert_eq!(status(err), 504);
+        }
+
+        for other in [pg("42P01", None), sqlx::Error::PoolClosed] {
+            let err = DbError::from(other);
+            assert!(matches!(err, DbError::Other(_)));
+            assert_eq!(status(err), 500);
+        }
+    }
+}
@@ -0,0 +1,29 @@
+//! LINE webhook deliveries already processed. LINE redelivers an event
+//! when it isn't sure the first delivery arrived; claiming its
+//! `webhookEventId` before acting on it turns the retry into a no-op, the
+//! same way `payment_events` guards payment webhooks.
+
+use sqlx::{PgConnection, PgPool};
+
+use super::error::DbError;
+
+/// Claim a LINE event for processing. Returns `false` if it was already
+/// seen; the caller should then answer 200 without doing anything. Run it
+/// in the transaction that acts on the event, so a failure releases it.
+pub async fn record_line_event(conn: &mut PgConnection, event_id: &str) -> Result<bool, DbError> {
+    let result =
+        sqlx::query("INSERT INTO line_events (event_id) VALUES ($1) ON CONFLICT DO NOTHING")
+            .bind(event_id)
+            .execute(conn)
+            .await?;
+    Ok(result.rows_affected() == 1)
+}
+
+/// Forget events past their `expires_at`, long after LINE stops
+/// redelivering them. Returns how many were removed.
+pub async fn delete_expired_line_events(pool: &PgPool) -> Result<u64, DbError> {
+    let result = sqlx::query("DELETE FROM line_events WHERE expires_at < NOW()")
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected())
+}
@@ -1,6 +1,28 @@
 //! Database helpers and queries.
 pub mod schema;
+pub mod datastore;
+pub mod drafts;
+pub mod error;
+pub mod line_events;
 pub mod queries;
+pub mod payments;
+pub mod pool;
+pub mod readings;
+pub mod referrals;
+pub mod redis_conn;
+pub mod shares;
+pub mod users;
 
 pub use schema::*;
+pub use datastore::*;
+pub use drafts::*;
+pub use error::*;
+pub use line_events::*;
 pub use queries::*;
+pub use payments::*;
+pub use pool::*;
+pub use readings::*;
+pub use referrals::*;
+pub use redis_conn::*;
+pub use shares::*;
+pub use users::*;
@@ -0,0 +1,191 @@
+//! Payment persistence.
+
+use sqlx::{PgConnection, PgPool};
+
+use super::error::DbError;
+use crate::models::Payment;
+use crate::services::{Charge, ChargeStatus};
+
+/// Record a newly created charge as a pending payment. Re-recording the same
+/// charge (an idempotent retry) returns the existing row.
+pub async fn insert_payment(
+    pool: &PgPool,
+    user_id: i64,
+    provider: &str,
+    charge: &Charge,
+    credits: i64,
+) -> Result<Payment, DbError> {
+    let (id, status): (i64, String) = sqlx::query_as(
+        "INSERT INTO payments (user_id, provider, charge_id, amount_baht, credits, status) \
+         VALUES ($1, $2, $3, $4, $5, $6) \
+         ON CONFLICT (charge_id) DO UPDATE SET updated_at = payments.updated_at \
+         RETURNING id, status",
+    )
+    .bind(user_id)
+    .bind(provider)
+    .bind(&charge.id)
+    .bind(charge.amount_baht as i32)
+    .bind(credits)
+    .bind(charge.status.as_str())
+    .fetch_one(pool)
+    .await?;
+    Ok(Payment {
+        id,
+        user_id,
+        amount_baht: charge.amount_baht,
+        charge_id: charge.id.clone(),
+        credits,
+        status,
+    })
+}
+
+/// Mark a webhook event as applied. Returns `false` if it was already seen.
+pub async fn record_payment_event(
+    conn: &mut PgConnection,
+    event_id: &str,
+) -> Result<bool, DbError> {
+    let result =
+        sqlx::query("INSERT INTO payment_events (event_id) VALUES ($1) ON CONFLICT DO NOTHING")
+            .bind(event_id)
+            .execute(conn)
+            .await?;
+    Ok(result.rows_affected() == 1)
+}
+
+/// Move a pending payment to `status`. Returns `(user_id, credits)` if this
+/// call made the transition, `None` if the payment is unknown or settled.
+pub async fn settle_payment(
+    conn: &mut PgConnection,
+    charge_id: &str,
+    status: ChargeStatus,
+) -> Result<Option<(i64, i64)>, DbError> {
+    let row = sqlx::query_as(
+        "UPDATE payments SET status = $1, updated_at = NOW() \
+         WHERE charge_id = $2 AND status = 'pending' \
+         RETURNING user_id, credits",
+    )
+    .bind(status.as_str())
+    .bind(charge_id)
+    .fetch_optional(conn)
+    .await?;
+    Ok(row)
+}
+
+/// A payment by id.
+pub async fn get_payment(pool: &PgPool, payment_id: i64) -> Result<Option<Payment>, DbError> {
+    let row: Option<(i64, i64, i32, String, i64, String)> = sqlx::query_as(
+        "SELECT id, user_id, amount_baht, charge_id, credits, status FROM payments WHERE id = $1",
+    )
+    .bind(payment_id)
+    .fetch_optional(pool)
+    .await?;
+    Ok(row.map(
+        |(id, user_id, amount_baht, charge_id, credits, status)| Payment {
+            id,
+            user_id,
+            amount_baht: amount_baht as u32,
+            charge_id,
+            credits,
+            status,
+        },
+    ))
+}
+
+/// Claim a succeeded payment for refunding by moving it to `refunding`.
+/// Returns `None` if it isn't refundable (unknown, unpaid, or already
+/// refunded/refunding), which is what stops double refunds. Runs on the
+/// caller's connection so the claim can commit with taking back the
+/// payment's credits.
+pub async fn claim_refund(
+    conn: &mut PgConnection,
+    payment_id: i64,
+) -> Result<Option<Payment>, DbError> {
+    let row: Option<(i64, i64, i32, String, i64, String)> = sqlx::query_as(
+        "UPDATE payments SET status = 'refunding', updated_at = NOW() \
+         WHERE id = $1 AND status = 'succeeded' \
+         RETURNING id, user_id, amount_baht, charge_id, credits, status",
+    )
+    .bind(payment_id)
+    .fetch_optional(conn)
+    .await?;
+    Ok(row.map(
+        |(id, user_id, amount_baht, charge_id, credits, status)| Payment {
+            id,
+            user_id,
+            amount_baht: amount_baht as u32,
+            charge_id,
+            credits,
+            status,
+        },
+    ))
+}
+
+/// Complete a claimed refund, recording why it was made.
+pub async fn mark_refunded(pool: &PgPool, payment_id: i64, reason: &str) -> Result<(), DbError> {
+    sqlx::query(
+        "UPDATE payments SET status = 'refunded', refund_reason = $2, refunded_at = NOW(), \
+         updated_at = NOW() WHERE id = $1 AND status = 'refunding'",
+    )
+    .bind(payment_id)
+    .bind(reason)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Give up a claimed refund (the provider call failed) so it can be retried.
+pub async fn release_refund(conn: &mut PgConnection, payment_id: i64) -> Result<(), DbError> {
+    sqlx::query(
+        "UPDATE payments SET status = 'succeeded', updated_at = NOW() \
+         WHERE id = $1 AND status = 'refunding'",
+    )
+    .bind(payment_id)
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// Use one of `user_id`'s succeeded payments to pay for a reading. Returns
+/// `false` if it isn't theirs, isn't paid, or has already paid for one; the
+/// check and the mark are a single `UPDATE`, so concurrent readings can't
+/// both use it.
+pub async fn consume_payment(
+    pool: &PgPool,
+    payment_id: i64,
+    user_id: i64,
+) -> Result<bool, DbError> {
+    let result = sqlx::query(
+        "UPDATE payments SET consumed_at = NOW(), updated_at = NOW() \
+         WHERE id = $1 AND user_id = $2 AND status = 'succeeded' AND consumed_at IS NULL",
+    )
+    .bind(payment_id)
+    .bind(user_id)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() == 1)
+}
+
+/// Record the reading a consumed payment paid for.
+pub async fn set_payment_reading(
+    pool: &PgPool,
+    payment_id: i64,
+    reading_id: i64,
+) -> Result<(), DbError> {
+    sqlx::query("UPDATE payments SET reading_id = $2 WHERE id = $1 AND consumed_at IS NOT NULL")
+        .bind(payment_id)
+        .bind(reading_id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Whether the user has ever completed a payment.
+pub async fn has_settled_payment(pool: &PgPool, user_id: i64) -> Result<bool, DbError> {
+    let exists = sqlx::query_scalar(
+        "SELECT EXISTS (SELECT 1 FROM payments WHERE user_id = $1 AND status = 'succeeded')",
+    )
+    .bind(user_id)
+    .fetch_one(pool)
+    .await?;
+    Ok(exists)
+}
@@ -0,0 +1,26 @@
+//! Postgres (Supabase) connection pool.
+
+use sqlx::postgres::PgPoolOptions;
+use sqlx::{Executor, PgPool};
+
+/// Connect to Postgres and apply pending migrations. Every pooled connection
+/// gets `statement_timeout` so Postgres cancels runaway queries instead of
+/// letting them hold a connection indefinitely.
+pub async fn create_pool(
+    database_url: &str,
+    statement_timeout_ms: u64,
+) -> Result<PgPool, sqlx::Error> {
+    let pool = PgPoolOptions::new()
+        .max_connections(10)
+        .after_connect(move |conn, _meta| {
+            Box::pin(async move {
+                conn.execute(format!("SET statement_timeout = {}", statement_timeout_ms).as_str())
+                    .await?;
+                Ok(())
+            })
+        })
+        .connect(database_url)
+        .await?;
+    sqlx::migrate!("./migrations").run(&pool).await?;
+    Ok(pool)
+}
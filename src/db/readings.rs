@@ -0,0 +1,571 @@
+//! Reading persistence.
+//!
+//! `readings.result` holds the serialized `TarotReading` tagged with a
+//! `schema_version`; `reading_from_stored` upgrades older shapes on read,
+//! so history keeps loading as the model changes.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::{Value, json};
+use sqlx::PgPool;
+
+use super::error::DbError;
+use crate::models::{DrawnCard, ModerationVerdict, StoredCard, TarotReading, Topic, card_by_id};
+use crate::services::Language;
+
+/// Shape version written into each stored `result`. Bump it when a
+/// `TarotReading` change would break older rows, and add the upgrade step
+/// to `reading_from_stored`.
+///
+/// Since version 3 drawn cards are stored as `StoredCard`s (ids without
+/// names).
+pub const READING_SCHEMA_VERSION: u64 = 3;
+
+/// `reading` as stored in `readings.result`, tagged with
+/// `READING_SCHEMA_VERSION`.
+pub fn stored_result(reading: &TarotReading) -> Value {
+    let mut result = serde_json::to_value(reading).unwrap_or_default();
+    for_each_card_list(&mut result, |cards| {
+        let Ok(drawn) = serde_json::from_value::<Vec<DrawnCard>>(cards.take()) else {
+            return;
+        };
+        *cards = stored_cards(&drawn);
+    });
+    if let Some(object) = result.as_object_mut() {
+        object.insert("schema_version".to_string(), READING_SCHEMA_VERSION.into());
+    }
+    result
+}
+
+/// `cards` as written to the `readings.cards` column: `StoredCard`s, like
+/// the cards inside `result`.
+fn stored_cards(cards: &[DrawnCard]) -> Value {
+    let stored: Vec<StoredCard> = cards.iter().map(StoredCard::from).collect();
+    serde_json::to_value(stored).unwrap_or_default()
+}
+
+/// Parse a stored `result`, upgrading older shapes to the current model
+/// first. Rows written before versioning count as version 1. Card names
+/// come from the current deck whatever the version.
+pub fn reading_from_stored(result: Value) -> Result<TarotReading, serde_json::Error> {
+    let mut result = result;
+    let version = result["schema_version"].as_u64().unwrap_or(1);
+    if version < 2 {
+        upgrade_v1(&mut result);
+    }
+    let mut unreadable = None;
+    for_each_card_list(&mut result, |cards| {
+        match serde_json::from_value::<Vec<StoredCard>>(cards.take()) {
+            Ok(stored) => *cards = serde_json::to_value(resolve_cards(stored)).unwrap_or_default(),
+            Err(e) => unreadable = Some(e),
+        }
+    });
+    if let Some(e) = unreadable {
+        return Err(e);
+    }
+    serde_json::from_value(result)
+}
+
+/// The reading's cards and its audit's cards, where present.
+fn for_each_card_list(result: &mut Value, mut f: impl FnMut(&mut Value)) {
+    if let Some(cards) = result.get_mut("cards") {
+        f(cards);
+    }
+    if let Some(cards) = result.pointer_mut("/meta/audit/cards") {
+        f(cards);
+    }
+}
+
+fn resolve_cards(stored: Vec<StoredCard>) -> Vec<DrawnCard> {
+    stored
+        .into_iter()
+        .map(|card| {
+            if card_by_id(card.card_id).is_none() {
+                log::warn!("stored card id {} is not in the deck", card.card_id);
+            }
+            card.resolve()
+        })
+        .collect()
+}
+
+/// Version 1 rows can predate the generation meta and the fields that are
+/// required now; fill them with neutral defaults.
+fn upgrade_v1(result: &mut Value) {
+    let Some(reading) = result.as_object_mut() else {
+        return;
+    };
+    for (key, default) in [
+        ("cards", json!([])),
+        ("interpretations", json!([])),
+        ("summary", json!("")),
+    ] {
+        reading.entry(key).or_insert(default);
+    }
+    if let Some(meta) = reading
+        .entry("meta")
+        .or_insert_with(|| json!({}))
+        .as_object_mut()
+    {
+        for (key, default) in [
+            ("seed", json!(0)),
+            ("attempts", json!(0)),
+            ("reshuffled", json!(false)),
+        ] {
+            meta.entry(key).or_insert(default);
+        }
+    }
+    reading.insert("schema_version".to_string(), json!(2));
+}
+
+/// How much of a reading's raw completion JSON goes into
+/// `readings.raw_llm` (`STORE_RAW_LLM`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RawLlmStorage {
+    /// The whole response, content included.
+    Full,
+    /// `usage` and `finish_reason` only: enough for cost and debugging
+    /// without a second copy of the reading text.
+    #[default]
+    Trimmed,
+    None,
+}
+
+impl RawLlmStorage {
+    pub fn from_name(name: &str) -> Option<RawLlmStorage> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "full" => Some(RawLlmStorage::Full),
+            "trimmed" => Some(RawLlmStorage::Trimmed),
+            "none" => Some(RawLlmStorage::None),
+            _ => None,
+        }
+    }
+
+    /// What to store for the raw response `raw`; `None` stores NULL.
+    pub fn stored(&self, raw: &Value) -> Option<Value> {
+        match self {
+            RawLlmStorage::Full => Some(raw.clone()),
+            RawLlmStorage::Trimmed => Some(json!({
+                "usage": raw["usage"],
+                "finish_reason": raw["choices"][0]["finish_reason"],
+            })),
+            RawLlmStorage::None => None,
+        }
+    }
+}
+
+/// Store a generated reading and return its id. `raw_llm` decides how much
+/// of the reading's raw completion is kept.
+pub async fn insert_reading(
+    pool: &PgPool,
+    user_id: i64,
+    reading: &TarotReading,
+    language: Language,
+    fingerprint: &str,
+    raw_llm: RawLlmStorage,
+) -> Result<i64, DbError> {
+    let cards = stored_cards(&reading.cards);
+    let result = stored_result(reading);
+    let raw = reading
+        .meta
+        .raw_llm
+        .as_ref()
+        .and_then(|raw| raw_llm.stored(raw));
+    let id = sqlx::query_scalar(
+        "INSERT INTO readings \
+         (user_id, question, spread, cards, result, language, fingerprint, raw_llm) \
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8) RETURNING id",
+    )
+    .bind(user_id)
+    .bind(&reading.question)
+    .bind(reading.spread.as_str())
+    .bind(cards)
+    .bind(result)
+    .bind(language.code())
+    .bind(fingerprint)
+    .bind(raw)
+    .fetch_one(pool)
+    .await?;
+    Ok(id)
+}
+
+/// Store a regenerated version of reading `source_id` and return its id and
+/// the original it is linked to. Versions of versions link to the original.
+/// `raw_llm` is applied as in `insert_reading`.
+pub async fn insert_reading_version(
+    pool: &PgPool,
+    user_id: i64,
+    source_id: i64,
+    reading: &TarotReading,
+    language: Language,
+    fingerprint: &str,
+    raw_llm: RawLlmStorage,
+) -> Result<(i64, i64), DbError> {
+    let cards = stored_cards(&reading.cards);
+    let result = stored_result(reading);
+    let raw = reading
+        .meta
+        .raw_llm
+        .as_ref()
+        .and_then(|raw| raw_llm.stored(raw));
+    let row = sqlx::query_as(
+        "INSERT INTO readings \
+         (user_id, question, spread, cards, result, language, fingerprint, raw_llm, parent_id) \
+         SELECT $1, $2, $3, $4, $5, $6, $7, $8, COALESCE(parent_id, id) \
+         FROM readings WHERE id = $9 \
+         RETURNING id, parent_id",
+    )
+    .bind(user_id)
+    .bind(&reading.question)
+    .bind(reading.spread.as_str())
+    .bind(cards)
+    .bind(result)
+    .bind(language.code())
+    .bind(fingerprint)
+    .bind(raw)
+    .bind(source_id)
+    .fetch_one(pool)
+    .await?;
+    Ok(row)
+}
+
+/// Store a follow-up question asked after reading `source_id` and return
+/// its id and the reading it is linked to: the first reading of the
+/// conversation, so follow-ups of follow-ups count against the same one.
+/// `raw_llm` is applied as in `insert_reading`.
+pub async fn insert_followup(
+    pool: &PgPool,
+    user_id: i64,
+    source_id: i64,
+    reading: &TarotReading,
+    language: Language,
+    fingerprint: &str,
+    raw_llm: RawLlmStorage,
+) -> Result<(i64, i64), DbError> {
+    let cards = stored_cards(&reading.cards);
+    let result = stored_result(reading);
+    let raw = reading
+        .meta
+        .raw_llm
+        .as_ref()
+        .and_then(|raw| raw_llm.stored(raw));
+    let row = sqlx::query_as(
+        "INSERT INTO readings \
+         (user_id, question, spread, cards, result, language, fingerprint, raw_llm, followup_of) \
+         SELECT $1, $2, $3, $4, $5, $6, $7, $8, COALESCE(followup_of, id) \
+         FROM readings WHERE id = $9 \
+         RETURNING id, followup_of",
+    )
+    .bind(user_id)
+    .bind(&reading.question)
+    .bind(reading.spread.as_str())
+    .bind(cards)
+    .bind(result)
+    .bind(language.code())
+    .bind(fingerprint)
+    .bind(raw)
+    .bind(source_id)
+    .fetch_one(pool)
+    .await?;
+    Ok(row)
+}
+
+/// Most rows one `INSERT` of `insert_many` carries; bigger imports are
+/// split into several statements in the same transaction.
+pub const MAX_INSERT_BATCH: usize = 500;
+
+/// A historical reading for `insert_many`, keeping its original time.
+#[derive(Debug, Clone)]
+pub struct NewReading {
+    pub user_id: i64,
+    pub reading: TarotReading,
+    pub language: Language,
+    pub fingerprint: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Bulk-insert `rows` (legacy imports) and return their ids in input
+/// order. Each chunk of `MAX_INSERT_BATCH` rows is one `INSERT ... SELECT
+/// FROM UNNEST`, and all of them share one transaction, so any failure
+/// stores none. Imported rows carry no raw completion.
+///
+/// Postgres doesn't promise `RETURNING` rows in input order, so the ids
+/// are drawn from the sequence first (ascending, one per row) and inserted
+/// explicitly; the result is that list, never a re-read of the rows.
+pub async fn insert_many(pool: &PgPool, rows: &[NewReading]) -> Result<Vec<i64>, DbError> {
+    if rows.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut tx = pool.begin().await?;
+    let mut ids: Vec<i64> = sqlx::query_scalar(
+        "SELECT nextval(pg_get_serial_sequence('readings', 'id')) FROM generate_series(1, $1)",
+    )
+    .bind(rows.len() as i64)
+    .fetch_all(&mut *tx)
+    .await?;
+    ids.sort_unstable();
+    for (chunk, chunk_ids) in rows
+        .chunks(MAX_INSERT_BATCH)
+        .zip(ids.chunks(MAX_INSERT_BATCH))
+    {
+        let user_ids: Vec<i64> = chunk.iter().map(|r| r.user_id).collect();
+        let questions: Vec<&str> = chunk.iter().map(|r| r.reading.question.as_str()).collect();
+        let spreads: Vec<&str> = chunk.iter().map(|r| r.reading.spread.as_str()).collect();
+        let cards: Vec<Value> = chunk
+            .iter()
+            .map(|r| stored_cards(&r.reading.cards))
+            .collect();
+        let results: Vec<Value> = chunk.iter().map(|r| stored_result(&r.reading)).collect();
+        let languages: Vec<&str> = chunk.iter().map(|r| r.language.code()).collect();
+        let fingerprints: Vec<&str> = chunk.iter().map(|r| r.fingerprint.as_str()).collect();
+        let created: Vec<DateTime<Utc>> = chunk.iter().map(|r| r.created_at).collect();
+        sqlx::query(
+            "INSERT INTO readings \
+             (id, user_id, question, spread, cards, result, language, fingerprint, created_at) \
+             SELECT * FROM UNNEST($1::BIGINT[], $2::BIGINT[], $3::TEXT[], $4::TEXT[], \
+                                  $5::JSONB[], $6::JSONB[], $7::TEXT[], $8::TEXT[], \
+                                  $9::TIMESTAMPTZ[])",
+        )
+        .bind(chunk_ids)
+        .bind(user_ids)
+        .bind(questions)
+        .bind(spreads)
+        .bind(cards)
+        .bind(results)
+        .bind(languages)
+        .bind(fingerprints)
+        .bind(created)
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await?;
+    Ok(ids)
+}
+
+/// Versions already regenerated from the original of `reading_id`.
+pub async fn count_reading_versions(pool: &PgPool, reading_id: i64) -> Result<i64, DbError> {
+    let count = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM readings \
+         WHERE parent_id = (SELECT COALESCE(parent_id, id) FROM readings WHERE id = $1)",
+    )
+    .bind(reading_id)
+    .fetch_one(pool)
+    .await?;
+    Ok(count)
+}
+
+/// Follow-ups already asked in the conversation `reading_id` belongs to.
+pub async fn count_followups(pool: &PgPool, reading_id: i64) -> Result<i64, DbError> {
+    let count = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM readings \
+         WHERE followup_of = (SELECT COALESCE(followup_of, id) FROM readings WHERE id = $1)",
+    )
+    .bind(reading_id)
+    .fetch_one(pool)
+    .await?;
+    Ok(count)
+}
+
+/// A row in the reading history list.
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct ReadingSummary {
+    pub id: i64,
+    pub question: String,
+    pub spread: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A user's readings, newest first, keyset-paginated on `id`.
+pub async fn list_readings(
+    pool: &PgPool,
+    user_id: i64,
+    before_id: Option<i64>,
+    limit: i64,
+) -> Result<Vec<ReadingSummary>, DbError> {
+    let rows = sqlx::query_as::<_, ReadingSummary>(
+        "SELECT id, question, spread, created_at FROM readings \
+         WHERE user_id = $1 AND ($2::BIGINT IS NULL OR id < $2) \
+         ORDER BY id DESC LIMIT $3",
+    )
+    .bind(user_id)
+    .bind(before_id)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows)
+}
+
+/// Leading characters of a question fingerprint `find_similar` compares.
+pub const SIMILAR_FINGERPRINT_PREFIX: usize = 16;
+
+/// The user's other readings related to `reading_id`: the same question
+/// asked again (fingerprint sharing its first `SIMILAR_FINGERPRINT_PREFIX`
+/// characters) first, then readings on the same `topic`, newest first
+/// within each. Versions of `reading_id` are left out.
+pub async fn find_similar(
+    pool: &PgPool,
+    user_id: i64,
+    reading_id: i64,
+    fingerprint: &str,
+    topic: Option<Topic>,
+    limit: i64,
+) -> Result<Vec<ReadingSummary>, DbError> {
+    let rows = sqlx::query_as::<_, ReadingSummary>(
+        "SELECT id, question, spread, created_at FROM readings \
+         WHERE user_id = $1 \
+         AND COALESCE(parent_id, id) <> \
+             COALESCE((SELECT COALESCE(parent_id, id) FROM readings WHERE id = $2), $2) \
+         AND (left(fingerprint, $6) = left($3, $6) OR result->'analysis'->>'topic' = $4) \
+         ORDER BY (left(fingerprint, $6) = left($3, $6)) DESC, id DESC LIMIT $5",
+    )
+    .bind(user_id)
+    .bind(reading_id)
+    .bind(fingerprint)
+    .bind(topic.map(|t| t.as_str()))
+    .bind(limit)
+    .bind(SIMILAR_FINGERPRINT_PREFIX as i32)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows)
+}
+
+/// Record `verdict` on its reading, replacing any earlier one. Returns
+/// whether the reading exists.
+pub async fn set_reading_moderation(
+    pool: &PgPool,
+    verdict: &ModerationVerdict,
+) -> Result<bool, DbError> {
+    let result = sqlx::query(
+        "UPDATE readings SET moderation_flagged = $1, moderation_reason = $2, \
+         moderated_by = $3, moderated_at = $4 WHERE id = $5",
+    )
+    .bind(verdict.flagged)
+    .bind(&verdict.reason)
+    .bind(verdict.moderated_by)
+    .bind(verdict.moderated_at)
+    .bind(verdict.reading_id)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Owner and stored result JSON of a reading.
+pub async fn get_reading_result(
+    pool: &PgPool,
+    reading_id: i64,
+) -> Result<Option<(i64, serde_json::Value)>, DbError> {
+    let row = sqlx::query_as("SELECT user_id, result FROM readings WHERE id = $1")
+        .bind(reading_id)
+        .fetch_optional(pool)
+        .await?;
+    Ok(row)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn upgrades_a_v1_blob_to_the_current_reading() {
+        // Written before versioning: no `schema_version`, no meta, card
+        // names inline.
+        let v1 = json!({
+            "question": "Will I get the job?",
+            "spread": "single",
+            "cards": [{"card_id": 0, "name": "Old name", "position": "present", "reversed": true}],
+            "interpretations": [{"card": "The Fool", "position": "present", "interpretation": "Go."}]
+        });
+        let reading = reading_from_stored(v1).unwrap();
+        assert_eq!(reading.question, "Will I get the job?");
+        assert_eq!(reading.spread, crate::models::Spread::Single);
+        assert_eq!(reading.summary, "");
+        assert_eq!(reading.cards.len(), 1);
+        assert_eq!(reading.cards[0].name, card_by_id(0).unwrap().name);
+        assert!(reading.cards[0].reversed);
+        assert_eq!(reading.interpretations[0].card_id, None);
+        assert_eq!(reading.meta.seed, 0);
+        assert_eq!(reading.meta.attempts, 0);
+        assert!(!reading.meta.reshuffled);
+    }
+
+    #[test]
+    fn a_v1_card_missing_from_the_deck_keeps_its_stored_name() {
+        let v1 = json!({
+            "question": "?",
+            "spread": "single",
+            "cards": [{"card_id": 200, "name": "Retired card", "position": "present", "reversed": false}],
+        });
+        let reading = reading_from_stored(v1).unwrap();
+        assert_eq!(reading.cards[0].name, "Retired card");
+    }
+
+    #[test]
+    fn the_current_shape_round_trips() {
+        let v1 = json!({
+            "question": "Love?",
+            "spread": "single",
+            "cards": [{"card_id": 1, "position": "present", "reversed": false}],
+            "summary": "Yes.",
+        });
+        let reading = reading_from_stored(v1).unwrap();
+        let stored = stored_result(&reading);
+        assert_eq!(stored["schema_version"], READING_SCHEMA_VERSION);
+        assert_eq!(stored["cards"][0].get("name"), None);
+        let read_back = reading_from_stored(stored).unwrap();
+        assert_eq!(read_back.cards, reading.cards);
+        assert_eq!(read_back.summary, "Yes.");
+    }
+
+    #[test]
+    fn an_unreadable_card_list_is_an_error() {
+        let stored = json!({
+            "schema_version": READING_SCHEMA_VERSION,
+            "question": "?",
+            "spread": "single",
+            "cards": [{"position": "present"}],
+            "interpretations": [],
+            "summary": "",
+            "meta": {"seed": 1, "attempts": 1, "reshuffled": false}
+        });
+        assert!(reading_from_stored(stored).is_err());
+    }
+
+    fn raw_completion() -> Value {
+        json!({
+            "id": "chatcmpl-1",
+            "model": "gpt-4o-mini",
+            "choices": [{
+                "index": 0,
+                "message": {"role": "assistant", "content": "A long reading..."},
+                "finish_reason": "stop"
+            }],
+            "usage": {"prompt_tokens": 120, "completion_tokens": 80, "total_tokens": 200}
+        })
+    }
+
+    #[test]
+    fn raw_llm_storage_names() {
+        assert_eq!(RawLlmStorage::from_name("full"), Some(RawLlmStorage::Full));
+        assert_eq!(
+            RawLlmStorage::from_name(" Trimmed "),
+            Some(RawLlmStorage::Trimmed)
+        );
+        assert_eq!(RawLlmStorage::from_name("NONE"), Some(RawLlmStorage::None));
+        assert_eq!(RawLlmStorage::from_name("some"), None);
+        assert_eq!(RawLlmStorage::default(), RawLlmStorage::Trimmed);
+    }
+
+    #[test]
+    fn each_mode_stores_its_own_shape() {
+        let raw = raw_completion();
+        assert_eq!(RawLlmStorage::Full.stored(&raw), Some(raw.clone()));
+        // Trimmed keeps cost and stop reason but not the reading text.
+        assert_eq!(
+            RawLlmStorage::Trimmed.stored(&raw),
+            Some(json!({
+                "usage": {"prompt_tokens": 120, "completion_tokens": 80, "total_tokens": 200},
+                "finish_reason": "stop"
+            }))
+        );
+        assert_eq!(RawLlmStorage::None.stored(&raw), None);
+    }
+}
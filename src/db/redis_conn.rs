@@ -0,0 +1,23 @@
+//! Redis (Upstash) connection helper.
+
+use redis::aio::ConnectionManager;
+
+/// Connect to Redis when a URL is configured. Failures are logged and
+/// treated as "no Redis" so callers can fall back to in-memory defaults.
+pub async fn connect_redis(url: Option<&str>) -> Option<ConnectionManager> {
+    let url = url?;
+    let client = match redis::Client::open(url) {
+        Ok(client) => client,
+        Err(e) => {
+            log::warn!("invalid redis url: {}", e);
+            return None;
+        }
+    };
+    match ConnectionManager::new(client).await {
+        Ok(conn) => Some(conn),
+        Err(e) => {
+            log::warn!("redis unavailable, continuing without it: {}", e);
+            None
+        }
+    }
+}
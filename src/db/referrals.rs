@@ -0,0 +1,77 @@
+//! Referral persistence.
+
+use rand::Rng;
+use sqlx::PgPool;
+
+use super::error::DbError;
+use crate::models::ReferralStats;
+
+/// Referral code characters, without the easily confused 0/O and 1/I.
+const CODE_ALPHABET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+const CODE_LEN: usize = 8;
+/// Fresh codes tried before giving up on a run of collisions.
+const CODE_ATTEMPTS: usize = 5;
+
+/// A random referral code; uniqueness is up to the caller.
+pub fn new_referral_code() -> String {
+    let mut rng = rand::thread_rng();
+    (0..CODE_LEN)
+        .map(|_| CODE_ALPHABET[rng.gen_range(0..CODE_ALPHABET.len())] as char)
+        .collect()
+}
+
+/// The user's referral code, generated on first use. `None` if there is no
+/// such user.
+pub async fn ensure_referral_code(pool: &PgPool, user_id: i64) -> Result<Option<String>, DbError> {
+    let existing: Option<Option<String>> =
+        sqlx::query_scalar("SELECT referral_code FROM users WHERE id = $1")
+            .bind(user_id)
+            .fetch_optional(pool)
+            .await?;
+    match existing {
+        None => return Ok(None),
+        Some(Some(code)) => return Ok(Some(code)),
+        Some(None) => {}
+    }
+    let mut last = None;
+    for _ in 0..CODE_ATTEMPTS {
+        // COALESCE keeps a code set by a concurrent request.
+        let result: Result<Option<String>, DbError> = sqlx::query_scalar(
+            "UPDATE users SET referral_code = COALESCE(referral_code, $2) \
+             WHERE id = $1 RETURNING referral_code",
+        )
+        .bind(user_id)
+        .bind(new_referral_code())
+        .fetch_optional(pool)
+        .await
+        .map_err(DbError::from);
+        match result {
+            Err(e @ DbError::UniqueViolation { .. }) => last = Some(e),
+            other => return other,
+        }
+    }
+    Err(last.unwrap_or(DbError::NotFound))
+}
+
+/// The user's referral code and how their referrals stand; `None` if there
+/// is no such user.
+pub async fn referral_stats(pool: &PgPool, user_id: i64) -> Result<Option<ReferralStats>, DbError> {
+    let Some(referral_code) = ensure_referral_code(pool, user_id).await? else {
+        return Ok(None);
+    };
+    let (successful_referrals, pending_referrals, credits_earned) = sqlx::query_as(
+        "SELECT COUNT(*) FILTER (WHERE status = 'rewarded'), \
+         COUNT(*) FILTER (WHERE status = 'pending'), \
+         COALESCE(SUM(credits_awarded) FILTER (WHERE status = 'rewarded'), 0)::BIGINT \
+         FROM referrals WHERE referrer_id = $1",
+    )
+    .bind(user_id)
+    .fetch_one(pool)
+    .await?;
+    Ok(Some(ReferralStats {
+        referral_code,
+        successful_referrals,
+        pending_referrals,
+        credits_earned,
+    }))
+}
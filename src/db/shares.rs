@@ -0,0 +1,55 @@
+//! Reading share links.
+
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+
+use super::error::DbError;
+
+pub async fn insert_share(
+    pool: &PgPool,
+    token: &str,
+    reading_id: i64,
+    expires_at: DateTime<Utc>,
+) -> Result<(), DbError> {
+    sqlx::query("INSERT INTO reading_shares (token, reading_id, expires_at) VALUES ($1, $2, $3)")
+        .bind(token)
+        .bind(reading_id)
+        .bind(expires_at)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Stored result JSON behind an unexpired share token.
+pub async fn get_shared_result(
+    pool: &PgPool,
+    token: &str,
+) -> Result<Option<(serde_json::Value, DateTime<Utc>)>, DbError> {
+    let row = sqlx::query_as(
+        "SELECT r.result, s.expires_at FROM reading_shares s \
+         JOIN readings r ON r.id = s.reading_id \
+         WHERE s.token = $1 AND s.expires_at > NOW()",
+    )
+    .bind(token)
+    .fetch_optional(pool)
+    .await?;
+    Ok(row)
+}
+
+/// Revoke every share link of a reading. Returns how many were removed.
+pub async fn delete_shares(pool: &PgPool, reading_id: i64) -> Result<u64, DbError> {
+    let result = sqlx::query("DELETE FROM reading_shares WHERE reading_id = $1")
+        .bind(reading_id)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected())
+}
+
+/// Delete share links past their expiry. Safe to run concurrently from
+/// several instances: each expired row is deleted at most once.
+pub async fn delete_expired_shares(pool: &PgPool) -> Result<u64, DbError> {
+    let result = sqlx::query("DELETE FROM reading_shares WHERE expires_at < NOW()")
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected())
+}
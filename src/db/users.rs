@@ -0,0 +1,146 @@
+//! User persistence.
+
+use chrono::{DateTime, Utc};
+use sqlx::{PgConnection, PgPool};
+
+use super::error::DbError;
+use crate::models::{Topic, User, UserStats};
+use crate::services::{CreditError, credit_service};
+
+/// Ledger reason for the credits granted to a new user.
+pub const SIGNUP_BONUS_REASON: &str = "signup_bonus";
+
+/// Create the user for a LINE id, or update the name of the existing one.
+/// A created user is granted `signup_bonus` credits in the same
+/// transaction; logins that find the row already there grant nothing.
+pub async fn upsert_user(
+    pool: &PgPool,
+    line_id: &str,
+    name: Option<&str>,
+    signup_bonus: i64,
+) -> Result<User, DbError> {
+    let mut tx = pool.begin().await?;
+    // `xmax = 0` only for a freshly inserted row, not one the conflict
+    // branch updated; concurrent first logins serialize on the unique
+    // index, so exactly one of them sees the insert.
+    let (id, line_id, name, created): (i64, Option<String>, Option<String>, bool) = sqlx::query_as(
+        "INSERT INTO users (line_id, name) VALUES ($1, $2) \
+         ON CONFLICT (line_id) DO UPDATE SET name = COALESCE(EXCLUDED.name, users.name) \
+         RETURNING id, line_id, name, (xmax = 0) AS created",
+    )
+    .bind(line_id)
+    .bind(name)
+    .fetch_one(&mut *tx)
+    .await?;
+    if created && signup_bonus > 0 {
+        credit_service::grant_in(&mut tx, id, signup_bonus, SIGNUP_BONUS_REASON)
+            .await
+            .map_err(|e| match e {
+                CreditError::Db(e) => DbError::from(e),
+                CreditError::UserNotFound | CreditError::Insufficient | CreditError::OutOfRange => {
+                    DbError::NotFound
+                }
+            })?;
+    }
+    tx.commit().await?;
+    Ok(User { id, line_id, name })
+}
+
+/// The id of the user who signed in with `line_id`, if any.
+pub async fn user_id_by_line_id(
+    conn: &mut PgConnection,
+    line_id: &str,
+) -> Result<Option<i64>, DbError> {
+    let id = sqlx::query_scalar("SELECT id FROM users WHERE line_id = $1")
+        .bind(line_id)
+        .fetch_optional(conn)
+        .await?;
+    Ok(id)
+}
+
+pub async fn get_user(pool: &PgPool, user_id: i64) -> Result<Option<User>, DbError> {
+    let row: Option<(i64, Option<String>, Option<String>)> =
+        sqlx::query_as("SELECT id, line_id, name FROM users WHERE id = $1")
+            .bind(user_id)
+            .fetch_optional(pool)
+            .await?;
+    Ok(row.map(|(id, line_id, name)| User { id, line_id, name }))
+}
+
+#[derive(sqlx::FromRow)]
+struct StatsRow {
+    joined_at: DateTime<Utc>,
+    total_readings: i64,
+    readings_this_month: i64,
+    credits_spent: i64,
+    top_topic: Option<String>,
+}
+
+/// Reading and spend aggregates for a user; `None` if there is no such user.
+/// Credits spent are the net of ledger entries for readings (`reading*`
+/// reasons), so refunds are already taken off.
+pub async fn user_stats(
+    pool: &PgPool,
+    user_id: i64,
+    month_start: DateTime<Utc>,
+) -> Result<Option<UserStats>, DbError> {
+    let row = sqlx::query_as::<_, StatsRow>(
+        "SELECT u.created_at AS joined_at, \
+         (SELECT COUNT(*) FROM readings WHERE user_id = u.id AND parent_id IS NULL) AS total_readings, \
+         (SELECT COUNT(*) FROM readings \
+          WHERE user_id = u.id AND parent_id IS NULL AND created_at >= $2) AS readings_this_month, \
+         (SELECT COALESCE(-SUM(delta), 0)::BIGINT FROM credit_ledger \
+          WHERE user_id = u.id AND reason LIKE 'reading%') AS credits_spent, \
+         (SELECT result->'analysis'->>'topic' FROM readings \
+          WHERE user_id = u.id AND parent_id IS NULL \
+          AND result->'analysis'->>'topic' IS NOT NULL \
+          GROUP BY 1 ORDER BY COUNT(*) DESC, 1 LIMIT 1) AS top_topic \
+         FROM users u WHERE u.id = $1",
+    )
+    .bind(user_id)
+    .bind(month_start)
+    .fetch_optional(pool)
+    .await?;
+    Ok(row.map(|row| UserStats {
+        total_readings: row.total_readings,
+        readings_this_month: row.readings_this_month,
+        credits_spent: row.credits_spent,
+        top_topic: row.top_topic.as_deref().map(Topic::from_name),
+        joined_at: row.joined_at,
+    }))
+}
+
+/// Mark a user suspended, keeping the original time and reason if they
+/// already are. Returns whether the user exists.
+pub async fn suspend_user(pool: &PgPool, user_id: i64, reason: &str) -> Result<bool, DbError> {
+    let result = sqlx::query(
+        "UPDATE users SET suspended_at = COALESCE(suspended_at, NOW()), \
+         suspension_reason = COALESCE(suspension_reason, $2) WHERE id = $1",
+    )
+    .bind(user_id)
+    .bind(reason)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Lift a suspension. Returns whether the user was suspended.
+pub async fn unsuspend_user(pool: &PgPool, user_id: i64) -> Result<bool, DbError> {
+    let result = sqlx::query(
+        "UPDATE users SET suspended_at = NULL, suspension_reason = NULL \
+         WHERE id = $1 AND suspended_at IS NOT NULL",
+    )
+    .bind(user_id)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn is_suspended(pool: &PgPool, user_id: i64) -> Result<bool, DbError> {
+    let suspended: Option<bool> =
+        sqlx::query_scalar("SELECT suspended_at IS NOT NULL FROM users WHERE id = $1")
+            .bind(user_id)
+            .fetch_optional(pool)
+            .await?;
+    Ok(suspended.unwrap_or(false))
+}
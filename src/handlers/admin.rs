@@ -0,0 +1,203 @@
+//! Admin-only endpoints.
+
+use actix_web::{HttpResponse, web};
+use chrono::Utc;
+use serde_json::json;
+
+use super::readings::{analyze_confident, filter_question, queue_redis};
+use crate::db::{NewReading, reading_from_stored};
+use crate::middleware::{AdminUser, ApiError};
+use crate::models::{AdjustCreditsRequest, ImportReadingsRequest, ModerationVerdict};
+use crate::services::{
+    AnalysisCache, CreditError, FilterMetrics, GuardrailPolicy, LlmError, PipelineSettings,
+    ReadingError, credit_service, fingerprint, lift_suspension, queue_stats, replay_job,
+    resolve_language,
+};
+use crate::state::AppState;
+
+/// `GET /admin/queue`: backlog depth, how long the oldest job has waited,
+/// and stored jobs by status.
+pub async fn get_queue_stats(
+    _admin: AdminUser,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let stats = queue_stats(queue_redis(&state)?, Utc::now().timestamp()).await?;
+    Ok(HttpResponse::Ok().json(stats))
+}
+
+/// `POST /admin/readings/jobs/{id}/replay`: re-run a failed job.
+pub async fn replay_reading_job(
+    admin: AdminUser,
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    let job = replay_job(queue_redis(&state)?, &path, admin.user_id).await?;
+    Ok(HttpResponse::Accepted().json(job))
+}
+
+/// `POST /admin/readings/{id}/remoderate`: run the current QuestionFilter
+/// (validation, language, QuestionAnalysis confidence) over a stored
+/// reading's question and record the verdict. A reading that now fails is
+/// flagged, not deleted.
+pub async fn remoderate_reading(
+    admin: AdminUser,
+    state: web::Data<AppState>,
+    path: web::Path<i64>,
+) -> Result<HttpResponse, ApiError> {
+    let config = state.config();
+    let store = state.store();
+    let reading_id = path.into_inner();
+    let (_, result) = store
+        .get_reading_result(reading_id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound("Reading not found".to_string()))?;
+    let reading = reading_from_stored(result).map_err(|e| {
+        log::error!("stored reading {} is unreadable: {}", reading_id, e);
+        ApiError::Internal("Stored reading is unreadable".to_string())
+    })?;
+    // Sweeps aren't user traffic, so they stay out of the live filter counters.
+    let metrics = FilterMetrics::default();
+    let rejection = match filter_question(config, &metrics, &reading.question) {
+        Err(e) => Some(e.to_string()),
+        Ok(question) => {
+            let mut budget = PipelineSettings::from_config(config).budget();
+            // Uncached: the point is the current prompt's verdict.
+            let mut cache = AnalysisCache::new(None, 0)
+                .with_guardrails(GuardrailPolicy::from_config(config).analysis);
+            match analyze_confident(
+                state.llm(),
+                config,
+                &metrics,
+                &mut cache,
+                question,
+                &mut budget,
+            )
+            .await
+            {
+                Ok(_) => None,
+                Err(ReadingError::Filtered(reason)) => Some(reason),
+                Err(ReadingError::GenerationFailed(LlmError::ContentFiltered)) => {
+                    Some("Refused by the provider's safety filter".to_string())
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    };
+    let verdict = ModerationVerdict {
+        reading_id,
+        flagged: rejection.is_some(),
+        reason: rejection,
+        moderated_by: admin.user_id,
+        moderated_at: Utc::now(),
+    };
+    if !store.set_reading_moderation(&verdict).await? {
+        return Err(ApiError::NotFound("Reading not found".to_string()));
+    }
+    if verdict.flagged {
+        log::warn!(
+            "admin {} flagged reading {} on re-moderation: {}",
+            admin.user_id,
+            reading_id,
+            verdict.reason.as_deref().unwrap_or_default()
+        );
+    }
+    Ok(HttpResponse::Ok().json(verdict))
+}
+
+/// `POST /admin/readings/import`: store legacy readings with their original
+/// times, all or none, and return their ids in request order. Language and
+/// fingerprint are derived from each question as for new readings.
+pub async fn import_readings(
+    admin: AdminUser,
+    state: web::Data<AppState>,
+    body: web::Json<ImportReadingsRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let default_language = state.config().default_language;
+    let rows: Vec<NewReading> = body
+        .into_inner()
+        .readings
+        .into_iter()
+        .map(|imported| {
+            let language = resolve_language(&imported.reading.question, default_language).language;
+            NewReading {
+                user_id: imported.user_id,
+                fingerprint: fingerprint(&imported.reading.question, language),
+                language,
+                created_at: imported.created_at,
+                reading: imported.reading,
+            }
+        })
+        .collect();
+    if rows.is_empty() {
+        return Err(ApiError::BadRequest(
+            "readings must not be empty".to_string(),
+        ));
+    }
+    let ids = state.store().insert_readings(&rows).await?;
+    log::info!("admin {} imported {} readings", admin.user_id, ids.len());
+    Ok(HttpResponse::Ok().json(json!({ "ids": ids })))
+}
+
+/// `POST /admin/users/{id}/credits`: grant or remove credits (refunds,
+/// goodwill). The ledger entry names the acting admin; a change that would
+/// leave the balance negative, or doesn't fit in one, is refused with 422.
+pub async fn adjust_user_credits(
+    admin: AdminUser,
+    state: web::Data<AppState>,
+    path: web::Path<i64>,
+    body: web::Json<AdjustCreditsRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let pool = state
+        .pool()
+        .ok_or_else(|| ApiError::ServiceUnavailable("Credits are unavailable".to_string()))?;
+    let user_id = path.into_inner();
+    let reason = body.reason.trim();
+    if reason.is_empty() {
+        return Err(ApiError::BadRequest("reason must not be empty".to_string()));
+    }
+    if body.delta == 0 {
+        return Err(ApiError::BadRequest("delta must not be zero".to_string()));
+    }
+    let ledger_reason = format!("admin:{}: {}", admin.user_id, reason);
+    let balance = match credit_service::adjust(pool, user_id, body.delta, &ledger_reason).await {
+        Ok(balance) => balance,
+        Err(CreditError::Insufficient) => {
+            return Err(ApiError::UnprocessableEntity(
+                "Adjustment would make the balance negative".to_string(),
+            ));
+        }
+        Err(e) => return Err(e.into()),
+    };
+    log::info!(
+        "admin {} adjusted credits of user {} by {} ({}); balance now {}",
+        admin.user_id,
+        user_id,
+        body.delta,
+        reason,
+        balance
+    );
+    Ok(HttpResponse::Ok().json(json!({
+        "user_id": user_id,
+        "balance": balance,
+    })))
+}
+
+/// `POST /admin/users/{id}/unsuspend`: lift an auto-suspension after review
+/// and reset the user's rejection count. Idempotent; `was_suspended` tells
+/// whether there was anything to lift.
+pub async fn unsuspend_user(
+    admin: AdminUser,
+    state: web::Data<AppState>,
+    path: web::Path<i64>,
+) -> Result<HttpResponse, ApiError> {
+    let user_id = path.into_inner();
+    let was_suspended =
+        lift_suspension(state.abuse_tracker(), state.store(), state.redis(), user_id).await?;
+    if was_suspended {
+        log::info!("admin {} unsuspended user {}", admin.user_id, user_id);
+    }
+    Ok(HttpResponse::Ok().json(json!({
+        "user_id": user_id,
+        "was_suspended": was_suspended,
+    })))
+}
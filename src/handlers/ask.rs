@@ -0,0 +1,103 @@
+//! `/ask`: a single free-form question answered by MiMi, without a card draw.
+
+use actix_web::http::header::{ACCEPT, ContentType};
+use actix_web::{HttpRequest, HttpResponse, web};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::middleware::{ApiError, AuthUser, JsonFormat};
+use crate::services::{AskParams, GuardrailPolicy, LlmResponse};
+use crate::state::AppState;
+
+const ASK_PERSONA: &str = "You are MiMi, a warm and insightful tarot reader.";
+
+const ASK_INSTRUCTIONS: &str =
+    "Answer the user's question briefly and kindly, in the language they wrote in.";
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AskRequest {
+    pub question: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AskQuery {
+    pub q: String,
+}
+
+/// Trim the question and enforce the same limits for every ask variant.
+pub fn validate_question(question: &str, max_chars: usize) -> Result<&str, ApiError> {
+    let question = question.trim();
+    if question.is_empty() {
+        return Err(ApiError::BadRequest(
+            "Question must not be empty".to_string(),
+        ));
+    }
+    if question.chars().count() > max_chars {
+        return Err(ApiError::BadRequest(format!(
+            "Question must be at most {} characters",
+            max_chars
+        )));
+    }
+    Ok(question)
+}
+
+async fn answer(state: &AppState, question: &str) -> Result<LlmResponse, ApiError> {
+    let config = state.config();
+    let question = validate_question(question, config.ask_max_question_chars)?;
+    let guardrails = GuardrailPolicy::from_config(config).ask;
+    let system = guardrails.system_prompt(ASK_PERSONA, &[ASK_INSTRUCTIONS]);
+    state
+        .llm()
+        .ask(
+            &system,
+            &guardrails.user_input(question),
+            &AskParams::default(),
+        )
+        .await
+        .map_err(ApiError::from)
+}
+
+fn plain_text(answer: String) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(ContentType::plaintext())
+        .body(answer)
+}
+
+/// `POST /ask`: JSON envelope with the answer and raw provider JSON, or the
+/// bare answer when the client sends `Accept: text/plain`.
+pub async fn ask(
+    _user: AuthUser,
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    format: JsonFormat,
+    body: web::Json<AskRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let response = answer(&state, &body.question).await?;
+    let wants_text = req
+        .headers()
+        .get(ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("text/plain"));
+    if wants_text {
+        return Ok(plain_text(response.content));
+    }
+    Ok(format.respond(
+        HttpResponse::Ok(),
+        &json!({
+            "answer": response.content,
+            "model": response.model,
+            "raw": response.raw,
+        }),
+    ))
+}
+
+/// `GET /ask?q=...`: the answer as `text/plain; charset=utf-8`.
+pub async fn ask_text(
+    _user: AuthUser,
+    state: web::Data<AppState>,
+    query: web::Query<AskQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let response = answer(&state, &query.q).await?;
+    Ok(plain_text(response.content))
+}
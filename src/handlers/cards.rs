@@ -0,0 +1,77 @@
+//! Card endpoints that need no reading pipeline.
+
+use actix_web::http::header::{CacheControl, CacheDirective};
+use actix_web::{HttpResponse, web};
+use serde::Deserialize;
+
+use crate::middleware::{ApiError, AuthUser};
+use crate::models::{CardReference, card_by_id, full_deck};
+use crate::services::{Language, card_of_the_day, today};
+use crate::state::AppState;
+
+/// How long clients and CDNs may reuse the deck reference; it only changes
+/// on deploy.
+const DECK_MAX_AGE_SECS: u32 = 86400;
+
+#[derive(Debug, Deserialize)]
+pub struct DeckQuery {
+    /// `th` or `en`; defaults to `DEFAULT_LANGUAGE` (English when that is
+    /// neither).
+    pub lang: Option<String>,
+}
+
+/// `GET /card-of-the-day`: today's card, shared by everyone, or personal to
+/// the caller when they send a valid token.
+pub async fn get_card_of_the_day(user: Option<AuthUser>) -> HttpResponse {
+    let user_id = user.map(|u| u.user_id);
+    HttpResponse::Ok().json(card_of_the_day(today(), user_id))
+}
+
+/// `GET /cards`: the whole deck in id order, for showing card meanings
+/// outside a reading. Public.
+pub async fn list_cards(
+    state: web::Data<AppState>,
+    query: web::Query<DeckQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let thai = wants_thai(&state, query.lang.as_deref())?;
+    let cards: Vec<CardReference> = full_deck()
+        .into_iter()
+        .map(|card| CardReference::new(card, thai))
+        .collect();
+    Ok(cacheable().json(serde_json::json!({ "items": cards })))
+}
+
+/// `GET /cards/{id}`: one card of the deck; 404 for an unknown id. Public.
+pub async fn get_card(
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+    query: web::Query<DeckQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let thai = wants_thai(&state, query.lang.as_deref())?;
+    let card = path
+        .parse()
+        .ok()
+        .and_then(card_by_id)
+        .ok_or_else(|| ApiError::NotFound("Card not found".to_string()))?;
+    Ok(cacheable().json(CardReference::new(card, thai)))
+}
+
+/// Whether `lang` (or the default language) asks for Thai names.
+fn wants_thai(state: &AppState, lang: Option<&str>) -> Result<bool, ApiError> {
+    let language = match lang.filter(|l| !l.trim().is_empty()) {
+        None => state.config().default_language,
+        Some(code) => Language::from_code(code)
+            .filter(|l| *l != Language::Other)
+            .ok_or_else(|| ApiError::BadRequest("lang must be th or en".to_string()))?,
+    };
+    Ok(language == Language::Thai)
+}
+
+fn cacheable() -> actix_web::HttpResponseBuilder {
+    let mut response = HttpResponse::Ok();
+    response.insert_header(CacheControl(vec![
+        CacheDirective::Public,
+        CacheDirective::MaxAge(DECK_MAX_AGE_SECS),
+    ]));
+    response
+}
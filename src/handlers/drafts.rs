@@ -0,0 +1,87 @@
+//! Draft questions: saved without a reading or a charge, read later.
+
+use actix_web::{HttpResponse, web};
+use serde_json::json;
+
+use super::ask::validate_question;
+use super::readings::create_reading;
+use crate::middleware::{ApiError, AuthUser, JsonFormat, UserTier};
+use crate::models::{CreateDraftRequest, CreateReadingRequest, SubmitDraftRequest};
+use crate::state::AppState;
+
+/// `POST /drafts`: save a question for later. Each user may keep
+/// `MAX_DRAFTS`; past that the request is refused with 409.
+pub async fn create_draft(
+    user: AuthUser,
+    state: web::Data<AppState>,
+    body: web::Json<CreateDraftRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let config = state.config();
+    let question = validate_question(&body.question, config.ask_max_question_chars)?;
+    let draft = state
+        .store()
+        .insert_draft(user.user_id, question, body.spread, config.max_drafts)
+        .await?
+        .ok_or_else(|| ApiError::Conflict("Draft limit reached".to_string()))?;
+    Ok(HttpResponse::Created().json(draft))
+}
+
+/// `GET /drafts`: the caller's drafts, newest first.
+pub async fn list_drafts(
+    user: AuthUser,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let items = state.store().list_drafts(user.user_id).await?;
+    Ok(HttpResponse::Ok().json(json!({ "items": items })))
+}
+
+/// `DELETE /drafts/{id}`: discard one of the caller's drafts.
+pub async fn delete_draft(
+    user: AuthUser,
+    state: web::Data<AppState>,
+    path: web::Path<i64>,
+) -> Result<HttpResponse, ApiError> {
+    if !state
+        .store()
+        .delete_draft(user.user_id, path.into_inner())
+        .await?
+    {
+        return Err(ApiError::NotFound("Draft not found".to_string()));
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// `POST /drafts/{id}/submit`: read a draft exactly as `POST /readings`
+/// would. The draft is deleted once the reading is stored and kept if the
+/// reading fails.
+pub async fn submit_draft(
+    user_tier: UserTier,
+    state: web::Data<AppState>,
+    format: JsonFormat,
+    path: web::Path<i64>,
+    body: Option<web::Json<SubmitDraftRequest>>,
+) -> Result<HttpResponse, ApiError> {
+    let user_id = user_tier.user.user_id;
+    let draft_id = path.into_inner();
+    let draft = state
+        .store()
+        .get_draft(user_id, draft_id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound("Draft not found".to_string()))?;
+    let options = body.map(web::Json::into_inner).unwrap_or_default();
+    let request = CreateReadingRequest {
+        question: draft.question,
+        spread: draft.spread,
+        card_count: None,
+        use_memory: options.use_memory,
+        payment_id: options.payment_id,
+        detail_level: options.detail_level,
+        include_shuffle: options.include_shuffle,
+        explain: options.explain,
+    };
+    let response = create_reading(user_tier, state.clone(), format, web::Json(request)).await?;
+    if let Err(e) = state.store().delete_draft(user_id, draft_id).await {
+        log::warn!("failed to delete submitted draft {}: {}", draft_id, e);
+    }
+    Ok(response)
+}
@@ -0,0 +1,193 @@
+//! Health checks. These make outbound calls, so they are admin-only and kept
+//! off the liveness path.
+
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+use actix_web::{HttpResponse, web};
+use redis::AsyncCommands;
+use redis::aio::ConnectionManager;
+use serde::Serialize;
+use serde_json::json;
+use sqlx::PgPool;
+
+use crate::config::Config;
+use crate::middleware::{AdminUser, ApiError};
+use crate::services::{OpenAiClient, QUEUE_KEY};
+use crate::state::AppState;
+
+/// Each `/health/full` check gives up after this long.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// `GET /health/llm`: verify the provider is reachable and the API key is
+/// accepted. 200 with latency when healthy, 503 otherwise.
+pub async fn llm_health(
+    _admin: AdminUser,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let llm = state.openai();
+    if state.config().llm_mock {
+        return Ok(HttpResponse::Ok().json(json!({
+            "status": "ok",
+            "mock": true,
+        })));
+    }
+
+    let started = Instant::now();
+    let result = llm.list_models().await;
+    let latency_ms = started.elapsed().as_millis() as u64;
+    match result {
+        Ok(()) => Ok(HttpResponse::Ok().json(json!({
+            "status": "ok",
+            "model": llm.model(),
+            "latency_ms": latency_ms,
+        }))),
+        Err(e) => {
+            log::warn!("LLM health check failed after {}ms: {}", latency_ms, e);
+            Ok(HttpResponse::ServiceUnavailable().json(json!({
+                "status": "unavailable",
+                "error": e.to_string(),
+                "latency_ms": latency_ms,
+            })))
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckStatus {
+    Ok,
+    Unavailable,
+    /// The subsystem isn't set up in this deployment (e.g. no Redis URL).
+    NotConfigured,
+}
+
+/// One subsystem in `/health/full`: its status plus whatever metrics the
+/// check gathered.
+#[derive(Debug, Clone, Serialize)]
+pub struct SubsystemCheck {
+    pub status: CheckStatus,
+    /// Whether an `Unavailable` status fails the whole check.
+    pub critical: bool,
+    #[serde(flatten)]
+    pub details: serde_json::Value,
+}
+
+impl SubsystemCheck {
+    fn not_configured(critical: bool) -> Self {
+        SubsystemCheck {
+            status: CheckStatus::NotConfigured,
+            critical,
+            details: json!({}),
+        }
+    }
+}
+
+/// Healthy unless a critical subsystem is unavailable.
+pub fn all_critical_healthy<'a>(checks: impl IntoIterator<Item = &'a SubsystemCheck>) -> bool {
+    checks
+        .into_iter()
+        .all(|check| !check.critical || check.status != CheckStatus::Unavailable)
+}
+
+/// Run `check` under `CHECK_TIMEOUT`, reporting its latency and any error.
+async fn timed<T, E: std::fmt::Display>(
+    critical: bool,
+    check: impl Future<Output = Result<T, E>>,
+    details: impl FnOnce(T) -> serde_json::Value,
+) -> SubsystemCheck {
+    let started = Instant::now();
+    let result = tokio::time::timeout(CHECK_TIMEOUT, check).await;
+    let latency_ms = started.elapsed().as_millis() as u64;
+    let (status, mut details) = match result {
+        Ok(Ok(value)) => (CheckStatus::Ok, details(value)),
+        Ok(Err(e)) => (CheckStatus::Unavailable, json!({ "error": e.to_string() })),
+        Err(_) => (
+            CheckStatus::Unavailable,
+            json!({ "error": format!("timed out after {:?}", CHECK_TIMEOUT) }),
+        ),
+    };
+    details["latency_ms"] = latency_ms.into();
+    SubsystemCheck {
+        status,
+        critical,
+        details,
+    }
+}
+
+async fn database_check(pool: Option<&PgPool>) -> SubsystemCheck {
+    let Some(pool) = pool else {
+        return SubsystemCheck::not_configured(true);
+    };
+    let ping = sqlx::query_scalar::<_, i32>("SELECT 1").fetch_one(pool);
+    timed(
+        true,
+        ping,
+        |_| json!({ "pool_size": pool.size(), "pool_idle": pool.num_idle() }),
+    )
+    .await
+}
+
+async fn redis_check(redis: Option<&ConnectionManager>) -> SubsystemCheck {
+    let Some(redis) = redis else {
+        return SubsystemCheck::not_configured(true);
+    };
+    let mut conn = redis.clone();
+    let ping = async move { redis::cmd("PING").query_async::<_, String>(&mut conn).await };
+    timed(true, ping, |_| json!({})).await
+}
+
+async fn llm_check(config: &Config, llm: &OpenAiClient) -> SubsystemCheck {
+    if config.llm_mock {
+        return SubsystemCheck {
+            status: CheckStatus::Ok,
+            critical: true,
+            details: json!({ "mock": true }),
+        };
+    }
+    timed(true, llm.list_models(), |_| json!({ "model": llm.model() })).await
+}
+
+/// Queue depth is informational: a long queue is worth seeing, not paging on.
+async fn queue_check(redis: Option<&ConnectionManager>) -> SubsystemCheck {
+    let Some(redis) = redis else {
+        return SubsystemCheck::not_configured(false);
+    };
+    let mut conn = redis.clone();
+    let length = async move { conn.llen::<_, u64>(QUEUE_KEY).await };
+    timed(false, length, |length| json!({ "length": length })).await
+}
+
+/// `GET /health/full`: database, Redis, LLM and queue in one call, checked
+/// concurrently. 200 when every critical subsystem that is configured is
+/// up, 503 otherwise; the body always carries each check.
+pub async fn full_health(
+    _admin: AdminUser,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let redis = state.redis();
+    let (database, redis_status, llm_status, queue) = tokio::join!(
+        database_check(state.pool()),
+        redis_check(redis),
+        llm_check(state.config(), state.openai()),
+        queue_check(redis),
+    );
+    let healthy = all_critical_healthy([&database, &redis_status, &llm_status, &queue]);
+    if !healthy {
+        log::warn!("full health check failed");
+    }
+    let body = json!({
+        "status": if healthy { "ok" } else { "degraded" },
+        "checks": {
+            "database": database,
+            "redis": redis_status,
+            "llm": llm_status,
+            "queue": queue,
+        },
+    });
+    Ok(if healthy {
+        HttpResponse::Ok().json(body)
+    } else {
+        HttpResponse::ServiceUnavailable().json(body)
+    })
+}
@@ -0,0 +1,118 @@
+//! LINE Messaging API webhook: questions sent to the LINE account are read
+//! through the async reading queue.
+
+use actix_web::{HttpRequest, HttpResponse, web};
+use chrono::Utc;
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::db::{DbError, record_line_event, user_id_by_line_id};
+use crate::handlers::readings::{filter_question, queue_redis, route_credit_cost};
+use crate::middleware::ApiError;
+use crate::models::{JobStatus, LineEvent, LineWebhook, ReadingJob, Spread};
+use crate::services::{
+    CardPicker, CreditError, LINE_SIGNATURE_HEADER, credit_service, enqueue_job,
+    verify_line_signature,
+};
+use crate::state::AppState;
+
+/// `POST /line/webhook`: each text message from a LINE user with an account
+/// is charged and queued as a reading. Events are claimed by
+/// `webhookEventId` first, so a redelivered event is answered 200 without
+/// queueing or charging again. Results are read back through
+/// `GET /readings/jobs/{id}`; nothing is pushed to LINE yet.
+pub async fn line_webhook(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    body: web::Bytes,
+) -> Result<HttpResponse, ApiError> {
+    let config = state.config();
+    let secret = config
+        .line_channel_secret
+        .as_deref()
+        .ok_or_else(|| ApiError::ServiceUnavailable("LINE webhook is unavailable".to_string()))?;
+    let signature = req
+        .headers()
+        .get(LINE_SIGNATURE_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .ok_or_else(|| ApiError::BadRequest("Missing webhook signature".to_string()))?;
+    if !verify_line_signature(secret, &body, signature) {
+        return Err(ApiError::Unauthorized(
+            "Invalid webhook signature".to_string(),
+        ));
+    }
+    let webhook: LineWebhook = serde_json::from_slice(&body)
+        .map_err(|e| ApiError::BadRequest(format!("Invalid webhook body: {}", e)))?;
+    let pool = state
+        .pool()
+        .ok_or_else(|| ApiError::ServiceUnavailable("LINE webhook is unavailable".to_string()))?;
+    let redis = queue_redis(&state)?;
+    let max_depth = Some(config.max_queue_depth).filter(|&max| max > 0);
+
+    let mut jobs = Vec::new();
+    let mut duplicates = 0;
+    for event in &webhook.events {
+        // The claim, the charge and the enqueue succeed together: on an
+        // error the transaction rolls back and LINE's redelivery retries.
+        let mut tx = pool.begin().await.map_err(DbError::from)?;
+        if !record_line_event(&mut tx, &event.webhook_event_id).await? {
+            duplicates += 1;
+            continue;
+        }
+        if let Some(job) = line_reading_job(&state, &mut tx, event).await? {
+            enqueue_job(redis, &job, max_depth).await?;
+            jobs.push(job.id);
+        }
+        tx.commit().await.map_err(DbError::from)?;
+    }
+    Ok(HttpResponse::Ok().json(json!({
+        "received": true,
+        "jobs": jobs,
+        "duplicates": duplicates,
+    })))
+}
+
+/// The reading job for a text message, charged to its sender. `None` for
+/// events there is nothing to do for: other event types, senders without
+/// an account, questions the filter refuses and senders out of credits.
+async fn line_reading_job(
+    state: &AppState,
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    event: &LineEvent,
+) -> Result<Option<ReadingJob>, ApiError> {
+    let config = state.config();
+    let Some((line_id, text)) = event.text_message() else {
+        return Ok(None);
+    };
+    let Some(user_id) = user_id_by_line_id(tx, line_id).await? else {
+        return Ok(None);
+    };
+    let Ok(question) = filter_question(config, state.filter_metrics(), text) else {
+        return Ok(None);
+    };
+    let cost = route_credit_cost(config, true);
+    match credit_service::deduct_in(tx, user_id, cost, "reading").await {
+        Ok(_) => {}
+        Err(CreditError::Insufficient) => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+    let created_at = Utc::now().timestamp();
+    Ok(Some(ReadingJob {
+        id: Uuid::new_v4().to_string(),
+        user_id,
+        question: question.to_string(),
+        analysis: None,
+        spread: Spread::default(),
+        detail_level: Default::default(),
+        seed: CardPicker::random().seed(),
+        explain: false,
+        status: JobStatus::Queued,
+        credits_charged: cost,
+        created_at,
+        deadline: (config.job_deadline_secs > 0).then(|| created_at + config.job_deadline_secs),
+        result: None,
+        error: None,
+        replay_count: 0,
+        replayed_by: None,
+    }))
+}
@@ -0,0 +1,14 @@
+//! Operational metrics.
+
+use actix_web::{HttpResponse, web};
+
+use crate::middleware::AdminUser;
+use crate::state::AppState;
+
+/// `GET /metrics`: counters in Prometheus text format (admin only; scrape
+/// with an admin bearer token), plus OpenAI's last reported rate limits.
+pub async fn get_metrics(_admin: AdminUser, state: web::Data<AppState>) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(state.filter_metrics().render() + &state.openai().throttle().render())
+}
@@ -1,10 +1,115 @@
 //! API handlers grouped here.
+pub mod admin;
+pub mod ask;
+pub mod cards;
+pub mod drafts;
+pub mod health;
+pub mod line;
+pub mod metrics;
+pub mod models;
 pub mod readings;
 pub mod payments;
+pub mod shares;
 pub mod users;
 pub mod referrals;
 
+pub use admin::*;
+pub use ask::*;
+pub use cards::*;
+pub use drafts::*;
+pub use health::*;
+pub use line::*;
+pub use metrics::*;
+pub use models::*;
 pub use readings::*;
 pub use payments::*;
+pub use shares::*;
 pub use users::*;
 pub use referrals::*;
+
+use actix_web::{HttpRequest, HttpResponse, web};
+
+use crate::middleware::ApiError;
+
+/// Register all API routes.
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.default_service(web::to(route_not_found))
+        .route("/ask", web::post().to(ask::ask))
+        .route("/ask", web::get().to(ask::ask_text))
+        .route("/card-of-the-day", web::get().to(cards::get_card_of_the_day))
+        .route("/cards", web::get().to(cards::list_cards))
+        .route("/cards/{id}", web::get().to(cards::get_card))
+        .route("/drafts", web::get().to(drafts::list_drafts))
+        .route("/drafts", web::post().to(drafts::create_draft))
+        .route("/drafts/{id}", web::delete().to(drafts::delete_draft))
+        .route("/drafts/{id}/submit", web::post().to(drafts::submit_draft))
+        .route("/health/llm", web::get().to(health::llm_health))
+        .route("/health/full", web::get().to(health::full_health))
+        .route("/metrics", web::get().to(metrics::get_metrics))
+        .route("/models", web::get().to(models::list_models))
+        .route("/readings", web::get().to(readings::list_reading_history))
+        .route("/readings", web::post().to(readings::create_reading))
+        .route("/readings/estimate", web::post().to(readings::estimate_reading))
+        .route("/readings/guest", web::post().to(readings::create_guest_reading))
+        .route(
+            "/readings/guest/claim",
+            web::post().to(readings::claim_guest_readings),
+        )
+        .route("/readings/{id}/audit", web::get().to(readings::get_reading_audit))
+        .route(
+            "/readings/{id}/card-image-data",
+            web::get().to(readings::get_card_image_data),
+        )
+        .route("/readings/{id}/followups", web::post().to(readings::create_followup))
+        .route("/readings/{id}/related", web::get().to(readings::get_related_readings))
+        .route(
+            "/readings/{id}/regenerate",
+            web::post().to(readings::regenerate_reading),
+        )
+        .route("/readings/{id}/share", web::post().to(shares::share_reading))
+        .route("/readings/{id}/share", web::delete().to(shares::revoke_reading_share))
+        .route("/readings/session", web::post().to(readings::create_reading_session))
+        .route("/readings/async", web::post().to(readings::create_reading_job))
+        .route("/readings/jobs/{id}", web::get().to(readings::get_reading_job))
+        .route("/readings/jobs/{id}", web::delete().to(readings::cancel_reading_job))
+        .route("/payments", web::post().to(payments::create_payment))
+        .route("/payments/webhook", web::post().to(payments::payment_webhook))
+        .route("/line/webhook", web::post().to(line::line_webhook))
+        .route("/shared/{token}", web::get().to(shares::get_shared_reading))
+        .route("/users/me", web::get().to(users::get_profile))
+        .route("/users/me/stats", web::get().to(users::get_user_stats))
+        .route("/users/me/memory", web::delete().to(users::clear_user_memory))
+        .route("/referrals/claim", web::post().to(referrals::claim_referral))
+        .route("/referrals/me", web::get().to(referrals::get_referral_stats))
+        .route("/admin/queue", web::get().to(admin::get_queue_stats))
+        .route(
+            "/admin/readings/import",
+            web::post().to(admin::import_readings),
+        )
+        .route(
+            "/admin/readings/{id}/remoderate",
+            web::post().to(admin::remoderate_reading),
+        )
+        .route(
+            "/admin/readings/jobs/{id}/replay",
+            web::post().to(admin::replay_reading_job),
+        )
+        .route(
+            "/admin/users/{id}/credits",
+            web::post().to(admin::adjust_user_credits),
+        )
+        .route(
+            "/admin/users/{id}/unsuspend",
+            web::post().to(admin::unsuspend_user),
+        );
+}
+
+/// Fallback for unmatched routes: our JSON `not_found` error instead of
+/// actix's empty 404.
+async fn route_not_found(req: HttpRequest) -> Result<HttpResponse, ApiError> {
+    Err(ApiError::NotFound(format!(
+        "No route for {} {}",
+        req.method(),
+        req.path()
+    )))
+}
@@ -0,0 +1,20 @@
+//! Model picker data.
+
+use actix_web::http::header::{CacheControl, CacheDirective};
+use actix_web::{HttpResponse, web};
+
+use crate::state::AppState;
+
+/// How long clients and CDNs may reuse `GET /models`; it only changes on deploy.
+const MODELS_MAX_AGE_SECS: u32 = 300;
+
+/// `GET /models`: the allow-listed models, the tier each requires, and the
+/// active provider. Public, and built once from config at startup.
+pub async fn list_models(state: web::Data<AppState>) -> HttpResponse {
+    HttpResponse::Ok()
+        .insert_header(CacheControl(vec![
+            CacheDirective::Public,
+            CacheDirective::MaxAge(MODELS_MAX_AGE_SECS),
+        ]))
+        .json(state.models())
+}
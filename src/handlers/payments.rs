@@ -1,7 +1,92 @@
-//! Placeholder for payments endpoints (Stripe integration later).
+//! Payments endpoints. Handlers go through `PaymentProvider`, so the mock
+//! provider can stand in for Stripe in dev and tests.
 
-use actix_web::{HttpResponse, Responder};
+use actix_web::{HttpRequest, HttpResponse, web};
+use serde_json::json;
+use sqlx::PgPool;
 
-pub async fn create_payment() -> impl Responder {
-    HttpResponse::Ok().body("create_payment placeholder")
+use crate::db::{DbError, insert_payment, record_payment_event, settle_payment};
+use crate::middleware::{ApiError, AuthUser};
+use crate::models::CreatePaymentRequest;
+use crate::services::{ChargeStatus, credit_service, invalidate_tier};
+use crate::state::AppState;
+
+fn payments_pool(state: &AppState) -> Result<&PgPool, ApiError> {
+    state
+        .pool()
+        .ok_or_else(|| ApiError::ServiceUnavailable("Payments are unavailable".to_string()))
+}
+
+/// `POST /payments`: create a charge for a credit purchase.
+pub async fn create_payment(
+    user: AuthUser,
+    state: web::Data<AppState>,
+    body: web::Json<CreatePaymentRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let config = state.config();
+    let provider = state.payments();
+    let pool = payments_pool(&state)?;
+    let credits = i64::from(body.amount_baht / config.baht_per_credit.max(1));
+    if credits == 0 {
+        return Err(ApiError::BadRequest(format!(
+            "Amount must be at least {} baht",
+            config.baht_per_credit
+        )));
+    }
+    if body.idempotency_key.trim().is_empty() {
+        return Err(ApiError::BadRequest(
+            "idempotency_key must not be empty".to_string(),
+        ));
+    }
+
+    // Scope the key to the user so two users can't collide on it.
+    let idempotency_key = format!("{}:{}", user.user_id, body.idempotency_key.trim());
+    let charge = provider
+        .create_charge(user.user_id, body.amount_baht, &idempotency_key)
+        .await?;
+    let payment = insert_payment(pool, user.user_id, provider.name(), &charge, credits).await?;
+    Ok(HttpResponse::Created().json(json!({
+        "payment": payment,
+        "client_secret": charge.client_secret,
+    })))
+}
+
+/// `POST /payments/webhook`: apply a provider notification. Redelivered
+/// events and already-settled payments are acknowledged without effect.
+pub async fn payment_webhook(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    body: web::Bytes,
+) -> Result<HttpResponse, ApiError> {
+    let provider = state.payments();
+    let pool = payments_pool(&state)?;
+    let signature = req
+        .headers()
+        .get(provider.signature_header())
+        .and_then(|v| v.to_str().ok())
+        .ok_or_else(|| ApiError::BadRequest("Missing webhook signature".to_string()))?;
+    let Some(event) = provider.verify_webhook(&body, signature)? else {
+        return Ok(HttpResponse::Ok().json(json!({ "received": true })));
+    };
+
+    // Event record, status change and credit grant commit together, so a
+    // failure part-way leaves the event unrecorded and the redelivery retries.
+    let mut tx = pool.begin().await.map_err(DbError::from)?;
+    if !record_payment_event(&mut tx, &event.event_id).await? {
+        return Ok(HttpResponse::Ok().json(json!({ "received": true, "duplicate": true })));
+    }
+    let settled = settle_payment(&mut tx, &event.charge_id, event.status).await?;
+    let paid_user = match (settled, event.status) {
+        (Some((user_id, credits)), ChargeStatus::Succeeded) => {
+            credit_service::grant_in(&mut tx, user_id, credits, "purchase").await?;
+            Some(user_id)
+        }
+        _ => None,
+    };
+    tx.commit().await.map_err(DbError::from)?;
+    // A first purchase upgrades the user to `Paid`; don't wait for the cache.
+    if let (Some(user_id), Some(redis)) = (paid_user, state.redis()) {
+        invalidate_tier(redis, user_id).await;
+    }
+    Ok(HttpResponse::Ok().json(json!({ "received": true })))
 }
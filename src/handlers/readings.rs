@@ -1,7 +1,1185 @@
-//! Placeholder for readings endpoints.
+//! Readings endpoints.
 
-use actix_web::{HttpResponse, Responder};
+use std::time::Instant;
 
-pub async fn create_reading() -> impl Responder {
-    HttpResponse::Ok().body("create_reading placeholder")
+use actix_web::http::header::HeaderName;
+use actix_web::{HttpRequest, HttpResponse, web};
+use chrono::Utc;
+use redis::aio::ConnectionManager;
+use serde_json::json;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::config::Config;
+use crate::db::{Datastore, reading_from_stored};
+use crate::handlers::ask::validate_question;
+use crate::middleware::{ApiError, AuthUser, JsonFormat, Pagination, UserTier};
+use crate::models::{
+    CardImageData, CreateFollowupRequest, CreateReadingRequest, CreateSessionRequest, DetailLevel,
+    EstimateReadingRequest, GuestReadingRequest, JobStatus, QuestionAnalysis, ReadingEstimate,
+    ReadingJob, ReadingResponse, Spread, Tier,
+};
+use crate::services::{
+    self, AbuseTracker, AnalysisCache, CardPicker, ChargeStatus, CreditError,
+    FLAG_NEW_READING_PROMPT, FilterMetrics, FilterRejection, GuestReading, GuestSettings, Language,
+    LlmError, LlmProvider, MemorySettings, MemoryTurn, PipelineSettings, PromptVersion, QueueError,
+    ReadingAgent, ReadingError, RetryBudget, analysis_params, cancel_job, check_queue_capacity,
+    condense, credit_service, detect_language, elapsed_ms, enqueue_job, estimate_reading_tokens,
+    fingerprint, generate_reading, generate_session, get_job, is_valid_device_id, payment_service,
+    recent_turns, record_rejection, record_turn, release_guest_reading, reserve_guest_reading,
+    resolve_language, stash_guest_reading, take_guest_readings,
+};
+use crate::state::AppState;
+
+/// `GET /readings`: the caller's reading history, newest first. `next_cursor`
+/// is a signed, opaque token; pass it back as `cursor` for the next page.
+pub async fn list_reading_history(
+    user: AuthUser,
+    page: Pagination,
+    state: web::Data<AppState>,
+    format: JsonFormat,
+) -> Result<HttpResponse, ApiError> {
+    let items = state
+        .store()
+        .list_readings(user.user_id, page.before_id, page.limit)
+        .await?;
+    let next_cursor = page.next_cursor(&items, |item| item.id, &state.config().cursor_secret);
+    Ok(format.respond(
+        HttpResponse::Ok(),
+        &json!({
+            "items": items,
+            "next_cursor": next_cursor,
+        }),
+    ))
+}
+
+pub async fn create_reading(
+    UserTier { user, tier }: UserTier,
+    state: web::Data<AppState>,
+    format: JsonFormat,
+    body: web::Json<CreateReadingRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let config = state.config();
+    let store = state.store();
+    let redis = state.redis();
+    body.spread
+        .check_card_count(body.card_count)
+        .map_err(ApiError::BadRequest)?;
+    check_spread_allowed(config, tier, body.spread)?;
+    if let Some(payment_id) = body.payment_id {
+        check_reading_payment(store, user.user_id, payment_id).await?;
+    }
+    let prompt = if state
+        .flags()
+        .is_enabled(FLAG_NEW_READING_PROMPT, user.user_id)
+        .await
+    {
+        PromptVersion::Experimental
+    } else {
+        PromptVersion::Stable
+    };
+    let started = Instant::now();
+    if let Err(e) = filter_question(config, state.filter_metrics(), &body.question) {
+        count_rejection(state.abuse_tracker(), store, redis, user).await;
+        return Err(e);
+    }
+    let filter_ms = elapsed_ms(started);
+    let settings = PipelineSettings::from_config(config);
+    let mut budget = settings.budget();
+    let started = Instant::now();
+    let mut analysis_cache = AnalysisCache::from_config(config, redis);
+    let analysis = analyze_confident(
+        state.llm(),
+        config,
+        state.filter_metrics(),
+        &mut analysis_cache,
+        &body.question,
+        &mut budget,
+    )
+    .await;
+    if let Err(e) = &analysis
+        && is_refusal(e)
+    {
+        count_rejection(state.abuse_tracker(), store, redis, user).await;
+    }
+    let analysis = analysis?;
+    let analysis_ms = elapsed_ms(started);
+    let memory_settings = MemorySettings::from_config(config);
+    let memory = match (redis, body.use_memory) {
+        (Some(redis), true) => match recent_turns(redis, user.user_id, &memory_settings).await {
+            Ok(turns) => condense(&turns, memory_settings.max_context_chars),
+            Err(e) => {
+                log::warn!("failed to load memory for user {}: {}", user.user_id, e);
+                None
+            }
+        },
+        _ => None,
+    };
+    let detail_level = allowed_detail(tier, body.detail_level);
+    let language = resolve_language(&body.question, settings.default_language);
+    let picker =
+        CardPicker::random().with_suit_weights(settings.suit_weights.for_topic(analysis.topic));
+    let agent = ReadingAgent::new(state.llm(), prompt)
+        .with_params(settings.reading_params.clone())
+        .with_guardrails(settings.guardrails.clone())
+        .with_max_prompt_tokens(settings.max_prompt_tokens)
+        .with_detail(detail_level)
+        .with_topic(analysis.topic)
+        .with_mood(analysis.mood)
+        .with_disclaimer(settings.is_sensitive(analysis.topic))
+        .with_explain(body.explain)
+        .with_language(language)
+        .with_seed(settings.completion_seed(&picker))
+        .with_memory(memory);
+    // A payment pays for one reading. It's used up only now, so requests
+    // refused above leave it unspent; if generation fails it is refunded.
+    if let Some(payment_id) = body.payment_id
+        && !store.consume_payment(payment_id, user.user_id).await?
+    {
+        return Err(ApiError::Conflict(
+            "Payment has already been used for a reading".to_string(),
+        ));
+    }
+    let reading = generate_reading(
+        &agent,
+        &body.question,
+        Some(analysis),
+        body.spread,
+        picker,
+        &settings,
+        &mut budget,
+    )
+    .await
+    .map_err(ReadingError::from);
+    let mut reading = match reading {
+        Ok(reading) => reading,
+        Err(e) => {
+            if let (Some(payment_id), Some(pool)) = (body.payment_id, state.pool())
+                && let Err(refund_err) =
+                    payment_service::refund(state.payments(), pool, payment_id, "reading_failed")
+                        .await
+            {
+                log::error!(
+                    "failed to refund payment {} after reading failure: {}",
+                    payment_id,
+                    refund_err
+                );
+            }
+            return Err(e.into());
+        }
+    };
+    if body.include_shuffle {
+        // `meta.seed` is the seed of the final draw, after any reshuffle.
+        let final_picker =
+            CardPicker::new(reading.meta.seed).with_suit_weights(picker.suit_weights());
+        reading.meta.shuffle = Some(final_picker.shuffle(reading.spread));
+    }
+    if let Some(timings) = reading.meta.timings.as_mut() {
+        timings.filter_ms = filter_ms;
+        timings.analysis_ms = analysis_ms;
+        log::info!(
+            "reading pipeline user={} filter_ms={:.1} analysis_ms={:.1} draw_ms={:.1} reading_ms={:.1}",
+            user.user_id,
+            timings.filter_ms,
+            timings.analysis_ms,
+            timings.draw_ms,
+            timings.reading_ms
+        );
+    }
+
+    if let Some(redis) = redis {
+        let turn = MemoryTurn::new(&reading.question, &reading.summary);
+        if let Err(e) = record_turn(redis, user.user_id, &turn, &memory_settings).await {
+            log::warn!("failed to record memory for user {}: {}", user.user_id, e);
+        }
+    }
+
+    let question_fingerprint = fingerprint(&body.question, language.language);
+    let id = store
+        .insert_reading(
+            user.user_id,
+            &reading,
+            language.language,
+            &question_fingerprint,
+        )
+        .await?;
+    if let Some(payment_id) = body.payment_id
+        && let Err(e) = store.set_payment_reading(payment_id, id).await
+    {
+        log::warn!(
+            "failed to link payment {} to reading {}: {}",
+            payment_id,
+            id,
+            e
+        );
+    }
+    if !user.is_admin {
+        reading.meta.timings = None;
+    }
+    Ok(format.respond(
+        HttpResponse::Ok(),
+        &ReadingResponse {
+            id: Some(id),
+            parent_id: None,
+            followup_of: None,
+            reading,
+        },
+    ))
+}
+
+/// Identifies a guest's device (see `guest_service`).
+pub const DEVICE_ID_HEADER: HeaderName = HeaderName::from_static("x-device-id");
+
+fn device_id(req: &HttpRequest) -> Result<String, ApiError> {
+    req.headers()
+        .get(&DEVICE_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|id| is_valid_device_id(id))
+        .map(str::to_string)
+        .ok_or_else(|| ApiError::BadRequest("A valid X-Device-Id header is required".to_string()))
+}
+
+/// Redis for guest readings, or 503 when guest readings are off or their
+/// quota can't be enforced.
+fn guest_redis(state: &AppState) -> Result<(&ConnectionManager, GuestSettings), ApiError> {
+    let settings = GuestSettings::from_config(state.config());
+    match (state.redis(), settings.quota) {
+        (Some(redis), Some(_)) => Ok((redis, settings)),
+        _ => Err(ApiError::ServiceUnavailable(
+            "Guest readings are unavailable".to_string(),
+        )),
+    }
+}
+
+/// `POST /readings/guest`: a free-tier reading without an account, for the
+/// device in `X-Device-Id`, within its lifetime quota
+/// (`GUEST_READINGS_PER_DEVICE`). Not stored for any user; the device's
+/// account can claim it later with `POST /readings/guest/claim`.
+pub async fn create_guest_reading(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    format: JsonFormat,
+    body: web::Json<GuestReadingRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let (redis, guest) = guest_redis(&state)?;
+    let device_id = device_id(&req)?;
+    let config = state.config();
+    check_spread_allowed(config, Tier::Free, body.spread)?;
+    let question = filter_question(config, state.filter_metrics(), &body.question)?;
+    let quota = guest.quota.unwrap_or_default();
+    let reserved = reserve_guest_reading(redis, &device_id, quota)
+        .await
+        .map_err(|e| {
+            log::error!("guest quota check failed for {}: {}", device_id, e);
+            ApiError::ServiceUnavailable("Guest readings are unavailable".to_string())
+        })?;
+    if !reserved {
+        return Err(ApiError::TooManyRequests(
+            "This device has used its free readings; sign in to keep reading".to_string(),
+        ));
+    }
+    let mut guest_reading = match generate_guest_reading(&state, question, body.spread).await {
+        Ok(guest_reading) => guest_reading,
+        Err(e) => {
+            if let Err(release_err) = release_guest_reading(redis, &device_id).await {
+                log::error!(
+                    "failed to release guest reading for {}: {}",
+                    device_id,
+                    release_err
+                );
+            }
+            return Err(e);
+        }
+    };
+    guest_reading.reading.meta.timings = None;
+    if let Err(e) =
+        stash_guest_reading(redis, &device_id, &guest_reading, guest.claim_ttl_secs).await
+    {
+        log::warn!("failed to keep guest reading for {}: {}", device_id, e);
+    }
+    Ok(format.respond(
+        HttpResponse::Ok(),
+        &ReadingResponse {
+            id: None,
+            parent_id: None,
+            followup_of: None,
+            reading: guest_reading.reading,
+        },
+    ))
+}
+
+/// The `create_reading` pipeline as a free user without memory, flags or
+/// payment.
+async fn generate_guest_reading(
+    state: &AppState,
+    question: &str,
+    spread: Spread,
+) -> Result<GuestReading, ApiError> {
+    let config = state.config();
+    let settings = PipelineSettings::from_config(config);
+    let mut budget = settings.budget();
+    let mut analysis_cache = AnalysisCache::from_config(config, state.redis());
+    let analysis = analyze_confident(
+        state.llm(),
+        config,
+        state.filter_metrics(),
+        &mut analysis_cache,
+        question,
+        &mut budget,
+    )
+    .await?;
+    let language = resolve_language(question, settings.default_language);
+    let picker =
+        CardPicker::random().with_suit_weights(settings.suit_weights.for_topic(analysis.topic));
+    let agent = ReadingAgent::new(state.llm(), PromptVersion::Stable)
+        .with_params(settings.reading_params.clone())
+        .with_guardrails(settings.guardrails.clone())
+        .with_max_prompt_tokens(settings.max_prompt_tokens)
+        .with_detail(allowed_detail(Tier::Free, DetailLevel::default()))
+        .with_topic(analysis.topic)
+        .with_mood(analysis.mood)
+        .with_disclaimer(settings.is_sensitive(analysis.topic))
+        .with_language(language)
+        .with_seed(settings.completion_seed(&picker));
+    let reading = generate_reading(
+        &agent,
+        question,
+        Some(analysis),
+        spread,
+        picker,
+        &settings,
+        &mut budget,
+    )
+    .await
+    .map_err(ReadingError::from)?;
+    Ok(GuestReading {
+        reading,
+        language: language.language,
+        fingerprint: fingerprint(question, language.language),
+    })
+}
+
+/// `POST /readings/guest/claim`: move the guest readings made on the device
+/// in `X-Device-Id` into the caller's history. Returns the new reading ids.
+pub async fn claim_guest_readings(
+    user: AuthUser,
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    format: JsonFormat,
+) -> Result<HttpResponse, ApiError> {
+    let (redis, guest) = guest_redis(&state)?;
+    let device_id = device_id(&req)?;
+    let readings = take_guest_readings(redis, &device_id).await.map_err(|e| {
+        log::error!("failed to load guest readings for {}: {}", device_id, e);
+        ApiError::ServiceUnavailable("Guest readings are unavailable".to_string())
+    })?;
+    let mut ids = Vec::with_capacity(readings.len());
+    for (i, guest_reading) in readings.iter().enumerate() {
+        let stored = state
+            .store()
+            .insert_reading(
+                user.user_id,
+                &guest_reading.reading,
+                guest_reading.language,
+                &guest_reading.fingerprint,
+            )
+            .await;
+        if let Err(e) = stored {
+            // Keep the rest claimable for a retry.
+            for unclaimed in &readings[i..] {
+                if let Err(e) =
+                    stash_guest_reading(redis, &device_id, unclaimed, guest.claim_ttl_secs).await
+                {
+                    log::error!("failed to keep guest reading for {}: {}", device_id, e);
+                }
+            }
+            return Err(e.into());
+        }
+        ids.extend(stored);
+    }
+    Ok(format.respond(HttpResponse::Ok(), &json!({ "claimed": ids })))
+}
+
+/// The error for someone else's reading: under `HIDE_OWNERSHIP` the same
+/// 404 as a missing one, so ids can't be probed for existence; otherwise
+/// 403.
+pub fn reading_not_yours(config: &Config) -> ApiError {
+    if config.hide_ownership {
+        ApiError::NotFound("Reading not found".to_string())
+    } else {
+        ApiError::Forbidden("This reading belongs to another user".to_string())
+    }
+}
+
+/// `POST /readings/{id}/regenerate`: fresh wording for the owner's reading
+/// over the same cards, stored as a new version linked to the original.
+/// Each original allows `MAX_REGENERATIONS` versions.
+pub async fn regenerate_reading(
+    user: AuthUser,
+    state: web::Data<AppState>,
+    format: JsonFormat,
+    path: web::Path<i64>,
+) -> Result<HttpResponse, ApiError> {
+    let config = state.config();
+    let store = state.store();
+    let source_id = path.into_inner();
+    let not_found = || ApiError::NotFound("Reading not found".to_string());
+    let (owner_id, result) = store
+        .get_reading_result(source_id)
+        .await?
+        .ok_or_else(not_found)?;
+    if owner_id != user.user_id {
+        return Err(reading_not_yours(state.config()));
+    }
+    let original = reading_from_stored(result).map_err(|e| {
+        log::error!("stored reading {} is unreadable: {}", source_id, e);
+        ApiError::Internal("Stored reading is unreadable".to_string())
+    })?;
+    if store.count_reading_versions(source_id).await? >= config.max_regenerations {
+        return Err(ApiError::Conflict(
+            "This reading cannot be regenerated again".to_string(),
+        ));
+    }
+
+    let pool = state.pool();
+    let credits_charged = charge_credits(
+        pool,
+        user.user_id,
+        config.regenerate_credit_cost,
+        "reading_regenerate",
+    )
+    .await?;
+    let settings = PipelineSettings::from_config(config);
+    let mut budget = settings.budget();
+    let language = resolve_language(&original.question, settings.default_language);
+    let topic = original
+        .analysis
+        .as_ref()
+        .map(|a| a.topic)
+        .unwrap_or_default();
+    let mood = original
+        .analysis
+        .as_ref()
+        .map(|a| a.mood)
+        .unwrap_or_default();
+    let agent = ReadingAgent::new(state.llm(), PromptVersion::Stable)
+        .with_params(settings.reading_params.clone())
+        .with_guardrails(settings.guardrails.clone())
+        .with_max_prompt_tokens(settings.max_prompt_tokens)
+        .with_detail(original.meta.detail_level)
+        .with_topic(topic)
+        .with_mood(mood)
+        .with_disclaimer(settings.is_sensitive(topic))
+        // Regenerated versions stay in explain mode if the original was.
+        .with_explain(
+            original
+                .interpretations
+                .iter()
+                .any(|interp| interp.reasoning.is_some()),
+        )
+        .with_language(language);
+    let mut reading = match services::regenerate_reading(&agent, &original, &settings, &mut budget)
+        .await
+        .map_err(ReadingError::from)
+    {
+        Ok(reading) => reading,
+        Err(e) => {
+            refund(
+                pool,
+                user.user_id,
+                credits_charged,
+                "reading_regenerate_failed",
+            )
+            .await;
+            return Err(e.into());
+        }
+    };
+
+    let question_fingerprint = fingerprint(&reading.question, language.language);
+    let (id, parent_id) = store
+        .insert_reading_version(
+            user.user_id,
+            source_id,
+            &reading,
+            language.language,
+            &question_fingerprint,
+        )
+        .await?;
+    if !user.is_admin {
+        reading.meta.timings = None;
+    }
+    Ok(format.respond(
+        HttpResponse::Ok(),
+        &ReadingResponse {
+            id: Some(id),
+            parent_id: Some(parent_id),
+            followup_of: None,
+            reading,
+        },
+    ))
+}
+
+/// `POST /readings/{id}/followups`: a further question after the owner's
+/// reading, drawn fresh on the same spread and stored linked to the first
+/// reading of the conversation. Each conversation allows
+/// `MAX_FOLLOWUPS_PER_READING` follow-ups, counted from the database; one
+/// more is a 422. Charged `READING_CREDIT_COST`.
+pub async fn create_followup(
+    user: AuthUser,
+    state: web::Data<AppState>,
+    format: JsonFormat,
+    path: web::Path<i64>,
+    body: web::Json<CreateFollowupRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let config = state.config();
+    let store = state.store();
+    let source_id = path.into_inner();
+    let not_found = || ApiError::NotFound("Reading not found".to_string());
+    let (owner_id, result) = store
+        .get_reading_result(source_id)
+        .await?
+        .ok_or_else(not_found)?;
+    if owner_id != user.user_id {
+        return Err(reading_not_yours(config));
+    }
+    let original = reading_from_stored(result).map_err(|e| {
+        log::error!("stored reading {} is unreadable: {}", source_id, e);
+        ApiError::Internal("Stored reading is unreadable".to_string())
+    })?;
+    if store.count_followups(source_id).await? >= config.max_followups_per_reading {
+        return Err(ApiError::UnprocessableEntity(format!(
+            "Too many follow-ups for this reading; the maximum is {}",
+            config.max_followups_per_reading
+        )));
+    }
+    let question = filter_question(config, state.filter_metrics(), &body.question)?;
+
+    let pool = state.pool();
+    let credits_charged = charge_credits(
+        pool,
+        user.user_id,
+        config.reading_credit_cost,
+        "reading_followup",
+    )
+    .await?;
+    let settings = PipelineSettings::from_config(config);
+    let mut budget = settings.budget();
+    let language = resolve_language(question, settings.default_language);
+    // A follow-up stays in the conversation's topic and mood.
+    let analysis = original.analysis.clone();
+    let topic = analysis.as_ref().map(|a| a.topic).unwrap_or_default();
+    let mood = analysis.as_ref().map(|a| a.mood).unwrap_or_default();
+    let picker = CardPicker::random().with_suit_weights(settings.suit_weights.for_topic(topic));
+    let agent = ReadingAgent::new(state.llm(), PromptVersion::Stable)
+        .with_params(settings.reading_params.clone())
+        .with_guardrails(settings.guardrails.clone())
+        .with_max_prompt_tokens(settings.max_prompt_tokens)
+        .with_detail(original.meta.detail_level)
+        .with_topic(topic)
+        .with_mood(mood)
+        .with_disclaimer(settings.is_sensitive(topic))
+        .with_language(language)
+        .with_seed(settings.completion_seed(&picker));
+    let reading = generate_reading(
+        &agent,
+        question,
+        analysis,
+        original.spread,
+        picker,
+        &settings,
+        &mut budget,
+    )
+    .await
+    .map_err(ReadingError::from);
+    let mut reading = match reading {
+        Ok(reading) => reading,
+        Err(e) => {
+            refund(
+                pool,
+                user.user_id,
+                credits_charged,
+                "reading_followup_failed",
+            )
+            .await;
+            return Err(e.into());
+        }
+    };
+
+    let question_fingerprint = fingerprint(&reading.question, language.language);
+    let (id, followup_of) = store
+        .insert_followup(
+            user.user_id,
+            source_id,
+            &reading,
+            language.language,
+            &question_fingerprint,
+        )
+        .await?;
+    if !user.is_admin {
+        reading.meta.timings = None;
+    }
+    Ok(format.respond(
+        HttpResponse::Ok(),
+        &ReadingResponse {
+            id: Some(id),
+            parent_id: None,
+            followup_of: Some(followup_of),
+            reading,
+        },
+    ))
+}
+
+/// `GET /readings/{id}/audit`: the stored draw audit (owner or admin), enough
+/// to reproduce the cards independently.
+pub async fn get_reading_audit(
+    user: AuthUser,
+    state: web::Data<AppState>,
+    format: JsonFormat,
+    path: web::Path<i64>,
+) -> Result<HttpResponse, ApiError> {
+    let not_found = || ApiError::NotFound("Reading not found".to_string());
+    let (owner_id, result) = state
+        .store()
+        .get_reading_result(*path)
+        .await?
+        .ok_or_else(not_found)?;
+    if owner_id != user.user_id && !user.is_admin {
+        return Err(reading_not_yours(state.config()));
+    }
+    let reading = reading_from_stored(result).map_err(|e| {
+        log::error!("stored reading {} is unreadable: {}", path, e);
+        ApiError::Internal("Stored reading is unreadable".to_string())
+    })?;
+    let audit = reading
+        .meta
+        .audit
+        .ok_or_else(|| ApiError::NotFound("No audit recorded for this reading".to_string()))?;
+    Ok(format.respond(HttpResponse::Ok(), &audit))
+}
+
+/// `GET /readings/{id}/card-image-data`: card names, orientations,
+/// positions and a one-line summary per card, for rendering a share image
+/// on the device. Owner only.
+pub async fn get_card_image_data(
+    user: AuthUser,
+    state: web::Data<AppState>,
+    format: JsonFormat,
+    path: web::Path<i64>,
+) -> Result<HttpResponse, ApiError> {
+    let reading_id = path.into_inner();
+    let not_found = || ApiError::NotFound("Reading not found".to_string());
+    let (owner_id, result) = state
+        .store()
+        .get_reading_result(reading_id)
+        .await?
+        .ok_or_else(not_found)?;
+    if owner_id != user.user_id {
+        return Err(reading_not_yours(state.config()));
+    }
+    let reading = reading_from_stored(result).map_err(|e| {
+        log::error!("stored reading {} is unreadable: {}", reading_id, e);
+        ApiError::Internal("Stored reading is unreadable".to_string())
+    })?;
+    // Readings stored before the language was recorded: go by the question.
+    let language = reading
+        .meta
+        .language
+        .as_deref()
+        .and_then(Language::from_code)
+        .unwrap_or_else(|| detect_language(&reading.question));
+    let data = CardImageData::new(reading_id, &reading, language == Language::Thai);
+    Ok(format.respond(HttpResponse::Ok(), &data))
+}
+
+/// Most readings `GET /readings/{id}/related` suggests.
+const RELATED_READINGS_LIMIT: i64 = 5;
+
+/// `GET /readings/{id}/related`: the owner's other readings of the same
+/// question or on the same topic, to suggest alongside the reading.
+pub async fn get_related_readings(
+    user: AuthUser,
+    state: web::Data<AppState>,
+    format: JsonFormat,
+    path: web::Path<i64>,
+) -> Result<HttpResponse, ApiError> {
+    let reading_id = path.into_inner();
+    let store = state.store();
+    let not_found = || ApiError::NotFound("Reading not found".to_string());
+    let (owner_id, result) = store
+        .get_reading_result(reading_id)
+        .await?
+        .ok_or_else(not_found)?;
+    if owner_id != user.user_id {
+        return Err(reading_not_yours(state.config()));
+    }
+    let reading = reading_from_stored(result).map_err(|e| {
+        log::error!("stored reading {} is unreadable: {}", reading_id, e);
+        ApiError::Internal("Stored reading is unreadable".to_string())
+    })?;
+    // The same fingerprint `create_reading` stored.
+    let language = resolve_language(&reading.question, state.config().default_language);
+    let items = store
+        .find_similar(
+            user.user_id,
+            reading_id,
+            &fingerprint(&reading.question, language.language),
+            reading.analysis.map(|a| a.topic),
+            RELATED_READINGS_LIMIT,
+        )
+        .await?;
+    Ok(format.respond(HttpResponse::Ok(), &json!({ "items": items })))
+}
+
+/// The QuestionFilter's cheap checks: `validate_question`, then refuse
+/// (422) a language outside `SUPPORTED_LANGUAGES`, since readings in other
+/// languages are unpredictable. Every question and rejection is counted.
+pub(crate) fn filter_question<'q>(
+    config: &Config,
+    metrics: &FilterMetrics,
+    question: &'q str,
+) -> Result<&'q str, ApiError> {
+    let language = resolve_language(question, config.default_language).language;
+    metrics.record_screened(language);
+    let question =
+        validate_question(question, config.ask_max_question_chars).inspect_err(|_| {
+            metrics.record_rejected(FilterRejection::Invalid, language);
+        })?;
+    if !config
+        .supported_languages
+        .iter()
+        .any(|code| code.eq_ignore_ascii_case(language.code()))
+    {
+        metrics.record_rejected(FilterRejection::UnsupportedLanguage, language);
+        return Err(ApiError::UnprocessableEntity(
+            "This language is not supported yet. Please ask in a supported language.".to_string(),
+        ));
+    }
+    Ok(question)
+}
+
+/// `POST /readings/estimate`: expected tokens, price, and credits for a
+/// reading of this question, without generating it or calling the LLM.
+/// `credits_required` is what the chosen route would actually charge.
+pub async fn estimate_reading(
+    UserTier { tier, .. }: UserTier,
+    state: web::Data<AppState>,
+    format: JsonFormat,
+    body: web::Json<EstimateReadingRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let config = state.config();
+    let catalog = state.models();
+    let question = validate_question(&body.question, config.ask_max_question_chars)?;
+    let model = match &body.model {
+        Some(id) => catalog
+            .get(id)
+            .ok_or_else(|| ApiError::BadRequest(format!("Unknown model '{}'", id)))?
+            .id
+            .clone(),
+        None => catalog.default_model.clone(),
+    };
+    let detail_level = allowed_detail(tier, body.detail_level);
+    let tokens = estimate_reading_tokens(question, body.spread, detail_level);
+    Ok(format.respond(
+        HttpResponse::Ok(),
+        &ReadingEstimate {
+            estimated_cost: state.pricing().get(&model).map(|price| tokens.cost(price)),
+            model,
+            detail_level,
+            estimated_tokens: tokens.total(),
+            credits_required: route_credit_cost(config, body.queued),
+        },
+    ))
+}
+
+/// Free users are held to `Brief`.
+fn allowed_detail(tier: Tier, requested: DetailLevel) -> DetailLevel {
+    if tier >= Tier::Paid {
+        requested
+    } else {
+        DetailLevel::Brief
+    }
+}
+
+/// Spreads bigger than the tier's `FREE_MAX_CARDS`/`PAID_MAX_CARDS` need
+/// an upgrade.
+fn check_spread_allowed(config: &Config, tier: Tier, spread: Spread) -> Result<(), ApiError> {
+    let max_cards = match tier {
+        Tier::Free => config.free_max_cards,
+        Tier::Paid => config.paid_max_cards,
+        Tier::Admin => return Ok(()),
+    };
+    if spread.card_count() > max_cards {
+        return Err(ApiError::PaymentRequired(
+            "Upgrade your plan to use this spread".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// A reading paid for directly must reference the caller's own, settled payment.
+async fn check_reading_payment(
+    store: &dyn Datastore,
+    user_id: i64,
+    payment_id: i64,
+) -> Result<(), ApiError> {
+    let payment = store
+        .get_payment(payment_id)
+        .await?
+        .filter(|p| p.user_id == user_id)
+        .ok_or_else(|| ApiError::NotFound("Payment not found".to_string()))?;
+    if payment.status != ChargeStatus::Succeeded.as_str() {
+        return Err(ApiError::PaymentRequired(
+            "Payment has not been completed".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// `POST /readings/session`: several related questions read against one
+/// shared draw, with a section per question and an overall synthesis. If
+/// generation runs out of time after a cut-off answer, the usable leading
+/// sections come back with `partial: true`, charged pro rata.
+pub async fn create_reading_session(
+    UserTier { user, tier }: UserTier,
+    state: web::Data<AppState>,
+    format: JsonFormat,
+    body: web::Json<CreateSessionRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let config = state.config();
+    let store = state.store();
+    let redis = state.redis();
+    let body = body.into_inner();
+    body.spread
+        .check_card_count(body.card_count)
+        .map_err(ApiError::BadRequest)?;
+    check_spread_allowed(config, tier, body.spread)?;
+    if body.questions.is_empty() {
+        return Err(ApiError::BadRequest(
+            "A session takes at least one question".to_string(),
+        ));
+    }
+    if body.questions.len() > config.max_session_questions {
+        return Err(ApiError::UnprocessableEntity(format!(
+            "Too many questions for one session; the maximum is {}",
+            config.max_session_questions
+        )));
+    }
+    let questions = body
+        .questions
+        .iter()
+        .map(|q| filter_question(config, state.filter_metrics(), q).map(str::to_string))
+        .collect::<Result<Vec<_>, _>>();
+    let questions = match questions {
+        Ok(questions) => questions,
+        Err(e) => {
+            count_rejection(state.abuse_tracker(), store, redis, user).await;
+            return Err(e);
+        }
+    };
+
+    let settings = PipelineSettings::from_config(config);
+    let mut budget = settings.budget();
+    // Repeated questions in one session are analyzed once.
+    let mut analysis_cache = AnalysisCache::from_config(config, redis);
+    let mut analyses = Vec::with_capacity(questions.len());
+    for question in &questions {
+        let analysis = analyze_confident(
+            state.llm(),
+            config,
+            state.filter_metrics(),
+            &mut analysis_cache,
+            question,
+            &mut budget,
+        )
+        .await;
+        if let Err(e) = &analysis
+            && is_refusal(e)
+        {
+            count_rejection(state.abuse_tracker(), store, redis, user).await;
+        }
+        analyses.push(analysis?);
+    }
+
+    let pool = state.pool();
+    let credits_charged = charge_credits(
+        pool,
+        user.user_id,
+        config.session_credit_cost,
+        "reading_session",
+    )
+    .await?;
+
+    let sensitive = analyses.iter().any(|a| settings.is_sensitive(a.topic));
+    let language = resolve_language(&questions.join("\n"), settings.default_language);
+    let picker = CardPicker::random().with_suit_weights(
+        settings
+            .suit_weights
+            .for_topics(analyses.iter().map(|a| a.topic)),
+    );
+    let agent = ReadingAgent::new(state.llm(), PromptVersion::Stable)
+        .with_params(settings.reading_params.clone())
+        .with_guardrails(settings.guardrails.clone())
+        .with_max_prompt_tokens(settings.max_prompt_tokens)
+        .with_disclaimer(sensitive)
+        .with_language(language)
+        .with_seed(settings.completion_seed(&picker));
+    match generate_session(
+        &agent,
+        &questions,
+        analyses,
+        body.spread,
+        picker,
+        &settings,
+        &mut budget,
+    )
+    .await
+    .map_err(ReadingError::from)
+    {
+        Ok(session) => {
+            if session.partial {
+                // Charge only for the questions that were answered.
+                let earned =
+                    credits_charged * session.sections.len() as i64 / questions.len() as i64;
+                refund(
+                    pool,
+                    user.user_id,
+                    credits_charged - earned,
+                    "reading_session_partial",
+                )
+                .await;
+            }
+            Ok(format.respond(HttpResponse::Ok(), &session))
+        }
+        Err(e) => {
+            refund(
+                pool,
+                user.user_id,
+                credits_charged,
+                "reading_session_failed",
+            )
+            .await;
+            Err(e.into())
+        }
+    }
+}
+
+/// Run QuestionAnalysis (through `cache`) and refuse (422) questions too
+/// vague to read well.
+pub(crate) async fn analyze_confident(
+    llm: &dyn LlmProvider,
+    config: &Config,
+    metrics: &FilterMetrics,
+    cache: &mut AnalysisCache<'_>,
+    question: &str,
+    budget: &mut RetryBudget,
+) -> Result<QuestionAnalysis, ReadingError> {
+    let language = resolve_language(question, config.default_language).language;
+    let analysis = cache
+        .analyze(llm, question, language, &analysis_params(config), budget)
+        .await?;
+    if analysis.confidence < config.analysis_min_confidence {
+        metrics.record_rejected(FilterRejection::Unclear, language);
+        return Err(ReadingError::Filtered(
+            "Your question is a little unclear. Please add more detail so MiMi can give you a meaningful reading.".to_string(),
+        ));
+    }
+    Ok(analysis)
+}
+
+/// Whether an `analyze_confident` error refused the question, as opposed
+/// to the LLM failing.
+fn is_refusal(e: &ReadingError) -> bool {
+    matches!(
+        e,
+        ReadingError::Filtered(_) | ReadingError::GenerationFailed(LlmError::ContentFiltered)
+    )
+}
+
+/// Count a refused question toward the caller's auto-suspension (see
+/// `AbuseTracker`). Admins aren't counted; failures are only logged.
+async fn count_rejection(
+    abuse: &AbuseTracker,
+    store: &dyn Datastore,
+    redis: Option<&ConnectionManager>,
+    user: AuthUser,
+) {
+    if user.is_admin {
+        return;
+    }
+    if let Err(e) = record_rejection(abuse, store, redis, user.user_id).await {
+        log::error!(
+            "failed to record rejection for user {}: {}",
+            user.user_id,
+            e
+        );
+    }
+}
+
+/// Credits a reading costs on its route: `READING_CREDIT_COST` when queued,
+/// nothing on `POST /readings`, which is paid per reading with `payment_id`.
+pub(crate) fn route_credit_cost(config: &Config, queued: bool) -> i64 {
+    if queued {
+        config.reading_credit_cost
+    } else {
+        0
+    }
+}
+
+/// Deduct `amount` credits for a reading, or 0 without a database.
+async fn charge_credits(
+    pool: Option<&PgPool>,
+    user_id: i64,
+    amount: i64,
+    reason: &str,
+) -> Result<i64, ApiError> {
+    let Some(pool) = pool else {
+        return Ok(0);
+    };
+    match credit_service::deduct(pool, user_id, amount, reason).await {
+        Ok(_) => Ok(amount),
+        Err(CreditError::Insufficient) => Err(ReadingError::InsufficientCredits.into()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Redis connection, or 503 when the queue backend isn't configured.
+pub(crate) fn queue_redis(state: &AppState) -> Result<&ConnectionManager, ApiError> {
+    state
+        .redis()
+        .ok_or_else(|| ApiError::ServiceUnavailable("Reading queue is unavailable".to_string()))
+}
+
+/// `POST /readings/async`: charge credits and queue the reading. While the
+/// queue holds `MAX_QUEUE_DEPTH` jobs the request is refused with 503 and
+/// `Retry-After`.
+pub async fn create_reading_job(
+    UserTier { user, tier }: UserTier,
+    state: web::Data<AppState>,
+    format: JsonFormat,
+    body: web::Json<CreateReadingRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let config = state.config();
+    let store = state.store();
+    let redis = queue_redis(&state)?;
+    let max_depth = Some(config.max_queue_depth).filter(|&max| max > 0);
+    let body = body.into_inner();
+    body.spread
+        .check_card_count(body.card_count)
+        .map_err(ApiError::BadRequest)?;
+    check_spread_allowed(config, tier, body.spread)?;
+    // Shed load before the analysis call and the charge; `enqueue_job`
+    // checks again.
+    check_queue_capacity(redis, max_depth).await?;
+    if let Err(e) = filter_question(config, state.filter_metrics(), &body.question) {
+        count_rejection(state.abuse_tracker(), store, Some(redis), user).await;
+        return Err(e);
+    }
+    let mut budget = PipelineSettings::from_config(config).budget();
+    let mut analysis_cache = AnalysisCache::from_config(config, Some(redis));
+    let analysis = analyze_confident(
+        state.llm(),
+        config,
+        state.filter_metrics(),
+        &mut analysis_cache,
+        &body.question,
+        &mut budget,
+    )
+    .await;
+    if let Err(e) = &analysis
+        && is_refusal(e)
+    {
+        count_rejection(state.abuse_tracker(), store, Some(redis), user).await;
+    }
+    let analysis = analysis?;
+    let detail_level = allowed_detail(tier, body.detail_level);
+
+    let credits_charged = charge_credits(
+        state.pool(),
+        user.user_id,
+        route_credit_cost(config, true),
+        "reading",
+    )
+    .await?;
+    let created_at = Utc::now().timestamp();
+    let job = ReadingJob {
+        id: Uuid::new_v4().to_string(),
+        user_id: user.user_id,
+        question: body.question,
+        analysis: Some(analysis),
+        spread: body.spread,
+        detail_level,
+        seed: CardPicker::random().seed(),
+        explain: body.explain,
+        status: JobStatus::Queued,
+        credits_charged,
+        created_at,
+        deadline: (config.job_deadline_secs > 0).then(|| created_at + config.job_deadline_secs),
+        result: None,
+        error: None,
+        replay_count: 0,
+        replayed_by: None,
+    };
+    if let Err(e) = enqueue_job(redis, &job, max_depth).await {
+        refund(
+            state.pool(),
+            job.user_id,
+            job.credits_charged,
+            "reading_enqueue_failed",
+        )
+        .await;
+        return Err(e.into());
+    }
+    Ok(format.respond(HttpResponse::Accepted(), &job))
+}
+
+/// `GET /readings/jobs/{id}`: the owner's view of a job.
+pub async fn get_reading_job(
+    user: AuthUser,
+    state: web::Data<AppState>,
+    format: JsonFormat,
+    path: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    let job = get_job(queue_redis(&state)?, &path).await?;
+    if job.user_id != user.user_id {
+        return Err(reading_not_yours(state.config()));
+    }
+    Ok(format.respond(HttpResponse::Ok(), &job))
+}
+
+/// `DELETE /readings/jobs/{id}`: cancel a still-queued job and refund it.
+pub async fn cancel_reading_job(
+    user: AuthUser,
+    state: web::Data<AppState>,
+    format: JsonFormat,
+    path: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    let job = cancel_job(queue_redis(&state)?, &path, user.user_id)
+        .await
+        .map_err(|e| match e {
+            QueueError::NotOwner => reading_not_yours(state.config()),
+            e => e.into(),
+        })?;
+    refund(
+        state.pool(),
+        job.user_id,
+        job.credits_charged,
+        "reading_cancelled",
+    )
+    .await;
+    Ok(format.respond(HttpResponse::Ok(), &job))
+}
+
+async fn refund(pool: Option<&PgPool>, user_id: i64, amount: i64, reason: &str) {
+    let Some(pool) = pool.filter(|_| amount > 0) else {
+        return;
+    };
+    if let Err(e) = credit_service::grant(pool, user_id, amount, reason).await {
+        log::error!(
+            "failed to refund {} credits to user {} ({}): {}",
+            amount,
+            user_id,
+            reason,
+            e
+        );
+    }
 }
@@ -1,7 +1,24 @@
-//! Placeholder for referral endpoints.
+//! Referral endpoints; claiming a code is still a placeholder.
 
-use actix_web::{HttpResponse, Responder};
+use actix_web::{HttpResponse, Responder, web};
+
+use crate::middleware::{ApiError, AuthUser};
+use crate::state::AppState;
 
 pub async fn claim_referral() -> impl Responder {
     HttpResponse::Ok().body("claim_referral placeholder")
 }
+
+/// `GET /referrals/me`: the caller's referral code, created on first
+/// request, with their successful and pending referrals and credits earned.
+pub async fn get_referral_stats(
+    user: AuthUser,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let stats = state
+        .store()
+        .referral_stats(user.user_id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound("User not found".to_string()))?;
+    Ok(HttpResponse::Ok().json(stats))
+}
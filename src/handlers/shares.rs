@@ -0,0 +1,95 @@
+//! Public share links for readings.
+
+use actix_web::{HttpResponse, web};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use chrono::{Duration, Utc};
+use rand::RngCore;
+use serde_json::json;
+use sqlx::PgPool;
+
+use crate::db::{
+    delete_shares, get_reading_result, get_shared_result, insert_share, reading_from_stored,
+};
+use crate::handlers::readings::reading_not_yours;
+use crate::middleware::{ApiError, AuthUser, RequestOrigin};
+use crate::models::SharedReading;
+use crate::state::AppState;
+
+/// 32 random bytes: unguessable and unrelated to the reading or its owner.
+fn new_share_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+fn require_pool(state: &AppState) -> Result<&PgPool, ApiError> {
+    state
+        .pool()
+        .ok_or_else(|| ApiError::ServiceUnavailable("Reading sharing is unavailable".to_string()))
+}
+
+/// `POST /readings/{id}/share`: mint a share link for one of the caller's
+/// readings, valid for `SHARE_TTL_SECS`. `url` is absolute, on the origin
+/// the caller used.
+pub async fn share_reading(
+    user: AuthUser,
+    origin: RequestOrigin,
+    state: web::Data<AppState>,
+    path: web::Path<i64>,
+) -> Result<HttpResponse, ApiError> {
+    let pool = require_pool(&state)?;
+    let reading_id = path.into_inner();
+    let (owner_id, _) = get_reading_result(pool, reading_id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound("Reading not found".to_string()))?;
+    if owner_id != user.user_id {
+        return Err(reading_not_yours(state.config()));
+    }
+    let token = new_share_token();
+    let expires_at = Utc::now() + Duration::seconds(state.config().share_ttl_secs);
+    insert_share(pool, &token, reading_id, expires_at).await?;
+    let path = format!("/shared/{}", token);
+    Ok(HttpResponse::Created().json(json!({
+        "token": token,
+        "url": origin.url(&path),
+        "path": path,
+        "expires_at": expires_at,
+    })))
+}
+
+/// `DELETE /readings/{id}/share`: revoke every link to the reading. Admins
+/// may revoke any reading's links.
+pub async fn revoke_reading_share(
+    user: AuthUser,
+    state: web::Data<AppState>,
+    path: web::Path<i64>,
+) -> Result<HttpResponse, ApiError> {
+    let pool = require_pool(&state)?;
+    let reading_id = path.into_inner();
+    let (owner_id, _) = get_reading_result(pool, reading_id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound("Reading not found".to_string()))?;
+    if owner_id != user.user_id && !user.is_admin {
+        return Err(reading_not_yours(state.config()));
+    }
+    let revoked = delete_shares(pool, reading_id).await?;
+    Ok(HttpResponse::Ok().json(json!({ "revoked": revoked })))
+}
+
+/// `GET /shared/{token}`: the read-only public view. Unknown, expired, and
+/// revoked tokens are all 404.
+pub async fn get_shared_reading(
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    let pool = require_pool(&state)?;
+    let (result, expires_at) = get_shared_result(pool, &path)
+        .await?
+        .ok_or_else(|| ApiError::NotFound("Shared reading not found".to_string()))?;
+    let reading = reading_from_stored(result).map_err(|e| {
+        log::error!("shared reading is unreadable: {}", e);
+        ApiError::Internal("Stored reading is unreadable".to_string())
+    })?;
+    Ok(HttpResponse::Ok().json(SharedReading::new(reading, expires_at)))
+}
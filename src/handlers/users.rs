@@ -1,7 +1,38 @@
 //! Placeholder for users endpoints.
 
-use actix_web::{HttpResponse, Responder};
+use actix_web::{HttpResponse, Responder, web};
+
+use crate::middleware::{ApiError, AuthUser};
+use crate::services::{clear_memory, load_user_stats};
+use crate::state::AppState;
 
 pub async fn get_profile() -> impl Responder {
     HttpResponse::Ok().body("get_profile placeholder")
 }
+
+/// `GET /users/me/stats`: the caller's reading count, spend, and favourite
+/// topic. Cached for a few minutes.
+pub async fn get_user_stats(
+    user: AuthUser,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let stats = load_user_stats(state.store(), state.redis(), user.user_id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound("User not found".to_string()))?;
+    Ok(HttpResponse::Ok().json(stats))
+}
+
+/// `DELETE /users/me/memory`: forget the caller's remembered readings.
+pub async fn clear_user_memory(
+    user: AuthUser,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let redis = state
+        .redis()
+        .ok_or_else(|| ApiError::ServiceUnavailable("Memory is unavailable".to_string()))?;
+    clear_memory(redis, user.user_id).await.map_err(|e| {
+        log::error!("failed to clear memory for user {}: {}", user.user_id, e);
+        ApiError::Internal("Failed to clear memory".to_string())
+    })?;
+    Ok(HttpResponse::NoContent().finish())
+}
@@ -0,0 +1,9 @@
+//! MiMiVibe backend: modules shared by the server binary.
+pub mod app;
+pub mod config;
+pub mod db;
+pub mod handlers;
+pub mod middleware;
+pub mod models;
+pub mod services;
+pub mod state;
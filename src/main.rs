@@ -1,4 +1,70 @@
+use std::time::Duration;
 
-fn main() {
-    println!("Hello, world!");
+use actix_web::{HttpServer, web};
+
+use mimi_backend::app::build_app;
+use mimi_backend::config::Config;
+use mimi_backend::db::{connect_redis, create_pool};
+use mimi_backend::middleware::install_panic_hook;
+use mimi_backend::services::{OpenAiClient, PipelineSettings, run_cleanup, run_worker};
+use mimi_backend::state::AppState;
+
+#[actix_web::main]
+async fn main() -> std::io::Result<()> {
+    dotenv::dotenv().ok();
+    env_logger::init_from_env(env_logger::Env::default().default_filter_or("info"));
+    install_panic_hook();
+
+    let config = match Config::from_env() {
+        Ok(config) => config,
+        Err(e) => {
+            log::error!("refusing to start: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let pool = match config.database_url.as_deref().filter(|_| !config.mock_db) {
+        Some(url) => Some(
+            create_pool(url, config.db_statement_timeout_ms)
+                .await
+                .expect("Failed to connect to database"),
+        ),
+        None => {
+            log::warn!("DATABASE_URL not set or MOCK_DB on; running without a database");
+            None
+        }
+    };
+    let redis = connect_redis(config.redis_url.as_deref()).await;
+    let bind = (config.host.clone(), config.port);
+    let state = web::Data::new(AppState::new(config, pool, redis));
+    let config = state.config();
+    if OpenAiClient::warmup_enabled(config) {
+        let state = state.clone();
+        actix_web::rt::spawn(async move { state.openai().warm_up().await });
+    }
+
+    // The worker gets its own connection since BRPOP blocks it.
+    if let Some(worker_redis) = connect_redis(config.redis_url.as_deref()).await {
+        actix_web::rt::spawn(run_worker(
+            worker_redis,
+            state.pool().cloned(),
+            state.llm_handle(),
+            PipelineSettings::from_config(config),
+        ));
+    }
+
+    if let Some(pool) = state.pool()
+        && config.cleanup_interval_secs > 0
+    {
+        actix_web::rt::spawn(run_cleanup(
+            pool.clone(),
+            Duration::from_secs(config.cleanup_interval_secs),
+        ));
+    }
+
+    log::info!("starting mimi-backend on {}:{}", bind.0, bind.1);
+
+    HttpServer::new(move || build_app(state.clone()))
+        .bind(bind)?
+        .run()
+        .await
 }
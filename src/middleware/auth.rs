@@ -1,7 +1,96 @@
-//! Placeholder for auth middleware (LINE LIFF + JWT) - implement later.
+//! Auth helpers (JWT issued after LINE LIFF login) and the `AuthUser` extractor.
 
-/// Validate an authorization token placeholder.
-pub fn validate_token(_token: &str) -> bool {
-    // TODO: implement real token validation (JWT, LINE LIFF)
-    true
+use std::future::{Ready, ready};
+
+use actix_web::{FromRequest, HttpRequest, dev::Payload, web};
+use jsonwebtoken::{DecodingKey, Validation, decode};
+use serde::{Deserialize, Serialize};
+
+use super::error_handler::ApiError;
+use crate::state::AppState;
+
+/// JWT claims carried by our access tokens.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Claims {
+    /// User id.
+    pub sub: i64,
+    pub exp: usize,
+    /// `"admin"` for staff accounts; absent for regular users.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub role: Option<String>,
+}
+
+/// Validate an access token and return its claims. A token up to
+/// `leeway_secs` past its `exp` is still accepted (`JWT_LEEWAY_SECS`).
+pub fn validate_token(token: &str, secret: &str, leeway_secs: u64) -> Option<Claims> {
+    let mut validation = Validation::default();
+    validation.leeway = leeway_secs;
+    decode::<Claims>(
+        token,
+        &DecodingKey::from_secret(secret.as_bytes()),
+        &validation,
+    )
+    .map(|data| data.claims)
+    .ok()
+}
+
+/// The authenticated caller, resolved from the `Authorization: Bearer` header.
+#[derive(Debug, Clone, Copy)]
+pub struct AuthUser {
+    pub user_id: i64,
+    pub is_admin: bool,
+}
+
+impl FromRequest for AuthUser {
+    type Error = ApiError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let (secret, leeway_secs) = req
+            .app_data::<web::Data<AppState>>()
+            .map(|s| (s.config().jwt_secret.clone(), s.config().jwt_leeway_secs))
+            .unwrap_or_default();
+        let token = req
+            .headers()
+            .get("Authorization")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "));
+
+        let claims = token.and_then(|t| validate_token(t, &secret, leeway_secs));
+
+        ready(match claims {
+            Some(claims) => Ok(AuthUser {
+                user_id: claims.sub,
+                is_admin: claims.role.as_deref() == Some("admin"),
+            }),
+            None => Err(ApiError::Unauthorized(
+                "missing or invalid token".to_string(),
+            )),
+        })
+    }
+}
+
+/// An authenticated caller with the admin role; 403 for everyone else.
+#[derive(Debug, Clone, Copy)]
+pub struct AdminUser {
+    pub user_id: i64,
+}
+
+impl FromRequest for AdminUser {
+    type Error = ApiError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let user = match AuthUser::from_request(req, payload).into_inner() {
+            Ok(user) => user,
+            Err(e) => return ready(Err(e)),
+        };
+        ready(if user.is_admin {
+            Ok(AdminUser {
+                user_id: user.user_id,
+            })
+        } else {
+            Err(ApiError::Forbidden("admin access required".to_string()))
+        })
+    }
 }
@@ -0,0 +1,135 @@
+//! Opt-in request body logging for debugging (`LOG_REQUEST_BODIES`).
+//! Questions can contain personal details, so bodies are passed through
+//! `redact_pii` first; headers (and so `Authorization`) are never logged.
+//!
+//! Register it inside `assign_request_id` so each line carries the id.
+
+use std::sync::LazyLock;
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::http::header::CONTENT_LENGTH;
+use actix_web::middleware::Next;
+use actix_web::{Error, HttpMessage, web};
+use regex::Regex;
+
+use super::request_id::RequestId;
+
+/// Bodies longer than this are cut off in the log.
+const MAX_LOGGED_BODY_CHARS: usize = 2048;
+
+/// Bodies declared larger than this aren't buffered for logging at all.
+const MAX_BUFFERED_BODY_BYTES: usize = 64 * 1024;
+
+const REDACTED: &str = "[REDACTED]";
+
+/// JSON string fields whose names suggest a credential.
+static SECRET_FIELD: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r#"(?i)"([a-z_]*(?:secret|token|password|signature|key)[a-z_]*)"\s*:\s*"(?:[^"\\]|\\.)*""#,
+    )
+    .expect("valid secret field regex")
+});
+
+/// PII patterns, most specific first so a national id isn't half-matched
+/// as a phone number.
+static PII_PATTERNS: LazyLock<[Regex; 3]> = LazyLock::new(|| {
+    [
+        // Email addresses.
+        r"[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}",
+        // Thai national ids: 13 digits, optionally grouped 1-4-5-2-1.
+        r"(?-u:\b)\d[- ]?\d{4}[- ]?\d{5}[- ]?\d{2}[- ]?\d(?-u:\b)",
+        // Phone numbers: Thai local (0x...) or international (+...).
+        r"(?:\+\d{1,3}[- ]?|(?-u:\b)0)\d{1,2}[- ]?\d{3}[- ]?\d{3,4}(?-u:\b)",
+    ]
+    .map(|pattern| Regex::new(pattern).expect("valid PII regex"))
+});
+
+/// `text` with credential-looking JSON fields, emails, Thai national ids
+/// and phone numbers replaced by `[REDACTED]`.
+pub fn redact_pii(text: &str) -> String {
+    let mut redacted = SECRET_FIELD
+        .replace_all(text, format!(r#""$1":"{}""#, REDACTED))
+        .into_owned();
+    for pattern in PII_PATTERNS.iter() {
+        redacted = pattern.replace_all(&redacted, REDACTED).into_owned();
+    }
+    redacted
+}
+
+/// The line logged for a request `body`: redacted and truncated.
+fn body_log_line(method: &str, path: &str, request_id: &str, body: &[u8]) -> String {
+    let text: String = String::from_utf8_lossy(body)
+        .chars()
+        .take(MAX_LOGGED_BODY_CHARS)
+        .collect();
+    format!(
+        "{} {} [{}] body: {}",
+        method,
+        path,
+        request_id,
+        redact_pii(&text)
+    )
+}
+
+/// Log each request's body, redacted and truncated, then hand the handler
+/// the original bytes.
+pub async fn log_request_bodies(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let declared_len = req
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<usize>().ok());
+    if declared_len.is_none_or(|len| len == 0 || len > MAX_BUFFERED_BODY_BYTES) {
+        return next.call(req).await;
+    }
+    let body = req.extract::<web::Bytes>().await?;
+    let request_id = req
+        .extensions()
+        .get::<RequestId>()
+        .map(|id| id.0.clone())
+        .unwrap_or_default();
+    log::info!(
+        "{}",
+        body_log_line(req.method().as_str(), req.path(), &request_id, &body)
+    );
+    req.set_payload(Payload::from(body));
+    next.call(req).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redacts_emails_phones_and_national_ids() {
+        let body = r#"{"question":"Mail somchai.j@example.co.th or call 081-234-5678 or +66 81 234 5678, id 1-1037-02345-67-8"}"#;
+        let line = body_log_line("POST", "/readings", "req-1", body.as_bytes());
+        assert_eq!(
+            line,
+            r#"POST /readings [req-1] body: {"question":"Mail [REDACTED] or call [REDACTED] or [REDACTED], id [REDACTED]"}"#
+        );
+    }
+
+    #[test]
+    fn redacts_credential_fields_but_keeps_the_rest() {
+        let body = r#"{"api_key":"sk-live-123","idempotency_key":"abc","amount_baht":100}"#;
+        assert_eq!(
+            redact_pii(body),
+            r#"{"api_key":"[REDACTED]","idempotency_key":"[REDACTED]","amount_baht":100}"#
+        );
+        let plain = r#"{"question":"Will I get the job in 2026?","spread":"three_card"}"#;
+        assert_eq!(redact_pii(plain), plain);
+    }
+
+    #[test]
+    fn long_bodies_are_cut_off() {
+        let body = "a".repeat(MAX_LOGGED_BODY_CHARS * 2);
+        let line = body_log_line("POST", "/ask", "req-2", body.as_bytes());
+        assert!(line.ends_with(&"a".repeat(MAX_LOGGED_BODY_CHARS)));
+        assert!(line.len() < MAX_LOGGED_BODY_CHARS + 40);
+    }
+}
@@ -0,0 +1,211 @@
+//! Per-user cap on readings in flight (`MAX_CONCURRENT_READINGS`, 0 for
+//! off). Unlike the rate limit, which counts requests per window, this
+//! counts requests still running, so a script firing readings in parallel
+//! can't tie up workers and drain credits at once. Admins are exempt.
+//!
+//! Slots live in Redis when it is available so every instance shares them,
+//! otherwise in process. A slot is released when its request finishes,
+//! errors, panics or is dropped on disconnect; the Redis counter also
+//! expires in case an instance dies holding slots.
+//!
+//! Register `limit_concurrent_readings` inside `localize_errors` so the 429
+//! is localized too.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::Method;
+use actix_web::http::header::AUTHORIZATION;
+use actix_web::middleware::Next;
+use actix_web::{Error, web};
+use redis::AsyncCommands;
+use redis::aio::ConnectionManager;
+
+use super::error_handler::ApiError;
+use super::tier::UserTier;
+use crate::config::Config;
+use crate::models::Tier;
+use crate::state::AppState;
+
+/// POST routes that run a reading through the LLM before answering.
+const LIMITED_ROUTES: [&str; 5] = [
+    "/ask",
+    "/readings",
+    "/readings/session",
+    "/readings/{id}/regenerate",
+    "/drafts/{id}/submit",
+];
+
+/// A Redis slot counter outlives its last request by at most this long,
+/// comfortably past the longest route timeout.
+const SLOT_TTL_SECS: i64 = 300;
+
+fn slot_key(user_id: i64) -> String {
+    format!("inflight_readings:{}", user_id)
+}
+
+/// The cap, plus the in-process slot counts.
+pub struct ConcurrencyLimiter {
+    max_in_flight: Option<u32>,
+    /// user id → readings in flight.
+    local: Mutex<HashMap<i64, u32>>,
+}
+
+impl ConcurrencyLimiter {
+    pub fn new(max_in_flight: Option<u32>) -> Self {
+        ConcurrencyLimiter {
+            max_in_flight,
+            local: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn from_config(config: &Config) -> Self {
+        ConcurrencyLimiter::new(Some(config.max_concurrent_readings).filter(|&max| max > 0))
+    }
+
+    /// The cap for `tier`; `None` means unlimited. Always `None` for admins.
+    pub fn limit_for(&self, tier: Tier) -> Option<u32> {
+        match tier {
+            Tier::Admin => None,
+            _ => self.max_in_flight,
+        }
+    }
+
+    fn take_local(&self, user_id: i64, max: u32) -> bool {
+        let mut local = self.local.lock().unwrap();
+        let in_flight = local.entry(user_id).or_insert(0);
+        if *in_flight >= max {
+            return false;
+        }
+        *in_flight += 1;
+        true
+    }
+
+    fn release_local(&self, user_id: i64) {
+        let mut local = self.local.lock().unwrap();
+        if let Some(in_flight) = local.get_mut(&user_id) {
+            *in_flight = in_flight.saturating_sub(1);
+            if *in_flight == 0 {
+                local.remove(&user_id);
+            }
+        }
+    }
+}
+
+/// Take one of `user_id`'s `max` slots, or `None` when all are in use.
+/// Redis errors fall back to the in-process counts.
+pub async fn acquire_slot(
+    limiter: &Arc<ConcurrencyLimiter>,
+    user_id: i64,
+    max: u32,
+    redis: Option<&ConnectionManager>,
+) -> Option<ConcurrencySlot> {
+    if let Some(redis) = redis {
+        match take_in_redis(redis, user_id, max).await {
+            Ok(true) => {
+                return Some(ConcurrencySlot {
+                    limiter: limiter.clone(),
+                    user_id,
+                    redis: Some(redis.clone()),
+                });
+            }
+            Ok(false) => return None,
+            Err(e) => log::warn!("concurrency slot failed for user {}: {}", user_id, e),
+        }
+    }
+    limiter.take_local(user_id, max).then(|| ConcurrencySlot {
+        limiter: limiter.clone(),
+        user_id,
+        redis: None,
+    })
+}
+
+/// Count a slot for `user_id` in Redis, giving it back straight away if
+/// that went over `max`.
+async fn take_in_redis(
+    redis: &ConnectionManager,
+    user_id: i64,
+    max: u32,
+) -> redis::RedisResult<bool> {
+    let key = slot_key(user_id);
+    let mut conn = redis.clone();
+    let (count,): (u32,) = redis::pipe()
+        .atomic()
+        .incr(&key, 1)
+        .expire(&key, SLOT_TTL_SECS)
+        .ignore()
+        .query_async(&mut conn)
+        .await?;
+    if count > max {
+        conn.decr::<_, _, i64>(&key, 1).await?;
+        return Ok(false);
+    }
+    Ok(true)
+}
+
+/// A reading in flight; dropping it frees the slot.
+pub struct ConcurrencySlot {
+    limiter: Arc<ConcurrencyLimiter>,
+    user_id: i64,
+    /// Set when the slot was counted in Redis.
+    redis: Option<ConnectionManager>,
+}
+
+impl Drop for ConcurrencySlot {
+    fn drop(&mut self) {
+        let Some(mut conn) = self.redis.take() else {
+            self.limiter.release_local(self.user_id);
+            return;
+        };
+        let key = slot_key(self.user_id);
+        actix_web::rt::spawn(async move {
+            if let Err(e) = conn.decr::<_, _, i64>(&key, 1).await {
+                log::warn!("failed to release concurrency slot {}: {}", key, e);
+            }
+        });
+    }
+}
+
+/// Enforce the `AppState`'s `ConcurrencyLimiter` on authenticated reading
+/// requests; anything else passes straight through.
+pub async fn limit_concurrent_readings(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let Some(state) = req.app_data::<web::Data<AppState>>().cloned() else {
+        return next.call(req).await;
+    };
+    let limited = req.method() == Method::POST
+        && req
+            .match_pattern()
+            .is_some_and(|pattern| LIMITED_ROUTES.contains(&pattern.as_str()));
+    if !limited || !req.headers().contains_key(AUTHORIZATION) {
+        return next.call(req).await;
+    }
+    let Ok(UserTier { user, tier }) = req.extract::<UserTier>().await else {
+        return next.call(req).await;
+    };
+    let Some(max) = state.concurrency_limiter().limit_for(tier) else {
+        return next.call(req).await;
+    };
+    let Some(slot) = acquire_slot(
+        state.concurrency_limiter(),
+        user.user_id,
+        max,
+        state.redis(),
+    )
+    .await
+    else {
+        log::info!(
+            "user {} already has {} reading(s) in flight",
+            user.user_id,
+            max
+        );
+        return Err(ApiError::TooManyRequests("Too many readings in progress".to_string()).into());
+    };
+    let res = next.call(req).await;
+    drop(slot);
+    res
+}
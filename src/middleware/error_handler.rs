@@ -1,8 +1,327 @@
-//! Error handling helpers for Actix responses. Keep minimal for now.
+//! Error handling helpers for Actix responses.
+//!
+//! Every API error is rendered as `{ "error": { "code": ..., "message": ... } }`
+//! where `code` is a stable machine-readable string. `localize_errors` adds
+//! `request_id` on the way out.
 
-use actix_web::HttpResponse;
+use actix_web::error::JsonPayloadError;
+use actix_web::http::header::RETRY_AFTER;
+use actix_web::{HttpRequest, HttpResponse, ResponseError, http::StatusCode};
+use serde_json::json;
+use thiserror::Error;
+
+use super::i18n::ErrorBody;
+use crate::db::DbError;
+use crate::services::{
+    CreditError, CursorError, LlmError, PaymentError, PipelineError, QUEUE_RETRY_AFTER_SECS,
+    QueueError, ReadingError,
+};
+
+#[derive(Debug, Error)]
+pub enum ApiError {
+    #[error("{0}")]
+    BadRequest(String),
+    #[error("{0}")]
+    Unauthorized(String),
+    #[error("{0}")]
+    PaymentRequired(String),
+    #[error("{0}")]
+    Forbidden(String),
+    #[error("{0}")]
+    NotFound(String),
+    #[error("{0}")]
+    Conflict(String),
+    #[error("{0}")]
+    UnsupportedMediaType(String),
+    #[error("{0}")]
+    UnprocessableEntity(String),
+    #[error("{0}")]
+    TooManyRequests(String),
+    #[error("{0}")]
+    BadGateway(String),
+    #[error("{0}")]
+    ServiceUnavailable(String),
+    /// A 503 that tells the client when to come back (`Retry-After`).
+    #[error("{message}")]
+    Overloaded {
+        message: String,
+        retry_after_secs: u64,
+    },
+    #[error("{0}")]
+    GatewayTimeout(String),
+    #[error("{0}")]
+    Internal(String),
+}
+
+impl ApiError {
+    /// Stable machine-readable error code.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::BadRequest(_) => "bad_request",
+            ApiError::Unauthorized(_) => "unauthorized",
+            ApiError::PaymentRequired(_) => "payment_required",
+            ApiError::Forbidden(_) => "forbidden",
+            ApiError::NotFound(_) => "not_found",
+            ApiError::Conflict(_) => "conflict",
+            ApiError::UnsupportedMediaType(_) => "unsupported_media_type",
+            ApiError::UnprocessableEntity(_) => "unprocessable_entity",
+            ApiError::TooManyRequests(_) => "rate_limited",
+            ApiError::BadGateway(_) => "bad_gateway",
+            ApiError::ServiceUnavailable(_) | ApiError::Overloaded { .. } => "service_unavailable",
+            ApiError::GatewayTimeout(_) => "timeout",
+            ApiError::Internal(_) => "internal_error",
+        }
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ApiError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            ApiError::PaymentRequired(_) => StatusCode::PAYMENT_REQUIRED,
+            ApiError::Forbidden(_) => StatusCode::FORBIDDEN,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::Conflict(_) => StatusCode::CONFLICT,
+            ApiError::UnsupportedMediaType(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            ApiError::UnprocessableEntity(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::TooManyRequests(_) => StatusCode::TOO_MANY_REQUESTS,
+            ApiError::BadGateway(_) => StatusCode::BAD_GATEWAY,
+            ApiError::ServiceUnavailable(_) | ApiError::Overloaded { .. } => {
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+            ApiError::GatewayTimeout(_) => StatusCode::GATEWAY_TIMEOUT,
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut builder = HttpResponse::build(self.status_code());
+        if let ApiError::Overloaded {
+            retry_after_secs, ..
+        } = self
+        {
+            builder.insert_header((RETRY_AFTER, *retry_after_secs));
+        }
+        let mut response = builder.json(json!({
+            "error": {
+                "code": self.code(),
+                "message": self.to_string(),
+            }
+        }));
+        // Lets `localize_errors` re-render the message per Accept-Language.
+        response.extensions_mut().insert(ErrorBody {
+            code: self.code(),
+            message: self.to_string(),
+        });
+        response
+    }
+}
+
+impl From<QueueError> for ApiError {
+    fn from(err: QueueError) -> Self {
+        match err {
+            QueueError::NotFound => ApiError::NotFound(err.to_string()),
+            QueueError::NotOwner => ApiError::Forbidden(err.to_string()),
+            QueueError::Conflict(_) => ApiError::Conflict(err.to_string()),
+            QueueError::Saturated { .. } => ApiError::Overloaded {
+                message: err.to_string(),
+                retry_after_secs: QUEUE_RETRY_AFTER_SECS,
+            },
+            QueueError::Redis(_) | QueueError::Corrupt(_) => ApiError::Internal(err.to_string()),
+        }
+    }
+}
+
+impl From<CreditError> for ApiError {
+    fn from(err: CreditError) -> Self {
+        match err {
+            CreditError::Insufficient => ApiError::PaymentRequired(err.to_string()),
+            CreditError::UserNotFound => ApiError::NotFound(err.to_string()),
+            CreditError::OutOfRange => ApiError::UnprocessableEntity(err.to_string()),
+            CreditError::Db(e) => DbError::from(e).into(),
+        }
+    }
+}
+
+impl From<CursorError> for ApiError {
+    fn from(err: CursorError) -> Self {
+        ApiError::BadRequest(err.to_string())
+    }
+}
+
+/// `JsonConfig` error handler. Request bodies deny unknown fields, so a
+/// typo like `questoin` gets a 400 naming the field instead of a confusing
+/// error about the one it was meant to be, and a body sent as anything but
+/// `application/json` gets a 415 saying so. Other payload errors (oversized
+/// body) keep actix's response.
+pub fn json_error(err: JsonPayloadError, _req: &HttpRequest) -> actix_web::Error {
+    match err {
+        JsonPayloadError::Deserialize(e) => {
+            ApiError::BadRequest(format!("Invalid request body: {}", e)).into()
+        }
+        JsonPayloadError::ContentType => {
+            ApiError::UnsupportedMediaType("Content-Type must be application/json".to_string())
+                .into()
+        }
+        err => err.into(),
+    }
+}
+
+/// Shown when the provider's safety filter withholds an answer.
+const CONTENT_FILTERED: &str = "MiMi can't give a reading for this question. Please rephrase it.";
+
+impl From<LlmError> for ApiError {
+    fn from(err: LlmError) -> Self {
+        log::error!("LLM call failed: {}", err);
+        match err {
+            LlmError::Timeout | LlmError::DeadlineExceeded(_) => {
+                ApiError::GatewayTimeout("The reader took too long to respond".to_string())
+            }
+            LlmError::Network(_) => ApiError::BadGateway("The reader is unreachable".to_string()),
+            LlmError::ContentFiltered => {
+                ApiError::UnprocessableEntity(CONTENT_FILTERED.to_string())
+            }
+            _ => ApiError::Internal("Failed to generate response".to_string()),
+        }
+    }
+}
+
+impl From<PipelineError> for ApiError {
+    fn from(err: PipelineError) -> Self {
+        ReadingError::from(err).into()
+    }
+}
+
+impl From<ReadingError> for ApiError {
+    fn from(err: ReadingError) -> Self {
+        match err {
+            ReadingError::Filtered(reason) => ApiError::UnprocessableEntity(reason),
+            ReadingError::InsufficientCredits => ApiError::PaymentRequired(err.to_string()),
+            ReadingError::QuotaExceeded => ApiError::TooManyRequests(err.to_string()),
+            ReadingError::GenerationFailed(LlmError::Timeout | LlmError::DeadlineExceeded(_)) => {
+                ApiError::GatewayTimeout("The reader took too long to respond".to_string())
+            }
+            ReadingError::GenerationFailed(LlmError::Network(_)) => {
+                ApiError::BadGateway("The reader is unreachable".to_string())
+            }
+            ReadingError::GenerationFailed(LlmError::ContentFiltered) => {
+                ApiError::UnprocessableEntity(CONTENT_FILTERED.to_string())
+            }
+            ReadingError::GenerationFailed(_)
+            | ReadingError::AnalysisFailed(_)
+            | ReadingError::ValidationFailed(_) => {
+                log::error!("{}", err);
+                ApiError::BadGateway(
+                    "The reader could not produce a valid reading; please try again".to_string(),
+                )
+            }
+            ReadingError::DrawFailed(_) => {
+                log::error!("{}", err);
+                ApiError::Internal("Failed to generate response".to_string())
+            }
+        }
+    }
+}
+
+impl From<PaymentError> for ApiError {
+    fn from(err: PaymentError) -> Self {
+        match err {
+            PaymentError::InvalidSignature | PaymentError::InvalidPayload(_) => {
+                ApiError::BadRequest(err.to_string())
+            }
+            PaymentError::NotConfigured => ApiError::ServiceUnavailable(err.to_string()),
+            PaymentError::NotFound => ApiError::NotFound(err.to_string()),
+            PaymentError::NotRefundable(_) | PaymentError::CreditsSpent => {
+                ApiError::Conflict(err.to_string())
+            }
+            PaymentError::Credit(e) => e.into(),
+            PaymentError::Db(e) => e.into(),
+            PaymentError::Provider(_) => {
+                log::error!("payment provider error: {}", err);
+                ApiError::BadGateway("Payment provider error".to_string())
+            }
+        }
+    }
+}
+
+impl From<DbError> for ApiError {
+    fn from(err: DbError) -> Self {
+        match err {
+            DbError::NotFound => ApiError::NotFound(err.to_string()),
+            DbError::UniqueViolation { ref constraint } => {
+                log::info!(
+                    "unique violation on {}",
+                    constraint.as_deref().unwrap_or("?")
+                );
+                ApiError::Conflict(err.to_string())
+            }
+            DbError::ForeignKeyViolation { ref constraint } => {
+                log::info!(
+                    "foreign key violation on {}",
+                    constraint.as_deref().unwrap_or("?")
+                );
+                ApiError::UnprocessableEntity(err.to_string())
+            }
+            DbError::Timeout => ApiError::GatewayTimeout(err.to_string()),
+            DbError::Other(e) => {
+                log::error!("database error: {}", e);
+                ApiError::Internal("Database error".to_string())
+            }
+        }
+    }
+}
 
 /// Convert an internal error message into a JSON HTTP response.
 pub fn internal_error(msg: &str) -> HttpResponse {
-    HttpResponse::InternalServerError().body(msg.to_string())
+    ApiError::Internal(msg.to_string()).error_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reading_errors_map_to_consistent_statuses() {
+        let cases = [
+            (ReadingError::Filtered("off topic".to_string()), 422),
+            (ReadingError::InsufficientCredits, 402),
+            (ReadingError::QuotaExceeded, 429),
+            (ReadingError::AnalysisFailed("bad json".to_string()), 502),
+            (
+                ReadingError::ValidationFailed("wrong cards".to_string()),
+                502,
+            ),
+            (ReadingError::GenerationFailed(LlmError::NotConfigured), 502),
+            (ReadingError::GenerationFailed(LlmError::Timeout), 504),
+            (
+                ReadingError::GenerationFailed(LlmError::Network("refused".to_string())),
+                502,
+            ),
+            (
+                ReadingError::GenerationFailed(LlmError::ContentFiltered),
+                422,
+            ),
+            (ReadingError::DrawFailed("empty deck".to_string()), 500),
+        ];
+        for (err, status) in cases {
+            let label = format!("{:?}", err);
+            assert_eq!(
+                ApiError::from(err).status_code().as_u16(),
+                status,
+                "{}",
+                label
+            );
+        }
+    }
+
+    #[test]
+    fn a_refusal_keeps_its_reason() {
+        let err = ApiError::from(ReadingError::Filtered("Please ask about tarot".to_string()));
+        assert!(
+            matches!(&err, ApiError::UnprocessableEntity(m) if m == "Please ask about tarot"),
+            "{:?}",
+            err
+        );
+    }
 }
@@ -0,0 +1,322 @@
+//! The scheme and host a client actually used, for deployments behind a
+//! TLS-terminating proxy. `X-Forwarded-Proto` and `X-Forwarded-Host` are
+//! only believed when the connection comes from one of `TRUSTED_PROXIES`
+//! (IPs or CIDR ranges); from anyone else they are ignored, so a client
+//! cannot claim https. With `FORCE_HTTPS` plain http requests are
+//! redirected (308) to https.
+//!
+//! Handlers take the result as a `RequestOrigin`.
+
+use std::future::{Ready, ready};
+use std::net::IpAddr;
+
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{AppConfig, Payload, ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HOST, HeaderMap, HeaderName, LOCATION};
+use actix_web::middleware::Next;
+use actix_web::{Error, FromRequest, HttpMessage, HttpRequest, HttpResponse, web};
+
+use crate::config::Config;
+use crate::state::AppState;
+
+pub const X_FORWARDED_PROTO: HeaderName = HeaderName::from_static("x-forwarded-proto");
+pub const X_FORWARDED_HOST: HeaderName = HeaderName::from_static("x-forwarded-host");
+
+/// Where the client sent the request: scheme and host as it saw them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestOrigin {
+    pub scheme: String,
+    pub host: String,
+}
+
+impl RequestOrigin {
+    pub fn is_https(&self) -> bool {
+        self.scheme == "https"
+    }
+
+    /// Absolute URL of `path` (which starts with `/`) on this origin.
+    pub fn url(&self, path: &str) -> String {
+        format!("{}://{}{}", self.scheme, self.host, path)
+    }
+}
+
+/// Proxies whose forwarded headers are believed (`TRUSTED_PROXIES`).
+#[derive(Debug, Clone, Default)]
+pub struct TrustedProxies {
+    /// Network address and prefix length.
+    ranges: Vec<(IpAddr, u8)>,
+}
+
+impl TrustedProxies {
+    /// Parse `TRUSTED_PROXIES` entries; malformed ones are skipped.
+    pub fn from_config(config: &Config) -> Self {
+        let ranges = config
+            .trusted_proxies
+            .iter()
+            .filter_map(|entry| {
+                let range = parse_range(entry);
+                if range.is_none() {
+                    log::warn!("ignoring malformed TRUSTED_PROXIES entry '{}'", entry);
+                }
+                range
+            })
+            .collect();
+        TrustedProxies { ranges }
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let ip = ip.to_canonical();
+        self.ranges
+            .iter()
+            .any(|&(network, prefix)| in_range(ip, network, prefix))
+    }
+
+    /// The origin of a request that arrived from `peer` over
+    /// `connection_scheme`. Forwarded headers count only from a trusted
+    /// `peer`; the first entry of a comma-separated list wins.
+    pub fn resolve(
+        &self,
+        peer: Option<IpAddr>,
+        connection_scheme: &str,
+        headers: &HeaderMap,
+    ) -> RequestOrigin {
+        let trusted = peer.is_some_and(|ip| self.contains(ip));
+        let forwarded = |name: &HeaderName| {
+            headers
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.split(',').next())
+                .map(str::trim)
+                .filter(|v| !v.is_empty() && trusted)
+        };
+        let scheme = forwarded(&X_FORWARDED_PROTO)
+            .map(str::to_ascii_lowercase)
+            .filter(|s| s == "http" || s == "https")
+            .unwrap_or_else(|| connection_scheme.to_string());
+        let host = forwarded(&X_FORWARDED_HOST)
+            .or_else(|| headers.get(HOST).and_then(|v| v.to_str().ok()))
+            .unwrap_or_default()
+            .to_string();
+        RequestOrigin { scheme, host }
+    }
+}
+
+fn connection_scheme(config: &AppConfig) -> &'static str {
+    if config.secure() { "https" } else { "http" }
+}
+
+/// `ip` or `ip/prefix`.
+fn parse_range(entry: &str) -> Option<(IpAddr, u8)> {
+    let (ip, prefix) = match entry.split_once('/') {
+        Some((ip, prefix)) => (ip, Some(prefix)),
+        None => (entry, None),
+    };
+    let ip: IpAddr = ip.trim().parse().ok()?;
+    let ip = ip.to_canonical();
+    let max = if ip.is_ipv4() { 32 } else { 128 };
+    let prefix = match prefix {
+        Some(prefix) => prefix.trim().parse().ok().filter(|p| *p <= max)?,
+        None => max,
+    };
+    Some((ip, prefix))
+}
+
+fn in_range(ip: IpAddr, network: IpAddr, prefix: u8) -> bool {
+    match (ip, network) {
+        (IpAddr::V4(ip), IpAddr::V4(network)) => {
+            let mask = u32::MAX.checked_shl(32 - u32::from(prefix)).unwrap_or(0);
+            u32::from(ip) & mask == u32::from(network) & mask
+        }
+        (IpAddr::V6(ip), IpAddr::V6(network)) => {
+            let mask = u128::MAX.checked_shl(128 - u32::from(prefix)).unwrap_or(0);
+            u128::from(ip) & mask == u128::from(network) & mask
+        }
+        _ => false,
+    }
+}
+
+/// Resolve the request's origin into the extensions and, with
+/// `FORCE_HTTPS`, redirect plain http to https.
+pub async fn resolve_origin(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let Some(state) = req.app_data::<web::Data<AppState>>().cloned() else {
+        return next
+            .call(req)
+            .await
+            .map(ServiceResponse::map_into_left_body);
+    };
+    let origin = state.trusted_proxies().resolve(
+        req.peer_addr().map(|addr| addr.ip()),
+        connection_scheme(req.app_config()),
+        req.headers(),
+    );
+    if state.config().force_https && !origin.is_https() && !origin.host.is_empty() {
+        let path = req
+            .uri()
+            .path_and_query()
+            .map_or("/", |p| p.as_str())
+            .to_string();
+        let location = RequestOrigin {
+            scheme: "https".to_string(),
+            ..origin
+        }
+        .url(&path);
+        let response = HttpResponse::PermanentRedirect()
+            .insert_header((LOCATION, location))
+            .finish();
+        return Ok(req.into_response(response).map_into_right_body());
+    }
+    req.extensions_mut().insert(origin);
+    next.call(req)
+        .await
+        .map(ServiceResponse::map_into_left_body)
+}
+
+impl FromRequest for RequestOrigin {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    /// The origin `resolve_origin` stored; without the middleware, the
+    /// connection's own scheme and `Host`, ignoring forwarded headers.
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let origin = req
+            .extensions()
+            .get::<RequestOrigin>()
+            .cloned()
+            .unwrap_or_else(|| {
+                TrustedProxies::default().resolve(
+                    None,
+                    connection_scheme(req.app_config()),
+                    req.headers(),
+                )
+            });
+        ready(Ok(origin))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::App;
+    use actix_web::http::header::HeaderValue;
+    use actix_web::middleware::from_fn;
+    use actix_web::test::{TestRequest, call_service, init_service, read_body};
+
+    use super::*;
+
+    fn config(trusted: &[&str]) -> Config {
+        let mut config =
+            Config::from_vars(|key| (key == "JWT_SECRET").then(|| "s".to_string())).unwrap();
+        config.trusted_proxies = trusted.iter().map(|s| s.to_string()).collect();
+        config
+    }
+
+    fn headers(pairs: &[(HeaderName, &'static str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.append(name.clone(), HeaderValue::from_static(value));
+        }
+        headers
+    }
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn trusted_ranges_match_addresses_and_skip_bad_entries() {
+        let proxies = TrustedProxies::from_config(&config(&[
+            "10.0.0.0/8",
+            "192.168.1.7",
+            "fd00::/8",
+            "nope",
+            "10.0.0.0/33",
+        ]));
+        assert_eq!(proxies.ranges.len(), 3);
+        assert!(proxies.contains(ip("10.20.30.40")));
+        assert!(!proxies.contains(ip("11.0.0.1")));
+        assert!(proxies.contains(ip("192.168.1.7")));
+        assert!(!proxies.contains(ip("192.168.1.8")));
+        assert!(proxies.contains(ip("fd12::1")));
+        assert!(!proxies.contains(ip("fe80::1")));
+        // An IPv4 peer seen over an IPv6 socket.
+        assert!(proxies.contains(ip("::ffff:10.0.0.1")));
+        assert!(!TrustedProxies::default().contains(ip("10.0.0.1")));
+    }
+
+    #[test]
+    fn forwarded_headers_count_only_from_a_trusted_peer() {
+        let proxies = TrustedProxies::from_config(&config(&["10.0.0.0/8"]));
+        let forwarded = headers(&[
+            (HOST, "internal:8080"),
+            (X_FORWARDED_PROTO, "HTTPS, http"),
+            (X_FORWARDED_HOST, "mimi.example"),
+        ]);
+
+        let origin = proxies.resolve(Some(ip("10.0.0.5")), "http", &forwarded);
+        assert_eq!(
+            origin,
+            RequestOrigin {
+                scheme: "https".to_string(),
+                host: "mimi.example".to_string()
+            }
+        );
+        assert_eq!(origin.url("/shared/t"), "https://mimi.example/shared/t");
+
+        // A client spoofing the headers, or no known peer at all.
+        for peer in [Some(ip("203.0.113.9")), None] {
+            let origin = proxies.resolve(peer, "http", &forwarded);
+            assert_eq!(origin.scheme, "http");
+            assert_eq!(origin.host, "internal:8080");
+        }
+
+        // Anything but http or https falls back to the connection's scheme.
+        let odd = headers(&[(HOST, "internal"), (X_FORWARDED_PROTO, "gopher")]);
+        let origin = proxies.resolve(Some(ip("10.0.0.5")), "http", &odd);
+        assert_eq!(origin.scheme, "http");
+        assert_eq!(origin.host, "internal");
+    }
+
+    async fn echo_origin(origin: RequestOrigin) -> HttpResponse {
+        HttpResponse::Ok().body(origin.url("/"))
+    }
+
+    #[actix_web::test]
+    async fn plain_http_is_redirected_when_https_is_forced() {
+        let mut config = config(&["10.0.0.0/8"]);
+        config.force_https = true;
+        let app = init_service(
+            App::new()
+                .app_data(web::Data::new(AppState::new(config, None, None)))
+                .wrap(from_fn(resolve_origin))
+                .route("/origin", web::get().to(echo_origin)),
+        )
+        .await;
+
+        // Spoofed from outside: still plain http, so redirected.
+        let req = TestRequest::get()
+            .uri("/origin?x=1")
+            .peer_addr("203.0.113.9:5000".parse().unwrap())
+            .insert_header((HOST, "mimi.example"))
+            .insert_header((X_FORWARDED_PROTO, "https"))
+            .to_request();
+        let resp = call_service(&app, req).await;
+        assert_eq!(resp.status(), 308);
+        assert_eq!(
+            resp.headers().get(LOCATION).unwrap(),
+            "https://mimi.example/origin?x=1"
+        );
+
+        let req = TestRequest::get()
+            .uri("/origin")
+            .peer_addr("10.0.0.5:5000".parse().unwrap())
+            .insert_header((HOST, "internal"))
+            .insert_header((X_FORWARDED_PROTO, "https"))
+            .insert_header((X_FORWARDED_HOST, "mimi.example"))
+            .to_request();
+        let resp = call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+        assert_eq!(read_body(resp).await, "https://mimi.example/");
+    }
+}
@@ -0,0 +1,341 @@
+//! Localized error messages. `ApiError` responses are rendered in English;
+//! the `localize_errors` middleware swaps the `message` for the caller's
+//! `Accept-Language` (Thai by default) and adds the `request_id`. `code` is
+//! never changed.
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::error::InternalError;
+use actix_web::http::header::{ACCEPT_LANGUAGE, CONTENT_LENGTH, CONTENT_TYPE};
+use actix_web::middleware::Next;
+use actix_web::{Error, HttpMessage, HttpResponse};
+use serde_json::json;
+
+use super::request_id::RequestId;
+
+/// Code and English message of an `ApiError` response, stashed in the
+/// response extensions for `localize_errors`.
+#[derive(Debug, Clone)]
+pub struct ErrorBody {
+    pub code: &'static str,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Locale {
+    Thai,
+    English,
+}
+
+impl Locale {
+    /// Pick the first of th/en in `Accept-Language` preference order,
+    /// falling back to Thai.
+    pub fn from_accept_language(header: Option<&str>) -> Locale {
+        let Some(header) = header else {
+            return Locale::Thai;
+        };
+        let mut ranges: Vec<(&str, f32)> = header
+            .split(',')
+            .filter_map(|part| {
+                let mut pieces = part.trim().split(';');
+                let tag = pieces.next()?.trim();
+                let q = pieces
+                    .find_map(|p| p.trim().strip_prefix("q="))
+                    .and_then(|q| q.parse().ok())
+                    .unwrap_or(1.0);
+                Some((tag, q))
+            })
+            .collect();
+        ranges.sort_by(|a, b| b.1.total_cmp(&a.1));
+        ranges
+            .iter()
+            .filter(|(_, q)| *q > 0.0)
+            .find_map(|(tag, _)| {
+                let primary = tag.split('-').next().unwrap_or("").to_ascii_lowercase();
+                match primary.as_str() {
+                    "th" => Some(Locale::Thai),
+                    "en" => Some(Locale::English),
+                    _ => None,
+                }
+            })
+            .unwrap_or(Locale::Thai)
+    }
+}
+
+/// Thai translations of specific messages.
+const THAI_MESSAGES: &[(&str, &str)] = &[
+    (
+        "Failed to generate response",
+        "ไม่สามารถสร้างคำตอบได้ กรุณาลองใหม่อีกครั้ง",
+    ),
+    (
+        "The reader took too long to respond",
+        "หมอดูใช้เวลานานเกินไป กรุณาลองใหม่อีกครั้ง",
+    ),
+    ("The reader is unreachable", "ไม่สามารถติดต่อหมอดูได้ในขณะนี้"),
+    (
+        "The reader could not produce a valid reading; please try again",
+        "หมอดูไม่สามารถทำนายได้ในครั้งนี้ กรุณาลองใหม่อีกครั้ง",
+    ),
+    ("Question must not be empty", "กรุณาพิมพ์คำถาม"),
+    (
+        "Your question is a little unclear. Please add more detail so MiMi can give you a meaningful reading.",
+        "คำถามยังไม่ชัดเจน กรุณาเพิ่มรายละเอียดเพื่อให้มิมิทำนายได้ตรงยิ่งขึ้น",
+    ),
+    (
+        "This language is not supported yet. Please ask in a supported language.",
+        "ยังไม่รองรับภาษานี้ กรุณาถามด้วยภาษาที่รองรับ",
+    ),
+    (
+        "MiMi can't give a reading for this question. Please rephrase it.",
+        "มิมิไม่สามารถทำนายคำถามนี้ได้ กรุณาเรียบเรียงคำถามใหม่",
+    ),
+    ("Too many requests", "ใช้งานบ่อยเกินไป กรุณารอสักครู่แล้วลองใหม่"),
+    (
+        "Content-Type must be application/json",
+        "Content-Type ต้องเป็น application/json",
+    ),
+    (
+        "Too many readings in progress",
+        "มีคำทำนายที่กำลังดำเนินการอยู่มากเกินไป กรุณารอให้เสร็จก่อนแล้วลองใหม่",
+    ),
+    (
+        "Your account is suspended pending review",
+        "บัญชีของคุณถูกระงับชั่วคราวระหว่างรอการตรวจสอบ",
+    ),
+    (
+        "Upgrade your plan to use this spread",
+        "กรุณาอัปเกรดแพ็กเกจเพื่อใช้รูปแบบการเปิดไพ่นี้",
+    ),
+    ("Insufficient credits", "เครดิตไม่เพียงพอ"),
+    (
+        "This device has used its free readings; sign in to keep reading",
+        "อุปกรณ์นี้ใช้สิทธิ์ดูดวงฟรีครบแล้ว กรุณาเข้าสู่ระบบเพื่อดูดวงต่อ",
+    ),
+    (
+        "A valid X-Device-Id header is required",
+        "กรุณาระบุ X-Device-Id ที่ถูกต้อง",
+    ),
+    (
+        "Guest readings are unavailable",
+        "การดูดวงโดยไม่เข้าสู่ระบบไม่พร้อมใช้งาน",
+    ),
+    (
+        "This reading cannot be regenerated again",
+        "คำทำนายนี้สร้างใหม่ครบจำนวนครั้งแล้ว",
+    ),
+    ("Draft limit reached", "บันทึกคำถามไว้ครบจำนวนแล้ว"),
+    (
+        "A session takes at least one question",
+        "เซสชันต้องมีคำถามอย่างน้อยหนึ่งข้อ",
+    ),
+    ("Draft not found", "ไม่พบคำถามที่บันทึกไว้"),
+    ("Reading not found", "ไม่พบคำทำนาย"),
+    (
+        "This reading belongs to another user",
+        "คำทำนายนี้เป็นของผู้ใช้อื่น",
+    ),
+    ("Card not found", "ไม่พบไพ่"),
+    ("Credits are unavailable", "ระบบเครดิตไม่พร้อมใช้งาน"),
+    ("User not found", "ไม่พบผู้ใช้"),
+    ("Job not found", "ไม่พบงานดูดวง"),
+    ("Job belongs to another user", "งานนี้เป็นของผู้ใช้อื่น"),
+    ("Payment not found", "ไม่พบการชำระเงิน"),
+    ("Payment has not been completed", "การชำระเงินยังไม่เสร็จสมบูรณ์"),
+    (
+        "Payment has already been used for a reading",
+        "การชำระเงินนี้ถูกใช้ดูดวงไปแล้ว",
+    ),
+    (
+        "The credits bought with this payment have already been spent",
+        "เครดิตที่ซื้อด้วยการชำระเงินนี้ถูกใช้ไปแล้ว",
+    ),
+    ("Payments are unavailable", "ระบบชำระเงินไม่พร้อมใช้งาน"),
+    (
+        "The reading queue is full",
+        "คิวดูดวงเต็มแล้ว กรุณารอสักครู่แล้วลองใหม่",
+    ),
+    ("Payment provider error", "ผู้ให้บริการชำระเงินขัดข้อง"),
+    ("Missing webhook signature", "ไม่พบลายเซ็นของ webhook"),
+    ("Invalid webhook signature", "ลายเซ็นของ webhook ไม่ถูกต้อง"),
+    ("Malformed cursor", "cursor ไม่ถูกต้อง"),
+    ("Invalid cursor signature", "cursor ไม่ถูกต้อง"),
+    (
+        "limit must be between 1 and 50",
+        "limit ต้องอยู่ระหว่าง 1 ถึง 50",
+    ),
+    ("Invalid query string", "query string ไม่ถูกต้อง"),
+    ("lang must be th or en", "lang ต้องเป็น th หรือ en"),
+    ("Reading history is unavailable", "ประวัติการดูดวงไม่พร้อมใช้งาน"),
+    ("Reading queue is unavailable", "คิวดูดวงไม่พร้อมใช้งาน"),
+    ("Memory is unavailable", "ระบบความจำไม่พร้อมใช้งาน"),
+    ("Failed to clear memory", "ไม่สามารถล้างความจำได้"),
+    ("Database error", "ระบบฐานข้อมูลขัดข้อง"),
+    (
+        "Database query timed out",
+        "ระบบตอบสนองช้าเกินไป กรุณาลองใหม่อีกครั้ง",
+    ),
+    ("missing or invalid token", "กรุณาเข้าสู่ระบบใหม่อีกครั้ง"),
+    ("admin access required", "ต้องใช้สิทธิ์ผู้ดูแลระบบ"),
+];
+
+/// Thai translations of message prefixes whose remainder is a detail worth
+/// keeping (e.g. the unknown field in a request body).
+const THAI_PREFIXES: &[(&str, &str)] = &[
+    ("Invalid request body: ", "ข้อมูลในคำขอไม่ถูกต้อง: "),
+    (
+        "Too many questions for one session; the maximum is ",
+        "คำถามในหนึ่งเซสชันมากเกินไป สูงสุดได้ ",
+    ),
+    (
+        "Too many follow-ups for this reading; the maximum is ",
+        "คำถามต่อเนื่องของคำทำนายนี้มากเกินไป สูงสุดได้ ",
+    ),
+];
+
+/// Generic Thai message per error code, for messages without a translation.
+fn thai_for_code(code: &str) -> Option<&'static str> {
+    Some(match code {
+        "bad_request" => "คำขอไม่ถูกต้อง",
+        "unauthorized" => "กรุณาเข้าสู่ระบบ",
+        "payment_required" => "กรุณาชำระเงินหรือเติมเครดิต",
+        "forbidden" => "คุณไม่มีสิทธิ์ดำเนินการนี้",
+        "not_found" => "ไม่พบข้อมูลที่ต้องการ",
+        "conflict" => "ไม่สามารถดำเนินการได้ในสถานะปัจจุบัน",
+        "unprocessable_entity" => "ไม่สามารถประมวลผลคำขอได้",
+        "rate_limited" => "ใช้งานเกินจำนวนที่กำหนด กรุณาลองใหม่ภายหลัง",
+        "bad_gateway" => "บริการภายนอกขัดข้อง",
+        "service_unavailable" => "บริการไม่พร้อมใช้งานชั่วคราว",
+        "timeout" => "ระบบตอบสนองช้าเกินไป กรุณาลองใหม่อีกครั้ง",
+        "internal_error" => "เกิดข้อผิดพลาดภายในระบบ",
+        _ => return None,
+    })
+}
+
+/// The message for `code`/`message` in `locale`. English messages are the
+/// source strings and pass through unchanged.
+pub fn localize(code: &str, message: &str, locale: Locale) -> String {
+    match locale {
+        Locale::English => message.to_string(),
+        Locale::Thai => {
+            if let Some((_, th)) = THAI_MESSAGES.iter().find(|(en, _)| *en == message) {
+                return th.to_string();
+            }
+            if let Some(translated) = THAI_PREFIXES.iter().find_map(|(en, th)| {
+                message
+                    .strip_prefix(en)
+                    .map(|detail| format!("{}{}", th, detail))
+            }) {
+                return translated;
+            }
+            thai_for_code(code).unwrap_or(message).to_string()
+        }
+    }
+}
+
+/// Re-render `ApiError` responses with a localized message and the
+/// request's `RequestId` (when `assign_request_id` runs outside this).
+pub async fn localize_errors(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let locale = Locale::from_accept_language(
+        req.headers()
+            .get(ACCEPT_LANGUAGE)
+            .and_then(|v| v.to_str().ok()),
+    );
+    let request_id = req.extensions().get::<RequestId>().cloned();
+    let res = match next.call(req).await {
+        Ok(res) => res,
+        // Inner middleware failed before producing a response; localize its
+        // rendered error the same way.
+        Err(err) => {
+            let rendered = err.error_response();
+            return match localized(&rendered, locale, request_id.as_ref()) {
+                Some(body) => Err(InternalError::from_response(err, body).into()),
+                None => Err(err),
+            };
+        }
+    };
+    match localized(res.response(), locale, request_id.as_ref()) {
+        Some(body) => Ok(res.into_response(body).map_into_right_body()),
+        None => Ok(res.map_into_left_body()),
+    }
+}
+
+/// `response` re-rendered in `locale` with `request_id`, if it is an
+/// `ApiError` that needs it.
+fn localized<B>(
+    response: &HttpResponse<B>,
+    locale: Locale,
+    request_id: Option<&RequestId>,
+) -> Option<HttpResponse> {
+    if locale == Locale::English && request_id.is_none() {
+        return None;
+    }
+    let error = response.extensions().get::<ErrorBody>().cloned()?;
+    let mut body = json!({
+        "code": error.code,
+        "message": localize(error.code, &error.message, locale),
+    });
+    if let Some(RequestId(id)) = request_id {
+        body["request_id"] = json!(id);
+    }
+    let mut localized = HttpResponse::build(response.status());
+    // Keep headers such as `Retry-After`; the body headers are rewritten.
+    for (name, value) in response.headers() {
+        if name != CONTENT_TYPE && name != CONTENT_LENGTH {
+            localized.append_header((name.clone(), value.clone()));
+        }
+    }
+    Some(localized.json(json!({ "error": body })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picks_the_preferred_supported_language() {
+        assert_eq!(Locale::from_accept_language(None), Locale::Thai);
+        assert_eq!(Locale::from_accept_language(Some("en-US")), Locale::English);
+        assert_eq!(
+            Locale::from_accept_language(Some("fr, en;q=0.8, th;q=0.5")),
+            Locale::English
+        );
+        assert_eq!(
+            Locale::from_accept_language(Some("en;q=0.2, th-TH;q=0.9")),
+            Locale::Thai
+        );
+        assert_eq!(
+            Locale::from_accept_language(Some("en;q=0, de")),
+            Locale::Thai
+        );
+    }
+
+    #[test]
+    fn translates_known_messages_prefixes_and_codes() {
+        assert_eq!(
+            localize("not_found", "Reading not found", Locale::English),
+            "Reading not found"
+        );
+        let (en, th) = THAI_MESSAGES[0];
+        assert_eq!(localize("bad_request", en, Locale::Thai), th);
+        assert_eq!(
+            localize(
+                "bad_request",
+                "Invalid request body: missing field",
+                Locale::Thai
+            ),
+            "ข้อมูลในคำขอไม่ถูกต้อง: missing field"
+        );
+        assert_eq!(
+            localize("forbidden", "No translation for this one", Locale::Thai),
+            "คุณไม่มีสิทธิ์ดำเนินการนี้"
+        );
+        assert_eq!(
+            localize("teapot", "No translation for this one", Locale::Thai),
+            "No translation for this one"
+        );
+    }
+}
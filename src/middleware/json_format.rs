@@ -0,0 +1,99 @@
+//! `?pretty=true`: indented JSON for developers reading responses in a
+//! browser. Only honoured with `DEV_MODE`; in production responses stay
+//! compact whatever the query says.
+
+use std::future::{Ready, ready};
+
+use actix_web::http::header::ContentType;
+use actix_web::{
+    FromRequest, HttpRequest, HttpResponse, HttpResponseBuilder, ResponseError, dev::Payload, web,
+};
+use serde::{Deserialize, Serialize};
+
+use super::error_handler::ApiError;
+use crate::state::AppState;
+
+#[derive(Debug, Deserialize)]
+struct PrettyQuery {
+    pretty: Option<bool>,
+}
+
+/// How a handler serializes its JSON body.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct JsonFormat {
+    pub pretty: bool,
+}
+
+impl JsonFormat {
+    /// Pretty only when `dev_mode` is on and the query has `pretty=true`.
+    pub fn from_query(query: &str, dev_mode: bool) -> Self {
+        let pretty = dev_mode
+            && web::Query::<PrettyQuery>::from_query(query).is_ok_and(|q| q.pretty == Some(true));
+        JsonFormat { pretty }
+    }
+
+    /// Finish `response` with `body` as its JSON.
+    pub fn respond<T: Serialize>(
+        &self,
+        mut response: HttpResponseBuilder,
+        body: &T,
+    ) -> HttpResponse {
+        if !self.pretty {
+            return response.json(body);
+        }
+        match serde_json::to_string_pretty(body) {
+            Ok(json) => response.content_type(ContentType::json()).body(json),
+            Err(e) => ApiError::Internal(e.to_string()).error_response(),
+        }
+    }
+}
+
+impl FromRequest for JsonFormat {
+    type Error = ApiError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let dev_mode = req
+            .app_data::<web::Data<AppState>>()
+            .is_some_and(|s| s.config().dev_mode);
+        ready(Ok(JsonFormat::from_query(req.query_string(), dev_mode)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::body::to_bytes;
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn pretty_needs_dev_mode_and_the_query() {
+        assert!(JsonFormat::from_query("pretty=true", true).pretty);
+        assert!(JsonFormat::from_query("limit=5&pretty=true", true).pretty);
+        assert!(!JsonFormat::from_query("pretty=true", false).pretty);
+        for query in ["", "pretty=false", "pretty=yes", "pretty"] {
+            assert!(!JsonFormat::from_query(query, true).pretty, "{}", query);
+        }
+    }
+
+    #[actix_web::test]
+    async fn pretty_bodies_are_indented_and_compact_ones_are_not() {
+        let body = json!({ "answer": "Yes", "cards": [1, 2] });
+        for (pretty, multiline) in [(true, true), (false, false)] {
+            let resp = JsonFormat { pretty }.respond(HttpResponse::Ok(), &body);
+            assert_eq!(resp.status(), 200);
+            assert_eq!(
+                resp.headers().get("content-type").unwrap(),
+                "application/json"
+            );
+            let bytes = to_bytes(resp.into_body()).await.unwrap();
+            let text = std::str::from_utf8(&bytes).unwrap();
+            assert_eq!(text.contains('\n'), multiline, "{}", text);
+            assert_eq!(
+                serde_json::from_str::<serde_json::Value>(text).unwrap(),
+                body
+            );
+        }
+    }
+}
@@ -1,9 +1,31 @@
 //! Middleware module group for the backend.
 pub mod auth;
+pub mod body_log;
+pub mod concurrency;
 pub mod rate_limit;
+pub mod request_id;
+pub mod suspension;
 pub mod error_handler;
+pub mod forwarded;
+pub mod i18n;
+pub mod json_format;
+pub mod pagination;
+pub mod panic;
+pub mod tier;
+pub mod timeout;
 
 // Re-export commonly used middleware pieces for convenience.
 pub use auth::*;
+pub use body_log::*;
+pub use concurrency::*;
 pub use rate_limit::*;
+pub use request_id::*;
+pub use suspension::*;
 pub use error_handler::*;
+pub use forwarded::*;
+pub use i18n::*;
+pub use json_format::*;
+pub use pagination::*;
+pub use panic::*;
+pub use tier::*;
+pub use timeout::*;
@@ -0,0 +1,155 @@
+//! The `Pagination` extractor: `limit` and signed `cursor` query params,
+//! validated before the handler runs, for every keyset-paginated list.
+
+use std::future::{Ready, ready};
+
+use actix_web::{FromRequest, HttpRequest, dev::Payload, web};
+use serde::Deserialize;
+
+use super::error_handler::ApiError;
+use crate::services::{decode_cursor, encode_cursor};
+use crate::state::AppState;
+
+pub const DEFAULT_PAGE_SIZE: i64 = 20;
+pub const MAX_PAGE_SIZE: i64 = 50;
+
+/// Raw query; `limit` stays a string so a bad value gets our 400.
+#[derive(Debug, Deserialize)]
+struct PageQuery {
+    limit: Option<String>,
+    cursor: Option<String>,
+}
+
+/// A page request. `limit` is within 1..=`MAX_PAGE_SIZE` (default
+/// `DEFAULT_PAGE_SIZE`); `before_id` is the verified position from `cursor`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pagination {
+    pub limit: i64,
+    pub before_id: Option<i64>,
+}
+
+impl Pagination {
+    /// Validate raw `limit` and `cursor` values.
+    pub fn parse(
+        limit: Option<&str>,
+        cursor: Option<&str>,
+        secret: &str,
+    ) -> Result<Self, ApiError> {
+        let limit = match limit.map(str::trim).filter(|l| !l.is_empty()) {
+            None => DEFAULT_PAGE_SIZE,
+            Some(raw) => raw
+                .parse()
+                .ok()
+                .filter(|limit| (1..=MAX_PAGE_SIZE).contains(limit))
+                .ok_or_else(|| {
+                    ApiError::BadRequest(format!("limit must be between 1 and {}", MAX_PAGE_SIZE))
+                })?,
+        };
+        let before_id = cursor
+            .filter(|c| !c.is_empty())
+            .map(|c| decode_cursor(c, secret))
+            .transpose()?;
+        Ok(Pagination { limit, before_id })
+    }
+
+    /// The cursor for the page after `items`, or `None` when this page was
+    /// short, so there is nothing after it.
+    pub fn next_cursor<T>(
+        &self,
+        items: &[T],
+        id: impl Fn(&T) -> i64,
+        secret: &str,
+    ) -> Option<String> {
+        match items.last() {
+            Some(last) if items.len() as i64 == self.limit => Some(encode_cursor(id(last), secret)),
+            _ => None,
+        }
+    }
+}
+
+impl FromRequest for Pagination {
+    type Error = ApiError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let secret = req
+            .app_data::<web::Data<AppState>>()
+            .map(|s| s.config().cursor_secret.clone())
+            .unwrap_or_default();
+        ready(
+            web::Query::<PageQuery>::from_query(req.query_string())
+                .map_err(|_| ApiError::BadRequest("Invalid query string".to_string()))
+                .and_then(|query| {
+                    Pagination::parse(query.limit.as_deref(), query.cursor.as_deref(), &secret)
+                }),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "cursor-secret";
+
+    #[test]
+    fn defaults_the_limit() {
+        for limit in [None, Some(""), Some("  ")] {
+            assert_eq!(
+                Pagination::parse(limit, None, SECRET).unwrap(),
+                Pagination {
+                    limit: DEFAULT_PAGE_SIZE,
+                    before_id: None
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn accepts_limits_within_range() {
+        for (raw, limit) in [("1", 1), ("20", 20), (" 50 ", 50)] {
+            assert_eq!(
+                Pagination::parse(Some(raw), None, SECRET).unwrap().limit,
+                limit
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_limits_out_of_range_or_not_numbers() {
+        for raw in ["0", "-1", "51", "ten", "2.5"] {
+            assert!(
+                matches!(
+                    Pagination::parse(Some(raw), None, SECRET),
+                    Err(ApiError::BadRequest(message)) if message == "limit must be between 1 and 50"
+                ),
+                "{}",
+                raw
+            );
+        }
+    }
+
+    #[test]
+    fn verifies_the_cursor() {
+        let cursor = encode_cursor(42, SECRET);
+        let page = Pagination::parse(Some("10"), Some(&cursor), SECRET).unwrap();
+        assert_eq!(page.before_id, Some(42));
+        assert!(Pagination::parse(None, Some(&cursor), "another-secret").is_err());
+        assert_eq!(
+            Pagination::parse(None, Some(""), SECRET).unwrap().before_id,
+            None
+        );
+    }
+
+    #[test]
+    fn a_full_page_has_a_next_cursor() {
+        let page = Pagination {
+            limit: 2,
+            before_id: None,
+        };
+        let next = page.next_cursor(&[9, 8], |&id| id, SECRET).unwrap();
+        assert_eq!(decode_cursor(&next, SECRET).unwrap(), 8);
+        assert_eq!(page.next_cursor(&[9], |&id| id, SECRET), None);
+        assert_eq!(page.next_cursor::<i64>(&[], |&id| id, SECRET), None);
+    }
+}
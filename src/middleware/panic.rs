@@ -0,0 +1,138 @@
+//! Panic safety net: a handler that panics answers with our JSON 500
+//! instead of actix dropping the connection. Ordinary `Err`s pass through
+//! untouched; only unwinding panics are caught.
+//!
+//! Register `catch_panics` inside `localize_errors` so the 500 carries the
+//! request id, and call `install_panic_hook` at startup so the panic and
+//! its backtrace reach the log.
+
+use std::any::Any;
+use std::backtrace::Backtrace;
+use std::future::Future;
+use std::panic::{AssertUnwindSafe, catch_unwind};
+use std::pin::pin;
+use std::task::Poll;
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{Error, HttpMessage};
+
+use super::error_handler::ApiError;
+use super::request_id::RequestId;
+
+/// Log panics (with a backtrace) through `log` rather than bare stderr.
+pub fn install_panic_hook() {
+    std::panic::set_hook(Box::new(|info| {
+        log::error!("{}\n{}", info, Backtrace::force_capture());
+    }));
+}
+
+/// The message a panic was raised with, when it is a string.
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("non-string panic payload")
+}
+
+/// Turn a panic anywhere inside the request into a 500.
+pub async fn catch_panics(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let label = format!("{} {}", req.method(), req.path());
+    let request_id = req
+        .extensions()
+        .get::<RequestId>()
+        .map(|id| id.0.clone())
+        .unwrap_or_default();
+    let mut call = pin!(next.call(req));
+    let outcome = std::future::poll_fn(|cx| {
+        match catch_unwind(AssertUnwindSafe(|| call.as_mut().poll(cx))) {
+            Ok(Poll::Ready(res)) => Poll::Ready(Ok(res)),
+            Ok(Poll::Pending) => Poll::Pending,
+            Err(payload) => Poll::Ready(Err(payload)),
+        }
+    })
+    .await;
+    match outcome {
+        Ok(res) => res,
+        Err(payload) => {
+            log::error!(
+                "{} [{}] panicked: {}",
+                label,
+                request_id,
+                panic_message(payload.as_ref())
+            );
+            Err(ApiError::Internal("Internal server error".to_string()).into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::body::to_bytes;
+    use actix_web::middleware::from_fn;
+    use actix_web::{App, HttpResponse, test, web};
+    use serde_json::Value;
+
+    use super::*;
+    use crate::middleware::{assign_request_id, localize_errors};
+
+    async fn panics() -> HttpResponse {
+        panic!("handler blew up")
+    }
+
+    async fn not_found() -> Result<HttpResponse, ApiError> {
+        Err(ApiError::NotFound("Reading not found".to_string()))
+    }
+
+    #[actix_web::test]
+    async fn a_panicking_handler_answers_with_a_json_500() {
+        let app = test::init_service(
+            App::new()
+                .wrap(from_fn(catch_panics))
+                .wrap(from_fn(localize_errors))
+                .wrap(from_fn(assign_request_id))
+                .route("/panic", web::get().to(panics)),
+        )
+        .await;
+        let req = test::TestRequest::get()
+            .uri("/panic")
+            .insert_header(("Accept-Language", "en"))
+            .insert_header(("X-Request-Id", "req-panic"))
+            .to_request();
+        let err = test::try_call_service(&app, req)
+            .await
+            .err()
+            .expect("a panic surfaces as an error");
+        let res = err.error_response();
+        assert_eq!(res.status(), 500);
+        let body: Value =
+            serde_json::from_slice(&to_bytes(res.into_body()).await.unwrap()).unwrap();
+        assert_eq!(body["error"]["code"], "internal_error");
+        assert_eq!(body["error"]["request_id"], "req-panic");
+    }
+
+    #[actix_web::test]
+    async fn ordinary_errors_pass_through() {
+        let app = test::init_service(
+            App::new()
+                .wrap(from_fn(catch_panics))
+                .route("/missing", web::get().to(not_found)),
+        )
+        .await;
+        let req = test::TestRequest::get().uri("/missing").to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), 404);
+    }
+
+    #[actix_web::test]
+    async fn reads_string_panic_payloads() {
+        assert_eq!(panic_message(&"boom"), "boom");
+        assert_eq!(panic_message(&"boom".to_string()), "boom");
+        assert_eq!(panic_message(&42), "non-string panic payload");
+    }
+}
@@ -1,7 +1,323 @@
-//! Simple rate limit placeholder using in-memory counters (replace with Redis/Upstash implementation).
+//! Per-tier request rate limits. `RATE_LIMIT_FREE` and `RATE_LIMIT_PAID`
+//! (`<requests>/<sec|min|hour>`, or `off`) set each tier's budget; admins
+//! are never limited. Counting is a fixed window per user, in Redis when it
+//! is available so every instance shares it, otherwise in process.
+//!
+//! A window starts at the user's first request in it, and no wall clock
+//! decides when it ends, so instances with skewed clocks agree: in Redis
+//! the counter key's TTL (kept on Redis's own clock) ends it, and the
+//! in-process fallback times it with `Instant`.
+//!
+//! Register `enforce_rate_limits` inside `localize_errors` so the 429 is
+//! localized too.
 
-/// Placeholder function for checking whether a user is allowed to perform an action.
-pub fn allow_action(_user_id: &str) -> bool {
-    // TODO: replace with Redis-backed rate limiter
-    true
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::AUTHORIZATION;
+use actix_web::middleware::Next;
+use actix_web::{Error, web};
+use redis::aio::ConnectionManager;
+
+use super::error_handler::ApiError;
+use super::tier::UserTier;
+use crate::config::Config;
+use crate::models::Tier;
+use crate::state::AppState;
+
+const DEFAULT_FREE_LIMIT: &str = "30/min";
+const DEFAULT_PAID_LIMIT: &str = "120/min";
+
+/// Past this many tracked users, stale in-process windows are dropped.
+const MAX_LOCAL_WINDOWS: usize = 10_000;
+
+/// A request budget: at most `requests` per `window`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rate {
+    pub requests: u32,
+    pub window: Duration,
+}
+
+impl FromStr for Rate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (requests, unit) = s
+            .trim()
+            .split_once('/')
+            .ok_or_else(|| format!("expected <requests>/<unit>, got '{}'", s))?;
+        let requests = requests
+            .trim()
+            .parse()
+            .map_err(|_| format!("invalid request count '{}'", requests))?;
+        let secs = match unit.trim().to_ascii_lowercase().as_str() {
+            "s" | "sec" | "second" => 1,
+            "m" | "min" | "minute" => 60,
+            "h" | "hour" => 60 * 60,
+            other => return Err(format!("unknown unit '{}'", other)),
+        };
+        Ok(Rate {
+            requests,
+            window: Duration::from_secs(secs),
+        })
+    }
+}
+
+/// Parse a `RATE_LIMIT_*` value; `None` means unlimited. Malformed values
+/// fall back to `default`.
+fn parse_limit(key: &str, raw: Option<&str>, default: &str) -> Option<Rate> {
+    let raw = raw.unwrap_or(default);
+    if raw.trim().eq_ignore_ascii_case("off") {
+        return None;
+    }
+    match raw.parse() {
+        Ok(rate) => Some(rate),
+        Err(e) => {
+            log::warn!("ignoring malformed {} '{}': {}", key, raw, e);
+            default.parse().ok()
+        }
+    }
+}
+
+/// Tier → budget, resolved once at startup, plus the in-process counters.
+pub struct RateLimiter {
+    limits: HashMap<Tier, Rate>,
+    /// user id → (window start, window length, requests so far).
+    local: Mutex<HashMap<i64, (Instant, Duration, u32)>>,
+}
+
+impl RateLimiter {
+    pub fn new(limits: HashMap<Tier, Rate>) -> Self {
+        RateLimiter {
+            limits,
+            local: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn from_config(config: &Config) -> Self {
+        let mut limits = HashMap::new();
+        let tiers = [
+            (
+                Tier::Free,
+                "RATE_LIMIT_FREE",
+                &config.rate_limit_free,
+                DEFAULT_FREE_LIMIT,
+            ),
+            (
+                Tier::Paid,
+                "RATE_LIMIT_PAID",
+                &config.rate_limit_paid,
+                DEFAULT_PAID_LIMIT,
+            ),
+        ];
+        for (tier, key, raw, default) in tiers {
+            if let Some(rate) = parse_limit(key, raw.as_deref(), default) {
+                limits.insert(tier, rate);
+            }
+        }
+        RateLimiter::new(limits)
+    }
+
+    /// The budget for `tier`; `None` means unlimited. Always `None` for
+    /// admins.
+    pub fn limit_for(&self, tier: Tier) -> Option<Rate> {
+        match tier {
+            Tier::Admin => None,
+            _ => self.limits.get(&tier).copied(),
+        }
+    }
+
+    /// Count a request by `user_id` and say whether it is within budget.
+    /// Redis errors fall back to the in-process counters.
+    pub async fn check(&self, user_id: i64, tier: Tier, redis: Option<&ConnectionManager>) -> bool {
+        let Some(rate) = self.limit_for(tier) else {
+            return true;
+        };
+        let window = Duration::from_secs(rate.window.as_secs().max(1));
+        if let Some(redis) = redis {
+            match count_in_redis(redis, user_id, window).await {
+                Ok(count) => return count <= rate.requests,
+                Err(e) => log::warn!("rate limit counter failed for user {}: {}", user_id, e),
+            }
+        }
+        let now = Instant::now();
+        let mut local = self.local.lock().unwrap();
+        if local.len() > MAX_LOCAL_WINDOWS {
+            local.retain(|_, (start, length, _)| now.duration_since(*start) < *length);
+        }
+        let entry = local.entry(user_id).or_insert((now, window, 0));
+        if entry.1 != window || now.duration_since(entry.0) >= window {
+            *entry = (now, window, 0);
+        }
+        entry.2 += 1;
+        entry.2 <= rate.requests
+    }
+}
+
+/// Increment the user's counter for its current `window`. The first
+/// request of a window gives the key its TTL, so the window ends on Redis's
+/// clock rather than this instance's.
+async fn count_in_redis(
+    redis: &ConnectionManager,
+    user_id: i64,
+    window: Duration,
+) -> redis::RedisResult<u32> {
+    let key = format!("rate_limit:{}:{}", user_id, window.as_secs());
+    let mut conn = redis.clone();
+    let (count, ttl): (u32, i64) = redis::pipe()
+        .atomic()
+        .incr(&key, 1)
+        .ttl(&key)
+        .query_async(&mut conn)
+        .await?;
+    // No TTL yet: this request opened the window (or an earlier EXPIRE was
+    // lost, which would otherwise leave the counter forever).
+    if ttl < 0 {
+        let _: () = redis::cmd("EXPIRE")
+            .arg(&key)
+            .arg(window.as_secs())
+            .query_async(&mut conn)
+            .await?;
+    }
+    Ok(count)
+}
+
+/// Enforce the `AppState`'s `RateLimiter` on authenticated
+/// requests; anonymous or badly authenticated requests pass through to the
+/// handler, which rejects them itself.
+pub async fn enforce_rate_limits(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let Some(state) = req.app_data::<web::Data<AppState>>().cloned() else {
+        return next.call(req).await;
+    };
+    if !req.headers().contains_key(AUTHORIZATION) {
+        return next.call(req).await;
+    }
+    // The extractor's request handle is dropped before routing, which
+    // needs sole ownership; the resolved tier stays in the extensions.
+    let Ok(UserTier { user, tier }) = req.extract::<UserTier>().await else {
+        return next.call(req).await;
+    };
+    if !state
+        .rate_limiter()
+        .check(user.user_id, tier, state.redis())
+        .await
+    {
+        log::info!(
+            "rate limited user {} ({} tier)",
+            user.user_id,
+            tier.as_str()
+        );
+        return Err(ApiError::TooManyRequests("Too many requests".to_string()).into());
+    }
+    next.call(req).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_rates_and_off() {
+        assert_eq!(
+            "30/min".parse(),
+            Ok(Rate {
+                requests: 30,
+                window: Duration::from_secs(60),
+            })
+        );
+        assert_eq!(
+            " 5 / s ".parse::<Rate>().map(|r| r.window),
+            Ok(Duration::from_secs(1))
+        );
+        assert!("30".parse::<Rate>().is_err());
+        assert!("30/day".parse::<Rate>().is_err());
+        assert_eq!(parse_limit("RATE_LIMIT_FREE", Some("off"), "30/min"), None);
+        // Malformed values fall back to the default.
+        assert_eq!(
+            parse_limit("RATE_LIMIT_FREE", Some("lots"), "30/min"),
+            "30/min".parse().ok()
+        );
+    }
+
+    #[tokio::test]
+    async fn each_tier_gets_its_own_budget_and_admins_none() {
+        let limiter = RateLimiter::new(HashMap::from([
+            (Tier::Free, "2/min".parse().unwrap()),
+            (Tier::Paid, "4/min".parse().unwrap()),
+        ]));
+        let mut free = Vec::new();
+        let mut paid = Vec::new();
+        for _ in 0..5 {
+            free.push(limiter.check(1, Tier::Free, None).await);
+            paid.push(limiter.check(2, Tier::Paid, None).await);
+        }
+        assert_eq!(free, [true, true, false, false, false]);
+        assert_eq!(paid, [true, true, true, true, false]);
+        assert_eq!(limiter.limit_for(Tier::Admin), None);
+        for _ in 0..10 {
+            assert!(limiter.check(3, Tier::Admin, None).await);
+        }
+    }
+
+    #[tokio::test]
+    async fn a_local_window_runs_from_its_first_request() {
+        let limiter = RateLimiter::new(HashMap::from([(Tier::Free, "1/s".parse().unwrap())]));
+        assert!(limiter.check(1, Tier::Free, None).await);
+        assert!(!limiter.check(1, Tier::Free, None).await);
+        tokio::time::sleep(Duration::from_millis(1100)).await;
+        assert!(limiter.check(1, Tier::Free, None).await);
+        assert!(!limiter.check(1, Tier::Free, None).await);
+    }
+
+    #[tokio::test]
+    async fn redis_windows_end_on_the_key_ttl() {
+        let Ok(url) = std::env::var("UPSTASH_REDIS_URL") else {
+            return;
+        };
+        let client = redis::Client::open(url).unwrap();
+        let mut redis = ConnectionManager::new(client).await.unwrap();
+        let user_id = -(std::process::id() as i64);
+        let window = Duration::from_secs(60);
+        let key = format!("rate_limit:{}:{}", user_id, window.as_secs());
+        let _: () = redis::cmd("DEL")
+            .arg(&key)
+            .query_async(&mut redis)
+            .await
+            .unwrap();
+
+        assert_eq!(count_in_redis(&redis, user_id, window).await.unwrap(), 1);
+        let ttl: i64 = redis::cmd("TTL")
+            .arg(&key)
+            .query_async(&mut redis)
+            .await
+            .unwrap();
+        assert!((1..=60).contains(&ttl), "{}", ttl);
+
+        // A counter left without a TTL gets one on its next request.
+        let _: () = redis::cmd("SET")
+            .arg(&key)
+            .arg(1)
+            .query_async(&mut redis)
+            .await
+            .unwrap();
+        assert_eq!(count_in_redis(&redis, user_id, window).await.unwrap(), 2);
+        let ttl: i64 = redis::cmd("TTL")
+            .arg(&key)
+            .query_async(&mut redis)
+            .await
+            .unwrap();
+        assert!((1..=60).contains(&ttl), "{}", ttl);
+        let _: () = redis::cmd("DEL")
+            .arg(&key)
+            .query_async(&mut redis)
+            .await
+            .unwrap();
+    }
 }
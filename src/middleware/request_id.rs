@@ -0,0 +1,58 @@
+//! Request ids: every request gets one, taken from a sane incoming
+//! `X-Request-Id` or generated, stored in the request extensions and echoed
+//! in the `X-Request-Id` response header. `ApiError` bodies carry it as
+//! `error.request_id` (see `localize_errors`) so users can quote it to support.
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::error::InternalError;
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::middleware::Next;
+use actix_web::{Error, HttpMessage};
+use uuid::Uuid;
+
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Longest client-supplied id we keep; longer ones are replaced.
+const MAX_REQUEST_ID_LEN: usize = 64;
+
+/// The current request's id, in the request extensions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestId(pub String);
+
+/// Accept a client id only if it is short and plain, so it is safe to log
+/// and echo back.
+fn is_acceptable(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_REQUEST_ID_LEN
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.'))
+}
+
+/// Assign the request id and echo it in the response header.
+pub async fn assign_request_id(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let id = req
+        .headers()
+        .get(&REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| is_acceptable(v))
+        .map(str::to_string)
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+    req.extensions_mut().insert(RequestId(id.clone()));
+    let header = HeaderValue::from_str(&id).expect("request ids are visible ASCII");
+    match next.call(req).await {
+        Ok(mut res) => {
+            res.headers_mut().insert(REQUEST_ID_HEADER, header);
+            Ok(res)
+        }
+        Err(err) => {
+            let mut rendered = err.error_response();
+            rendered.headers_mut().insert(REQUEST_ID_HEADER, header);
+            Err(InternalError::from_response(err, rendered).into())
+        }
+    }
+}
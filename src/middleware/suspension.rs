@@ -0,0 +1,41 @@
+//! Refuses (403) every authenticated request from a suspended user until an
+//! admin lifts the suspension (see `suspension_service`). Requests without
+//! a valid token pass through for the handler to reject.
+//!
+//! Register `reject_suspended` inside `localize_errors` so the 403 is
+//! localized too.
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::AUTHORIZATION;
+use actix_web::middleware::Next;
+use actix_web::{Error, web};
+
+use super::auth::AuthUser;
+use super::error_handler::ApiError;
+use crate::services::is_suspended;
+use crate::state::AppState;
+
+pub async fn reject_suspended(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    if !req.headers().contains_key(AUTHORIZATION) {
+        return next.call(req).await;
+    }
+    let Some(state) = req.app_data::<web::Data<AppState>>().cloned() else {
+        return next.call(req).await;
+    };
+    let Ok(user) = req.extract::<AuthUser>().await else {
+        return next.call(req).await;
+    };
+    let suspended = is_suspended(user.user_id, user.is_admin, state.store(), state.redis())
+        .await
+        .map_err(ApiError::from)?;
+    if suspended {
+        return Err(
+            ApiError::Forbidden("Your account is suspended pending review".to_string()).into(),
+        );
+    }
+    next.call(req).await
+}
@@ -0,0 +1,43 @@
+//! The `UserTier` extractor: the authenticated caller plus their `Tier`,
+//! resolved at most once per request.
+
+use std::future::Future;
+use std::pin::Pin;
+
+use actix_web::{FromRequest, HttpMessage, HttpRequest, dev::Payload, web};
+
+use super::auth::AuthUser;
+use super::error_handler::ApiError;
+use crate::models::Tier;
+use crate::services::resolve_tier;
+use crate::state::AppState;
+
+/// An authenticated caller and their tier. The tier is stashed in the
+/// request extensions, so extracting this twice costs one lookup.
+#[derive(Debug, Clone, Copy)]
+pub struct UserTier {
+    pub user: AuthUser,
+    pub tier: Tier,
+}
+
+impl FromRequest for UserTier {
+    type Error = ApiError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let user = AuthUser::from_request(req, payload).into_inner();
+        let req = req.clone();
+        Box::pin(async move {
+            let user = user?;
+            if let Some(tier) = req.extensions().get::<Tier>().copied() {
+                return Ok(UserTier { user, tier });
+            }
+            let state = req.app_data::<web::Data<AppState>>();
+            let store = state.map(|s| s.store());
+            let redis = state.and_then(|s| s.redis());
+            let tier = resolve_tier(user.user_id, user.is_admin, store, redis).await?;
+            req.extensions_mut().insert(tier);
+            Ok(UserTier { user, tier })
+        })
+    }
+}
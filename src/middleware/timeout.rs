@@ -0,0 +1,89 @@
+//! Per-route request deadlines. Each request races its handler against a
+//! timer chosen by route pattern; the loser is dropped, and a timeout is
+//! answered with our JSON 504.
+//!
+//! Register it inside `localize_errors` so the 504 is localized too.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{Error, web};
+
+use super::error_handler::ApiError;
+use crate::config::Config;
+use crate::state::AppState;
+
+/// Used for `ROUTE_TIMEOUTS` entries that aren't set explicitly.
+const BUILTIN_TIMEOUTS: [(&str, u64); 7] = [
+    ("/health/llm", 10),
+    ("/health/full", 10),
+    ("/ask", 60),
+    ("/readings", 120),
+    ("/readings/session", 120),
+    ("/readings/{id}/regenerate", 120),
+    ("/drafts/{id}/submit", 120),
+];
+
+/// Route pattern (as registered, e.g. `/readings/{id}/audit`) to deadline.
+#[derive(Debug, Clone)]
+pub struct RouteTimeouts {
+    routes: HashMap<String, Duration>,
+    default: Duration,
+}
+
+impl RouteTimeouts {
+    /// `BUILTIN_TIMEOUTS`, overridden by `ROUTE_TIMEOUTS` (`pattern=secs`
+    /// entries); other routes get `DEFAULT_ROUTE_TIMEOUT_SECS`.
+    pub fn from_config(config: &Config) -> Self {
+        let mut routes: HashMap<String, Duration> = BUILTIN_TIMEOUTS
+            .iter()
+            .map(|(route, secs)| (route.to_string(), Duration::from_secs(*secs)))
+            .collect();
+        for entry in &config.route_timeouts {
+            let parsed = entry
+                .split_once('=')
+                .and_then(|(route, secs)| Some((route.trim(), secs.trim().parse().ok()?)));
+            match parsed {
+                Some((route, secs)) => {
+                    routes.insert(route.to_string(), Duration::from_secs(secs));
+                }
+                None => log::warn!("ignoring malformed ROUTE_TIMEOUTS entry '{}'", entry),
+            }
+        }
+        RouteTimeouts {
+            routes,
+            default: Duration::from_secs(config.default_route_timeout_secs),
+        }
+    }
+
+    pub fn for_route(&self, pattern: Option<&str>) -> Duration {
+        pattern
+            .and_then(|p| self.routes.get(p))
+            .copied()
+            .unwrap_or(self.default)
+    }
+}
+
+/// Enforce the `AppState`'s `RouteTimeouts`; a no-op without it.
+pub async fn enforce_timeouts(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let Some(state) = req.app_data::<web::Data<AppState>>() else {
+        return next.call(req).await;
+    };
+    let deadline = state.timeouts().for_route(req.match_pattern().as_deref());
+    // Routing needs sole ownership of the request, so only copy what the
+    // log line needs; the error is rendered (and localized) further out.
+    let label = format!("{} {}", req.method(), req.path());
+    match tokio::time::timeout(deadline, next.call(req)).await {
+        Ok(res) => res,
+        Err(_) => {
+            log::warn!("{} timed out after {:?}", label, deadline);
+            Err(ApiError::GatewayTimeout("The request took too long".to_string()).into())
+        }
+    }
+}
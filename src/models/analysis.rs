@@ -0,0 +1,74 @@
+use serde::{Deserialize, Serialize};
+
+/// What a question is about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Topic {
+    Love,
+    Career,
+    Finance,
+    Health,
+    Legal,
+    Family,
+    #[default]
+    #[serde(other)]
+    Other,
+}
+
+impl Topic {
+    /// The serialized name, as used in config (`SENSITIVE_TOPICS`).
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Topic::Love => "love",
+            Topic::Career => "career",
+            Topic::Finance => "finance",
+            Topic::Health => "health",
+            Topic::Legal => "legal",
+            Topic::Family => "family",
+            Topic::Other => "other",
+        }
+    }
+
+    /// Inverse of `as_str`; unknown names are `Other`.
+    pub fn from_name(name: &str) -> Topic {
+        match name {
+            "love" => Topic::Love,
+            "career" => Topic::Career,
+            "finance" => Topic::Finance,
+            "health" => Topic::Health,
+            "legal" => Topic::Legal,
+            "family" => Topic::Family,
+            _ => Topic::Other,
+        }
+    }
+}
+
+/// The asker's emotional state as read from the question.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Mood {
+    Anxious,
+    Curious,
+    Hopeful,
+    Sad,
+    #[default]
+    #[serde(other)]
+    Neutral,
+}
+
+/// Output of the QuestionAnalysis agent.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuestionAnalysis {
+    #[serde(default)]
+    pub topic: Topic,
+    #[serde(default)]
+    pub mood: Mood,
+    /// How clearly the question can be read (0.0-1.0). Older analyses
+    /// without the field are treated as fully confident.
+    #[serde(default = "full_confidence")]
+    pub confidence: f32,
+}
+
+fn full_confidence() -> f32 {
+    1.0
+}
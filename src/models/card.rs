@@ -0,0 +1,577 @@
+//! Tarot deck and spread models.
+
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Arcana {
+    Major,
+    Minor,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Suit {
+    Wands,
+    Cups,
+    Swords,
+    Pentacles,
+}
+
+impl Suit {
+    pub const ALL: [Suit; 4] = [Suit::Wands, Suit::Cups, Suit::Swords, Suit::Pentacles];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Suit::Wands => "Wands",
+            Suit::Cups => "Cups",
+            Suit::Swords => "Swords",
+            Suit::Pentacles => "Pentacles",
+        }
+    }
+
+    /// Case-insensitive inverse of `name`, for config.
+    pub fn from_name(name: &str) -> Option<Suit> {
+        Suit::ALL
+            .into_iter()
+            .find(|suit| suit.name().eq_ignore_ascii_case(name))
+    }
+
+    fn keyword(&self) -> &'static str {
+        match self {
+            Suit::Wands => "passion",
+            Suit::Cups => "emotion",
+            Suit::Swords => "thought",
+            Suit::Pentacles => "material",
+        }
+    }
+
+    fn name_th(&self) -> &'static str {
+        match self {
+            Suit::Wands => "ไม้เท้า",
+            Suit::Cups => "ถ้วย",
+            Suit::Swords => "ดาบ",
+            Suit::Pentacles => "เหรียญ",
+        }
+    }
+
+    fn keyword_th(&self) -> &'static str {
+        match self {
+            Suit::Wands => "ความหลงใหล",
+            Suit::Cups => "อารมณ์",
+            Suit::Swords => "ความคิด",
+            Suit::Pentacles => "วัตถุ",
+        }
+    }
+
+    pub fn element(&self) -> Element {
+        match self {
+            Suit::Wands => Element::Fire,
+            Suit::Cups => Element::Water,
+            Suit::Swords => Element::Air,
+            Suit::Pentacles => Element::Earth,
+        }
+    }
+}
+
+/// Classical element of a card: its suit's, or for the major arcana the
+/// traditional astrological attribution.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Element {
+    Fire,
+    Water,
+    Air,
+    Earth,
+}
+
+/// Relative draw weights of the minor suits; the major arcana weigh 1.
+/// Every weight is positive, so every card stays possible.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SuitWeights {
+    pub wands: f64,
+    pub cups: f64,
+    pub swords: f64,
+    pub pentacles: f64,
+}
+
+impl Default for SuitWeights {
+    fn default() -> Self {
+        SuitWeights {
+            wands: 1.0,
+            cups: 1.0,
+            swords: 1.0,
+            pentacles: 1.0,
+        }
+    }
+}
+
+impl SuitWeights {
+    pub fn get(&self, suit: Suit) -> f64 {
+        match suit {
+            Suit::Wands => self.wands,
+            Suit::Cups => self.cups,
+            Suit::Swords => self.swords,
+            Suit::Pentacles => self.pentacles,
+        }
+    }
+
+    pub fn set(&mut self, suit: Suit, weight: f64) {
+        match suit {
+            Suit::Wands => self.wands = weight,
+            Suit::Cups => self.cups = weight,
+            Suit::Swords => self.swords = weight,
+            Suit::Pentacles => self.pentacles = weight,
+        }
+    }
+
+    pub fn for_card(&self, card: &Card) -> f64 {
+        card.suit.map_or(1.0, |suit| self.get(suit))
+    }
+}
+
+/// A card in the 78-card Rider-Waite deck. Ids 0-21 are the major arcana,
+/// 22-77 the minor arcana ordered by suit then rank.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Card {
+    pub id: u8,
+    pub name: String,
+    pub name_th: String,
+    pub arcana: Arcana,
+    pub suit: Option<Suit>,
+    pub element: Element,
+    pub keywords: Vec<&'static str>,
+    pub keywords_th: Vec<&'static str>,
+}
+
+const MAJOR_ARCANA: [(&str, [&str; 3]); 22] = [
+    ("The Fool", ["beginnings", "spontaneity", "faith"]),
+    ("The Magician", ["willpower", "skill", "manifestation"]),
+    (
+        "The High Priestess",
+        ["intuition", "mystery", "inner voice"],
+    ),
+    ("The Empress", ["abundance", "nurturing", "fertility"]),
+    ("The Emperor", ["authority", "structure", "stability"]),
+    ("The Hierophant", ["tradition", "guidance", "belief"]),
+    ("The Lovers", ["love", "harmony", "choices"]),
+    ("The Chariot", ["determination", "control", "victory"]),
+    ("Strength", ["courage", "patience", "compassion"]),
+    ("The Hermit", ["introspection", "solitude", "wisdom"]),
+    ("Wheel of Fortune", ["cycles", "destiny", "turning point"]),
+    ("Justice", ["fairness", "truth", "cause and effect"]),
+    ("The Hanged Man", ["surrender", "new perspective", "pause"]),
+    ("Death", ["endings", "transformation", "transition"]),
+    ("Temperance", ["balance", "moderation", "purpose"]),
+    ("The Devil", ["attachment", "temptation", "shadow self"]),
+    ("The Tower", ["upheaval", "revelation", "sudden change"]),
+    ("The Star", ["hope", "renewal", "inspiration"]),
+    ("The Moon", ["illusion", "fear", "subconscious"]),
+    ("The Sun", ["joy", "success", "vitality"]),
+    ("Judgement", ["reflection", "reckoning", "awakening"]),
+    ("The World", ["completion", "fulfilment", "wholeness"]),
+];
+
+/// Thai names and keywords of `MAJOR_ARCANA`, in the same order, with each
+/// card's element.
+const MAJOR_ARCANA_TH: [(&str, [&str; 3], Element); 22] = [
+    (
+        "คนโง่",
+        ["การเริ่มต้น", "ความเป็นธรรมชาติ", "ศรัทธา"],
+        Element::Air,
+    ),
+    ("นักมายากล", ["พลังใจ", "ทักษะ", "การทำให้เป็นจริง"], Element::Air),
+    (
+        "นักบวชหญิง",
+        ["สัญชาตญาณ", "ความลึกลับ", "เสียงภายใน"],
+        Element::Water,
+    ),
+    (
+        "จักรพรรดินี",
+        ["ความอุดมสมบูรณ์", "การดูแลเอาใจใส่", "การให้กำเนิด"],
+        Element::Earth,
+    ),
+    ("จักรพรรดิ", ["อำนาจ", "โครงสร้าง", "ความมั่นคง"], Element::Fire),
+    (
+        "พระสังฆราช",
+        ["ประเพณี", "การชี้แนะ", "ความเชื่อ"],
+        Element::Earth,
+    ),
+    ("คู่รัก", ["ความรัก", "ความกลมเกลียว", "ทางเลือก"], Element::Air),
+    ("รถศึก", ["ความมุ่งมั่น", "การควบคุม", "ชัยชนะ"], Element::Water),
+    (
+        "พละกำลัง",
+        ["ความกล้าหาญ", "ความอดทน", "ความเมตตา"],
+        Element::Fire,
+    ),
+    (
+        "ฤๅษี",
+        ["การมองเข้าไปในตัวเอง", "ความสันโดษ", "ปัญญา"],
+        Element::Earth,
+    ),
+    (
+        "กงล้อแห่งโชคชะตา",
+        ["วัฏจักร", "โชคชะตา", "จุดเปลี่ยน"],
+        Element::Fire,
+    ),
+    (
+        "ความยุติธรรม",
+        ["ความเป็นธรรม", "ความจริง", "เหตุและผล"],
+        Element::Air,
+    ),
+    (
+        "คนแขวน",
+        ["การยอมปล่อยวาง", "มุมมองใหม่", "การหยุดพัก"],
+        Element::Water,
+    ),
+    (
+        "ความตาย",
+        ["การสิ้นสุด", "การเปลี่ยนแปลง", "การผ่านพ้น"],
+        Element::Water,
+    ),
+    (
+        "ความพอดี",
+        ["ความสมดุล", "ความพอประมาณ", "เป้าหมาย"],
+        Element::Fire,
+    ),
+    ("ปีศาจ", ["การยึดติด", "สิ่งยั่วยุ", "ด้านมืดในตัวเอง"], Element::Earth),
+    (
+        "หอคอย",
+        ["ความวุ่นวาย", "การเปิดเผย", "การเปลี่ยนแปลงฉับพลัน"],
+        Element::Fire,
+    ),
+    (
+        "ดวงดาว",
+        ["ความหวัง", "การเริ่มใหม่", "แรงบันดาลใจ"],
+        Element::Air,
+    ),
+    (
+        "ดวงจันทร์",
+        ["ภาพลวงตา", "ความกลัว", "จิตใต้สำนึก"],
+        Element::Water,
+    ),
+    ("ดวงอาทิตย์", ["ความสุข", "ความสำเร็จ", "พลังชีวิต"], Element::Fire),
+    (
+        "การพิพากษา",
+        ["การทบทวน", "การชำระสะสาง", "การตื่นรู้"],
+        Element::Fire,
+    ),
+    (
+        "โลก",
+        ["ความสมบูรณ์", "การบรรลุผล", "ความครบถ้วน"],
+        Element::Earth,
+    ),
+];
+
+const RANKS: [(&str, &str); 14] = [
+    ("Ace", "new potential"),
+    ("Two", "balance"),
+    ("Three", "growth"),
+    ("Four", "stability"),
+    ("Five", "conflict"),
+    ("Six", "harmony"),
+    ("Seven", "reflection"),
+    ("Eight", "movement"),
+    ("Nine", "fruition"),
+    ("Ten", "completion"),
+    ("Page", "curiosity"),
+    ("Knight", "action"),
+    ("Queen", "maturity"),
+    ("King", "mastery"),
+];
+
+/// Thai names and keywords of `RANKS`, in the same order.
+const RANKS_TH: [(&str, &str); 14] = [
+    ("เอซ", "ศักยภาพใหม่"),
+    ("สอง", "ความสมดุล"),
+    ("สาม", "การเติบโต"),
+    ("สี่", "ความมั่นคง"),
+    ("ห้า", "ความขัดแย้ง"),
+    ("หก", "ความกลมเกลียว"),
+    ("เจ็ด", "การไตร่ตรอง"),
+    ("แปด", "การเคลื่อนไหว"),
+    ("เก้า", "การผลิดอกออกผล"),
+    ("สิบ", "ความสำเร็จลุล่วง"),
+    ("เพจ", "ความอยากรู้"),
+    ("อัศวิน", "การลงมือทำ"),
+    ("ราชินี", "ความเป็นผู้ใหญ่"),
+    ("ราชา", "ความเชี่ยวชาญ"),
+];
+
+/// Number of cards in the deck.
+pub const DECK_SIZE: usize = 78;
+
+/// The full deck in id order.
+pub fn full_deck() -> Vec<Card> {
+    let majors = MAJOR_ARCANA
+        .iter()
+        .zip(MAJOR_ARCANA_TH.iter())
+        .enumerate()
+        .map(
+            |(i, ((name, keywords), (name_th, keywords_th, element)))| Card {
+                id: i as u8,
+                name: name.to_string(),
+                name_th: name_th.to_string(),
+                arcana: Arcana::Major,
+                suit: None,
+                element: *element,
+                keywords: keywords.to_vec(),
+                keywords_th: keywords_th.to_vec(),
+            },
+        );
+    let minors = Suit::ALL.into_iter().enumerate().flat_map(|(s, suit)| {
+        RANKS.iter().zip(RANKS_TH.iter()).enumerate().map(
+            move |(r, ((rank, keyword), (rank_th, keyword_th)))| Card {
+                id: (MAJOR_ARCANA.len() + s * RANKS.len() + r) as u8,
+                name: format!("{} of {}", rank, suit.name()),
+                name_th: format!("{}{}", rank_th, suit.name_th()),
+                arcana: Arcana::Minor,
+                suit: Some(suit),
+                element: suit.element(),
+                keywords: vec![keyword, suit.keyword()],
+                keywords_th: vec![keyword_th, suit.keyword_th()],
+            },
+        )
+    });
+    majors.chain(minors).collect()
+}
+
+/// Look up a card by id.
+pub fn card_by_id(id: u8) -> Option<Card> {
+    full_deck().into_iter().nth(id as usize)
+}
+
+/// Thai label for a spread position (as in `Spread::positions`).
+pub fn position_name_th(position: &str) -> Option<&'static str> {
+    Some(match position {
+        "Guidance" | "Advice" => "คำแนะนำ",
+        "Past" => "อดีต",
+        "Present" => "ปัจจุบัน",
+        "Future" => "อนาคต",
+        "Situation" => "สถานการณ์",
+        "Challenge" => "อุปสรรค",
+        "Hidden influence" => "อิทธิพลที่ซ่อนอยู่",
+        "Outcome" => "ผลลัพธ์",
+        "Foundation" => "รากฐาน",
+        "Recent past" => "อดีตที่ผ่านมาไม่นาน",
+        "Potential" => "ความเป็นไปได้",
+        "Near future" => "อนาคตอันใกล้",
+        "Self" => "ตัวคุณ",
+        "Environment" => "สภาพแวดล้อม",
+        "Hopes and fears" => "ความหวังและความกลัว",
+        _ => return None,
+    })
+}
+
+/// Supported spreads and their positions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Spread {
+    Single,
+    #[default]
+    ThreeCard,
+    FiveCard,
+    CelticCross,
+}
+
+impl FromStr for Spread {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "single" => Ok(Spread::Single),
+            "three_card" => Ok(Spread::ThreeCard),
+            "five_card" => Ok(Spread::FiveCard),
+            "celtic_cross" => Ok(Spread::CelticCross),
+            other => Err(format!("unknown spread '{}'", other)),
+        }
+    }
+}
+
+impl Spread {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Spread::Single => "single",
+            Spread::ThreeCard => "three_card",
+            Spread::FiveCard => "five_card",
+            Spread::CelticCross => "celtic_cross",
+        }
+    }
+
+    /// Cards dealt for this spread, one per position.
+    pub fn card_count(&self) -> usize {
+        self.positions().len()
+    }
+
+    /// The one place a requested `card_count` is checked: it may be omitted
+    /// (derived from the spread) but never disagree with it.
+    pub fn check_card_count(&self, card_count: Option<usize>) -> Result<(), String> {
+        match card_count {
+            Some(count) if count != self.card_count() => Err(format!(
+                "card_count {} does not match spread {} ({} cards)",
+                count,
+                self.as_str(),
+                self.card_count()
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn positions(&self) -> &'static [&'static str] {
+        match self {
+            Spread::Single => &["Guidance"],
+            Spread::ThreeCard => &["Past", "Present", "Future"],
+            Spread::FiveCard => &[
+                "Situation",
+                "Challenge",
+                "Advice",
+                "Hidden influence",
+                "Outcome",
+            ],
+            Spread::CelticCross => &[
+                "Present",
+                "Challenge",
+                "Foundation",
+                "Recent past",
+                "Potential",
+                "Near future",
+                "Self",
+                "Environment",
+                "Hopes and fears",
+                "Outcome",
+            ],
+        }
+    }
+}
+
+/// A card as drawn into a spread position.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DrawnCard {
+    pub card_id: u8,
+    pub name: String,
+    pub position: String,
+    pub reversed: bool,
+}
+
+/// How a `DrawnCard` is stored inside a reading. The deck id is the source
+/// of truth: names are not written, and `resolve` looks them up in the
+/// current deck, so renamed or retranslated cards show up in old readings.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StoredCard {
+    pub card_id: u8,
+    pub position: String,
+    pub reversed: bool,
+    /// The name as drawn; only in rows stored before names were dropped,
+    /// and only used when the deck no longer has `card_id`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+impl From<&DrawnCard> for StoredCard {
+    fn from(card: &DrawnCard) -> Self {
+        StoredCard {
+            card_id: card.card_id,
+            position: card.position.clone(),
+            reversed: card.reversed,
+            name: None,
+        }
+    }
+}
+
+impl StoredCard {
+    /// The card with its name from the current deck. An id the deck doesn't
+    /// have keeps the stored name if there is one, else a placeholder, so
+    /// one bad card doesn't make the whole reading unreadable.
+    pub fn resolve(self) -> DrawnCard {
+        let name = match card_by_id(self.card_id) {
+            Some(card) => card.name,
+            None => self
+                .name
+                .unwrap_or_else(|| format!("Unknown card #{}", self.card_id)),
+        };
+        DrawnCard {
+            card_id: self.card_id,
+            name,
+            position: self.position,
+            reversed: self.reversed,
+        }
+    }
+}
+
+/// Everything needed to reproduce a draw outside this service: seed the
+/// named RNG with `seed`, run the named procedure, and compare with `cards`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DrawAudit {
+    pub algorithm: String,
+    pub procedure: String,
+    pub seed: u64,
+    pub spread: Spread,
+    /// Suit weights of a topic-biased draw (see `DRAW_PROCEDURE`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub suit_weights: Option<SuitWeights>,
+    /// Card ids in shuffled deck order.
+    pub deck_order: Vec<u8>,
+    pub cards: Vec<DrawnCard>,
+}
+
+/// The shuffled deck a draw was dealt from, so a client shuffle animation
+/// can land on the cards the server drew.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeckShuffle {
+    /// Card ids in shuffled deck order.
+    pub deck_order: Vec<u8>,
+    /// Index into `deck_order` of each drawn card, in spread position order.
+    pub drawn_indices: Vec<usize>,
+}
+
+/// A deck entry from `GET /cards`: both names, with `name` and `keywords`
+/// in the requested language.
+#[derive(Debug, Clone, Serialize)]
+pub struct CardReference {
+    pub id: u8,
+    pub name: String,
+    pub name_en: String,
+    pub name_th: String,
+    pub arcana: Arcana,
+    pub suit: Option<Suit>,
+    pub element: Element,
+    pub keywords: Vec<&'static str>,
+}
+
+impl CardReference {
+    /// `card` with `name` and `keywords` in Thai when `thai`, else English.
+    pub fn new(card: Card, thai: bool) -> Self {
+        let (name, keywords) = if thai {
+            (card.name_th.clone(), card.keywords_th)
+        } else {
+            (card.name.clone(), card.keywords)
+        };
+        CardReference {
+            id: card.id,
+            name,
+            name_en: card.name,
+            name_th: card.name_th,
+            arcana: card.arcana,
+            suit: card.suit,
+            element: card.element,
+            keywords,
+        }
+    }
+}
+
+/// Response of `GET /card-of-the-day`.
+#[derive(Debug, Clone, Serialize)]
+pub struct DailyCard {
+    /// The day this card is for, in Thai time.
+    pub date: chrono::NaiveDate,
+    pub card_id: u8,
+    pub name: String,
+    pub arcana: Arcana,
+    pub suit: Option<Suit>,
+    pub reversed: bool,
+    pub keywords: Vec<&'static str>,
+    /// A short templated message; no LLM is involved.
+    pub meaning: String,
+}
@@ -0,0 +1,40 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::card::Spread;
+use super::reading::DetailLevel;
+
+/// A question saved for later, not yet read or charged.
+#[derive(Debug, Clone, Serialize)]
+pub struct ReadingDraft {
+    pub id: i64,
+    pub question: String,
+    pub spread: Spread,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Body of `POST /drafts`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CreateDraftRequest {
+    pub question: String,
+    #[serde(default)]
+    pub spread: Spread,
+}
+
+/// Optional body of `POST /drafts/{id}/submit`: the per-reading options of
+/// `CreateReadingRequest` that aren't part of the draft.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SubmitDraftRequest {
+    #[serde(default)]
+    pub use_memory: bool,
+    #[serde(default)]
+    pub payment_id: Option<i64>,
+    #[serde(default)]
+    pub detail_level: DetailLevel,
+    #[serde(default)]
+    pub include_shuffle: bool,
+    #[serde(default)]
+    pub explain: bool,
+}
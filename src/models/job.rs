@@ -0,0 +1,66 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use super::analysis::QuestionAnalysis;
+use super::card::Spread;
+use super::reading::{DetailLevel, TarotReading};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Done,
+    Failed,
+    Cancelled,
+    /// Passed its deadline before a worker picked it up; credits refunded.
+    Expired,
+}
+
+/// An async reading job, stored in Redis as JSON.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReadingJob {
+    pub id: String,
+    pub user_id: i64,
+    pub question: String,
+    #[serde(default)]
+    pub analysis: Option<QuestionAnalysis>,
+    pub spread: Spread,
+    #[serde(default)]
+    pub detail_level: DetailLevel,
+    pub seed: u64,
+    #[serde(default)]
+    pub explain: bool,
+    pub status: JobStatus,
+    /// Credits deducted at enqueue time, refunded if the job never runs.
+    pub credits_charged: i64,
+    /// Unix timestamp (seconds).
+    pub created_at: i64,
+    /// Unix timestamp after which the worker won't start the job; `None`
+    /// means it waits indefinitely.
+    #[serde(default)]
+    pub deadline: Option<i64>,
+    #[serde(default)]
+    pub result: Option<TarotReading>,
+    #[serde(default)]
+    pub error: Option<String>,
+    /// How many times an admin re-queued this job after it failed.
+    #[serde(default)]
+    pub replay_count: u32,
+    /// Admin who last replayed the job, for auditing.
+    #[serde(default)]
+    pub replayed_by: Option<i64>,
+}
+
+/// Response of `GET /admin/queue`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct QueueStats {
+    /// Job ids waiting in the queue.
+    pub depth: u64,
+    /// Seconds since the oldest still-queued job was created; `None` when
+    /// nothing is waiting.
+    pub oldest_age_secs: Option<i64>,
+    /// Stored jobs (kept for a week) by status.
+    pub by_status: BTreeMap<JobStatus, u64>,
+}
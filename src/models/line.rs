@@ -0,0 +1,52 @@
+//! LINE Messaging API webhook payloads. Only the fields the webhook acts on
+//! are modelled; LINE adds fields over time, so unknown ones are ignored.
+
+use serde::Deserialize;
+
+/// Body of `POST /line/webhook`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct LineWebhook {
+    #[serde(default)]
+    pub events: Vec<LineEvent>,
+}
+
+/// One webhook event. `webhook_event_id` stays the same when LINE
+/// redelivers it.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LineEvent {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub webhook_event_id: String,
+    #[serde(default)]
+    pub source: Option<LineSource>,
+    #[serde(default)]
+    pub message: Option<LineMessage>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LineSource {
+    #[serde(default)]
+    pub user_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct LineMessage {
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(default)]
+    pub text: Option<String>,
+}
+
+impl LineEvent {
+    /// The sender's LINE user id and text, for a text message event.
+    pub fn text_message(&self) -> Option<(&str, &str)> {
+        if self.kind != "message" {
+            return None;
+        }
+        let message = self.message.as_ref().filter(|m| m.kind == "text")?;
+        let user_id = self.source.as_ref()?.user_id.as_deref()?;
+        Some((user_id, message.text.as_deref()?))
+    }
+}
@@ -1,8 +1,22 @@
 //! Domain models used across the backend.
+pub mod analysis;
 pub mod user;
 pub mod reading;
 pub mod payment;
+pub mod card;
+pub mod draft;
+pub mod job;
+pub mod referral;
+pub mod tier;
+pub mod line;
 
+pub use analysis::*;
 pub use user::*;
 pub use reading::*;
 pub use payment::*;
+pub use card::*;
+pub use draft::*;
+pub use job::*;
+pub use referral::*;
+pub use tier::*;
+pub use line::*;
@@ -5,4 +5,18 @@ pub struct Payment {
     pub id: i64,
     pub user_id: i64,
     pub amount_baht: u32,
+    /// Provider-side charge id.
+    pub charge_id: String,
+    /// Credits granted once the charge succeeds.
+    pub credits: i64,
+    pub status: String,
+}
+
+/// Body of `POST /payments`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CreatePaymentRequest {
+    pub amount_baht: u32,
+    /// Client-generated key so a retried purchase doesn't charge twice.
+    pub idempotency_key: String,
 }
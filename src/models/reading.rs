@@ -1,8 +1,447 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
+use super::analysis::QuestionAnalysis;
+use super::card::{DeckShuffle, DrawAudit, DrawnCard, Spread, card_by_id, position_name_th};
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Reading {
     pub id: i64,
     pub user_id: i64,
     pub question: String,
 }
+
+/// How verbose a reading is. Free users always get `Brief`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DetailLevel {
+    Brief,
+    #[default]
+    Standard,
+    Detailed,
+}
+
+impl DetailLevel {
+    /// Target length of each card interpretation, in words.
+    pub fn words_per_card(&self) -> u32 {
+        match self {
+            DetailLevel::Brief => 30,
+            DetailLevel::Standard => 60,
+            DetailLevel::Detailed => 120,
+        }
+    }
+
+    /// Target length of the summary, in words.
+    pub fn summary_words(&self) -> u32 {
+        match self {
+            DetailLevel::Brief => 40,
+            DetailLevel::Standard => 80,
+            DetailLevel::Detailed => 160,
+        }
+    }
+
+    /// Completion budget for a reading at this level.
+    pub fn max_tokens(&self) -> u32 {
+        match self {
+            DetailLevel::Brief => 600,
+            DetailLevel::Standard => 1200,
+            DetailLevel::Detailed => 2400,
+        }
+    }
+}
+
+/// Body of `POST /readings`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CreateReadingRequest {
+    pub question: String,
+    #[serde(default)]
+    pub spread: Spread,
+    /// Optional; must equal `spread.card_count()` when given.
+    #[serde(default)]
+    pub card_count: Option<usize>,
+    /// Give the reader a condensed view of the user's recent readings.
+    #[serde(default)]
+    pub use_memory: bool,
+    /// A succeeded payment covering this reading; refunded automatically if
+    /// the reading fails.
+    #[serde(default)]
+    pub payment_id: Option<i64>,
+    /// Requested verbosity; downgraded to `Brief` for free users.
+    #[serde(default)]
+    pub detail_level: DetailLevel,
+    /// Also return the shuffled deck the cards were dealt from
+    /// (`meta.shuffle`), for the shuffle animation.
+    #[serde(default)]
+    pub include_shuffle: bool,
+    /// Explain mode: each interpretation also carries a `reasoning`.
+    #[serde(default)]
+    pub explain: bool,
+}
+
+/// Body of `POST /readings/guest`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GuestReadingRequest {
+    pub question: String,
+    #[serde(default)]
+    pub spread: Spread,
+}
+
+/// Body of `POST /readings/estimate`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EstimateReadingRequest {
+    pub question: String,
+    #[serde(default)]
+    pub spread: Spread,
+    #[serde(default)]
+    pub detail_level: DetailLevel,
+    /// Defaults to `OPENAI_MODEL`.
+    #[serde(default)]
+    pub model: Option<String>,
+    /// Estimate `POST /readings/async` rather than `POST /readings`.
+    #[serde(default)]
+    pub queued: bool,
+}
+
+/// Body of `POST /admin/readings/import`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ImportReadingsRequest {
+    pub readings: Vec<ImportedReading>,
+}
+
+/// A legacy reading to import, stored with its original time.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ImportedReading {
+    pub user_id: i64,
+    pub created_at: DateTime<Utc>,
+    pub reading: TarotReading,
+}
+
+/// A reading's expected size and price, before it is generated.
+#[derive(Debug, Clone, Serialize)]
+pub struct ReadingEstimate {
+    pub model: String,
+    /// The level the reading would actually use (free users get `brief`).
+    pub detail_level: DetailLevel,
+    pub estimated_tokens: u32,
+    /// In USD; `None` when the model has no price in `MODEL_PRICING`.
+    pub estimated_cost: Option<f64>,
+    /// Credits the chosen route costs: `READING_CREDIT_COST` for a queued
+    /// reading, 0 for `POST /readings` (paid per reading with `payment_id`,
+    /// not credits).
+    pub credits_required: i64,
+}
+
+/// The ReadingAgent's interpretation of one drawn card.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CardInterpretation {
+    /// Id of the drawn card this interprets. The model must send it;
+    /// readings stored before it was required have none.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub card_id: Option<u8>,
+    pub card: String,
+    pub position: String,
+    pub interpretation: String,
+    /// How the card's traditional meaning bears on the question; only
+    /// requested in explain mode.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reasoning: Option<String>,
+}
+
+/// Wall-clock time spent in each pipeline stage, in milliseconds.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct StageTimings {
+    pub filter_ms: f64,
+    pub analysis_ms: f64,
+    /// Summed across redraws.
+    pub draw_ms: f64,
+    /// Summed across ReadingAgent calls.
+    pub reading_ms: f64,
+}
+
+/// How a reading was produced, for monitoring model behaviour.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ReadingMeta {
+    /// Seed of the draw that produced the final cards.
+    pub seed: u64,
+    /// Number of ReadingAgent calls made (including reprompts).
+    pub attempts: u32,
+    /// Whether the cards were redrawn after a validation failure.
+    pub reshuffled: bool,
+    /// Verbosity the reading was generated at.
+    #[serde(default)]
+    pub detail_level: DetailLevel,
+    /// Model that wrote the final answer; differs from `OPENAI_MODEL` when
+    /// a fallback answered.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Only shown to admins.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timings: Option<StageTimings>,
+    /// Reproducibility record for the final draw, when audit mode is on.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audit: Option<DrawAudit>,
+    /// Deck order behind the final draw, when the client asked for it
+    /// (`include_shuffle`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shuffle: Option<DeckShuffle>,
+    /// The draw was weighted toward the question topic's suits
+    /// (`TOPIC_SUIT_WEIGHTS`).
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub topic_biased: bool,
+    /// Code of the language the reader was told to write in.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    /// The question was too short to classify, so `language` is
+    /// `DEFAULT_LANGUAGE`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub language_fallback: bool,
+    /// `seed` sent with the completion, when `SEED_COMPLETIONS` is on.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub llm_seed: Option<u64>,
+    /// Provider backend that wrote the answer; with `llm_seed`, what it
+    /// takes to reproduce the wording.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_fingerprint: Option<String>,
+    /// The final completion's raw JSON. Never serialized; stored apart from
+    /// the reading, as `STORE_RAW_LLM` allows.
+    #[serde(skip)]
+    pub raw_llm: Option<serde_json::Value>,
+}
+
+/// A generated tarot reading.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TarotReading {
+    pub question: String,
+    pub spread: Spread,
+    #[serde(default)]
+    pub analysis: Option<QuestionAnalysis>,
+    pub cards: Vec<DrawnCard>,
+    pub interpretations: Vec<CardInterpretation>,
+    pub summary: String,
+    /// Set for sensitive topics (`SENSITIVE_TOPICS`): the reading is not
+    /// professional advice.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub disclaimer: Option<String>,
+    pub meta: ReadingMeta,
+}
+
+/// A reading as returned to the client; `id` is set once it has been stored.
+#[derive(Debug, Clone, Serialize)]
+pub struct ReadingResponse {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<i64>,
+    /// For a regenerated version, the original reading it was made from.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent_id: Option<i64>,
+    /// For a follow-up, the first reading of its conversation.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub followup_of: Option<i64>,
+    #[serde(flatten)]
+    pub reading: TarotReading,
+}
+
+/// Public view of a shared reading: the reading itself without the owner,
+/// question analysis, or generation meta.
+#[derive(Debug, Clone, Serialize)]
+pub struct SharedReading {
+    pub question: String,
+    pub spread: Spread,
+    pub cards: Vec<DrawnCard>,
+    pub interpretations: Vec<CardInterpretation>,
+    pub summary: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl SharedReading {
+    pub fn new(reading: TarotReading, expires_at: DateTime<Utc>) -> Self {
+        SharedReading {
+            question: reading.question,
+            spread: reading.spread,
+            cards: reading.cards,
+            interpretations: reading.interpretations,
+            summary: reading.summary,
+            expires_at,
+        }
+    }
+}
+
+/// Longest per-card summary in `CardImageData`, in characters.
+const IMAGE_SUMMARY_MAX_CHARS: usize = 120;
+
+/// Response of `GET /readings/{id}/card-image-data`: only what the app's
+/// share-image renderer draws, in the reading's language.
+#[derive(Debug, Clone, Serialize)]
+pub struct CardImageData {
+    pub reading_id: i64,
+    pub language: String,
+    pub spread: Spread,
+    pub cards: Vec<CardImageCard>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CardImageCard {
+    pub card_id: u8,
+    pub name: String,
+    /// `upright` or `reversed`.
+    pub orientation: &'static str,
+    /// `orientation` for display.
+    pub orientation_label: &'static str,
+    pub position: String,
+    /// First sentence of the card's interpretation, cut to
+    /// `IMAGE_SUMMARY_MAX_CHARS`.
+    pub summary: String,
+}
+
+impl CardImageData {
+    /// Card names, orientations and positions in Thai when `thai`, else
+    /// English; summaries are in whatever language the reading was written.
+    pub fn new(reading_id: i64, reading: &TarotReading, thai: bool) -> Self {
+        let cards = reading
+            .cards
+            .iter()
+            .enumerate()
+            .map(|(i, card)| {
+                let interpretation = reading
+                    .interpretations
+                    .iter()
+                    .find(|interp| interp.card_id == Some(card.card_id))
+                    .or_else(|| reading.interpretations.get(i))
+                    .map_or("", |interp| interp.interpretation.as_str());
+                let (name, position) = if thai {
+                    (
+                        card_by_id(card.card_id).map_or_else(|| card.name.clone(), |c| c.name_th),
+                        position_name_th(&card.position)
+                            .map_or_else(|| card.position.clone(), str::to_string),
+                    )
+                } else {
+                    (card.name.clone(), card.position.clone())
+                };
+                let (orientation, orientation_label) = match (card.reversed, thai) {
+                    (false, false) => ("upright", "Upright"),
+                    (true, false) => ("reversed", "Reversed"),
+                    (false, true) => ("upright", "ตั้งตรง"),
+                    (true, true) => ("reversed", "กลับหัว"),
+                };
+                CardImageCard {
+                    card_id: card.card_id,
+                    name,
+                    orientation,
+                    orientation_label,
+                    position,
+                    summary: one_line(interpretation, IMAGE_SUMMARY_MAX_CHARS),
+                }
+            })
+            .collect();
+        CardImageData {
+            reading_id,
+            language: if thai { "th" } else { "en" }.to_string(),
+            spread: reading.spread,
+            cards,
+        }
+    }
+}
+
+/// The first sentence (or line) of `text`, with an ellipsis when longer
+/// than `max_chars`.
+fn one_line(text: &str, max_chars: usize) -> String {
+    let text = text.trim();
+    let end = text
+        .char_indices()
+        .find(|(_, c)| matches!(c, '.' | '!' | '?' | '\n'))
+        .map_or(
+            text.len(),
+            |(i, c)| if c == '\n' { i } else { i + c.len_utf8() },
+        );
+    let sentence = text[..end].trim();
+    if sentence.chars().count() <= max_chars {
+        return sentence.to_string();
+    }
+    let cut: String = sentence.chars().take(max_chars - 1).collect();
+    format!("{}…", cut.trim_end())
+}
+
+/// Body of `POST /readings/session`: related questions read against one draw.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CreateSessionRequest {
+    pub questions: Vec<String>,
+    #[serde(default)]
+    pub spread: Spread,
+    /// Optional; must equal `spread.card_count()` when given.
+    #[serde(default)]
+    pub card_count: Option<usize>,
+}
+
+/// Body of `POST /readings/{id}/followups`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CreateFollowupRequest {
+    pub question: String,
+}
+
+/// One question's part of a session reading.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionSection {
+    pub question: String,
+    #[serde(default)]
+    pub analysis: Option<QuestionAnalysis>,
+    pub interpretations: Vec<CardInterpretation>,
+}
+
+/// A multi-question session reading over a single shared draw.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionReading {
+    pub spread: Spread,
+    pub cards: Vec<DrawnCard>,
+    pub sections: Vec<SessionSection>,
+    pub synthesis: String,
+    /// Set when any question is on a sensitive topic.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub disclaimer: Option<String>,
+    pub meta: ReadingMeta,
+    /// Generation ran out of time; `sections` covers only the leading
+    /// questions and `synthesis` may be empty.
+    #[serde(default)]
+    pub partial: bool,
+}
+
+/// Outcome of re-running the QuestionFilter on a stored reading's question
+/// (`POST /admin/readings/{id}/remoderate`).
+#[derive(Debug, Clone, Serialize)]
+pub struct ModerationVerdict {
+    pub reading_id: i64,
+    /// The question no longer passes the filter.
+    pub flagged: bool,
+    /// Why it failed, when `flagged`.
+    pub reason: Option<String>,
+    pub moderated_by: i64,
+    pub moderated_at: DateTime<Utc>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn image_summaries_are_one_short_line() {
+        assert_eq!(
+            one_line("  Change is coming. Be ready for it.", 120),
+            "Change is coming."
+        );
+        assert_eq!(one_line("First line\nsecond line", 120), "First line");
+        assert_eq!(one_line("No full stop", 120), "No full stop");
+        assert_eq!(one_line("", 120), "");
+        let long = "word ".repeat(40);
+        let cut = one_line(&long, 20);
+        assert_eq!(cut, "word word word word…");
+        // Thai has no full stops; cut by characters, not bytes.
+        let thai = "ความรัก".repeat(10);
+        let cut = one_line(&thai, 8);
+        assert_eq!(cut, "ความรัก…");
+    }
+}
@@ -0,0 +1,12 @@
+use serde::Serialize;
+
+/// Response of `GET /referrals/me`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ReferralStats {
+    pub referral_code: String,
+    /// Referrals the user has been rewarded for.
+    pub successful_referrals: i64,
+    /// Sign-ups with the user's code that haven't been rewarded yet.
+    pub pending_referrals: i64,
+    pub credits_earned: i64,
+}
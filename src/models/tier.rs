@@ -0,0 +1,39 @@
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+/// Account tier that features are gated on. Ordered so `tier >= required`
+/// reads as "has access".
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Tier {
+    #[default]
+    Free,
+    Paid,
+    Admin,
+}
+
+impl Tier {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Tier::Free => "free",
+            Tier::Paid => "paid",
+            Tier::Admin => "admin",
+        }
+    }
+}
+
+impl FromStr for Tier {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "free" => Ok(Tier::Free),
+            "paid" => Ok(Tier::Paid),
+            "admin" => Ok(Tier::Admin),
+            other => Err(format!("unknown tier '{}'", other)),
+        }
+    }
+}
@@ -1,8 +1,34 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
+use super::Topic;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct User {
     pub id: i64,
     pub line_id: Option<String>,
     pub name: Option<String>,
 }
+
+/// Body of `POST /admin/users/{id}/credits`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AdjustCreditsRequest {
+    /// Credits to add (positive) or remove (negative).
+    pub delta: i64,
+    /// Why; recorded in the credit ledger.
+    pub reason: String,
+}
+
+/// Body of `GET /users/me/stats`. Counts cover original readings; spend is
+/// net of refunds and includes regenerations.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UserStats {
+    pub total_readings: i64,
+    /// Since the 1st of the month, Thai time.
+    pub readings_this_month: i64,
+    pub credits_spent: i64,
+    /// `None` until a reading with a question analysis exists.
+    pub top_topic: Option<Topic>,
+    pub joined_at: DateTime<Utc>,
+}
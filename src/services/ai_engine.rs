@@ -1,7 +1,657 @@
-//! Placeholder AI engine service.
+//! AI engine service: runs the reading pipeline (draw -> ReadingAgent).
 
-/// Generate an interpretation for a reading question (stub).
-pub async fn interpret_question(_question: &str) -> String {
-    // TODO: call OpenAI / GPT wrapper
-    "This is a placeholder interpretation.".to_string()
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use super::card_picker::CardPicker;
+use super::guardrails::{GuardrailPolicy, GuardrailSet};
+use super::llm::{AskParams, LlmError};
+use super::reading_agent::{AgentFailure, Answered, ReadingAgent, SessionOutput};
+use super::retry_budget::RetryBudget;
+use super::suit_weights::TopicSuitWeights;
+use crate::config::Config;
+use crate::models::{
+    DrawnCard, QuestionAnalysis, ReadingMeta, SessionReading, SessionSection, Spread, StageTimings,
+    TarotReading, Topic,
+};
+
+/// Why the reading pipeline failed.
+#[derive(Debug, Error)]
+pub enum PipelineError {
+    #[error("Question analysis failed: {0}")]
+    Analysis(String),
+    #[error("Reading validation failed: {0}")]
+    Validation(String),
+    #[error("Failed to generate reading: {0}")]
+    Llm(#[from] LlmError),
+    /// A stage needed a retry after the shared `RetryBudget` was spent.
+    #[error("Retry budget exhausted during {stage}: {reason}")]
+    RetryBudgetExhausted { stage: &'static str, reason: String },
+}
+
+impl PipelineError {
+    fn from_agent(failure: AgentFailure) -> Self {
+        match failure {
+            AgentFailure::Llm(e) => PipelineError::Llm(e),
+            AgentFailure::Validation(reason) => PipelineError::Validation(reason),
+            AgentFailure::RetryBudgetExhausted(reason) => PipelineError::RetryBudgetExhausted {
+                stage: "reading",
+                reason,
+            },
+        }
+    }
+}
+
+/// Why a reading request failed, from the handler's point of view. Every
+/// reading endpoint reports failures through this so the same failure
+/// always gets the same status.
+#[derive(Debug, Error)]
+pub enum ReadingError {
+    /// The QuestionFilter refused the question.
+    #[error("{0}")]
+    Filtered(String),
+    #[error("Question analysis failed: {0}")]
+    AnalysisFailed(String),
+    #[error("Card draw failed: {0}")]
+    DrawFailed(String),
+    #[error("Failed to generate reading: {0}")]
+    GenerationFailed(LlmError),
+    /// The reader's answers never matched the draw.
+    #[error("Reading validation failed: {0}")]
+    ValidationFailed(String),
+    #[error("Reading quota exceeded")]
+    QuotaExceeded,
+    #[error("Insufficient credits")]
+    InsufficientCredits,
+}
+
+impl From<PipelineError> for ReadingError {
+    fn from(err: PipelineError) -> Self {
+        match err {
+            PipelineError::Analysis(reason) => ReadingError::AnalysisFailed(reason),
+            PipelineError::Validation(reason) => ReadingError::ValidationFailed(reason),
+            PipelineError::Llm(e) => ReadingError::GenerationFailed(e),
+            PipelineError::RetryBudgetExhausted { stage, reason } => {
+                log::warn!("retry budget exhausted during {}: {}", stage, reason);
+                match stage {
+                    "analysis" => ReadingError::AnalysisFailed(reason),
+                    _ => ReadingError::ValidationFailed(reason),
+                }
+            }
+        }
+    }
+}
+
+/// Knobs for the reading pipeline.
+#[derive(Debug, Clone)]
+pub struct PipelineSettings {
+    /// Redraw the cards once if the ReadingAgent still fails validation
+    /// after its reprompt.
+    pub reshuffle_on_failure: bool,
+    /// Hard cap on ReadingAgent calls per reading, across reprompts and reshuffles.
+    pub max_attempts: u32,
+    /// Retries shared by every stage of one request; see `RetryBudget`.
+    pub retry_budget: u32,
+    /// No retry starts once this much of the request has elapsed.
+    pub total_deadline: Duration,
+    /// Sampling for ReadingAgent calls (`READING_*`).
+    pub reading_params: AskParams,
+    /// Record a `DrawAudit` in each reading's meta.
+    pub audit_draws: bool,
+    /// `MAX_PROMPT_TOKENS`, `None` when trimming is disabled.
+    pub max_prompt_tokens: Option<usize>,
+    /// `SENSITIVE_TOPICS`: topic names whose readings carry a disclaimer.
+    pub sensitive_topics: Vec<String>,
+    /// `DEFAULT_LANGUAGE`, for questions too short to classify.
+    pub default_language: Language,
+    /// `SEED_COMPLETIONS`: send the draw seed as the completion `seed`.
+    pub seed_completions: bool,
+    /// `TOPIC_SUIT_WEIGHTS`: per-topic draw bias.
+    pub suit_weights: TopicSuitWeights,
+    /// Guardrails composed into the ReadingAgent's prompts.
+    pub guardrails: GuardrailSet,
+}
+
+impl PipelineSettings {
+    pub fn from_config(config: &Config) -> Self {
+        PipelineSettings {
+            reshuffle_on_failure: config.reshuffle_on_failure,
+            max_attempts: config.reading_max_attempts,
+            retry_budget: config.pipeline_retry_budget,
+            total_deadline: Duration::from_secs(config.llm_total_deadline_secs),
+            reading_params: AskParams {
+                temperature: Some(config.reading_temperature),
+                max_tokens: config.reading_max_tokens,
+                top_p: config.reading_top_p,
+                seed: None,
+            },
+            audit_draws: config.audit_draws,
+            max_prompt_tokens: Some(config.max_prompt_tokens).filter(|&max| max > 0),
+            sensitive_topics: config.sensitive_topics.clone(),
+            default_language: config.default_language,
+            seed_completions: config.seed_completions,
+            suit_weights: TopicSuitWeights::from_config(config),
+            guardrails: GuardrailPolicy::from_config(config).reading,
+        }
+    }
+
+    /// The completion seed for a reading drawn by `picker`, if seeding is on.
+    pub fn completion_seed(&self, picker: &CardPicker) -> Option<u64> {
+        self.seed_completions.then(|| picker.seed())
+    }
+
+    /// Whether readings on `topic` need the disclaimer treatment.
+    pub fn is_sensitive(&self, topic: Topic) -> bool {
+        self.sensitive_topics
+            .iter()
+            .any(|t| t.eq_ignore_ascii_case(topic.as_str()))
+    }
+
+    /// A fresh budget for one request.
+    pub fn budget(&self) -> RetryBudget {
+        RetryBudget::new(self.retry_budget).with_deadline(self.total_deadline)
+    }
+}
+
+/// Milliseconds since `start`.
+pub fn elapsed_ms(start: Instant) -> f64 {
+    start.elapsed().as_secs_f64() * 1000.0
+}
+
+/// Draw cards for `spread` and generate the reading with `agent`. Fills
+/// `draw_ms`/`reading_ms` of `meta.timings`; callers add the earlier stages.
+pub async fn generate_reading(
+    agent: &ReadingAgent<'_>,
+    question: &str,
+    analysis: Option<QuestionAnalysis>,
+    spread: Spread,
+    picker: CardPicker,
+    settings: &PipelineSettings,
+    budget: &mut RetryBudget,
+) -> Result<TarotReading, PipelineError> {
+    let mut picker = picker;
+    let mut attempts = 0;
+    let mut reshuffled = false;
+    let mut timings = StageTimings::default();
+
+    loop {
+        let started = Instant::now();
+        let cards = picker.draw(spread);
+        timings.draw_ms += elapsed_ms(started);
+        let started = Instant::now();
+        let result = agent
+            .generate(
+                question,
+                &cards,
+                &mut attempts,
+                settings.max_attempts,
+                budget,
+            )
+            .await;
+        timings.reading_ms += elapsed_ms(started);
+        match result {
+            Ok(Answered {
+                output,
+                model,
+                system_fingerprint,
+                raw,
+            }) => {
+                return Ok(TarotReading {
+                    question: question.to_string(),
+                    spread,
+                    analysis,
+                    cards,
+                    interpretations: output.interpretations,
+                    summary: output.summary,
+                    disclaimer: output.disclaimer,
+                    meta: ReadingMeta {
+                        seed: picker.seed(),
+                        attempts,
+                        reshuffled,
+                        detail_level: agent.detail(),
+                        model: Some(model),
+                        timings: Some(timings),
+                        audit: settings.audit_draws.then(|| picker.audit(spread)),
+                        topic_biased: picker.suit_weights().is_some(),
+                        llm_seed: agent.seed(),
+                        system_fingerprint,
+                        raw_llm: Some(raw),
+                        ..agent.language_meta()
+                    },
+                });
+            }
+            Err(AgentFailure::Validation(reason))
+                if settings.reshuffle_on_failure
+                    && !reshuffled
+                    && attempts < settings.max_attempts =>
+            {
+                if !budget.try_consume() {
+                    return Err(PipelineError::RetryBudgetExhausted {
+                        stage: "reshuffle",
+                        reason: budget.exhausted_reason(reason),
+                    });
+                }
+                log::warn!("reshuffling after validation failure: {}", reason);
+                picker = picker.reshuffled();
+                reshuffled = true;
+            }
+            Err(failure) => return Err(PipelineError::from_agent(failure)),
+        }
+    }
+}
+
+/// Re-run only the ReadingAgent over `original`'s cards for fresh wording.
+/// The question, analysis, seed and audit carry over; there is no reshuffle,
+/// since keeping the draw is the point.
+pub async fn regenerate_reading(
+    agent: &ReadingAgent<'_>,
+    original: &TarotReading,
+    settings: &PipelineSettings,
+    budget: &mut RetryBudget,
+) -> Result<TarotReading, PipelineError> {
+    let mut attempts = 0;
+    let started = Instant::now();
+    let Answered {
+        output,
+        model,
+        system_fingerprint,
+        raw,
+    } = agent
+        .generate(
+            &original.question,
+            &original.cards,
+            &mut attempts,
+            settings.max_attempts,
+            budget,
+        )
+        .await
+        .map_err(PipelineError::from_agent)?;
+    Ok(TarotReading {
+        question: original.question.clone(),
+        spread: original.spread,
+        analysis: original.analysis.clone(),
+        cards: original.cards.clone(),
+        interpretations: output.interpretations,
+        summary: output.summary,
+        disclaimer: output.disclaimer,
+        meta: ReadingMeta {
+            attempts,
+            detail_level: agent.detail(),
+            model: Some(model),
+            timings: Some(StageTimings {
+                reading_ms: elapsed_ms(started),
+                ..StageTimings::default()
+            }),
+            language: agent.language_meta().language,
+            language_fallback: agent.language_meta().language_fallback,
+            llm_seed: agent.seed(),
+            system_fingerprint,
+            raw_llm: Some(raw),
+            ..original.meta.clone()
+        },
+    })
+}
+
+/// Draw one shared spread and read every question in `questions` against it
+/// with a single generation call. `analyses` pairs with `questions` by index.
+pub async fn generate_session(
+    agent: &ReadingAgent<'_>,
+    questions: &[String],
+    analyses: Vec<QuestionAnalysis>,
+    spread: Spread,
+    picker: CardPicker,
+    settings: &PipelineSettings,
+    budget: &mut RetryBudget,
+) -> Result<SessionReading, PipelineError> {
+    let mut picker = picker;
+    let mut attempts = 0;
+    let mut reshuffled = false;
+    // The best cut-off answer so far, with the draw it was for.
+    let mut partial: Option<(Answered<SessionOutput>, Vec<DrawnCard>, CardPicker)> = None;
+
+    loop {
+        let cards = picker.draw(spread);
+        let mut round_partial = None;
+        let result = agent
+            .generate_session(
+                questions,
+                &cards,
+                &mut attempts,
+                settings.max_attempts,
+                budget,
+                &mut round_partial,
+            )
+            .await;
+        if let Some(answered) = round_partial {
+            partial = Some((answered, cards.clone(), picker));
+        }
+        let (error, timed_out) = match result {
+            Ok(answered) => {
+                let meta = session_meta(agent, settings, spread, picker, attempts, reshuffled);
+                return Ok(session_reading(
+                    questions, analyses, spread, cards, answered, meta, false,
+                ));
+            }
+            Err(AgentFailure::Validation(reason))
+                if settings.reshuffle_on_failure
+                    && !reshuffled
+                    && attempts < settings.max_attempts =>
+            {
+                if budget.try_consume() {
+                    log::warn!("reshuffling session after validation failure: {}", reason);
+                    picker = picker.reshuffled();
+                    reshuffled = true;
+                    continue;
+                }
+                let error = PipelineError::RetryBudgetExhausted {
+                    stage: "reshuffle",
+                    reason: budget.exhausted_reason(reason),
+                };
+                (error, budget.deadline_hit())
+            }
+            Err(failure) => {
+                let timed_out = match &failure {
+                    AgentFailure::Llm(e) => e.is_timeout(),
+                    AgentFailure::RetryBudgetExhausted(_) => budget.deadline_hit(),
+                    AgentFailure::Validation(_) => false,
+                };
+                (PipelineError::from_agent(failure), timed_out)
+            }
+        };
+        if timed_out && let Some((answered, cards, picker)) = partial {
+            log::warn!(
+                "session ran out of time; returning {} of {} sections",
+                answered.output.sections.len(),
+                questions.len()
+            );
+            let meta = session_meta(agent, settings, spread, picker, attempts, reshuffled);
+            return Ok(session_reading(
+                questions, analyses, spread, cards, answered, meta, true,
+            ));
+        }
+        return Err(error);
+    }
+}
+
+fn session_meta(
+    agent: &ReadingAgent<'_>,
+    settings: &PipelineSettings,
+    spread: Spread,
+    picker: CardPicker,
+    attempts: u32,
+    reshuffled: bool,
+) -> ReadingMeta {
+    ReadingMeta {
+        seed: picker.seed(),
+        attempts,
+        reshuffled,
+        detail_level: agent.detail(),
+        model: None,
+        timings: None,
+        audit: settings.audit_draws.then(|| picker.audit(spread)),
+        topic_biased: picker.suit_weights().is_some(),
+        llm_seed: agent.seed(),
+        ..agent.language_meta()
+    }
+}
+
+/// Pair each answered section with its question and analysis. A partial
+/// answer covers only the leading questions.
+fn session_reading(
+    questions: &[String],
+    analyses: Vec<QuestionAnalysis>,
+    spread: Spread,
+    cards: Vec<DrawnCard>,
+    answered: Answered<SessionOutput>,
+    mut meta: ReadingMeta,
+    partial: bool,
+) -> SessionReading {
+    let Answered {
+        output,
+        model,
+        system_fingerprint,
+        raw,
+    } = answered;
+    meta.model = Some(model);
+    meta.system_fingerprint = system_fingerprint;
+    meta.raw_llm = Some(raw);
+    let sections = questions
+        .iter()
+        .zip(
+            analyses
+                .into_iter()
+                .map(Some)
+                .chain(std::iter::repeat(None)),
+        )
+        .zip(output.sections)
+        .map(|((question, analysis), interpretations)| SessionSection {
+            question: question.clone(),
+            analysis,
+            interpretations,
+        })
+        .collect();
+    SessionReading {
+        spread,
+        cards,
+        sections,
+        synthesis: output.synthesis,
+        disclaimer: output.disclaimer,
+        meta,
+        partial,
+    }
+}
+
+/// Language of a question, used to pick prompts and normalization rules.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Language {
+    Thai,
+    English,
+    Other,
+}
+
+impl Language {
+    pub const ALL: [Language; 3] = [Language::Thai, Language::English, Language::Other];
+
+    /// The language for a `code` (`th`, `en`, `other`), e.g. from config.
+    pub fn from_code(code: &str) -> Option<Language> {
+        Language::ALL
+            .into_iter()
+            .find(|l| l.code().eq_ignore_ascii_case(code.trim()))
+    }
+
+    /// The name used when telling the model which language to write in.
+    pub fn name(&self) -> Option<&'static str> {
+        match self {
+            Language::Thai => Some("Thai"),
+            Language::English => Some("English"),
+            Language::Other => None,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            Language::Thai => "th",
+            Language::English => "en",
+            Language::Other => "other",
+        }
+    }
+}
+
+fn is_thai(c: char) -> bool {
+    ('\u{0E00}'..='\u{0E7F}').contains(&c)
+}
+
+/// Classify a question by script: any Thai characters outnumbering Latin
+/// letters means Thai, otherwise Latin letters mean English.
+pub fn detect_language(text: &str) -> Language {
+    let thai = text.chars().filter(|c| is_thai(*c)).count();
+    let latin = text.chars().filter(|c| c.is_ascii_alphabetic()).count();
+    if thai > 0 && thai >= latin {
+        Language::Thai
+    } else if latin > 0 {
+        Language::English
+    } else {
+        Language::Other
+    }
+}
+
+/// Fewer letters than this are too little to tell the language from.
+const MIN_CLASSIFIABLE_LETTERS: usize = 3;
+
+/// The language a reading is written in, and whether it came from
+/// `DEFAULT_LANGUAGE` rather than the question.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResolvedLanguage {
+    pub language: Language,
+    pub fallback: bool,
+}
+
+/// `detect_language`, except that text with too few letters to classify
+/// (emoji, digits, "ok?") gets `default`. Text in another script is still
+/// `Other`.
+pub fn resolve_language(text: &str, default: Language) -> ResolvedLanguage {
+    let letters = text.chars().filter(|c| c.is_alphabetic()).count();
+    if letters < MIN_CLASSIFIABLE_LETTERS {
+        log::info!(
+            "question too short to classify; using DEFAULT_LANGUAGE={}",
+            default.code()
+        );
+        return ResolvedLanguage {
+            language: default,
+            fallback: true,
+        };
+    }
+    ResolvedLanguage {
+        language: detect_language(text),
+        fallback: false,
+    }
+}
+
+/// Thai abbreviation/repetition marks (ฯ, ๆ) treated as punctuation.
+const THAI_MARKS: [char; 2] = ['\u{0E2F}', '\u{0E46}'];
+
+/// Polite sentence-final particles that don't change a Thai question's meaning.
+const THAI_PARTICLES: [&str; 6] = ["ครับ", "ค่ะ", "คะ", "จ้ะ", "จ้า", "นะ"];
+
+/// Normalize a question so trivially different phrasings compare equal:
+/// lowercase (English), strip punctuation and whitespace, and for Thai fold
+/// the decomposed sara am and drop trailing polite particles.
+pub fn normalize_question(question: &str, language: Language) -> String {
+    let text = question
+        .replace('\u{200B}', "")
+        .replace("\u{0E4D}\u{0E32}", "\u{0E33}");
+    let text = match language {
+        Language::Thai => text,
+        _ => text.to_lowercase(),
+    };
+    let mut words: Vec<String> = text
+        .split_whitespace()
+        .map(|w| {
+            w.chars()
+                .filter(|c| (c.is_alphanumeric() || is_thai(*c)) && !THAI_MARKS.contains(c))
+                .collect::<String>()
+        })
+        .filter(|w| !w.is_empty())
+        .collect();
+    if language == Language::Thai {
+        let mut joined = words.concat();
+        while let Some(stripped) = THAI_PARTICLES
+            .iter()
+            .find_map(|p| joined.strip_suffix(p).filter(|s| !s.is_empty()))
+        {
+            joined = stripped.to_string();
+        }
+        words = vec![joined];
+    }
+    words.join(" ")
+}
+
+/// Stable hex fingerprint of a question, independent of casing, whitespace,
+/// and punctuation. Used as the cache key and stored on readings for
+/// "popular questions" analytics.
+pub fn fingerprint(question: &str, language: Language) -> String {
+    let normalized = normalize_question(question, language);
+    let mut hasher = Sha256::new();
+    hasher.update(language.code().as_bytes());
+    hasher.update(b":");
+    hasher.update(normalized.as_bytes());
+    hex::encode(hasher.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fingerprints_ignore_case_spacing_and_punctuation() {
+        assert_eq!(
+            fingerprint("Will I get the job?", Language::English),
+            fingerprint("  will i GET the job!!  ", Language::English)
+        );
+        assert_ne!(
+            fingerprint("Will I get the job?", Language::English),
+            fingerprint("Will I lose the job?", Language::English)
+        );
+        assert_eq!(
+            normalize_question("Will   I get, the job?", Language::English),
+            "will i get the job"
+        );
+    }
+
+    #[test]
+    fn thai_fingerprints_drop_polite_particles() {
+        assert_eq!(
+            fingerprint("งานใหม่จะดีไหมคะ", Language::Thai),
+            fingerprint("งานใหม่ จะดีไหม ครับ?", Language::Thai)
+        );
+        assert_eq!(
+            normalize_question("ทํางาน", Language::Thai),
+            normalize_question("ทำงาน", Language::Thai)
+        );
+    }
+
+    #[test]
+    fn the_same_text_in_another_language_is_another_fingerprint() {
+        assert_ne!(
+            fingerprint("ok", Language::English),
+            fingerprint("ok", Language::Other)
+        );
+    }
+
+    #[test]
+    fn too_short_to_classify_falls_back_to_the_default() {
+        for text in ["ok?", "🙏🙏", "123", ""] {
+            assert_eq!(
+                resolve_language(text, Language::Thai),
+                ResolvedLanguage {
+                    language: Language::Thai,
+                    fallback: true,
+                },
+                "{:?}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn a_clearly_detected_language_ignores_the_default() {
+        assert_eq!(
+            resolve_language("Will I get the job?", Language::Thai),
+            ResolvedLanguage {
+                language: Language::English,
+                fallback: false,
+            }
+        );
+        assert_eq!(
+            resolve_language("ฉันจะได้งานใหม่ไหม", Language::English),
+            ResolvedLanguage {
+                language: Language::Thai,
+                fallback: false,
+            }
+        );
+    }
 }
@@ -0,0 +1,216 @@
+//! Seeded card drawing.
+
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use crate::models::{DeckShuffle, DrawAudit, DrawnCard, Spread, SuitWeights, full_deck};
+
+/// RNG recorded in draw audits.
+pub const DRAW_RNG_ALGORITHM: &str = "ChaCha8 (rand_chacha 0.3, seed_from_u64)";
+
+/// The draw procedure recorded in audits. Changing `draw` means changing this.
+pub const DRAW_PROCEDURE: &str = "v1: deck of card ids 0..=77 in ascending order; \
+Fisher-Yates shuffle with rand 0.8 SliceRandom::shuffle; deal the first card to \
+position 1, second to position 2, ...; after dealing each card draw gen_bool(0.5) \
+from the same RNG, true meaning reversed. With suit_weights, the shuffle is \
+instead: for each card in id order draw u = gen::<f64>() and key it ln(u) / w, w \
+being its suit's weight (1 for major arcana); order the deck by key, highest \
+first (ties by id)";
+
+/// Draws cards for a spread from a seeded RNG, so a (seed, spread) pair
+/// always yields the same cards.
+#[derive(Debug, Clone, Copy)]
+pub struct CardPicker {
+    seed: u64,
+    suit_weights: Option<SuitWeights>,
+}
+
+impl CardPicker {
+    pub fn new(seed: u64) -> Self {
+        CardPicker {
+            seed,
+            suit_weights: None,
+        }
+    }
+
+    /// Bias the shuffle toward heavier suits; `None` keeps it uniform.
+    pub fn with_suit_weights(mut self, suit_weights: Option<SuitWeights>) -> Self {
+        self.suit_weights = suit_weights;
+        self
+    }
+
+    pub fn suit_weights(&self) -> Option<SuitWeights> {
+        self.suit_weights
+    }
+
+    /// A picker with a fresh random seed.
+    pub fn random() -> Self {
+        CardPicker::new(rand::random())
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// A picker for a redraw, deterministically derived from this seed
+    /// (splitmix64) so the whole reading stays reproducible.
+    pub fn reshuffled(&self) -> Self {
+        let mut z = self.seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        CardPicker::new(z ^ (z >> 31)).with_suit_weights(self.suit_weights)
+    }
+
+    /// Shuffle the deck and deal one card per spread position.
+    pub fn draw(&self, spread: Spread) -> Vec<DrawnCard> {
+        self.deal(spread).1
+    }
+
+    /// The draw plus what an auditor needs to reproduce it.
+    pub fn audit(&self, spread: Spread) -> DrawAudit {
+        let (deck_order, cards) = self.deal(spread);
+        DrawAudit {
+            algorithm: DRAW_RNG_ALGORITHM.to_string(),
+            procedure: DRAW_PROCEDURE.to_string(),
+            seed: self.seed,
+            spread,
+            suit_weights: self.suit_weights,
+            deck_order,
+            cards,
+        }
+    }
+
+    /// The shuffled deck behind `draw(spread)` and where each drawn card sits
+    /// in it.
+    pub fn shuffle(&self, spread: Spread) -> DeckShuffle {
+        let (deck_order, cards) = self.deal(spread);
+        let drawn_indices = cards
+            .iter()
+            .filter_map(|card| deck_order.iter().position(|&id| id == card.card_id))
+            .collect();
+        DeckShuffle {
+            deck_order,
+            drawn_indices,
+        }
+    }
+
+    /// `DRAW_PROCEDURE`: returns the shuffled deck order and the dealt cards.
+    fn deal(&self, spread: Spread) -> (Vec<u8>, Vec<DrawnCard>) {
+        let mut rng = ChaCha8Rng::seed_from_u64(self.seed);
+        let mut deck = full_deck();
+        match self.suit_weights {
+            Some(weights) => {
+                // Weighted sampling without replacement (Efraimidis-Spirakis).
+                let mut keyed: Vec<_> = deck
+                    .into_iter()
+                    .map(|card| (rng.r#gen::<f64>().ln() / weights.for_card(&card), card))
+                    .collect();
+                keyed.sort_by(|(a, x), (b, y)| b.total_cmp(a).then(x.id.cmp(&y.id)));
+                deck = keyed.into_iter().map(|(_, card)| card).collect();
+            }
+            None => deck.shuffle(&mut rng),
+        }
+        let deck_order = deck.iter().map(|card| card.id).collect();
+        let cards = spread
+            .positions()
+            .iter()
+            .zip(deck)
+            .map(|(position, card)| DrawnCard {
+                card_id: card.id,
+                name: card.name,
+                position: position.to_string(),
+                reversed: rng.gen_bool(0.5),
+            })
+            .collect();
+        (deck_order, cards)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Suit, card_by_id};
+
+    #[test]
+    fn drawn_cards_sit_at_the_reported_shuffle_indices() {
+        let weighted = SuitWeights {
+            cups: 3.0,
+            ..SuitWeights::default()
+        };
+        for picker in [
+            CardPicker::new(42),
+            CardPicker::new(42).with_suit_weights(Some(weighted)),
+        ] {
+            let shuffle = picker.shuffle(Spread::CelticCross);
+            let cards = picker.draw(Spread::CelticCross);
+            assert_eq!(shuffle.deck_order.len(), full_deck().len());
+            assert_eq!(shuffle.drawn_indices.len(), cards.len());
+            for (card, &index) in cards.iter().zip(&shuffle.drawn_indices) {
+                assert_eq!(shuffle.deck_order[index], card.card_id);
+            }
+            // Cards are dealt from the top of the shuffled deck.
+            assert_eq!(shuffle.drawn_indices, (0..cards.len()).collect::<Vec<_>>());
+        }
+    }
+
+    fn cups_drawn(weights: Option<SuitWeights>) -> usize {
+        (0..2000)
+            .flat_map(|seed| {
+                CardPicker::new(seed)
+                    .with_suit_weights(weights)
+                    .draw(Spread::ThreeCard)
+            })
+            .filter(|card| card_by_id(card.card_id).unwrap().suit == Some(Suit::Cups))
+            .count()
+    }
+
+    #[test]
+    fn love_weights_draw_cups_more_often() {
+        let love = SuitWeights {
+            cups: 2.0,
+            ..SuitWeights::default()
+        };
+        let uniform = cups_drawn(None);
+        let biased = cups_drawn(Some(love));
+        // 14 of 78 cards are cups: about 1077 of 6000 uniformly.
+        assert!((900..1250).contains(&uniform), "{}", uniform);
+        assert!(
+            biased as f64 > uniform as f64 * 1.4,
+            "{} vs {}",
+            biased,
+            uniform
+        );
+    }
+
+    #[test]
+    fn heavy_weights_leave_every_suit_possible() {
+        let skewed = Some(SuitWeights {
+            cups: 10.0,
+            ..SuitWeights::default()
+        });
+        let suits: Vec<_> = (0..500)
+            .flat_map(|seed| {
+                CardPicker::new(seed)
+                    .with_suit_weights(skewed)
+                    .draw(Spread::ThreeCard)
+            })
+            .filter_map(|card| card_by_id(card.card_id).unwrap().suit)
+            .collect();
+        for suit in [Suit::Wands, Suit::Swords, Suit::Pentacles] {
+            assert!(suits.contains(&suit), "{:?} never drawn", suit);
+        }
+    }
+
+    #[test]
+    fn the_shuffle_follows_the_seed() {
+        assert_eq!(
+            CardPicker::new(7).shuffle(Spread::ThreeCard).deck_order,
+            CardPicker::new(7).shuffle(Spread::ThreeCard).deck_order
+        );
+        assert_ne!(
+            CardPicker::new(7).shuffle(Spread::ThreeCard).deck_order,
+            CardPicker::new(8).shuffle(Spread::ThreeCard).deck_order
+        );
+    }
+}
@@ -0,0 +1,31 @@
+//! Periodic purge of expired rows from tables with a TTL.
+//!
+//! Every pass is a plain `DELETE ... WHERE expires_at < NOW()`, so running
+//! it on several instances at once is harmless.
+
+use std::time::Duration;
+
+use sqlx::PgPool;
+
+use crate::db::{DbError, delete_expired_line_events, delete_expired_shares};
+
+/// One cleanup pass. Returns the number of rows removed.
+pub async fn purge_expired(pool: &PgPool) -> Result<u64, DbError> {
+    let shares = delete_expired_shares(pool).await?;
+    let line_events = delete_expired_line_events(pool).await?;
+    Ok(shares + line_events)
+}
+
+/// Run `purge_expired` every `interval`, forever. Failures are logged and
+/// retried on the next tick.
+pub async fn run_cleanup(pool: PgPool, interval: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+        match purge_expired(&pool).await {
+            Ok(0) => {}
+            Ok(removed) => log::info!("cleanup removed {} expired rows", removed),
+            Err(e) => log::warn!("cleanup pass failed: {}", e),
+        }
+    }
+}
@@ -0,0 +1,152 @@
+//! Per-user conversation memory: the last few readings (question + brief
+//! result), kept in Redis so new readings can refer back to them.
+//!
+//! Turns live in the list `conversation:<user_id>`, newest first, trimmed to
+//! `max_turns` and expiring `ttl_secs` after the last write.
+
+use chrono::Utc;
+use redis::AsyncCommands;
+use redis::aio::ConnectionManager;
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+
+/// Longest stored result summary, in characters.
+const TURN_SUMMARY_CHARS: usize = 200;
+
+#[derive(Debug, Clone)]
+pub struct MemorySettings {
+    pub max_turns: usize,
+    pub ttl_secs: i64,
+    /// Cap on the condensed context injected into a prompt, in characters.
+    pub max_context_chars: usize,
+}
+
+impl MemorySettings {
+    pub fn from_config(config: &Config) -> Self {
+        MemorySettings {
+            max_turns: config.memory_max_turns,
+            ttl_secs: config.memory_ttl_secs,
+            max_context_chars: config.memory_max_context_chars,
+        }
+    }
+}
+
+/// One remembered reading.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MemoryTurn {
+    pub question: String,
+    pub summary: String,
+    /// Unix timestamp (seconds).
+    pub created_at: i64,
+}
+
+impl MemoryTurn {
+    pub fn new(question: &str, summary: &str) -> Self {
+        MemoryTurn {
+            question: question.to_string(),
+            summary: summary.chars().take(TURN_SUMMARY_CHARS).collect(),
+            created_at: Utc::now().timestamp(),
+        }
+    }
+}
+
+fn memory_key(user_id: i64) -> String {
+    format!("conversation:{}", user_id)
+}
+
+/// Remember a turn, dropping the oldest beyond `max_turns` and refreshing the TTL.
+pub async fn record_turn(
+    redis: &ConnectionManager,
+    user_id: i64,
+    turn: &MemoryTurn,
+    settings: &MemorySettings,
+) -> redis::RedisResult<()> {
+    let key = memory_key(user_id);
+    let payload = serde_json::to_string(turn).unwrap_or_default();
+    let mut conn = redis.clone();
+    redis::pipe()
+        .atomic()
+        .lpush(&key, payload)
+        .ignore()
+        .ltrim(&key, 0, settings.max_turns as isize - 1)
+        .ignore()
+        .expire(&key, settings.ttl_secs)
+        .ignore()
+        .query_async(&mut conn)
+        .await
+}
+
+/// The user's remembered turns, newest first. Unreadable entries are skipped.
+pub async fn recent_turns(
+    redis: &ConnectionManager,
+    user_id: i64,
+    settings: &MemorySettings,
+) -> redis::RedisResult<Vec<MemoryTurn>> {
+    let mut conn = redis.clone();
+    let raw: Vec<String> = conn
+        .lrange(memory_key(user_id), 0, settings.max_turns as isize - 1)
+        .await?;
+    Ok(raw
+        .iter()
+        .filter_map(|entry| serde_json::from_str(entry).ok())
+        .collect())
+}
+
+/// Forget everything remembered for the user.
+pub async fn clear_memory(redis: &ConnectionManager, user_id: i64) -> redis::RedisResult<()> {
+    let mut conn = redis.clone();
+    let _: () = conn.del(memory_key(user_id)).await?;
+    Ok(())
+}
+
+/// Condense turns (newest first) into prompt context, oldest line first.
+/// Keeps the newest turns that fit in `max_chars`; `None` if nothing fits.
+pub fn condense(turns: &[MemoryTurn], max_chars: usize) -> Option<String> {
+    let mut lines = Vec::new();
+    let mut used = 0;
+    for turn in turns {
+        let line = format!("- Q: {} → {}", turn.question, turn.summary);
+        let len = line.chars().count() + 1;
+        if used + len > max_chars {
+            break;
+        }
+        used += len;
+        lines.push(line);
+    }
+    if lines.is_empty() {
+        return None;
+    }
+    lines.reverse();
+    Some(lines.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn turn(question: &str) -> MemoryTurn {
+        MemoryTurn::new(question, "a short summary")
+    }
+
+    #[test]
+    fn condenses_oldest_first_keeping_the_newest_that_fit() {
+        let turns = [turn("third"), turn("second"), turn("first")];
+        assert_eq!(
+            condense(&turns, 1000).unwrap(),
+            "- Q: first → a short summary\n- Q: second → a short summary\n- Q: third → a short summary"
+        );
+        assert_eq!(
+            condense(&turns, 60).unwrap(),
+            "- Q: second → a short summary\n- Q: third → a short summary"
+        );
+        assert_eq!(condense(&turns, 10), None);
+        assert_eq!(condense(&[], 1000), None);
+    }
+
+    #[test]
+    fn truncates_long_summaries() {
+        let turn = MemoryTurn::new("q", &"x".repeat(500));
+        assert_eq!(turn.summary.chars().count(), TURN_SUMMARY_CHARS);
+    }
+}
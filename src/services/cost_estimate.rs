@@ -0,0 +1,130 @@
+//! Cost previews for `POST /readings/estimate`: token counts from the same
+//! prompt builders a reading uses, priced with `MODEL_PRICING`. Nothing
+//! here calls the LLM.
+//!
+//! `MODEL_PRICING` is comma-separated `model=input/output` entries in USD
+//! per million tokens; entries override `BUILTIN_PRICING`.
+
+use std::collections::HashMap;
+
+use super::card_picker::CardPicker;
+use super::guardrails::GuardrailPolicy;
+use super::prompt_budget::estimate_tokens;
+use super::question_analysis::analysis_system_prompt;
+use super::reading_agent::{PromptVersion, ReadingAgent};
+use crate::config::Config;
+use crate::models::{DetailLevel, Mood, Spread, Topic};
+
+/// List prices (USD per million input/output tokens) of the default models.
+const BUILTIN_PRICING: [(&str, f64, f64); 2] =
+    [("gpt-4o-mini", 0.15, 0.60), ("gpt-4o", 2.50, 10.00)];
+
+/// Tokens per requested word of output. Thai runs well above English, so
+/// this errs high.
+const TOKENS_PER_WORD: u32 = 2;
+
+/// JSON keys and card/position names around each interpretation.
+const OUTPUT_TOKENS_PER_CARD: u32 = 20;
+
+/// The analysis answer is a short JSON object.
+const ANALYSIS_OUTPUT_TOKENS: u32 = 30;
+
+/// USD per million tokens for one model.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ModelPrice {
+    pub input: f64,
+    pub output: f64,
+}
+
+/// Model id → price, parsed once at startup.
+#[derive(Debug, Clone)]
+pub struct PricingTable {
+    prices: HashMap<String, ModelPrice>,
+}
+
+impl PricingTable {
+    pub fn from_config(config: &Config) -> Self {
+        let mut prices: HashMap<String, ModelPrice> = BUILTIN_PRICING
+            .iter()
+            .map(|(model, input, output)| {
+                (
+                    model.to_string(),
+                    ModelPrice {
+                        input: *input,
+                        output: *output,
+                    },
+                )
+            })
+            .collect();
+        for entry in &config.model_pricing {
+            let parsed = entry.split_once('=').and_then(|(model, price)| {
+                let (input, output) = price.split_once('/')?;
+                Some((
+                    model.trim(),
+                    ModelPrice {
+                        input: input.trim().parse().ok()?,
+                        output: output.trim().parse().ok()?,
+                    },
+                ))
+            });
+            match parsed {
+                Some((model, price)) => {
+                    prices.insert(model.to_string(), price);
+                }
+                None => log::warn!("ignoring malformed MODEL_PRICING entry '{}'", entry),
+            }
+        }
+        PricingTable { prices }
+    }
+
+    pub fn get(&self, model: &str) -> Option<ModelPrice> {
+        self.prices.get(model).copied()
+    }
+}
+
+/// Estimated tokens for one reading, analysis call included.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TokenEstimate {
+    pub input_tokens: u32,
+    pub output_tokens: u32,
+}
+
+impl TokenEstimate {
+    pub fn total(&self) -> u32 {
+        self.input_tokens + self.output_tokens
+    }
+
+    /// Cost in USD at `price`.
+    pub fn cost(&self, price: ModelPrice) -> f64 {
+        (f64::from(self.input_tokens) * price.input + f64::from(self.output_tokens) * price.output)
+            / 1_000_000.0
+    }
+}
+
+/// Tokens a reading of `question` would use. The prompt is built with a
+/// fixed draw and the general template, so equal requests get equal
+/// estimates; the answer is sized from the detail level's word targets,
+/// capped at its `max_tokens`.
+pub fn estimate_reading_tokens(
+    question: &str,
+    spread: Spread,
+    detail: DetailLevel,
+) -> TokenEstimate {
+    let cards = CardPicker::new(0).draw(spread);
+    let guardrails = GuardrailPolicy::default();
+    let system =
+        PromptVersion::Stable.system_prompt(Topic::Other, Mood::Neutral, &guardrails.reading);
+    let prompt = ReadingAgent::build_prompt(&guardrails.reading, question, &cards, true);
+    let reading_input = estimate_tokens(&system) + estimate_tokens(&prompt);
+    let analysis_input = estimate_tokens(&analysis_system_prompt(&guardrails.analysis))
+        + estimate_tokens(&guardrails.analysis.user_input(question));
+
+    let card_count = cards.len() as u32;
+    let words = detail.words_per_card() * card_count + detail.summary_words();
+    let reading_output =
+        (words * TOKENS_PER_WORD + OUTPUT_TOKENS_PER_CARD * card_count).min(detail.max_tokens());
+    TokenEstimate {
+        input_tokens: (reading_input + analysis_input) as u32,
+        output_tokens: reading_output + ANALYSIS_OUTPUT_TOKENS,
+    }
+}
@@ -0,0 +1,144 @@
+//! Credit balance changes. Every change is written to `credit_ledger` in the
+//! same transaction as the balance update.
+
+use sqlx::PgPool;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum CreditError {
+    #[error("Insufficient credits")]
+    Insufficient,
+    #[error("User not found")]
+    UserNotFound,
+    /// The change doesn't fit in a balance (an `i64`).
+    #[error("Credit change is out of range")]
+    OutOfRange,
+    #[error("Database error: {0}")]
+    Db(#[from] sqlx::Error),
+}
+
+/// Deduct `amount` credits, failing with `Insufficient` rather than going negative.
+/// Returns the new balance.
+///
+/// The balance check is part of the `UPDATE`, so concurrent deductions
+/// can't both pass a check made against the same old balance.
+pub async fn deduct(
+    pool: &PgPool,
+    user_id: i64,
+    amount: i64,
+    reason: &str,
+) -> Result<i64, CreditError> {
+    let mut tx = pool.begin().await?;
+    let new_balance = deduct_in(&mut tx, user_id, amount, reason).await?;
+    tx.commit().await?;
+    Ok(new_balance)
+}
+
+/// `deduct` inside a caller's transaction, for changes that must commit
+/// together with other writes (e.g. refunding a payment).
+pub async fn deduct_in(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    user_id: i64,
+    amount: i64,
+    reason: &str,
+) -> Result<i64, CreditError> {
+    let new_balance: Option<i64> = sqlx::query_scalar(
+        "UPDATE users SET credits = credits - $1 WHERE id = $2 AND credits >= $1 \
+         RETURNING credits",
+    )
+    .bind(amount)
+    .bind(user_id)
+    .fetch_optional(&mut **tx)
+    .await?;
+    let Some(new_balance) = new_balance else {
+        // No row updated: either the user is missing or the balance is short.
+        let exists: bool = sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM users WHERE id = $1)")
+            .bind(user_id)
+            .fetch_one(&mut **tx)
+            .await?;
+        return Err(if exists {
+            CreditError::Insufficient
+        } else {
+            CreditError::UserNotFound
+        });
+    };
+    record(tx, user_id, -amount, reason).await?;
+    Ok(new_balance)
+}
+
+/// Add `amount` credits (refunds, grants). Returns the new balance.
+pub async fn grant(
+    pool: &PgPool,
+    user_id: i64,
+    amount: i64,
+    reason: &str,
+) -> Result<i64, CreditError> {
+    let mut tx = pool.begin().await?;
+    let new_balance = grant_in(&mut tx, user_id, amount, reason).await?;
+    tx.commit().await?;
+    Ok(new_balance)
+}
+
+/// Apply a signed change: `grant` for positive `delta`, `deduct` for
+/// negative. Returns the new balance; `OutOfRange` for `i64::MIN`, which
+/// has no positive counterpart, or a grant past the largest balance.
+pub async fn adjust(
+    pool: &PgPool,
+    user_id: i64,
+    delta: i64,
+    reason: &str,
+) -> Result<i64, CreditError> {
+    if delta >= 0 {
+        grant(pool, user_id, delta, reason).await
+    } else {
+        let amount = delta.checked_neg().ok_or(CreditError::OutOfRange)?;
+        deduct(pool, user_id, amount, reason).await
+    }
+}
+
+/// `grant` inside a caller's transaction, for changes that must commit
+/// together with other writes (e.g. settling a payment).
+pub async fn grant_in(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    user_id: i64,
+    amount: i64,
+    reason: &str,
+) -> Result<i64, CreditError> {
+    let new_balance: Option<i64> = sqlx::query_scalar(
+        "UPDATE users SET credits = credits + $1 WHERE id = $2 RETURNING credits",
+    )
+    .bind(amount)
+    .bind(user_id)
+    .fetch_optional(&mut **tx)
+    .await
+    .map_err(out_of_range)?;
+    let new_balance = new_balance.ok_or(CreditError::UserNotFound)?;
+    record(tx, user_id, amount, reason).await?;
+    Ok(new_balance)
+}
+
+/// `OutOfRange` for Postgres' numeric overflow (SQLSTATE 22003), e.g. a
+/// balance pushed past `BIGINT`.
+fn out_of_range(err: sqlx::Error) -> CreditError {
+    match &err {
+        sqlx::Error::Database(db) if db.code().as_deref() == Some("22003") => {
+            CreditError::OutOfRange
+        }
+        _ => CreditError::Db(err),
+    }
+}
+
+async fn record(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    user_id: i64,
+    delta: i64,
+    reason: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("INSERT INTO credit_ledger (user_id, delta, reason) VALUES ($1, $2, $3)")
+        .bind(user_id)
+        .bind(delta)
+        .bind(reason)
+        .execute(&mut **tx)
+        .await?;
+    Ok(())
+}
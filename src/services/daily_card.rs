@@ -0,0 +1,119 @@
+//! Card of the day: one deterministic card per date, optionally per user,
+//! with a templated meaning. No LLM call.
+
+use chrono::{FixedOffset, NaiveDate, Utc};
+use sha2::{Digest, Sha256};
+
+use super::card_picker::CardPicker;
+use crate::models::{DailyCard, Spread, card_by_id};
+
+/// Days roll over at midnight Thai time (UTC+7).
+pub(crate) const DAY_OFFSET_SECS: i32 = 7 * 60 * 60;
+
+/// Today's date in Thai time.
+pub fn today() -> NaiveDate {
+    let offset = FixedOffset::east_opt(DAY_OFFSET_SECS).expect("valid offset");
+    Utc::now().with_timezone(&offset).date_naive()
+}
+
+/// Draw seed for `date`, shared by everyone unless `user_id` is given.
+fn daily_seed(date: NaiveDate, user_id: Option<i64>) -> u64 {
+    let input = match user_id {
+        Some(id) => format!("card-of-the-day:{}:{}", date, id),
+        None => format!("card-of-the-day:{}", date),
+    };
+    let digest = Sha256::digest(input.as_bytes());
+    u64::from_be_bytes(digest[..8].try_into().expect("digest is 32 bytes"))
+}
+
+/// The card for `date`: the same for every caller passing the same
+/// `user_id` (or none).
+pub fn card_of_the_day(date: NaiveDate, user_id: Option<i64>) -> DailyCard {
+    let drawn = CardPicker::new(daily_seed(date, user_id))
+        .draw(Spread::Single)
+        .remove(0);
+    let card = card_by_id(drawn.card_id).expect("drawn card is in the deck");
+    let meaning = daily_meaning(&card.name, &card.keywords, drawn.reversed);
+    DailyCard {
+        date,
+        card_id: card.id,
+        name: card.name,
+        arcana: card.arcana,
+        suit: card.suit,
+        reversed: drawn.reversed,
+        keywords: card.keywords,
+        meaning,
+    }
+}
+
+fn daily_meaning(name: &str, keywords: &[&str], reversed: bool) -> String {
+    let themes = match keywords {
+        [] => "reflection".to_string(),
+        [only] => only.to_string(),
+        [rest @ .., last] => format!("{} and {}", rest.join(", "), last),
+    };
+    if reversed {
+        format!(
+            "{} appears reversed today: notice where {} feels blocked, and be gentle with yourself.",
+            name, themes
+        )
+    } else {
+        format!(
+            "{} guides your day: make room for {}, and act on it in one small way.",
+            name, themes
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2026, 3, day).unwrap()
+    }
+
+    fn drawn(card: &DailyCard) -> (u8, bool) {
+        (card.card_id, card.reversed)
+    }
+
+    #[test]
+    fn one_card_per_date() {
+        assert_eq!(
+            drawn(&card_of_the_day(date(1), None)),
+            drawn(&card_of_the_day(date(1), None))
+        );
+        assert_eq!(card_of_the_day(date(1), None).date, date(1));
+        assert_ne!(
+            drawn(&card_of_the_day(date(1), None)),
+            drawn(&card_of_the_day(date(2), None))
+        );
+        let month: std::collections::HashSet<_> = (1..=31)
+            .map(|day| card_of_the_day(date(day), None).card_id)
+            .collect();
+        assert!(month.len() > 10, "{} distinct cards", month.len());
+    }
+
+    #[test]
+    fn signed_in_users_get_their_own_card() {
+        assert_eq!(
+            drawn(&card_of_the_day(date(1), Some(42))),
+            drawn(&card_of_the_day(date(1), Some(42)))
+        );
+        let users: std::collections::HashSet<_> = (1..=20)
+            .map(|id| drawn(&card_of_the_day(date(1), Some(id))))
+            .collect();
+        assert!(users.len() > 1);
+    }
+
+    #[test]
+    fn meaning_names_the_card_and_its_keywords() {
+        let meaning = daily_meaning("The Star", &["hope", "renewal", "calm"], false);
+        assert_eq!(
+            meaning,
+            "The Star guides your day: make room for hope, renewal and calm, and act on it \
+             in one small way."
+        );
+        assert!(daily_meaning("The Star", &["hope"], true).contains("reversed"));
+    }
+}
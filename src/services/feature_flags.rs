@@ -0,0 +1,147 @@
+//! Feature flags for gating experimental behavior per user or by percentage.
+//!
+//! Flags are read from Redis (`feature_flag:<name>`) so they can change
+//! without a redeploy. When Redis is absent or has no value for a flag, the
+//! static defaults from `default_rules` apply.
+
+use std::collections::HashMap;
+
+use redis::AsyncCommands;
+use redis::aio::ConnectionManager;
+
+/// Use the experimental reading prompt.
+pub const FLAG_NEW_READING_PROMPT: &str = "new_reading_prompt";
+
+/// How a flag evaluates for a given user.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlagRule {
+    On,
+    Off,
+    /// Enabled for this percentage (0-100) of users, bucketed by user id.
+    Rollout(u8),
+}
+
+impl FlagRule {
+    /// Parse a stored flag value: `on`/`true`/`1`, `off`/`false`/`0`, or a
+    /// percentage like `25%`.
+    pub fn parse(raw: &str) -> Option<Self> {
+        let raw = raw.trim().to_ascii_lowercase();
+        match raw.as_str() {
+            "on" | "true" | "1" => Some(FlagRule::On),
+            "off" | "false" | "0" => Some(FlagRule::Off),
+            _ => raw
+                .strip_suffix('%')
+                .and_then(|pct| pct.trim().parse::<u8>().ok())
+                .map(|pct| FlagRule::Rollout(pct.min(100))),
+        }
+    }
+
+    /// Evaluate the rule for a user. Rollouts are deterministic: the same
+    /// user always lands in the same bucket for a given flag.
+    pub fn evaluate(&self, flag: &str, user_id: i64) -> bool {
+        match self {
+            FlagRule::On => true,
+            FlagRule::Off => false,
+            FlagRule::Rollout(pct) => rollout_bucket(flag, user_id) < *pct,
+        }
+    }
+}
+
+/// Stable 0-99 bucket for a (flag, user) pair (FNV-1a, so it doesn't change
+/// between builds the way `DefaultHasher` may).
+pub fn rollout_bucket(flag: &str, user_id: i64) -> u8 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in flag
+        .bytes()
+        .chain([b':'])
+        .chain(user_id.to_string().bytes())
+    {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    (hash % 100) as u8
+}
+
+/// Flag lookups shared across handlers.
+#[derive(Clone)]
+pub struct FeatureFlags {
+    redis: Option<ConnectionManager>,
+    defaults: HashMap<String, FlagRule>,
+}
+
+impl FeatureFlags {
+    pub fn new(redis: Option<ConnectionManager>, defaults: HashMap<String, FlagRule>) -> Self {
+        FeatureFlags { redis, defaults }
+    }
+
+    /// Built-in defaults used when Redis has no value: everything experimental is off.
+    pub fn default_rules() -> HashMap<String, FlagRule> {
+        [FLAG_NEW_READING_PROMPT]
+            .into_iter()
+            .map(|flag| (flag.to_string(), FlagRule::Off))
+            .collect()
+    }
+
+    /// Whether `flag` is enabled for `user_id`. Unknown flags are off.
+    pub async fn is_enabled(&self, flag: &str, user_id: i64) -> bool {
+        let rule = match self.stored_rule(flag).await {
+            Some(rule) => rule,
+            None => self.defaults.get(flag).copied().unwrap_or(FlagRule::Off),
+        };
+        rule.evaluate(flag, user_id)
+    }
+
+    async fn stored_rule(&self, flag: &str) -> Option<FlagRule> {
+        let mut conn = self.redis.clone()?;
+        let raw: Option<String> = match conn.get(format!("feature_flag:{}", flag)).await {
+            Ok(raw) => raw,
+            Err(e) => {
+                log::warn!("feature flag lookup failed for {}: {}", flag, e);
+                return None;
+            }
+        };
+        raw.as_deref().and_then(FlagRule::parse)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_stored_values() {
+        assert_eq!(FlagRule::parse(" ON "), Some(FlagRule::On));
+        assert_eq!(FlagRule::parse("1"), Some(FlagRule::On));
+        assert_eq!(FlagRule::parse("false"), Some(FlagRule::Off));
+        assert_eq!(FlagRule::parse("25%"), Some(FlagRule::Rollout(25)));
+        assert_eq!(FlagRule::parse("250%"), Some(FlagRule::Rollout(100)));
+        assert_eq!(FlagRule::parse("sometimes"), None);
+    }
+
+    #[test]
+    fn rollouts_are_stable_and_roughly_proportional() {
+        let rule = FlagRule::Rollout(30);
+        let enabled = (0..1000)
+            .filter(|&user_id| rule.evaluate(FLAG_NEW_READING_PROMPT, user_id))
+            .count();
+        assert!((200..400).contains(&enabled), "{} of 1000 enabled", enabled);
+        for user_id in 0..50 {
+            assert_eq!(
+                rule.evaluate(FLAG_NEW_READING_PROMPT, user_id),
+                rule.evaluate(FLAG_NEW_READING_PROMPT, user_id)
+            );
+        }
+        assert!(!FlagRule::Rollout(0).evaluate(FLAG_NEW_READING_PROMPT, 7));
+        assert!(FlagRule::Rollout(100).evaluate(FLAG_NEW_READING_PROMPT, 7));
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_defaults_without_redis() {
+        let mut defaults = FeatureFlags::default_rules();
+        defaults.insert("beta".to_string(), FlagRule::On);
+        let flags = FeatureFlags::new(None, defaults);
+        assert!(flags.is_enabled("beta", 1).await);
+        assert!(!flags.is_enabled(FLAG_NEW_READING_PROMPT, 1).await);
+        assert!(!flags.is_enabled("no_such_flag", 1).await);
+    }
+}
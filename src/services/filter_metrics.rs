@@ -0,0 +1,150 @@
+//! Counters for the QuestionFilter: questions screened and questions
+//! rejected, by reason and language, for tuning how strict it is.
+//!
+//! Both labels come from fixed enums, so the series count is bounded.
+
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use super::ai_engine::Language;
+
+/// Why the QuestionFilter turned a question away.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterRejection {
+    /// Empty or over `ASK_MAX_QUESTION_CHARS`.
+    Invalid,
+    /// Outside `SUPPORTED_LANGUAGES`.
+    UnsupportedLanguage,
+    /// QuestionAnalysis confidence under `ANALYSIS_MIN_CONFIDENCE`.
+    Unclear,
+}
+
+impl FilterRejection {
+    pub const ALL: [FilterRejection; 3] = [
+        FilterRejection::Invalid,
+        FilterRejection::UnsupportedLanguage,
+        FilterRejection::Unclear,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FilterRejection::Invalid => "invalid",
+            FilterRejection::UnsupportedLanguage => "unsupported_language",
+            FilterRejection::Unclear => "unclear",
+        }
+    }
+}
+
+const LANGUAGES: usize = Language::ALL.len();
+const REJECTIONS: usize = FilterRejection::ALL.len();
+
+fn language_index(language: Language) -> usize {
+    Language::ALL
+        .iter()
+        .position(|l| *l == language)
+        .expect("Language::ALL lists every language")
+}
+
+fn rejection_index(rejection: FilterRejection) -> usize {
+    FilterRejection::ALL
+        .iter()
+        .position(|r| *r == rejection)
+        .expect("FilterRejection::ALL lists every reason")
+}
+
+/// Process-wide filter counters, shared through `AppState`.
+#[derive(Debug, Default)]
+pub struct FilterMetrics {
+    screened: [AtomicU64; LANGUAGES],
+    rejected: [[AtomicU64; LANGUAGES]; REJECTIONS],
+}
+
+impl FilterMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count a question entering the filter.
+    pub fn record_screened(&self, language: Language) {
+        self.screened[language_index(language)].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Count a question the filter rejected.
+    pub fn record_rejected(&self, rejection: FilterRejection, language: Language) {
+        self.rejected[rejection_index(rejection)][language_index(language)]
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn screened(&self, language: Language) -> u64 {
+        self.screened[language_index(language)].load(Ordering::Relaxed)
+    }
+
+    pub fn rejected(&self, rejection: FilterRejection, language: Language) -> u64 {
+        self.rejected[rejection_index(rejection)][language_index(language)].load(Ordering::Relaxed)
+    }
+
+    /// The counters in Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        out.push_str(
+            "# HELP mimi_questions_screened_total Questions checked by the QuestionFilter.\n",
+        );
+        out.push_str("# TYPE mimi_questions_screened_total counter\n");
+        for language in Language::ALL {
+            let _ = writeln!(
+                out,
+                "mimi_questions_screened_total{{language=\"{}\"}} {}",
+                language.code(),
+                self.screened(language)
+            );
+        }
+        out.push_str(
+            "# HELP mimi_filter_rejections_total Questions rejected by the QuestionFilter.\n",
+        );
+        out.push_str("# TYPE mimi_filter_rejections_total counter\n");
+        for rejection in FilterRejection::ALL {
+            for language in Language::ALL {
+                let _ = writeln!(
+                    out,
+                    "mimi_filter_rejections_total{{reason=\"{}\",language=\"{}\"}} {}",
+                    rejection.as_str(),
+                    language.code(),
+                    self.rejected(rejection, language)
+                );
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejections_are_counted_under_their_reason_and_language() {
+        let metrics = FilterMetrics::new();
+        metrics.record_screened(Language::English);
+        metrics.record_rejected(FilterRejection::Unclear, Language::English);
+        assert_eq!(metrics.screened(Language::English), 1);
+        assert_eq!(
+            metrics.rejected(FilterRejection::Unclear, Language::English),
+            1
+        );
+        assert_eq!(
+            metrics.rejected(FilterRejection::Unclear, Language::Thai),
+            0
+        );
+        assert_eq!(
+            metrics.rejected(FilterRejection::Invalid, Language::English),
+            0
+        );
+    }
+
+    #[test]
+    fn every_series_is_rendered_even_at_zero() {
+        let text = FilterMetrics::new().render();
+        let series = text.lines().filter(|l| !l.starts_with('#')).count();
+        assert_eq!(series, LANGUAGES + REJECTIONS * LANGUAGES);
+    }
+}
@@ -0,0 +1,161 @@
+//! Golden-reading harness: replays recorded model answers through the
+//! reading pipeline with a fixed seed and compares the assembled
+//! `TarotReading` with a stored snapshot, so changes to prompting, parsing
+//! or assembly that alter the output shape are caught.
+//!
+//! Cases live in `tests/golden/<name>.json` (a `GoldenCase`); the expected
+//! output sits beside each in `<name>.snap.json`. A case can also list text
+//! its system prompts must contain (`expect_in_system_prompt`). They are
+//! checked by `cargo test --test golden`; `UPDATE_GOLDEN=1 cargo test --test
+//! golden` (re)records the snapshots.
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use serde::Deserialize;
+
+use super::ai_engine::{Language, PipelineError, PipelineSettings, generate_reading};
+use super::card_picker::CardPicker;
+use super::guardrails::GuardrailPolicy;
+use super::llm::{AskParams, FinishReason, LlmError, LlmProvider, LlmResponse};
+use super::reading_agent::{PromptVersion, ReadingAgent};
+use super::retry_budget::RetryBudget;
+use super::suit_weights::TopicSuitWeights;
+use crate::models::{QuestionAnalysis, Spread, TarotReading};
+
+/// Model name reported by `RecordedLlm`.
+pub const RECORDED_MODEL: &str = "recorded";
+
+/// An `LlmProvider` that answers with canned contents, in call order, and
+/// keeps the system prompts it was sent.
+pub struct RecordedLlm {
+    responses: Mutex<VecDeque<String>>,
+    system_prompts: Mutex<Vec<String>>,
+}
+
+impl RecordedLlm {
+    pub fn new(responses: impl IntoIterator<Item = String>) -> Self {
+        RecordedLlm {
+            responses: Mutex::new(responses.into_iter().collect()),
+            system_prompts: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Every system prompt sent so far, in call order.
+    pub fn system_prompts(&self) -> Vec<String> {
+        self.system_prompts.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl LlmProvider for RecordedLlm {
+    async fn ask(
+        &self,
+        system: &str,
+        _user: &str,
+        _params: &AskParams,
+    ) -> Result<LlmResponse, LlmError> {
+        self.system_prompts.lock().unwrap().push(system.to_string());
+        let content =
+            self.responses.lock().unwrap().pop_front().ok_or_else(|| {
+                LlmError::InvalidResponse("no recorded response left".to_string())
+            })?;
+        Ok(LlmResponse {
+            content,
+            model: RECORDED_MODEL.to_string(),
+            usage: None,
+            finish_reason: Some(FinishReason::Stop),
+            system_fingerprint: None,
+            raw: serde_json::Value::Null,
+        })
+    }
+}
+
+/// One golden question and the model answers recorded for it.
+#[derive(Debug, Clone, Deserialize)]
+pub struct GoldenCase {
+    pub question: String,
+    #[serde(default)]
+    pub spread: Spread,
+    pub seed: u64,
+    #[serde(default)]
+    pub analysis: Option<QuestionAnalysis>,
+    /// ReadingAgent answers, replayed in order (include invalid ones to
+    /// exercise the reprompt path).
+    pub responses: Vec<String>,
+    /// Text every system prompt sent must contain, e.g. a mood's tone.
+    #[serde(default)]
+    pub expect_in_system_prompt: Vec<String>,
+}
+
+/// Why a golden case failed before its snapshot could be compared.
+#[derive(Debug, thiserror::Error)]
+pub enum GoldenFailure {
+    #[error("pipeline error: {0}")]
+    Pipeline(#[from] PipelineError),
+    #[error("system prompt {index} does not contain {expected:?}")]
+    MissingFromPrompt { index: usize, expected: String },
+}
+
+/// Settings for golden runs: temperature 0, no reshuffle, no audit, and
+/// the default `SENSITIVE_TOPICS`.
+fn golden_settings() -> PipelineSettings {
+    PipelineSettings {
+        reshuffle_on_failure: false,
+        max_attempts: 4,
+        retry_budget: 3,
+        total_deadline: std::time::Duration::from_secs(60),
+        reading_params: AskParams {
+            temperature: Some(0.0),
+            ..AskParams::default()
+        },
+        audit_draws: false,
+        max_prompt_tokens: None,
+        sensitive_topics: ["health", "legal", "finance"].map(str::to_string).to_vec(),
+        default_language: Language::Thai,
+        seed_completions: false,
+        suit_weights: TopicSuitWeights::default(),
+        guardrails: GuardrailPolicy::default().reading,
+    }
+}
+
+/// Run `case` through `generate_reading` and return the snapshot JSON.
+/// Timings are dropped, since they differ on every run.
+pub async fn run_golden_case(case: &GoldenCase) -> Result<serde_json::Value, GoldenFailure> {
+    let llm = RecordedLlm::new(case.responses.clone());
+    let settings = golden_settings();
+    let mut budget = RetryBudget::new(settings.retry_budget);
+    let topic = case.analysis.as_ref().map(|a| a.topic).unwrap_or_default();
+    let mood = case.analysis.as_ref().map(|a| a.mood).unwrap_or_default();
+    let agent = ReadingAgent::new(&llm, PromptVersion::Stable)
+        .with_params(settings.reading_params.clone())
+        .with_guardrails(settings.guardrails.clone())
+        .with_topic(topic)
+        .with_mood(mood)
+        .with_disclaimer(settings.is_sensitive(topic));
+    let mut reading: TarotReading = generate_reading(
+        &agent,
+        &case.question,
+        case.analysis.clone(),
+        case.spread,
+        CardPicker::new(case.seed),
+        &settings,
+        &mut budget,
+    )
+    .await?;
+    for (index, system) in llm.system_prompts().iter().enumerate() {
+        if let Some(expected) = case
+            .expect_in_system_prompt
+            .iter()
+            .find(|expected| !system.contains(expected.as_str()))
+        {
+            return Err(GoldenFailure::MissingFromPrompt {
+                index,
+                expected: expected.clone(),
+            });
+        }
+    }
+    reading.meta.timings = None;
+    Ok(serde_json::to_value(&reading).unwrap_or_default())
+}
@@ -0,0 +1,137 @@
+//! Guest readings: a reading without an account, for trying MiMi before
+//! signing up. Guests are told apart by a client-provided device id; each
+//! device gets `GUEST_READINGS_PER_DEVICE` readings for its lifetime,
+//! counted in Redis under `guest_readings:<device_id>` with no expiry.
+//! Without Redis the quota can't be enforced, so there are no guest
+//! readings.
+//!
+//! Guest readings belong to no user. Each is kept under
+//! `guest_reading_results:<device_id>` for `GUEST_CLAIM_TTL_SECS`, so the
+//! account the device signs in with can claim it.
+
+use redis::AsyncCommands;
+use redis::aio::ConnectionManager;
+use serde::{Deserialize, Serialize};
+
+use super::ai_engine::Language;
+use crate::config::Config;
+use crate::models::TarotReading;
+
+const MIN_DEVICE_ID_LEN: usize = 8;
+const MAX_DEVICE_ID_LEN: usize = 128;
+
+fn quota_key(device_id: &str) -> String {
+    format!("guest_readings:{}", device_id)
+}
+
+fn results_key(device_id: &str) -> String {
+    format!("guest_reading_results:{}", device_id)
+}
+
+/// A device id is accepted only if it is plain enough to use in a Redis
+/// key and log.
+pub fn is_valid_device_id(id: &str) -> bool {
+    (MIN_DEVICE_ID_LEN..=MAX_DEVICE_ID_LEN).contains(&id.len())
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_'))
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct GuestSettings {
+    /// Lifetime readings per device; `None` when guest readings are off.
+    pub quota: Option<u32>,
+    pub claim_ttl_secs: u64,
+}
+
+impl GuestSettings {
+    pub fn from_config(config: &Config) -> Self {
+        GuestSettings {
+            quota: Some(config.guest_readings_per_device).filter(|&n| n > 0),
+            claim_ttl_secs: config.guest_claim_ttl_secs,
+        }
+    }
+}
+
+/// A guest reading waiting to be claimed, with what storing it needs.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GuestReading {
+    pub reading: TarotReading,
+    pub language: Language,
+    pub fingerprint: String,
+}
+
+/// Use one of `device_id`'s `quota` readings; `false` once they are all
+/// used. A refused attempt doesn't count.
+pub async fn reserve_guest_reading(
+    redis: &ConnectionManager,
+    device_id: &str,
+    quota: u32,
+) -> redis::RedisResult<bool> {
+    let key = quota_key(device_id);
+    let mut conn = redis.clone();
+    let used: u32 = conn.incr(&key, 1).await?;
+    if used > quota {
+        let _: i64 = conn.decr(&key, 1).await?;
+        return Ok(false);
+    }
+    Ok(true)
+}
+
+/// Give back a reading `reserve_guest_reading` took, when it failed.
+pub async fn release_guest_reading(
+    redis: &ConnectionManager,
+    device_id: &str,
+) -> redis::RedisResult<()> {
+    let mut conn = redis.clone();
+    let _: i64 = conn.decr(quota_key(device_id), 1).await?;
+    Ok(())
+}
+
+/// Keep `reading` for `device_id` to claim; the claim window restarts with
+/// each reading.
+pub async fn stash_guest_reading(
+    redis: &ConnectionManager,
+    device_id: &str,
+    reading: &GuestReading,
+    ttl_secs: u64,
+) -> redis::RedisResult<()> {
+    let key = results_key(device_id);
+    let payload = serde_json::to_string(reading).unwrap_or_default();
+    let mut conn = redis.clone();
+    redis::pipe()
+        .atomic()
+        .rpush(&key, payload)
+        .ignore()
+        .expire(&key, ttl_secs as i64)
+        .ignore()
+        .query_async::<_, ()>(&mut conn)
+        .await
+}
+
+/// Remove and return `device_id`'s unclaimed readings, oldest first.
+/// Entries that no longer parse are dropped.
+pub async fn take_guest_readings(
+    redis: &ConnectionManager,
+    device_id: &str,
+) -> redis::RedisResult<Vec<GuestReading>> {
+    let key = results_key(device_id);
+    let mut conn = redis.clone();
+    let (payloads,): (Vec<String>,) = redis::pipe()
+        .atomic()
+        .lrange(&key, 0, -1)
+        .del(&key)
+        .ignore()
+        .query_async(&mut conn)
+        .await?;
+    Ok(payloads
+        .iter()
+        .filter_map(|payload| match serde_json::from_str(payload) {
+            Ok(reading) => Some(reading),
+            Err(e) => {
+                log::warn!("dropping unreadable guest reading for {}: {}", device_id, e);
+                None
+            }
+        })
+        .collect())
+}
@@ -0,0 +1,753 @@
+//! OpenAI chat-completions client used by the AI engine.
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use reqwest::StatusCode;
+use reqwest::header::{AUTHORIZATION, CONTENT_TYPE, HeaderMap, HeaderValue};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use super::llm_log::{LlmLogSampler, redact_api_key, redacted_headers};
+use super::llm_throttle::{LlmThrottle, RateLimitSnapshot};
+use super::retry_budget::MIN_ATTEMPT_WINDOW;
+use super::token_limits::TokenLimits;
+use crate::config::Config;
+
+/// Errors returned by the LLM provider.
+#[derive(Debug, Error)]
+pub enum LlmError {
+    #[error("OpenAI API key is not configured")]
+    NotConfigured,
+    #[error("{0}")]
+    Request(String),
+    /// The provider didn't answer within `OPENAI_TIMEOUT_SECS`.
+    #[error("OpenAI request timed out")]
+    Timeout,
+    /// DNS, TCP or TLS failure before a response arrived.
+    #[error("Could not reach OpenAI: {0}")]
+    Network(String),
+    /// Non-2xx from the provider. `code`/`error_type` come from OpenAI's
+    /// `{"error":{"message","type","code"}}` body when it has that shape.
+    #[error("OpenAI API error ({status}): {message}")]
+    Upstream {
+        status: u16,
+        message: String,
+        code: Option<String>,
+        error_type: Option<String>,
+    },
+    #[error("Invalid response: {0}")]
+    InvalidResponse(String),
+    /// Fallbacks stopped at `LLM_TOTAL_DEADLINE_SECS`; wraps the last failure.
+    #[error("{0} (stopped at the total LLM deadline)")]
+    DeadlineExceeded(Box<LlmError>),
+    /// The provider's safety filter withheld the answer
+    /// (`finish_reason: content_filter`).
+    #[error("OpenAI content filter withheld the answer")]
+    ContentFiltered,
+    /// The response body was over `LLM_MAX_RESPONSE_BYTES`; reading stopped
+    /// at the cap.
+    #[error("OpenAI response exceeded {limit} bytes")]
+    ResponseTooLarge { limit: usize },
+}
+
+impl LlmError {
+    /// Whether another model might succeed where this one failed: the model
+    /// is unknown/retired, rate limited, or overloaded. Account-wide quota
+    /// and client errors (bad request, auth) would fail on every model.
+    pub fn is_model_specific(&self) -> bool {
+        let LlmError::Upstream { status, code, .. } = self else {
+            return false;
+        };
+        match code.as_deref() {
+            Some("model_not_found") => return true,
+            Some("insufficient_quota") => return false,
+            _ => {}
+        }
+        matches!(status, 429 | 500 | 502 | 503 | 529)
+    }
+
+    /// Whether the call ran out of time rather than failing outright.
+    pub fn is_timeout(&self) -> bool {
+        matches!(self, LlmError::Timeout | LlmError::DeadlineExceeded(_))
+    }
+}
+
+/// Per-call sampling parameters.
+#[derive(Debug, Clone, Default)]
+pub struct AskParams {
+    pub temperature: Option<f32>,
+    pub max_tokens: Option<u32>,
+    pub top_p: Option<f32>,
+    /// Best-effort reproducible sampling; see `system_fingerprint`.
+    pub seed: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub role: String,
+    pub content: String,
+}
+
+impl ChatMessage {
+    pub fn system(content: impl Into<String>) -> Self {
+        ChatMessage {
+            role: "system".to_string(),
+            content: content.into(),
+        }
+    }
+
+    pub fn user(content: impl Into<String>) -> Self {
+        ChatMessage {
+            role: "user".to_string(),
+            content: content.into(),
+        }
+    }
+}
+
+/// Body of `POST /chat/completions`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ChatRequest {
+    pub model: String,
+    pub messages: Vec<ChatMessage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+    /// Groups requests with the same static prefix for provider-side
+    /// prompt caching.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prompt_cache_key: Option<String>,
+}
+
+/// Token counts for one completion. Reads OpenAI's `usage` object, where
+/// `cached_tokens` sits under `prompt_tokens_details`, as well as our own
+/// flat serialization.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(from = "RawTokenUsage")]
+pub struct TokenUsage {
+    pub prompt_tokens: u32,
+    pub completion_tokens: u32,
+    pub total_tokens: u32,
+    /// Prompt tokens served from the provider's prompt cache.
+    pub cached_tokens: u32,
+}
+
+impl TokenUsage {
+    /// Share of prompt tokens served from cache, in percent.
+    pub fn cache_hit_percent(&self) -> f64 {
+        if self.prompt_tokens == 0 {
+            return 0.0;
+        }
+        f64::from(self.cached_tokens) * 100.0 / f64::from(self.prompt_tokens)
+    }
+}
+
+#[derive(Deserialize)]
+struct RawTokenUsage {
+    #[serde(default)]
+    prompt_tokens: u32,
+    #[serde(default)]
+    completion_tokens: u32,
+    #[serde(default)]
+    total_tokens: u32,
+    #[serde(default)]
+    cached_tokens: Option<u32>,
+    #[serde(default)]
+    prompt_tokens_details: Option<PromptTokensDetails>,
+}
+
+#[derive(Deserialize)]
+struct PromptTokensDetails {
+    #[serde(default)]
+    cached_tokens: u32,
+}
+
+impl From<RawTokenUsage> for TokenUsage {
+    fn from(raw: RawTokenUsage) -> Self {
+        TokenUsage {
+            prompt_tokens: raw.prompt_tokens,
+            completion_tokens: raw.completion_tokens,
+            total_tokens: raw.total_tokens,
+            cached_tokens: raw
+                .cached_tokens
+                .or(raw.prompt_tokens_details.map(|d| d.cached_tokens))
+                .unwrap_or(0),
+        }
+    }
+}
+
+/// Cache key for a system prompt: the same prompt always maps to the same
+/// key, so its requests share a cache entry.
+pub fn prompt_cache_key(system: &str) -> String {
+    let digest = Sha256::digest(system.as_bytes());
+    let hex: String = digest[..8].iter().map(|b| format!("{:02x}", b)).collect();
+    format!("mimi-{}", hex)
+}
+
+/// Why the model stopped generating (`choices[0].finish_reason`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FinishReason {
+    /// A natural end or a stop sequence.
+    Stop,
+    /// Cut off at `max_tokens` or the context window.
+    Length,
+    /// Withheld by the provider's safety filter.
+    ContentFilter,
+    ToolCalls,
+    #[serde(other)]
+    Other,
+}
+
+impl FinishReason {
+    /// Parse `choices[0].finish_reason`; `None` when absent or null.
+    pub fn from_response(raw: &serde_json::Value) -> Option<FinishReason> {
+        serde_json::from_value(raw["choices"][0]["finish_reason"].clone()).ok()
+    }
+}
+
+/// A parsed completion plus the raw JSON we got back.
+#[derive(Debug, Clone)]
+pub struct LlmResponse {
+    pub content: String,
+    /// The model that produced `content`; a fallback if the primary failed.
+    pub model: String,
+    pub usage: Option<TokenUsage>,
+    /// `None` when the provider didn't say.
+    pub finish_reason: Option<FinishReason>,
+    /// Backend configuration that served the request; a seeded completion
+    /// only reproduces under the same fingerprint.
+    pub system_fingerprint: Option<String>,
+    pub raw: serde_json::Value,
+}
+
+/// A chat-completion backend. `OpenAiClient` is the production
+/// implementation; agents depend on the trait so they can be driven by mocks.
+#[async_trait]
+pub trait LlmProvider: Send + Sync {
+    async fn ask(
+        &self,
+        system: &str,
+        user: &str,
+        params: &AskParams,
+    ) -> Result<LlmResponse, LlmError>;
+}
+
+/// Thin wrapper over the OpenAI REST API.
+#[derive(Clone)]
+pub struct OpenAiClient {
+    http: reqwest::Client,
+    base_url: String,
+    model: String,
+    /// Tried in order when `model` fails with a model-specific error.
+    fallbacks: Vec<String>,
+    /// Per-attempt cap (`OPENAI_TIMEOUT_SECS`).
+    timeout: Duration,
+    /// Cap across the primary attempt and all fallbacks.
+    total_deadline: Duration,
+    /// `LLM_MAX_RESPONSE_BYTES`; `None` reads bodies whole.
+    max_response_bytes: Option<usize>,
+    headers: HeaderMap,
+    configured: bool,
+    /// `OPENAI_PROMPT_CACHE`.
+    prompt_cache: bool,
+    /// `max_tokens` ceilings per model (`MODEL_TOKEN_LIMITS`).
+    token_limits: TokenLimits,
+    /// Which exchanges get their payloads logged (`LLM_LOG_SAMPLE_RATE`).
+    log_sampler: LlmLogSampler,
+    /// Rate limits the provider last reported; shared across clones.
+    throttle: Arc<LlmThrottle>,
+    /// Kept only to scrub it from logged payloads.
+    api_key: String,
+}
+
+impl OpenAiClient {
+    pub fn new(config: &Config) -> Self {
+        let api_key = config.openai_api_key.as_deref().unwrap_or_default();
+        OpenAiClient {
+            http: reqwest::Client::builder()
+                .timeout(Duration::from_secs(config.openai_timeout_secs))
+                .build()
+                .unwrap_or_default(),
+            base_url: config.openai_base_url.trim_end_matches('/').to_string(),
+            model: config.openai_model.clone(),
+            fallbacks: config.openai_model_fallbacks.clone(),
+            timeout: Duration::from_secs(config.openai_timeout_secs),
+            total_deadline: Duration::from_secs(config.llm_total_deadline_secs),
+            max_response_bytes: Some(config.llm_max_response_bytes).filter(|&max| max > 0),
+            headers: openai_headers(
+                api_key,
+                config.openai_org_id.as_deref(),
+                config.openai_project_id.as_deref(),
+            ),
+            configured: !api_key.is_empty(),
+            prompt_cache: config.openai_prompt_cache,
+            token_limits: TokenLimits::from_config(config),
+            log_sampler: LlmLogSampler::from_config(config),
+            throttle: Arc::new(LlmThrottle::from_config(config)),
+            api_key: api_key.to_string(),
+        }
+    }
+
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
+    /// Cheap reachability/auth check: `GET /models` costs no tokens but still
+    /// fails on an expired or revoked key.
+    pub async fn list_models(&self) -> Result<(), LlmError> {
+        if !self.configured {
+            return Err(LlmError::NotConfigured);
+        }
+        let response = self
+            .http
+            .get(format!("{}/models", self.base_url))
+            .headers(self.headers.clone())
+            .send()
+            .await
+            .map_err(transport_error)?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = read_body(response, self.max_response_bytes)
+                .await
+                .unwrap_or_default();
+            return Err(upstream_error(status.as_u16(), &body));
+        }
+        // Drain the body so the connection goes back to the pool for reuse.
+        let _ = read_body(response, self.max_response_bytes).await;
+        Ok(())
+    }
+
+    /// Whether to `warm_up` at startup: `WARMUP_LLM` is on and the real
+    /// provider is in use (the mock needs no connection).
+    pub fn warmup_enabled(config: &Config) -> bool {
+        config.warmup_llm && !config.llm_mock
+    }
+
+    /// Open a pooled connection to the provider (DNS, TCP, TLS) before the
+    /// first user request needs one. Never fails startup: the outcome is
+    /// only logged.
+    pub async fn warm_up(&self) {
+        let started = std::time::Instant::now();
+        match self.list_models().await {
+            Ok(()) => log::info!("LLM warmup done in {}ms", started.elapsed().as_millis()),
+            Err(e) => log::warn!("LLM warmup failed: {}", e),
+        }
+    }
+
+    /// Headers sent on every outbound request.
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// What the provider's `x-ratelimit-*` headers said last.
+    pub fn rate_limits(&self) -> Option<RateLimitSnapshot> {
+        self.throttle.latest()
+    }
+
+    pub fn throttle(&self) -> &LlmThrottle {
+        &self.throttle
+    }
+
+    /// One chat completion against `model`, abandoned after `timeout`.
+    async fn complete(
+        &self,
+        model: &str,
+        system: &str,
+        user: &str,
+        params: &AskParams,
+        timeout: Duration,
+    ) -> Result<LlmResponse, LlmError> {
+        let request = ChatRequest {
+            model: model.to_string(),
+            messages: vec![ChatMessage::system(system), ChatMessage::user(user)],
+            temperature: params.temperature,
+            max_tokens: self
+                .token_limits
+                .clamp_max_tokens(model, params.max_tokens, system, user),
+            top_p: params.top_p,
+            seed: params.seed,
+            prompt_cache_key: self.prompt_cache.then(|| prompt_cache_key(system)),
+        };
+
+        let (status, body) = match self.send(&request, timeout).await {
+            Ok(exchange) => exchange,
+            Err(e) => {
+                self.log_exchange(&request, None, &e.to_string(), true);
+                return Err(e);
+            }
+        };
+        if !status.is_success() {
+            log::error!(
+                "OpenAI API error {} from {}: {}",
+                status,
+                model,
+                redact_api_key(&body, &self.api_key)
+            );
+        }
+        let result = parse_completion(model, status, &body);
+        self.log_exchange(&request, Some(status), &body, result.is_err());
+        result
+    }
+
+    /// POST `request` and read the response body, up to `max_response_bytes`.
+    /// Held back first while the provider's rate limits are nearly spent.
+    async fn send(
+        &self,
+        request: &ChatRequest,
+        timeout: Duration,
+    ) -> Result<(StatusCode, String), LlmError> {
+        self.throttle.wait(Instant::now() + timeout).await;
+        let response = self
+            .http
+            .post(format!("{}/chat/completions", self.base_url))
+            .headers(self.headers.clone())
+            .timeout(timeout)
+            .json(request)
+            .send()
+            .await
+            .map_err(transport_error)?;
+        self.throttle.observe(response.headers());
+        let status = response.status();
+        let body = read_body(response, self.max_response_bytes).await?;
+        Ok((status, body))
+    }
+
+    /// Log the full request and response if `LLM_LOG_SAMPLE_RATE` picks
+    /// this exchange; `failed` ones always are.
+    fn log_exchange(
+        &self,
+        request: &ChatRequest,
+        status: Option<StatusCode>,
+        response: &str,
+        failed: bool,
+    ) {
+        if !self.log_sampler.should_log(failed) {
+            return;
+        }
+        let line = format!(
+            "LLM exchange POST {}/chat/completions ({}) headers: [{}] request: {} response: {}",
+            self.base_url,
+            status.map_or_else(|| "no response".to_string(), |s| s.to_string()),
+            redacted_headers(&self.headers),
+            serde_json::to_string(request).unwrap_or_default(),
+            response
+        );
+        let line = redact_api_key(&line, &self.api_key);
+        if failed {
+            log::warn!("{}", line);
+        } else {
+            log::info!("{}", line);
+        }
+    }
+}
+
+/// Turn a chat-completions response into an `LlmResponse`.
+pub(crate) fn parse_completion(
+    model: &str,
+    status: StatusCode,
+    body: &str,
+) -> Result<LlmResponse, LlmError> {
+    if !status.is_success() {
+        return Err(upstream_error(status.as_u16(), body));
+    }
+    let raw: serde_json::Value =
+        serde_json::from_str(body).map_err(|e| LlmError::InvalidResponse(e.to_string()))?;
+    let finish_reason = FinishReason::from_response(&raw);
+    let Some(content) = raw["choices"][0]["message"]["content"].as_str() else {
+        if finish_reason == Some(FinishReason::ContentFilter) {
+            return Err(LlmError::ContentFiltered);
+        }
+        return Err(LlmError::InvalidResponse(
+            "missing choices[0].message.content".into(),
+        ));
+    };
+    let content = content.to_string();
+    let usage: Option<TokenUsage> = serde_json::from_value(raw["usage"].clone()).ok();
+    if let Some(usage) = &usage {
+        log::debug!(
+            "{} prompt cache: {}/{} prompt tokens cached ({:.0}%)",
+            model,
+            usage.cached_tokens,
+            usage.prompt_tokens,
+            usage.cache_hit_percent()
+        );
+    }
+    Ok(LlmResponse {
+        content,
+        model: model.to_string(),
+        usage,
+        finish_reason,
+        system_fingerprint: raw["system_fingerprint"].as_str().map(str::to_string),
+        raw,
+    })
+}
+
+#[derive(Deserialize)]
+struct ErrorEnvelope {
+    error: ErrorBody,
+}
+
+#[derive(Deserialize)]
+struct ErrorBody {
+    message: Option<String>,
+    #[serde(rename = "type")]
+    error_type: Option<String>,
+    /// OpenAI sends this as a string, but occasionally as a number or null.
+    code: Option<serde_json::Value>,
+}
+
+/// Turn a non-2xx body into `LlmError::Upstream`, using OpenAI's structured
+/// error when present and falling back to the raw text otherwise.
+pub fn upstream_error(status: u16, body: &str) -> LlmError {
+    if let Ok(ErrorEnvelope { error }) = serde_json::from_str::<ErrorEnvelope>(body) {
+        let code = error.code.and_then(|c| match c {
+            serde_json::Value::String(s) => Some(s),
+            serde_json::Value::Null => None,
+            other => Some(other.to_string()),
+        });
+        return LlmError::Upstream {
+            status,
+            message: error.message.unwrap_or_else(|| format!("HTTP {}", status)),
+            code,
+            error_type: error.error_type,
+        };
+    }
+    let text = body.trim();
+    LlmError::Upstream {
+        status,
+        message: if text.is_empty() {
+            format!("HTTP {}", status)
+        } else {
+            text.chars().take(500).collect()
+        },
+        code: None,
+        error_type: None,
+    }
+}
+
+#[async_trait]
+impl LlmProvider for OpenAiClient {
+    /// Send a system + user prompt and return the first choice's content,
+    /// walking `OPENAI_MODEL_FALLBACKS` on model-specific failures while
+    /// `LLM_TOTAL_DEADLINE_SECS` leaves room for another attempt.
+    async fn ask(
+        &self,
+        system: &str,
+        user: &str,
+        params: &AskParams,
+    ) -> Result<LlmResponse, LlmError> {
+        if !self.configured {
+            return Err(LlmError::NotConfigured);
+        }
+        let started = Instant::now();
+        let first_timeout = self.timeout.min(self.total_deadline);
+        let mut last = match self
+            .complete(&self.model, system, user, params, first_timeout)
+            .await
+        {
+            Ok(response) => return Ok(response),
+            Err(e) => e,
+        };
+        let mut failed = &self.model;
+        for fallback in &self.fallbacks {
+            if !last.is_model_specific() {
+                break;
+            }
+            let remaining = self.total_deadline.saturating_sub(started.elapsed());
+            if remaining < MIN_ATTEMPT_WINDOW {
+                log::warn!(
+                    "{} failed ({}); no time left for fallback {}",
+                    failed,
+                    last,
+                    fallback
+                );
+                return Err(LlmError::DeadlineExceeded(Box::new(last)));
+            }
+            log::warn!("{} failed ({}); falling back to {}", failed, last, fallback);
+            match self
+                .complete(fallback, system, user, params, self.timeout.min(remaining))
+                .await
+            {
+                Ok(response) => return Ok(response),
+                Err(e) => last = e,
+            }
+            failed = fallback;
+        }
+        Err(last)
+    }
+}
+
+/// Read `response`'s body as text, at most `limit` bytes
+/// (`LLM_MAX_RESPONSE_BYTES`; `None` reads it whole). A longer body fails
+/// with `ResponseTooLarge` as soon as that is known: up front from
+/// `Content-Length`, else while reading, so it is never buffered whole.
+async fn read_body(
+    mut response: reqwest::Response,
+    limit: Option<usize>,
+) -> Result<String, LlmError> {
+    let read_error =
+        |e: reqwest::Error| LlmError::Request(format!("Failed to read response: {}", e));
+    let Some(limit) = limit else {
+        return response.text().await.map_err(read_error);
+    };
+    if response
+        .content_length()
+        .is_some_and(|length| length > limit as u64)
+    {
+        return Err(LlmError::ResponseTooLarge { limit });
+    }
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await.map_err(read_error)? {
+        if body.len() + chunk.len() > limit {
+            return Err(LlmError::ResponseTooLarge { limit });
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(String::from_utf8_lossy(&body).into_owned())
+}
+
+/// Classify a failed `send()` so timeouts and connection failures can be
+/// retried and reported differently.
+fn transport_error(err: reqwest::Error) -> LlmError {
+    if err.is_timeout() {
+        LlmError::Timeout
+    } else if err.is_connect() {
+        LlmError::Network(err.to_string())
+    } else {
+        LlmError::Request(format!("Request failed: {}", err))
+    }
+}
+
+/// Build the auth headers. `OpenAI-Organization` / `OpenAI-Project` are only
+/// included when configured, never sent empty.
+pub fn openai_headers(api_key: &str, org_id: Option<&str>, project_id: Option<&str>) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    if let Ok(value) = HeaderValue::from_str(&format!("Bearer {}", api_key)) {
+        headers.insert(AUTHORIZATION, value);
+    }
+    let optional = [
+        ("OpenAI-Organization", org_id),
+        ("OpenAI-Project", project_id),
+    ];
+    for (name, value) in optional {
+        let Some(value) = value.map(str::trim).filter(|v| !v.is_empty()) else {
+            continue;
+        };
+        match HeaderValue::from_str(value) {
+            Ok(value) => {
+                headers.insert(name, value);
+            }
+            Err(_) => log::warn!("ignoring invalid {} header value", name),
+        }
+    }
+    headers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sends_org_and_project_only_when_configured() {
+        let headers = openai_headers("sk-test", Some("org-1"), Some(" proj-1 "));
+        assert_eq!(headers["OpenAI-Organization"], "org-1");
+        assert_eq!(headers["OpenAI-Project"], "proj-1");
+        assert_eq!(headers[AUTHORIZATION], "Bearer sk-test");
+
+        let headers = openai_headers("sk-test", None, Some("  "));
+        assert!(!headers.contains_key("OpenAI-Organization"));
+        assert!(!headers.contains_key("OpenAI-Project"));
+    }
+
+    #[test]
+    fn reads_structured_and_plain_error_bodies() {
+        let body =
+            r#"{"error": {"message": "Rate limit reached", "type": "requests", "code": 429}}"#;
+        match upstream_error(429, body) {
+            LlmError::Upstream {
+                status,
+                message,
+                code,
+                error_type,
+            } => {
+                assert_eq!(status, 429);
+                assert_eq!(message, "Rate limit reached");
+                assert_eq!(code.as_deref(), Some("429"));
+                assert_eq!(error_type.as_deref(), Some("requests"));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+
+        match upstream_error(502, "<html>Bad Gateway</html>\n") {
+            LlmError::Upstream { message, code, .. } => {
+                assert_eq!(message, "<html>Bad Gateway</html>");
+                assert_eq!(code, None);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        match upstream_error(503, "") {
+            LlmError::Upstream { message, .. } => assert_eq!(message, "HTTP 503"),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn cached_tokens_parse_from_a_usage_payload() {
+        let body = serde_json::json!({
+            "choices": [{"message": {"content": "{}"}, "finish_reason": "stop"}],
+            "usage": {
+                "prompt_tokens": 2000,
+                "completion_tokens": 300,
+                "total_tokens": 2300,
+                "prompt_tokens_details": {"cached_tokens": 1536},
+            },
+        });
+        let response = parse_completion("gpt-4o-mini", StatusCode::OK, &body.to_string()).unwrap();
+        let usage = response.usage.expect("usage");
+        assert_eq!(usage.prompt_tokens, 2000);
+        assert_eq!(usage.cached_tokens, 1536);
+        assert_eq!(usage.cache_hit_percent(), 76.8);
+    }
+
+    #[test]
+    fn cached_tokens_round_trip_and_default_to_zero() {
+        let usage: TokenUsage = serde_json::from_value(serde_json::json!({
+            "prompt_tokens": 10,
+            "completion_tokens": 5,
+            "total_tokens": 15,
+        }))
+        .unwrap();
+        assert_eq!(usage.cached_tokens, 0);
+        assert_eq!(usage.cache_hit_percent(), 0.0);
+
+        let stored = serde_json::to_value(TokenUsage {
+            cached_tokens: 8,
+            ..usage
+        })
+        .unwrap();
+        assert_eq!(stored["cached_tokens"], 8);
+        let read: TokenUsage = serde_json::from_value(stored).unwrap();
+        assert_eq!(read.cached_tokens, 8);
+    }
+
+    #[test]
+    fn the_same_system_prompt_shares_a_cache_key() {
+        assert_eq!(
+            prompt_cache_key("You are a tarot reader."),
+            prompt_cache_key("You are a tarot reader.")
+        );
+        assert_ne!(
+            prompt_cache_key("You are a tarot reader."),
+            prompt_cache_key("You analyse questions.")
+        );
+    }
+}
@@ -0,0 +1,179 @@
+//! Record/replay of LLM exchanges ("cassettes"), for deterministic tests
+//! with real-shaped model output. With `LLM_RECORD=true` every successful
+//! completion is saved to `LLM_CASSETTE_DIR/<hash>.json`, keyed by a hash
+//! of the prompts and sampling parameters; with `LLM_REPLAY=true` a request
+//! whose cassette exists is answered from it without calling the provider.
+//! Replay alone fails requests that have no cassette; with both set, those
+//! are sent and recorded.
+//!
+//! A cassette keeps the completion body as the provider sent it, and replay
+//! runs it through the same parser as a live response.
+
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::llm::{AskParams, LlmError, LlmProvider, LlmResponse, parse_completion};
+use crate::config::Config;
+
+/// What a cassette is keyed by: everything that shapes the completion.
+#[derive(Debug, Serialize, Deserialize)]
+struct CassetteRequest {
+    system: String,
+    user: String,
+    temperature: Option<f32>,
+    max_tokens: Option<u32>,
+    top_p: Option<f32>,
+    seed: Option<u64>,
+}
+
+impl CassetteRequest {
+    fn new(system: &str, user: &str, params: &AskParams) -> Self {
+        CassetteRequest {
+            system: system.to_string(),
+            user: user.to_string(),
+            temperature: params.temperature,
+            max_tokens: params.max_tokens,
+            top_p: params.top_p,
+            seed: params.seed,
+        }
+    }
+
+    fn key(&self) -> String {
+        let json = serde_json::to_string(self).unwrap_or_default();
+        hex::encode(Sha256::digest(json.as_bytes()))
+    }
+}
+
+/// One recorded exchange, as stored on disk.
+#[derive(Debug, Serialize, Deserialize)]
+struct Cassette {
+    /// Kept for reading the file; matching is by the file name.
+    request: CassetteRequest,
+    /// The model that answered (a fallback if the primary failed).
+    model: String,
+    /// The completion body as the provider sent it.
+    response: serde_json::Value,
+}
+
+/// Wraps a provider (normally `OpenAiClient`) to record or replay its
+/// completions.
+pub struct CassetteLlm {
+    inner: Arc<dyn LlmProvider>,
+    dir: PathBuf,
+    record: bool,
+    replay: bool,
+}
+
+impl CassetteLlm {
+    pub fn new(
+        inner: Arc<dyn LlmProvider>,
+        dir: impl Into<PathBuf>,
+        record: bool,
+        replay: bool,
+    ) -> Self {
+        CassetteLlm {
+            inner,
+            dir: dir.into(),
+            record,
+            replay,
+        }
+    }
+
+    /// `inner` wrapped per `LLM_RECORD`/`LLM_REPLAY`, or as is when neither
+    /// is set.
+    pub fn from_config(config: &Config, inner: Arc<dyn LlmProvider>) -> Arc<dyn LlmProvider> {
+        if !config.llm_record && !config.llm_replay {
+            return inner;
+        }
+        log::warn!(
+            "LLM cassettes in {} (record: {}, replay: {})",
+            config.llm_cassette_dir,
+            config.llm_record,
+            config.llm_replay
+        );
+        Arc::new(CassetteLlm::new(
+            inner,
+            &config.llm_cassette_dir,
+            config.llm_record,
+            config.llm_replay,
+        ))
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", key))
+    }
+
+    /// The recorded response for `key`; `None` when there is no cassette.
+    async fn replay(&self, key: &str) -> Result<Option<LlmResponse>, LlmError> {
+        let text = match tokio::fs::read_to_string(self.path(key)).await {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => {
+                return Err(LlmError::Request(format!(
+                    "Could not read cassette {}: {}",
+                    key, e
+                )));
+            }
+        };
+        let cassette: Cassette = serde_json::from_str(&text)
+            .map_err(|e| LlmError::Request(format!("Unreadable cassette {}: {}", key, e)))?;
+        parse_completion(
+            &cassette.model,
+            StatusCode::OK,
+            &cassette.response.to_string(),
+        )
+        .map(Some)
+    }
+
+    /// Save `response` under `key`. Failing to write only logs: the live
+    /// response is still good.
+    async fn record(&self, key: &str, request: CassetteRequest, response: &LlmResponse) {
+        let cassette = Cassette {
+            request,
+            model: response.model.clone(),
+            response: response.raw.clone(),
+        };
+        let written = async {
+            tokio::fs::create_dir_all(&self.dir).await?;
+            let json = serde_json::to_string_pretty(&cassette).map_err(std::io::Error::other)?;
+            tokio::fs::write(self.path(key), json).await
+        };
+        if let Err(e) = written.await {
+            log::warn!("could not record LLM cassette {}: {}", key, e);
+        }
+    }
+}
+
+#[async_trait]
+impl LlmProvider for CassetteLlm {
+    async fn ask(
+        &self,
+        system: &str,
+        user: &str,
+        params: &AskParams,
+    ) -> Result<LlmResponse, LlmError> {
+        let request = CassetteRequest::new(system, user, params);
+        let key = request.key();
+        if self.replay {
+            if let Some(response) = self.replay(&key).await? {
+                return Ok(response);
+            }
+            if !self.record {
+                return Err(LlmError::Request(format!(
+                    "No recorded LLM response for this request (cassette {})",
+                    key
+                )));
+            }
+        }
+        let response = self.inner.ask(system, user, params).await?;
+        if self.record {
+            self.record(&key, request, &response).await;
+        }
+        Ok(response)
+    }
+}
@@ -0,0 +1,127 @@
+//! Sampled logging of full LLM request/response payloads. Logging every
+//! completion is too much volume, so successes are logged at
+//! `LLM_LOG_SAMPLE_RATE` (0.0-1.0, off by default) while failures are
+//! always logged. The API key never reaches the log: the `Authorization`
+//! header is masked and any echo of the key in a body is scrubbed.
+
+use reqwest::header::{AUTHORIZATION, HeaderMap};
+
+use crate::config::Config;
+
+const REDACTED: &str = "[REDACTED]";
+
+/// Decides which LLM exchanges get their payloads logged.
+#[derive(Debug, Clone, Copy)]
+pub struct LlmLogSampler {
+    /// Share of successful exchanges to log, clamped to 0.0-1.0.
+    rate: f64,
+}
+
+impl LlmLogSampler {
+    pub fn new(rate: f64) -> Self {
+        LlmLogSampler {
+            rate: if rate.is_finite() {
+                rate.clamp(0.0, 1.0)
+            } else {
+                0.0
+            },
+        }
+    }
+
+    pub fn from_config(config: &Config) -> Self {
+        LlmLogSampler::new(config.llm_log_sample_rate)
+    }
+
+    pub fn rate(&self) -> f64 {
+        self.rate
+    }
+
+    /// Whether to log an exchange, given a uniform `roll` in `[0, 1)`.
+    /// Failures are always logged.
+    pub fn should_log_with(&self, failed: bool, roll: f64) -> bool {
+        failed || roll < self.rate
+    }
+
+    pub fn should_log(&self, failed: bool) -> bool {
+        self.should_log_with(failed, rand::random())
+    }
+}
+
+/// `text` with every occurrence of `api_key` replaced.
+pub fn redact_api_key(text: &str, api_key: &str) -> String {
+    if api_key.is_empty() {
+        return text.to_string();
+    }
+    text.replace(api_key, REDACTED)
+}
+
+/// `headers` as `name: value` lines, with the credential masked.
+pub fn redacted_headers(headers: &HeaderMap) -> String {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let value = if name == AUTHORIZATION {
+                format!("Bearer {}", REDACTED)
+            } else {
+                value.to_str().unwrap_or("<binary>").to_string()
+            };
+            format!("{}: {}", name, value)
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::header::{CONTENT_TYPE, HeaderValue};
+
+    use super::*;
+
+    #[test]
+    fn failures_are_always_logged_and_successes_sampled() {
+        let sampler = LlmLogSampler::new(0.01);
+        for roll in [0.0, 0.5, 0.999] {
+            assert!(sampler.should_log_with(true, roll));
+        }
+        assert!(sampler.should_log_with(false, 0.005));
+        assert!(!sampler.should_log_with(false, 0.01));
+        assert!(!sampler.should_log_with(false, 0.5));
+
+        let off = LlmLogSampler::new(0.0);
+        assert!(off.should_log(true));
+        assert!((0..1000).all(|_| !off.should_log(false)));
+        let all = LlmLogSampler::new(1.0);
+        assert!((0..1000).all(|_| all.should_log(false)));
+    }
+
+    #[test]
+    fn clamps_the_rate() {
+        assert_eq!(LlmLogSampler::new(2.0).rate(), 1.0);
+        assert_eq!(LlmLogSampler::new(-0.5).rate(), 0.0);
+        assert_eq!(LlmLogSampler::new(f64::NAN).rate(), 0.0);
+    }
+
+    #[test]
+    fn keeps_the_api_key_out_of_logged_payloads() {
+        assert_eq!(
+            redact_api_key(r#"{"error":"bad key sk-live-123"}"#, "sk-live-123"),
+            r#"{"error":"bad key [REDACTED]"}"#
+        );
+        assert_eq!(redact_api_key("no key here", ""), "no key here");
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            AUTHORIZATION,
+            HeaderValue::from_static("Bearer sk-live-123"),
+        );
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        let line = redacted_headers(&headers);
+        assert!(!line.contains("sk-live-123"), "{}", line);
+        assert!(
+            line.contains("authorization: Bearer [REDACTED]"),
+            "{}",
+            line
+        );
+        assert!(line.contains("content-type: application/json"), "{}", line);
+    }
+}
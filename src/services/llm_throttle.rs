@@ -0,0 +1,378 @@
+//! Adaptive throttling from OpenAI's rate-limit headers. Every completion
+//! response reports `x-ratelimit-limit-{requests,tokens}`, the matching
+//! `x-ratelimit-remaining-*` and `x-ratelimit-reset-*` (a duration such as
+//! `1s`, `6m0s` or `20ms`). The latest report is kept, and once either
+//! budget drops below `LLM_THROTTLE_THRESHOLD` of its limit, outbound calls
+//! are spaced out over the time left until it resets instead of running
+//! into 429s.
+
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use reqwest::header::HeaderMap;
+
+use crate::config::Config;
+
+/// One budget (requests or tokens) as last reported by the provider.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RateLimitBudget {
+    pub limit: Option<u64>,
+    pub remaining: Option<u64>,
+    /// Until the budget is fully replenished, as of the response.
+    pub reset: Option<Duration>,
+}
+
+impl RateLimitBudget {
+    fn from_headers(headers: &HeaderMap, kind: &str) -> Self {
+        let header = |name: &str| {
+            headers
+                .get(format!("x-ratelimit-{}-{}", name, kind))
+                .and_then(|v| v.to_str().ok())
+                .map(str::trim)
+        };
+        RateLimitBudget {
+            limit: header("limit").and_then(|v| v.parse().ok()),
+            remaining: header("remaining").and_then(|v| v.parse().ok()),
+            reset: header("reset").and_then(parse_reset),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.limit.is_none() && self.remaining.is_none() && self.reset.is_none()
+    }
+
+    /// How long to hold a call back, `elapsed` after this was observed.
+    /// Nothing at or above `threshold` of the limit; below it, a growing
+    /// share of the time left until reset, all of it once exhausted.
+    fn delay(&self, threshold: f64, elapsed: Duration) -> Duration {
+        let (Some(limit), Some(remaining), Some(reset)) = (self.limit, self.remaining, self.reset)
+        else {
+            return Duration::ZERO;
+        };
+        let left = reset.saturating_sub(elapsed);
+        if limit == 0 || left.is_zero() {
+            return Duration::ZERO;
+        }
+        let share = remaining as f64 / limit as f64;
+        if share >= threshold {
+            return Duration::ZERO;
+        }
+        left.mul_f64(1.0 - share / threshold)
+    }
+}
+
+/// Both budgets from one response.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RateLimitSnapshot {
+    pub requests: RateLimitBudget,
+    pub tokens: RateLimitBudget,
+}
+
+impl RateLimitSnapshot {
+    /// The rate-limit headers of a response; `None` when it carries none
+    /// (mock servers, proxies that strip them).
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let snapshot = RateLimitSnapshot {
+            requests: RateLimitBudget::from_headers(headers, "requests"),
+            tokens: RateLimitBudget::from_headers(headers, "tokens"),
+        };
+        (!(snapshot.requests.is_empty() && snapshot.tokens.is_empty())).then_some(snapshot)
+    }
+}
+
+/// Parse a reset duration as OpenAI formats them: unit-suffixed parts
+/// (`h`, `m`, `s`, `ms`), seconds possibly fractional, e.g. `6m0s`,
+/// `1.5s`, `20ms`.
+pub fn parse_reset(value: &str) -> Option<Duration> {
+    let mut rest = value.trim();
+    if rest.is_empty() {
+        return None;
+    }
+    let mut total = Duration::ZERO;
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .unwrap_or(rest.len());
+        let amount: f64 = rest[..digits].parse().ok()?;
+        rest = &rest[digits..];
+        let (unit_secs, unit_len) = if rest.starts_with("ms") {
+            (0.001, 2)
+        } else if rest.starts_with('h') {
+            (3600.0, 1)
+        } else if rest.starts_with('m') {
+            (60.0, 1)
+        } else if rest.starts_with('s') {
+            (1.0, 1)
+        } else {
+            return None;
+        };
+        rest = &rest[unit_len..];
+        total += Duration::try_from_secs_f64(amount * unit_secs).ok()?;
+    }
+    Some(total)
+}
+
+/// Latest provider rate limits, shared by every clone of the client.
+#[derive(Debug)]
+pub struct LlmThrottle {
+    /// `LLM_THROTTLE_THRESHOLD`, clamped to 0.0-1.0; 0 never throttles.
+    threshold: f64,
+    max_delay: Duration,
+    latest: Mutex<Option<(RateLimitSnapshot, Instant)>>,
+}
+
+impl LlmThrottle {
+    pub fn new(threshold: f64, max_delay: Duration) -> Self {
+        LlmThrottle {
+            threshold: if threshold.is_finite() {
+                threshold.clamp(0.0, 1.0)
+            } else {
+                0.0
+            },
+            max_delay,
+            latest: Mutex::new(None),
+        }
+    }
+
+    pub fn from_config(config: &Config) -> Self {
+        LlmThrottle::new(
+            config.llm_throttle_threshold,
+            Duration::from_millis(config.llm_throttle_max_delay_ms),
+        )
+    }
+
+    /// Remember the limits a response reported; responses without any
+    /// leave the previous ones in place.
+    pub fn observe(&self, headers: &HeaderMap) {
+        if let Some(snapshot) = RateLimitSnapshot::from_headers(headers) {
+            *self.latest.lock().unwrap_or_else(|e| e.into_inner()) =
+                Some((snapshot, Instant::now()));
+        }
+    }
+
+    /// The limits last reported, if any response carried them.
+    pub fn latest(&self) -> Option<RateLimitSnapshot> {
+        self.latest
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .map(|(snapshot, _)| snapshot)
+    }
+
+    /// How long the next call should wait, capped at `max_delay`.
+    pub fn delay(&self) -> Duration {
+        if self.threshold <= 0.0 {
+            return Duration::ZERO;
+        }
+        let Some((snapshot, observed)) = *self.latest.lock().unwrap_or_else(|e| e.into_inner())
+        else {
+            return Duration::ZERO;
+        };
+        let elapsed = observed.elapsed();
+        snapshot
+            .requests
+            .delay(self.threshold, elapsed)
+            .max(snapshot.tokens.delay(self.threshold, elapsed))
+            .min(self.max_delay)
+    }
+
+    /// Sleep off `delay()`, but never past `deadline`.
+    pub async fn wait(&self, deadline: Instant) {
+        let delay = self
+            .delay()
+            .min(deadline.saturating_duration_since(Instant::now()));
+        if !delay.is_zero() {
+            log::info!(
+                "LLM rate limit nearly spent; delaying call {}ms",
+                delay.as_millis()
+            );
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    /// The latest limits as Prometheus gauges; empty until a response has
+    /// reported them.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let Some(snapshot) = self.latest() else {
+            return out;
+        };
+        let budgets = [("requests", snapshot.requests), ("tokens", snapshot.tokens)];
+        gauge(
+            &mut out,
+            "limit",
+            "Limit last reported by OpenAI.",
+            budgets.map(|(kind, b)| (kind, b.limit.map(|v| v.to_string()))),
+        );
+        gauge(
+            &mut out,
+            "remaining",
+            "Budget left as last reported by OpenAI.",
+            budgets.map(|(kind, b)| (kind, b.remaining.map(|v| v.to_string()))),
+        );
+        gauge(
+            &mut out,
+            "reset_seconds",
+            "Seconds until the budget resets, as last reported by OpenAI.",
+            budgets.map(|(kind, b)| (kind, b.reset.map(|d| d.as_secs_f64().to_string()))),
+        );
+        out
+    }
+}
+
+fn gauge(out: &mut String, name: &str, help: &str, values: [(&str, Option<String>); 2]) {
+    let _ = writeln!(out, "# HELP mimi_llm_ratelimit_{} {}", name, help);
+    let _ = writeln!(out, "# TYPE mimi_llm_ratelimit_{} gauge", name);
+    for (kind, value) in values {
+        if let Some(value) = value {
+            let _ = writeln!(
+                out,
+                "mimi_llm_ratelimit_{}{{kind=\"{}\"}} {}",
+                name, kind, value
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::header::HeaderValue;
+
+    use super::*;
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, HeaderValue::from_static(value));
+        }
+        headers
+    }
+
+    fn budget(limit: u64, remaining: u64, reset_secs: u64) -> RateLimitBudget {
+        RateLimitBudget {
+            limit: Some(limit),
+            remaining: Some(remaining),
+            reset: Some(Duration::from_secs(reset_secs)),
+        }
+    }
+
+    #[test]
+    fn parses_openai_reset_durations() {
+        assert_eq!(parse_reset("1s"), Some(Duration::from_secs(1)));
+        assert_eq!(parse_reset("6m0s"), Some(Duration::from_secs(360)));
+        assert_eq!(parse_reset("1h2m3s"), Some(Duration::from_secs(3723)));
+        assert_eq!(parse_reset("1.5s"), Some(Duration::from_millis(1500)));
+        assert_eq!(parse_reset("20ms"), Some(Duration::from_millis(20)));
+        assert_eq!(parse_reset(" 2m "), Some(Duration::from_secs(120)));
+        for bad in ["", "10", "5x", "s", "1s2"] {
+            assert_eq!(parse_reset(bad), None, "{}", bad);
+        }
+    }
+
+    #[test]
+    fn reads_both_budgets_from_the_headers() {
+        let snapshot = RateLimitSnapshot::from_headers(&headers(&[
+            ("x-ratelimit-limit-requests", "500"),
+            ("x-ratelimit-remaining-requests", "499"),
+            ("x-ratelimit-reset-requests", "120ms"),
+            ("x-ratelimit-limit-tokens", "30000"),
+            ("x-ratelimit-remaining-tokens", "29000"),
+            ("x-ratelimit-reset-tokens", "2s"),
+        ]))
+        .unwrap();
+        assert_eq!(
+            snapshot.requests,
+            RateLimitBudget {
+                limit: Some(500),
+                remaining: Some(499),
+                reset: Some(Duration::from_millis(120)),
+            }
+        );
+        assert_eq!(snapshot.tokens, budget(30000, 29000, 2));
+
+        let partial =
+            RateLimitSnapshot::from_headers(&headers(&[("x-ratelimit-remaining-tokens", "7")]))
+                .unwrap();
+        assert_eq!(partial.tokens.remaining, Some(7));
+        assert_eq!(partial.requests, RateLimitBudget::default());
+        assert_eq!(
+            RateLimitSnapshot::from_headers(&headers(&[("content-type", "application/json")])),
+            None
+        );
+    }
+
+    #[test]
+    fn calls_slow_down_as_a_budget_runs_out() {
+        let threshold = 0.1;
+        let now = Duration::ZERO;
+        assert_eq!(budget(100, 50, 10).delay(threshold, now), Duration::ZERO);
+        assert_eq!(budget(100, 10, 10).delay(threshold, now), Duration::ZERO);
+        assert_eq!(
+            budget(100, 5, 10).delay(threshold, now),
+            Duration::from_secs(5)
+        );
+        assert_eq!(
+            budget(100, 0, 10).delay(threshold, now),
+            Duration::from_secs(10)
+        );
+        // Only the time still left until the reset.
+        assert_eq!(
+            budget(100, 0, 10).delay(threshold, Duration::from_secs(4)),
+            Duration::from_secs(6)
+        );
+        assert_eq!(
+            budget(100, 0, 10).delay(threshold, Duration::from_secs(11)),
+            Duration::ZERO
+        );
+        assert_eq!(
+            RateLimitBudget::default().delay(threshold, now),
+            Duration::ZERO
+        );
+    }
+
+    #[test]
+    fn the_throttle_tracks_the_latest_report() {
+        let throttle = LlmThrottle::new(0.1, Duration::from_secs(3));
+        assert_eq!(throttle.latest(), None);
+        assert_eq!(throttle.delay(), Duration::ZERO);
+        assert_eq!(throttle.render(), "");
+
+        throttle.observe(&headers(&[
+            ("x-ratelimit-limit-requests", "100"),
+            ("x-ratelimit-remaining-requests", "0"),
+            ("x-ratelimit-reset-requests", "1m"),
+        ]));
+        assert_eq!(throttle.latest().unwrap().requests.remaining, Some(0));
+        // Capped at the configured longest delay.
+        assert_eq!(throttle.delay(), Duration::from_secs(3));
+        let rendered = throttle.render();
+        assert!(
+            rendered.contains("mimi_llm_ratelimit_remaining{kind=\"requests\"} 0"),
+            "{}",
+            rendered
+        );
+        assert!(
+            rendered.contains("mimi_llm_ratelimit_reset_seconds{kind=\"requests\"} 60"),
+            "{}",
+            rendered
+        );
+
+        // A response without the headers leaves the last report in place.
+        throttle.observe(&headers(&[]));
+        assert_eq!(throttle.latest().unwrap().requests.limit, Some(100));
+        throttle.observe(&headers(&[
+            ("x-ratelimit-limit-requests", "100"),
+            ("x-ratelimit-remaining-requests", "90"),
+            ("x-ratelimit-reset-requests", "1m"),
+        ]));
+        assert_eq!(throttle.delay(), Duration::ZERO);
+
+        let off = LlmThrottle::new(0.0, Duration::from_secs(3));
+        off.observe(&headers(&[
+            ("x-ratelimit-limit-requests", "100"),
+            ("x-ratelimit-remaining-requests", "0"),
+            ("x-ratelimit-reset-requests", "1m"),
+        ]));
+        assert_eq!(off.delay(), Duration::ZERO);
+    }
+}
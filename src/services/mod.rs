@@ -1,8 +1,62 @@
 //! Services used by handlers (business logic layer).
 pub mod ai_engine;
+pub mod card_picker;
+pub mod cleanup;
+pub mod conversation_service;
+pub mod cost_estimate;
+pub mod credit_service;
+pub mod cursor;
+pub mod daily_card;
+pub mod feature_flags;
+pub mod filter_metrics;
+pub mod golden;
+pub mod guardrails;
+pub mod guest_service;
+pub mod line_webhook;
+pub mod llm;
+pub mod llm_cassette;
+pub mod llm_log;
+pub mod llm_throttle;
+pub mod model_catalog;
 pub mod payment_service;
+pub mod prompt_budget;
 pub mod queue_service;
+pub mod question_analysis;
+pub mod reading_agent;
+pub mod retry_budget;
+pub mod stats_service;
+pub mod suit_weights;
+pub mod suspension_service;
+pub mod tier_service;
+pub mod token_limits;
 
 pub use ai_engine::*;
+pub use card_picker::*;
+pub use cleanup::*;
+pub use conversation_service::*;
+pub use cost_estimate::*;
+pub use credit_service::*;
+pub use cursor::*;
+pub use daily_card::*;
+pub use feature_flags::*;
+pub use filter_metrics::*;
+pub use golden::*;
+pub use guardrails::*;
+pub use guest_service::*;
+pub use line_webhook::*;
+pub use llm::*;
+pub use llm_cassette::*;
+pub use llm_log::*;
+pub use llm_throttle::*;
+pub use model_catalog::*;
 pub use payment_service::*;
+pub use prompt_budget::*;
 pub use queue_service::*;
+pub use question_analysis::*;
+pub use reading_agent::*;
+pub use retry_budget::*;
+pub use stats_service::*;
+pub use suit_weights::*;
+pub use suspension_service::*;
+pub use tier_service::*;
+pub use token_limits::*;
@@ -0,0 +1,81 @@
+//! Models users may pick, from `OPENAI_ALLOWED_MODELS`.
+//!
+//! Each comma-separated entry is `id|Display name|tier`; the display name
+//! defaults to the id and the tier to `free`. Unset means only
+//! `OPENAI_MODEL`, open to everyone.
+
+use serde::Serialize;
+
+use crate::config::Config;
+use crate::models::Tier;
+
+/// One selectable model. Only public fields; keys and endpoints stay in `Config`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelInfo {
+    pub id: String,
+    pub display_name: String,
+    /// Lowest tier allowed to select this model.
+    pub tier: Tier,
+}
+
+/// The allow-list, parsed once at startup.
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelCatalog {
+    /// `openai`, or `mock` under `LLM_MOCK`.
+    pub provider: &'static str,
+    /// Model used when the request doesn't pick one.
+    pub default_model: String,
+    pub models: Vec<ModelInfo>,
+}
+
+impl ModelCatalog {
+    pub fn from_config(config: &Config) -> Self {
+        let mut models = config
+            .openai_allowed_models
+            .as_deref()
+            .map(parse_allowed_models)
+            .unwrap_or_default();
+        if models.is_empty() {
+            models.push(ModelInfo {
+                id: config.openai_model.clone(),
+                display_name: config.openai_model.clone(),
+                tier: Tier::Free,
+            });
+        }
+        ModelCatalog {
+            provider: if config.llm_mock { "mock" } else { "openai" },
+            default_model: config.openai_model.clone(),
+            models,
+        }
+    }
+
+    pub fn get(&self, id: &str) -> Option<&ModelInfo> {
+        self.models.iter().find(|m| m.id == id)
+    }
+}
+
+/// Parse `id|Display name|tier` entries, skipping (and logging) bad ones.
+fn parse_allowed_models(raw: &str) -> Vec<ModelInfo> {
+    raw.split(',')
+        .filter_map(|entry| {
+            let mut fields = entry.split('|').map(str::trim);
+            let id = fields.next().filter(|id| !id.is_empty())?;
+            let display_name = fields.next().filter(|n| !n.is_empty()).unwrap_or(id);
+            let tier = match fields.next().filter(|t| !t.is_empty()) {
+                Some(tier) => match tier.parse() {
+                    Ok(tier) => tier,
+                    Err(e) => {
+                        log::warn!("ignoring allowed model '{}': {}", id, e);
+                        return None;
+                    }
+                },
+                None => Tier::Free,
+            };
+            Some(ModelInfo {
+                id: id.to_string(),
+                display_name: display_name.to_string(),
+                tier,
+            })
+        })
+        .collect()
+}
@@ -1,7 +1,406 @@
-//! Placeholder payment service (Stripe integration will replace this).
+//! Payment providers. Handlers depend on `PaymentProvider`; the Stripe
+//! implementation is used in production and `MockPaymentProvider` in dev/tests
+//! (`MOCK_PAYMENTS=true` or `PAYMENT_PROVIDER=mock`).
 
-/// Create a payment intent placeholder.
-pub async fn create_payment_intent(_amount_baht: u32) -> Result<String, &'static str> {
-    // TODO: implement Stripe integration
-    Ok("pi_placeholder_123".to_string())
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use thiserror::Error;
+
+use super::credit_service::{self, CreditError};
+use crate::config::Config;
+use crate::db::{DbError, claim_refund, get_payment, mark_refunded, release_refund};
+use crate::models::Payment;
+
+#[derive(Debug, Error)]
+pub enum PaymentError {
+    #[error("Payment provider is not configured")]
+    NotConfigured,
+    #[error("Invalid webhook signature")]
+    InvalidSignature,
+    #[error("Invalid webhook payload: {0}")]
+    InvalidPayload(String),
+    #[error("Payment provider error: {0}")]
+    Provider(String),
+    #[error("Payment not found")]
+    NotFound,
+    #[error("Payment is not refundable (status: {0})")]
+    NotRefundable(String),
+    #[error("The credits bought with this payment have already been spent")]
+    CreditsSpent,
+    #[error(transparent)]
+    Credit(CreditError),
+    #[error(transparent)]
+    Db(#[from] DbError),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChargeStatus {
+    Pending,
+    Succeeded,
+    Failed,
+    Refunded,
+}
+
+impl ChargeStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChargeStatus::Pending => "pending",
+            ChargeStatus::Succeeded => "succeeded",
+            ChargeStatus::Failed => "failed",
+            ChargeStatus::Refunded => "refunded",
+        }
+    }
+}
+
+/// A charge as created at the provider.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Charge {
+    /// Provider-side id (e.g. a Stripe PaymentIntent id).
+    pub id: String,
+    pub amount_baht: u32,
+    pub status: ChargeStatus,
+    /// Handed to the frontend to complete payment, when the provider uses one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_secret: Option<String>,
+}
+
+/// A verified webhook notification about a charge.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PaymentEvent {
+    /// Provider event id, used to drop redelivered webhooks.
+    pub event_id: String,
+    pub charge_id: String,
+    pub status: ChargeStatus,
+}
+
+#[async_trait]
+pub trait PaymentProvider: Send + Sync {
+    /// Name stored alongside payments (`stripe`, `mock`).
+    fn name(&self) -> &'static str;
+
+    /// Header carrying the webhook signature.
+    fn signature_header(&self) -> &'static str;
+
+    /// Create a charge. `idempotency_key` makes retries of the same purchase
+    /// return the same charge.
+    async fn create_charge(
+        &self,
+        user_id: i64,
+        amount_baht: u32,
+        idempotency_key: &str,
+    ) -> Result<Charge, PaymentError>;
+
+    /// Check the signature on a webhook body and extract the event. Events
+    /// that aren't about a charge's outcome return `Ok(None)`.
+    fn verify_webhook(
+        &self,
+        payload: &[u8],
+        signature: &str,
+    ) -> Result<Option<PaymentEvent>, PaymentError>;
+
+    async fn refund(&self, charge_id: &str, amount_baht: u32) -> Result<Charge, PaymentError>;
+}
+
+/// Ledger reason for taking back a refunded payment's credits, and for
+/// returning them when the provider refund fails.
+pub const REFUND_CREDITS_REASON: &str = "payment_refunded";
+pub const REFUND_FAILED_CREDITS_REASON: &str = "payment_refund_failed";
+
+/// Refund a succeeded payment in full through `provider` and mark it
+/// `refunded` with `reason`. A payment can only be refunded once; later
+/// calls fail with `NotRefundable`.
+///
+/// The credits the payment bought are taken back in the transaction that
+/// claims the refund; if the user has already spent them the refund fails
+/// with `CreditsSpent` and nothing changes. They're given back if the
+/// provider then refuses the refund.
+pub async fn refund(
+    provider: &dyn PaymentProvider,
+    pool: &PgPool,
+    payment_id: i64,
+    reason: &str,
+) -> Result<Payment, PaymentError> {
+    let mut tx = pool.begin().await.map_err(DbError::from)?;
+    let Some(payment) = claim_refund(&mut tx, payment_id).await? else {
+        drop(tx);
+        let payment = get_payment(pool, payment_id)
+            .await?
+            .ok_or(PaymentError::NotFound)?;
+        return Err(PaymentError::NotRefundable(payment.status));
+    };
+    if payment.credits > 0 {
+        credit_service::deduct_in(
+            &mut tx,
+            payment.user_id,
+            payment.credits,
+            REFUND_CREDITS_REASON,
+        )
+        .await
+        .map_err(credit_error)?;
+    }
+    tx.commit().await.map_err(DbError::from)?;
+    if let Err(e) = provider
+        .refund(&payment.charge_id, payment.amount_baht)
+        .await
+    {
+        let mut tx = pool.begin().await.map_err(DbError::from)?;
+        release_refund(&mut tx, payment_id).await?;
+        if payment.credits > 0 {
+            credit_service::grant_in(
+                &mut tx,
+                payment.user_id,
+                payment.credits,
+                REFUND_FAILED_CREDITS_REASON,
+            )
+            .await
+            .map_err(credit_error)?;
+        }
+        tx.commit().await.map_err(DbError::from)?;
+        return Err(e);
+    }
+    mark_refunded(pool, payment_id, reason).await?;
+    log::info!(
+        "refunded payment {} ({} baht, {} credits): {}",
+        payment_id,
+        payment.amount_baht,
+        payment.credits,
+        reason
+    );
+    Ok(Payment {
+        status: ChargeStatus::Refunded.as_str().to_string(),
+        ..payment
+    })
+}
+
+fn credit_error(err: CreditError) -> PaymentError {
+    match err {
+        CreditError::Insufficient => PaymentError::CreditsSpent,
+        e => PaymentError::Credit(e),
+    }
+}
+
+/// Pick the provider from config.
+pub fn payment_provider_from_config(config: &Config) -> Arc<dyn PaymentProvider> {
+    if config.mock_payments || config.payment_provider == "mock" {
+        log::warn!("using mock payment provider");
+        return Arc::new(MockPaymentProvider);
+    }
+    Arc::new(StripeProvider::new(
+        config.stripe_secret_key.as_deref(),
+        config.stripe_webhook_secret.as_deref(),
+    ))
+}
+
+/// Stripe, via PaymentIntents (amounts in satang).
+pub struct StripeProvider {
+    client: Option<stripe::Client>,
+    webhook_secret: Option<String>,
+}
+
+impl StripeProvider {
+    pub fn new(secret_key: Option<&str>, webhook_secret: Option<&str>) -> Self {
+        StripeProvider {
+            client: secret_key.map(stripe::Client::new),
+            webhook_secret: webhook_secret.map(str::to_string),
+        }
+    }
+
+    fn client(&self) -> Result<&stripe::Client, PaymentError> {
+        self.client.as_ref().ok_or(PaymentError::NotConfigured)
+    }
+}
+
+fn stripe_status(status: stripe::PaymentIntentStatus) -> ChargeStatus {
+    match status {
+        stripe::PaymentIntentStatus::Succeeded => ChargeStatus::Succeeded,
+        stripe::PaymentIntentStatus::Canceled => ChargeStatus::Failed,
+        _ => ChargeStatus::Pending,
+    }
+}
+
+#[async_trait]
+impl PaymentProvider for StripeProvider {
+    fn name(&self) -> &'static str {
+        "stripe"
+    }
+
+    fn signature_header(&self) -> &'static str {
+        "Stripe-Signature"
+    }
+
+    async fn create_charge(
+        &self,
+        user_id: i64,
+        amount_baht: u32,
+        idempotency_key: &str,
+    ) -> Result<Charge, PaymentError> {
+        let client = self
+            .client()?
+            .clone()
+            .with_strategy(stripe::RequestStrategy::Idempotent(
+                idempotency_key.to_string(),
+            ));
+        let mut params =
+            stripe::CreatePaymentIntent::new(i64::from(amount_baht) * 100, stripe::Currency::THB);
+        params.metadata = Some([("user_id".to_string(), user_id.to_string())].into());
+        let intent = stripe::PaymentIntent::create(&client, params)
+            .await
+            .map_err(|e| PaymentError::Provider(e.to_string()))?;
+        Ok(Charge {
+            id: intent.id.to_string(),
+            amount_baht,
+            status: stripe_status(intent.status),
+            client_secret: intent.client_secret,
+        })
+    }
+
+    fn verify_webhook(
+        &self,
+        payload: &[u8],
+        signature: &str,
+    ) -> Result<Option<PaymentEvent>, PaymentError> {
+        let secret = self
+            .webhook_secret
+            .as_deref()
+            .ok_or(PaymentError::NotConfigured)?;
+        let payload = std::str::from_utf8(payload)
+            .map_err(|e| PaymentError::InvalidPayload(e.to_string()))?;
+        let event =
+            stripe::Webhook::construct_event(payload, signature, secret).map_err(|e| match e {
+                stripe::WebhookError::BadParse(e) => PaymentError::InvalidPayload(e.to_string()),
+                _ => PaymentError::InvalidSignature,
+            })?;
+        let status = match event.type_ {
+            stripe::EventType::PaymentIntentSucceeded => ChargeStatus::Succeeded,
+            stripe::EventType::PaymentIntentPaymentFailed
+            | stripe::EventType::PaymentIntentCanceled => ChargeStatus::Failed,
+            _ => return Ok(None),
+        };
+        let stripe::EventObject::PaymentIntent(intent) = event.data.object else {
+            return Ok(None);
+        };
+        Ok(Some(PaymentEvent {
+            event_id: event.id.to_string(),
+            charge_id: intent.id.to_string(),
+            status,
+        }))
+    }
+
+    async fn refund(&self, charge_id: &str, amount_baht: u32) -> Result<Charge, PaymentError> {
+        let client = self.client()?;
+        let intent_id = charge_id
+            .parse::<stripe::PaymentIntentId>()
+            .map_err(|e| PaymentError::Provider(e.to_string()))?;
+        let mut params = stripe::CreateRefund::new();
+        params.payment_intent = Some(intent_id);
+        params.amount = Some(i64::from(amount_baht) * 100);
+        stripe::Refund::create(client, params)
+            .await
+            .map_err(|e| PaymentError::Provider(e.to_string()))?;
+        Ok(Charge {
+            id: charge_id.to_string(),
+            amount_baht,
+            status: ChargeStatus::Refunded,
+            client_secret: None,
+        })
+    }
+}
+
+/// Signature the mock provider accepts on webhooks.
+pub const MOCK_WEBHOOK_SIGNATURE: &str = "mock-signature";
+
+/// In-process provider for dev and tests. Charges are created pending;
+/// webhooks are `PaymentEvent` JSON signed with `MOCK_WEBHOOK_SIGNATURE`.
+#[derive(Debug, Default)]
+pub struct MockPaymentProvider;
+
+#[async_trait]
+impl PaymentProvider for MockPaymentProvider {
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+
+    fn signature_header(&self) -> &'static str {
+        "X-Mock-Signature"
+    }
+
+    async fn create_charge(
+        &self,
+        _user_id: i64,
+        amount_baht: u32,
+        idempotency_key: &str,
+    ) -> Result<Charge, PaymentError> {
+        // Deterministic per idempotency key, like a real provider's replay.
+        let digest = hex::encode(Sha256::digest(idempotency_key.as_bytes()));
+        let id = &digest[..24];
+        Ok(Charge {
+            id: format!("mock_ch_{}", id),
+            amount_baht,
+            status: ChargeStatus::Pending,
+            client_secret: Some(format!("mock_secret_{}", id)),
+        })
+    }
+
+    fn verify_webhook(
+        &self,
+        payload: &[u8],
+        signature: &str,
+    ) -> Result<Option<PaymentEvent>, PaymentError> {
+        if signature != MOCK_WEBHOOK_SIGNATURE {
+            return Err(PaymentError::InvalidSignature);
+        }
+        serde_json::from_slice(payload)
+            .map(Some)
+            .map_err(|e| PaymentError::InvalidPayload(e.to_string()))
+    }
+
+    async fn refund(&self, charge_id: &str, amount_baht: u32) -> Result<Charge, PaymentError> {
+        Ok(Charge {
+            id: charge_id.to_string(),
+            amount_baht,
+            status: ChargeStatus::Refunded,
+            client_secret: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn mock_charges_are_stable_per_idempotency_key() {
+        let provider = MockPaymentProvider;
+        let first = provider.create_charge(1, 100, "1:key").await.unwrap();
+        let retry = provider.create_charge(1, 100, "1:key").await.unwrap();
+        let other = provider.create_charge(1, 100, "1:other").await.unwrap();
+        assert_eq!(first.id, retry.id);
+        assert_ne!(first.id, other.id);
+        assert_eq!(first.status, ChargeStatus::Pending);
+    }
+
+    #[test]
+    fn mock_webhooks_need_the_mock_signature() {
+        let provider = MockPaymentProvider;
+        let payload = br#"{"event_id": "evt_1", "charge_id": "mock_ch_1", "status": "succeeded"}"#;
+        assert!(matches!(
+            provider.verify_webhook(payload, "forged"),
+            Err(PaymentError::InvalidSignature)
+        ));
+        let event = provider
+            .verify_webhook(payload, MOCK_WEBHOOK_SIGNATURE)
+            .unwrap()
+            .unwrap();
+        assert_eq!(event.charge_id, "mock_ch_1");
+        assert_eq!(event.status, ChargeStatus::Succeeded);
+        assert!(matches!(
+            provider.verify_webhook(b"{}", MOCK_WEBHOOK_SIGNATURE),
+            Err(PaymentError::InvalidPayload(_))
+        ));
+    }
 }
@@ -0,0 +1,132 @@
+//! Keeping prompts under `MAX_PROMPT_TOKENS`.
+//!
+//! Token counts are estimated, not exact: about four ASCII characters per
+//! token, and one token per non-ASCII character (Thai script tokenizes
+//! close to that). The estimate errs high, which is the safe side.
+
+/// Rough token count of `text`.
+pub fn estimate_tokens(text: &str) -> usize {
+    let (ascii, other) = text.chars().fold((0usize, 0usize), |(ascii, other), c| {
+        if c.is_ascii() {
+            (ascii + 1, other)
+        } else {
+            (ascii, other + 1)
+        }
+    });
+    ascii.div_ceil(4) + other
+}
+
+/// The optional context a prompt can shed, least important first.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PromptContext<'a> {
+    /// Memory lines, oldest first; dropped from the front.
+    pub memory: Vec<&'a str>,
+    /// Whether to list each card's keywords; dropped after all memory.
+    pub card_keywords: bool,
+}
+
+/// Render a prompt with `render`, shedding context until `overhead` plus
+/// the prompt fits in `max_tokens`: oldest memory lines first, then card
+/// keywords. The question and cards are never dropped, so the result can
+/// still be over budget; that is logged and left to the provider.
+pub fn fit_prompt<'a>(
+    max_tokens: usize,
+    overhead: usize,
+    mut context: PromptContext<'a>,
+    render: impl Fn(&PromptContext<'a>) -> String,
+) -> String {
+    let memory_lines = context.memory.len();
+    let had_keywords = context.card_keywords;
+    let mut prompt = render(&context);
+    loop {
+        if overhead + estimate_tokens(&prompt) <= max_tokens {
+            break;
+        }
+        if !context.memory.is_empty() {
+            context.memory.remove(0);
+        } else if context.card_keywords {
+            context.card_keywords = false;
+        } else {
+            log::warn!(
+                "prompt is ~{} tokens after trimming, over MAX_PROMPT_TOKENS={}",
+                overhead + estimate_tokens(&prompt),
+                max_tokens
+            );
+            return prompt;
+        }
+        prompt = render(&context);
+    }
+    let dropped_memory = memory_lines - context.memory.len();
+    let dropped_keywords = had_keywords && !context.card_keywords;
+    if dropped_memory > 0 || dropped_keywords {
+        log::info!(
+            "trimmed prompt to fit MAX_PROMPT_TOKENS={}: dropped {} memory line(s){}",
+            max_tokens,
+            dropped_memory,
+            if dropped_keywords {
+                " and card keywords"
+            } else {
+                ""
+            }
+        );
+    }
+    prompt
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context<'a>(memory: &[&'a str]) -> PromptContext<'a> {
+        PromptContext {
+            memory: memory.to_vec(),
+            card_keywords: true,
+        }
+    }
+
+    /// Memory lines, then the cards (with keywords while they're kept).
+    fn render(context: &PromptContext) -> String {
+        let cards = if context.card_keywords {
+            "The Tower [keywords: upheaval, sudden change, revelation]"
+        } else {
+            "The Tower"
+        };
+        format!("{}\n{}", context.memory.join("\n"), cards)
+    }
+
+    #[test]
+    fn estimates_ascii_and_thai_text() {
+        assert_eq!(estimate_tokens(""), 0);
+        assert_eq!(estimate_tokens("abcd"), 1);
+        assert_eq!(estimate_tokens("abcde"), 2);
+        assert_eq!(estimate_tokens("ไพ่ทาโร่"), 8);
+    }
+
+    #[test]
+    fn leaves_a_prompt_that_fits_alone() {
+        let memory = ["Asked about work", "Asked about love"];
+        let prompt = fit_prompt(1000, 100, context(&memory), render);
+        assert_eq!(prompt, render(&context(&memory)));
+    }
+
+    #[test]
+    fn drops_the_oldest_memory_first_then_keywords() {
+        let oldest = "Long ago: a very long remembered reading about moving abroad";
+        let newest = "Yesterday: work";
+        let memory = [oldest, newest];
+        let full = estimate_tokens(&render(&context(&memory)));
+        let without_oldest = estimate_tokens(&render(&context(&[newest])));
+        assert!(without_oldest < full);
+
+        let prompt = fit_prompt(without_oldest + 10, 10, context(&memory), render);
+        assert!(
+            !prompt.contains(oldest) && prompt.contains(newest),
+            "{}",
+            prompt
+        );
+        assert!(prompt.contains("keywords"), "{}", prompt);
+
+        let prompt = fit_prompt(10, 0, context(&memory), render);
+        assert_eq!(prompt, "\nThe Tower");
+    }
+}
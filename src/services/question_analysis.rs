@@ -0,0 +1,193 @@
+//! QuestionAnalysis agent: classifies a question's topic and mood and how
+//! confidently it can be read.
+//!
+//! Results are cached by question fingerprint (which includes the
+//! language): repeats within a request reuse the first answer, and with
+//! Redis so do repeats within `ANALYSIS_CACHE_SECS` across requests.
+
+use redis::AsyncCommands;
+use redis::aio::ConnectionManager;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+use super::ai_engine::{Language, PipelineError, fingerprint};
+use super::guardrails::{GuardrailPolicy, GuardrailSet};
+use super::llm::{AskParams, LlmProvider};
+use super::retry_budget::RetryBudget;
+use crate::config::Config;
+use crate::models::{Mood, QuestionAnalysis, Topic};
+
+const ANALYSIS_PERSONA: &str = "You analyze questions submitted for a tarot reading.";
+
+const ANALYSIS_INSTRUCTIONS: &str = "Respond in the shape \
+{\"topic\":\"love|career|finance|health|legal|family|other\",\
+\"mood\":\"anxious|curious|hopeful|sad|neutral\",\"confidence\":0.0-1.0}. \
+confidence is how clear and specific the question is; vague or unintelligible questions score low.";
+
+/// The analysis agent's system prompt under `guardrails`.
+pub fn analysis_system_prompt(guardrails: &GuardrailSet) -> String {
+    guardrails.system_prompt(ANALYSIS_PERSONA, &[ANALYSIS_INSTRUCTIONS])
+}
+
+/// Short hash of the system prompt in cache keys, so answers to an older
+/// prompt (or other guardrails) aren't reused after it changes.
+fn prompt_tag(system: &str) -> String {
+    hex::encode(&Sha256::digest(system.as_bytes())[..4])
+}
+
+#[derive(Deserialize)]
+struct AnalysisPayload {
+    #[serde(default)]
+    topic: Topic,
+    #[serde(default)]
+    mood: Mood,
+    confidence: Option<f32>,
+}
+
+/// Sampling for the analysis call (`FILTER_*`).
+pub fn analysis_params(config: &Config) -> AskParams {
+    AskParams {
+        temperature: Some(config.filter_temperature),
+        max_tokens: config.filter_max_tokens,
+        top_p: config.filter_top_p,
+        seed: None,
+    }
+}
+
+/// Ask the LLM to analyze `question`. An unparseable answer is retried once
+/// if `budget` allows.
+pub async fn analyze_question(
+    llm: &dyn LlmProvider,
+    guardrails: &GuardrailSet,
+    question: &str,
+    params: &AskParams,
+    budget: &mut RetryBudget,
+) -> Result<QuestionAnalysis, PipelineError> {
+    let system = analysis_system_prompt(guardrails);
+    let question = guardrails.user_input(question);
+    let mut retried = false;
+    loop {
+        let response = llm.ask(&system, &question, params).await?;
+        let reason = match parse_analysis(&response.content) {
+            Ok(analysis) => return Ok(analysis),
+            Err(reason) => reason,
+        };
+        if retried {
+            return Err(PipelineError::Analysis(reason));
+        }
+        if !budget.try_consume() {
+            return Err(PipelineError::RetryBudgetExhausted {
+                stage: "analysis",
+                reason: budget.exhausted_reason(reason),
+            });
+        }
+        log::warn!("retrying question analysis: {}", reason);
+        retried = true;
+    }
+}
+
+/// QuestionAnalysis results for one request, keyed by question
+/// fingerprint, backed by Redis for `ttl_secs` when it is available.
+pub struct AnalysisCache<'a> {
+    redis: Option<&'a ConnectionManager>,
+    ttl_secs: u64,
+    guardrails: GuardrailSet,
+    /// `prompt_tag` of the system prompt under `guardrails`.
+    prompt_tag: String,
+    local: HashMap<String, QuestionAnalysis>,
+}
+
+impl<'a> AnalysisCache<'a> {
+    /// A `ttl_secs` of 0 keeps results for this request only. Analyses use
+    /// the default guardrails until `with_guardrails`.
+    pub fn new(redis: Option<&'a ConnectionManager>, ttl_secs: u64) -> Self {
+        let guardrails = GuardrailPolicy::default().analysis;
+        AnalysisCache {
+            redis: redis.filter(|_| ttl_secs > 0),
+            ttl_secs,
+            prompt_tag: prompt_tag(&analysis_system_prompt(&guardrails)),
+            guardrails,
+            local: HashMap::new(),
+        }
+    }
+
+    pub fn from_config(config: &Config, redis: Option<&'a ConnectionManager>) -> Self {
+        AnalysisCache::new(redis, config.analysis_cache_secs)
+            .with_guardrails(GuardrailPolicy::from_config(config).analysis)
+    }
+
+    /// The guardrails in the analysis prompt (`ANALYSIS_GUARDRAILS`).
+    pub fn with_guardrails(mut self, guardrails: GuardrailSet) -> Self {
+        self.prompt_tag = prompt_tag(&analysis_system_prompt(&guardrails));
+        self.guardrails = guardrails;
+        self.local.clear();
+        self
+    }
+
+    fn cache_key(&self, question_fingerprint: &str) -> String {
+        format!(
+            "question_analysis:{}:{}",
+            self.prompt_tag, question_fingerprint
+        )
+    }
+
+    /// `analyze_question`, unless `question` (in `language`) was analyzed
+    /// already. Cache errors fall back to the LLM.
+    pub async fn analyze(
+        &mut self,
+        llm: &dyn LlmProvider,
+        question: &str,
+        language: Language,
+        params: &AskParams,
+        budget: &mut RetryBudget,
+    ) -> Result<QuestionAnalysis, PipelineError> {
+        let key = self.cache_key(&fingerprint(question, language));
+        if let Some(analysis) = self.local.get(&key) {
+            return Ok(analysis.clone());
+        }
+        if let Some(redis) = self.redis {
+            let mut conn = redis.clone();
+            match conn.get::<_, Option<String>>(&key).await {
+                Ok(Some(cached)) => {
+                    if let Ok(analysis) = serde_json::from_str::<QuestionAnalysis>(&cached) {
+                        self.local.insert(key, analysis.clone());
+                        return Ok(analysis);
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => log::warn!("analysis cache read failed: {}", e),
+            }
+        }
+        let analysis = analyze_question(llm, &self.guardrails, question, params, budget).await?;
+        if let Some(redis) = self.redis
+            && let Ok(json) = serde_json::to_string(&analysis)
+        {
+            let mut conn = redis.clone();
+            let written: Result<(), _> = conn.set_ex(&key, json, self.ttl_secs).await;
+            if let Err(e) = written {
+                log::warn!("analysis cache write failed: {}", e);
+            }
+        }
+        self.local.insert(key, analysis.clone());
+        Ok(analysis)
+    }
+}
+
+/// Parse the agent's JSON. A missing `confidence` defaults to 1.0 for
+/// backward compatibility; out-of-range values are clamped.
+pub fn parse_analysis(content: &str) -> Result<QuestionAnalysis, String> {
+    let start = content
+        .find('{')
+        .ok_or("analysis contained no JSON object")?;
+    let end = content
+        .rfind('}')
+        .ok_or("analysis contained no JSON object")?;
+    let payload: AnalysisPayload = serde_json::from_str(&content[start..=end])
+        .map_err(|e| format!("malformed analysis JSON: {}", e))?;
+    Ok(QuestionAnalysis {
+        topic: payload.topic,
+        mood: payload.mood,
+        confidence: payload.confidence.unwrap_or(1.0).clamp(0.0, 1.0),
+    })
+}
@@ -1,7 +1,323 @@
-//! Placeholder queue service (Upstash/Redis will be used in production).
+//! Async reading job queue on Redis (Upstash).
+//!
+//! Jobs are stored as JSON under `reading_job:<id>`; pending job ids live in
+//! the `reading_jobs` list (LPUSH on enqueue, BRPOP in the worker). A job
+//! still waiting when its `deadline` passes is marked `Expired` and refunded
+//! instead of being run.
+//!
+//! New jobs are refused while the list holds `MAX_QUEUE_DEPTH` ids or more,
+//! so a surge is shed with a 503 instead of making every job wait.
 
-/// Enqueue a background job (stub).
-pub async fn enqueue_job(_payload: &str) -> Result<(), &'static str> {
-    // TODO: push to Redis/Upstash
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use chrono::Utc;
+use redis::AsyncCommands;
+use redis::aio::ConnectionManager;
+use sqlx::PgPool;
+use thiserror::Error;
+
+use super::ai_engine::{PipelineSettings, generate_reading, resolve_language};
+use super::card_picker::CardPicker;
+use super::credit_service;
+use super::llm::LlmProvider;
+use super::reading_agent::{PromptVersion, ReadingAgent};
+use crate::models::{JobStatus, QueueStats, ReadingJob};
+
+pub const QUEUE_KEY: &str = "reading_jobs";
+const JOB_TTL_SECS: u64 = 7 * 24 * 60 * 60;
+/// Job payloads fetched per MGET by `queue_stats`.
+const STATS_BATCH_SIZE: usize = 200;
+/// `Retry-After` sent when the queue is saturated.
+pub const QUEUE_RETRY_AFTER_SECS: u64 = 30;
+
+#[derive(Debug, Error)]
+pub enum QueueError {
+    #[error("Job not found")]
+    NotFound,
+    #[error("Job belongs to another user")]
+    NotOwner,
+    #[error("Job is already {0:?}")]
+    Conflict(JobStatus),
+    #[error("The reading queue is full")]
+    Saturated { depth: u64 },
+    #[error("Queue error: {0}")]
+    Redis(#[from] redis::RedisError),
+    #[error("Corrupt job payload: {0}")]
+    Corrupt(#[from] serde_json::Error),
+}
+
+fn job_key(job_id: &str) -> String {
+    format!("reading_job:{}", job_id)
+}
+
+/// Persist the job record (without touching the queue).
+pub async fn save_job(redis: &ConnectionManager, job: &ReadingJob) -> Result<(), QueueError> {
+    let mut conn = redis.clone();
+    let payload = serde_json::to_string(job)?;
+    let _: () = conn.set_ex(job_key(&job.id), payload, JOB_TTL_SECS).await?;
+    Ok(())
+}
+
+pub async fn get_job(redis: &ConnectionManager, job_id: &str) -> Result<ReadingJob, QueueError> {
+    let mut conn = redis.clone();
+    let payload: Option<String> = conn.get(job_key(job_id)).await?;
+    let payload = payload.ok_or(QueueError::NotFound)?;
+    Ok(serde_json::from_str(&payload)?)
+}
+
+/// `Saturated` when the queue already holds `max_depth` jobs or more;
+/// `None` never refuses.
+pub async fn check_queue_capacity(
+    redis: &ConnectionManager,
+    max_depth: Option<u64>,
+) -> Result<(), QueueError> {
+    let Some(max_depth) = max_depth else {
+        return Ok(());
+    };
+    let mut conn = redis.clone();
+    let depth: u64 = conn.llen(QUEUE_KEY).await?;
+    if depth >= max_depth {
+        log::warn!("reading queue saturated: {} job(s) waiting", depth);
+        return Err(QueueError::Saturated { depth });
+    }
+    Ok(())
+}
+
+/// Store a queued job and push it onto the queue, unless the queue is at
+/// `max_depth` (see `check_queue_capacity`).
+pub async fn enqueue_job(
+    redis: &ConnectionManager,
+    job: &ReadingJob,
+    max_depth: Option<u64>,
+) -> Result<(), QueueError> {
+    check_queue_capacity(redis, max_depth).await?;
+    save_job(redis, job).await?;
+    let mut conn = redis.clone();
+    let _: () = conn.lpush(QUEUE_KEY, &job.id).await?;
     Ok(())
 }
+
+/// Cancel a job that is still queued. Only the owner may cancel; running or
+/// finished jobs return `Conflict`. Returns the cancelled job so the caller
+/// can refund `credits_charged`.
+pub async fn cancel_job(
+    redis: &ConnectionManager,
+    job_id: &str,
+    user_id: i64,
+) -> Result<ReadingJob, QueueError> {
+    let mut job = get_job(redis, job_id).await?;
+    if job.user_id != user_id {
+        return Err(QueueError::NotOwner);
+    }
+    if job.status != JobStatus::Queued {
+        return Err(QueueError::Conflict(job.status));
+    }
+    // LREM is the arbiter: if the worker already popped the id, nothing is
+    // removed and the job is (about to be) running.
+    let mut conn = redis.clone();
+    let removed: i64 = conn.lrem(QUEUE_KEY, 0, job_id).await?;
+    if removed == 0 {
+        return Err(QueueError::Conflict(JobStatus::Running));
+    }
+    job.status = JobStatus::Cancelled;
+    save_job(redis, &job).await?;
+    Ok(job)
+}
+
+/// Re-queue a failed job under its original id with its original inputs
+/// (question, spread, seed), so the user's existing job id picks up the new
+/// result. Only `Failed` jobs can be replayed; the job gets as long to wait
+/// as it had originally.
+pub async fn replay_job(
+    redis: &ConnectionManager,
+    job_id: &str,
+    admin_id: i64,
+) -> Result<ReadingJob, QueueError> {
+    let mut job = get_job(redis, job_id).await?;
+    if job.status != JobStatus::Failed {
+        return Err(QueueError::Conflict(job.status));
+    }
+    log::info!(
+        "admin {} replaying job {} (previous error: {})",
+        admin_id,
+        job.id,
+        job.error.as_deref().unwrap_or("unknown")
+    );
+    let now = Utc::now().timestamp();
+    job.deadline = job.deadline.map(|d| now + (d - job.created_at));
+    job.status = JobStatus::Queued;
+    job.error = None;
+    job.replay_count += 1;
+    job.replayed_by = Some(admin_id);
+    // Admin replays are never shed.
+    enqueue_job(redis, &job, None).await?;
+    Ok(job)
+}
+
+/// `depth` plus the age and status counts of `jobs`, as of `now` (Unix
+/// seconds).
+pub fn summarize_queue(depth: u64, jobs: &[ReadingJob], now: i64) -> QueueStats {
+    let mut by_status = BTreeMap::new();
+    for job in jobs {
+        *by_status.entry(job.status).or_insert(0) += 1;
+    }
+    let oldest_age_secs = jobs
+        .iter()
+        .filter(|job| job.status == JobStatus::Queued)
+        .map(|job| job.created_at)
+        .min()
+        .map(|created_at| (now - created_at).max(0));
+    QueueStats {
+        depth,
+        oldest_age_secs,
+        by_status,
+    }
+}
+
+/// Queue depth, oldest waiting job and counts by status as of `now`. Scans
+/// every stored job, so it is for the admin view, not the request path.
+pub async fn queue_stats(redis: &ConnectionManager, now: i64) -> Result<QueueStats, QueueError> {
+    let mut conn = redis.clone();
+    let depth: u64 = conn.llen(QUEUE_KEY).await?;
+    let mut keys: Vec<String> = Vec::new();
+    {
+        let mut scan = conn.scan_match::<_, String>(job_key("*")).await?;
+        while let Some(key) = scan.next_item().await {
+            keys.push(key);
+        }
+    }
+    let mut jobs = Vec::with_capacity(keys.len());
+    for chunk in keys.chunks(STATS_BATCH_SIZE) {
+        // MGET, as a command so a one-key chunk still comes back as a list.
+        let payloads: Vec<Option<String>> =
+            redis::cmd("MGET").arg(chunk).query_async(&mut conn).await?;
+        for payload in payloads.into_iter().flatten() {
+            match serde_json::from_str::<ReadingJob>(&payload) {
+                Ok(job) => jobs.push(job),
+                Err(e) => log::warn!("skipping corrupt job payload in queue stats: {}", e),
+            }
+        }
+    }
+    Ok(summarize_queue(depth, &jobs, now))
+}
+
+/// Pop and process jobs forever. Uses its own Redis connection because BRPOP
+/// blocks the connection it runs on; `pool` is used to refund expired jobs.
+pub async fn run_worker(
+    redis: ConnectionManager,
+    pool: Option<PgPool>,
+    llm: Arc<dyn LlmProvider>,
+    settings: PipelineSettings,
+) {
+    loop {
+        let mut conn = redis.clone();
+        let popped: Option<(String, String)> = match conn.brpop(QUEUE_KEY, 5.0).await {
+            Ok(popped) => popped,
+            Err(e) => {
+                log::error!("queue worker pop failed: {}", e);
+                tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+                continue;
+            }
+        };
+        let Some((_, job_id)) = popped else {
+            continue;
+        };
+        if let Err(e) = process_job(&redis, pool.as_ref(), llm.as_ref(), &settings, &job_id).await {
+            log::error!("job {} failed to process: {}", job_id, e);
+        }
+    }
+}
+
+/// Mark a job that missed its deadline `Expired` and give its credits back.
+/// The status is saved first so a failed refund can't lead to a second one.
+async fn expire_job(
+    redis: &ConnectionManager,
+    pool: Option<&PgPool>,
+    mut job: ReadingJob,
+) -> Result<(), QueueError> {
+    log::warn!(
+        "job {} expired after waiting {}s; refunding {} credits",
+        job.id,
+        Utc::now().timestamp() - job.created_at,
+        job.credits_charged
+    );
+    job.status = JobStatus::Expired;
+    job.error = Some("The job expired before it could start".to_string());
+    save_job(redis, &job).await?;
+    let Some(pool) = pool.filter(|_| job.credits_charged > 0) else {
+        return Ok(());
+    };
+    if let Err(e) =
+        credit_service::grant(pool, job.user_id, job.credits_charged, "reading_expired").await
+    {
+        log::error!(
+            "failed to refund {} credits to user {} for expired job {}: {}",
+            job.credits_charged,
+            job.user_id,
+            job.id,
+            e
+        );
+    }
+    Ok(())
+}
+
+/// Run the job the worker popped as `job_id`, unless it is no longer
+/// queued or has missed its deadline.
+pub async fn process_job(
+    redis: &ConnectionManager,
+    pool: Option<&PgPool>,
+    llm: &dyn LlmProvider,
+    settings: &PipelineSettings,
+    job_id: &str,
+) -> Result<(), QueueError> {
+    let mut job = get_job(redis, job_id).await?;
+    // Checked before any LLM work: a cancelled job was refunded by the
+    // cancel handler, a stale one is refunded here.
+    if job.status != JobStatus::Queued {
+        log::debug!("skipping job {} ({:?})", job.id, job.status);
+        return Ok(());
+    }
+    if job.deadline.is_some_and(|d| Utc::now().timestamp() > d) {
+        return expire_job(redis, pool, job).await;
+    }
+    job.status = JobStatus::Running;
+    save_job(redis, &job).await?;
+
+    let topic = job.analysis.as_ref().map(|a| a.topic).unwrap_or_default();
+    let mood = job.analysis.as_ref().map(|a| a.mood).unwrap_or_default();
+    let picker =
+        CardPicker::new(job.seed).with_suit_weights(settings.suit_weights.for_topic(topic));
+    let agent = ReadingAgent::new(llm, PromptVersion::Stable)
+        .with_params(settings.reading_params.clone())
+        .with_guardrails(settings.guardrails.clone())
+        .with_max_prompt_tokens(settings.max_prompt_tokens)
+        .with_detail(job.detail_level)
+        .with_topic(topic)
+        .with_mood(mood)
+        .with_disclaimer(settings.is_sensitive(topic))
+        .with_explain(job.explain)
+        .with_language(resolve_language(&job.question, settings.default_language))
+        .with_seed(settings.completion_seed(&picker));
+    match generate_reading(
+        &agent,
+        &job.question,
+        job.analysis.clone(),
+        job.spread,
+        picker,
+        settings,
+        &mut settings.budget(),
+    )
+    .await
+    {
+        Ok(reading) => {
+            job.status = JobStatus::Done;
+            job.result = Some(reading);
+        }
+        Err(e) => {
+            job.status = JobStatus::Failed;
+            job.error = Some(e.to_string());
+        }
+    }
+    save_job(redis, &job).await
+}
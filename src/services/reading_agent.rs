@@ -0,0 +1,834 @@
+//! ReadingAgent: asks the LLM to interpret the drawn cards and validates
+//! that the interpretations match them.
+
+use serde::Deserialize;
+
+use super::ai_engine::ResolvedLanguage;
+use super::guardrails::{GuardrailPolicy, GuardrailSet};
+use super::llm::{AskParams, FinishReason, LlmError, LlmProvider, LlmResponse};
+use super::prompt_budget::{PromptContext, estimate_tokens, fit_prompt};
+use super::retry_budget::RetryBudget;
+use crate::models::{
+    CardInterpretation, DetailLevel, DrawnCard, Mood, ReadingMeta, Topic, card_by_id,
+};
+
+const STABLE_PROMPT: &str = "You are MiMi, a warm and insightful tarot reader. \
+Interpret each drawn card in the context of the user's question, then give a short summary.";
+
+const EXPERIMENTAL_PROMPT: &str = "You are MiMi, a warm and insightful tarot reader. \
+Interpret each drawn card in the context of the user's question, then give a short summary \
+that ends with one practical next step.";
+
+const FORMAT_INSTRUCTIONS: &str = "Respond in the shape \
+{\"interpretations\":[{\"card_id\":<card id>,\"card\":\"<card name>\",\"position\":\"<position>\",\"interpretation\":\"...\"}],\"summary\":\"...\"}. \
+Include exactly one interpretation per drawn card, in the order given, each with the card's id.";
+
+const SESSION_FORMAT_INSTRUCTIONS: &str = "The user asks several related questions about one shared draw. \
+Respond in the shape \
+{\"sections\":[{\"question\":\"...\",\"interpretations\":[{\"card_id\":<card id>,\"card\":\"<card name>\",\"position\":\"<position>\",\"interpretation\":\"...\"}]}],\"synthesis\":\"...\"}. \
+Include exactly one section per question, in the order given, each with one interpretation per drawn card \
+in the order given, with the card's id; the synthesis ties all the questions together.";
+
+/// Added to the system prompt for sensitive topics (`SENSITIVE_TOPICS`).
+const DISCLAIMER_INSTRUCTIONS: &str = "This question touches a sensitive area (health, legal or \
+financial matters). Offer reflection, not advice: avoid definitive claims, predictions of \
+outcomes, diagnoses or instructions, and suggest consulting a qualified professional where it \
+fits. Add a \"disclaimer\" field to the JSON with one sentence saying the reading is for \
+reflection only and is not professional advice.";
+
+/// Added to the system prompt in explain mode.
+const EXPLAIN_INSTRUCTIONS: &str = "The user wants to understand the reading. Add a \"reasoning\" \
+field to every interpretation with one or two sentences linking the card's traditional \
+meaning, upright or reversed, to the topic and mood of the question.";
+
+/// Used when a sensitive reading comes back without a disclaimer.
+pub const DEFAULT_DISCLAIMER: &str = "This reading is for reflection only and is not medical, \
+legal or financial advice; please consult a qualified professional about your situation.";
+
+const ANXIOUS_TONE: &str = "The asker seems anxious. Use a calm, reassuring tone: acknowledge \
+the worry, and frame the cards around what they can steady or influence, without promising outcomes.";
+
+const CURIOUS_TONE: &str = "The asker seems curious. Use an open, exploratory tone: invite them \
+to consider what each card might point to, and suggest questions worth reflecting on.";
+
+const HOPEFUL_TONE: &str = "The asker seems hopeful. Use a warm, encouraging tone that honours \
+their hope while staying honest about any challenges the cards show.";
+
+const SAD_TONE: &str = "The asker seems sad. Use a gentle, compassionate tone: acknowledge how \
+they feel before anything else, and highlight sources of comfort and strength in the cards.";
+
+const NEUTRAL_TONE: &str = "Use a balanced, even tone: clear and kind, neither gloomy nor \
+overly upbeat.";
+
+const GENERAL_TEMPLATE: &str = include_str!("../../prompts/reading/general.txt");
+const LOVE_TEMPLATE: &str = include_str!("../../prompts/reading/love.txt");
+const CAREER_TEMPLATE: &str = include_str!("../../prompts/reading/career.txt");
+const FINANCE_TEMPLATE: &str = include_str!("../../prompts/reading/finance.txt");
+
+/// Topic-specific framing for the reading prompt, from `prompts/reading/`.
+/// The match is exhaustive, so a new `Topic` must pick a template or fall
+/// back to the general one here explicitly.
+pub fn topic_template(topic: Topic) -> &'static str {
+    match topic {
+        Topic::Love => LOVE_TEMPLATE,
+        Topic::Career => CAREER_TEMPLATE,
+        Topic::Finance => FINANCE_TEMPLATE,
+        Topic::Health | Topic::Legal | Topic::Family | Topic::Other => GENERAL_TEMPLATE,
+    }
+}
+
+/// Tone guidance for the asker's `mood` (from QuestionAnalysis). It only
+/// changes the wording; the response shape is the same for every mood.
+pub fn mood_tone(mood: Mood) -> &'static str {
+    match mood {
+        Mood::Anxious => ANXIOUS_TONE,
+        Mood::Curious => CURIOUS_TONE,
+        Mood::Hopeful => HOPEFUL_TONE,
+        Mood::Sad => SAD_TONE,
+        Mood::Neutral => NEUTRAL_TONE,
+    }
+}
+
+/// Which reading prompt to use. `Experimental` is gated behind the
+/// `new_reading_prompt` feature flag.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PromptVersion {
+    Stable,
+    Experimental,
+}
+
+impl PromptVersion {
+    fn persona(&self) -> &'static str {
+        match self {
+            PromptVersion::Stable => STABLE_PROMPT,
+            PromptVersion::Experimental => EXPERIMENTAL_PROMPT,
+        }
+    }
+
+    /// System prompt for a reading framed by `topic`'s template, in the tone
+    /// for `mood`. The parts shared by every topic and mood come first so
+    /// they form a cacheable prefix.
+    pub fn system_prompt(&self, topic: Topic, mood: Mood, guardrails: &GuardrailSet) -> String {
+        guardrails.system_prompt(
+            self.persona(),
+            &[
+                FORMAT_INSTRUCTIONS,
+                topic_template(topic).trim(),
+                mood_tone(mood),
+            ],
+        )
+    }
+
+    /// System prompt for a multi-question session over one draw.
+    pub fn session_system_prompt(&self, guardrails: &GuardrailSet) -> String {
+        guardrails.system_prompt(
+            self.persona(),
+            &[SESSION_FORMAT_INSTRUCTIONS, GENERAL_TEMPLATE.trim()],
+        )
+    }
+}
+
+/// Why a generation attempt failed.
+#[derive(Debug)]
+pub enum AgentFailure {
+    Llm(LlmError),
+    /// The model answered, but its interpretations don't match the cards.
+    Validation(String),
+    /// A reprompt was needed but the shared retry budget is spent.
+    RetryBudgetExhausted(String),
+}
+
+/// Validated ReadingAgent output.
+#[derive(Debug, Clone)]
+pub struct ReadingOutput {
+    pub interpretations: Vec<CardInterpretation>,
+    pub summary: String,
+    pub disclaimer: Option<String>,
+}
+
+/// Validated output plus the model that produced it.
+#[derive(Debug, Clone)]
+pub struct Answered<T> {
+    pub output: T,
+    pub model: String,
+    pub system_fingerprint: Option<String>,
+    /// The completion's raw JSON.
+    pub raw: serde_json::Value,
+}
+
+/// Validated session output: one interpretation list per question, in
+/// question order, plus the overall synthesis.
+#[derive(Debug, Clone)]
+pub struct SessionOutput {
+    pub sections: Vec<Vec<CardInterpretation>>,
+    pub synthesis: String,
+    pub disclaimer: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct SessionSectionPayload {
+    interpretations: Vec<CardInterpretation>,
+}
+
+#[derive(Deserialize)]
+struct SessionPayload {
+    sections: Vec<SessionSectionPayload>,
+    synthesis: String,
+    #[serde(default)]
+    disclaimer: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ReadingPayload {
+    interpretations: Vec<CardInterpretation>,
+    summary: String,
+    #[serde(default)]
+    disclaimer: Option<String>,
+}
+
+pub struct ReadingAgent<'a> {
+    llm: &'a dyn LlmProvider,
+    prompt: PromptVersion,
+    params: AskParams,
+    memory: Option<String>,
+    detail: DetailLevel,
+    topic: Topic,
+    mood: Mood,
+    max_prompt_tokens: Option<usize>,
+    disclaimer: bool,
+    explain: bool,
+    language: Option<ResolvedLanguage>,
+    seed: Option<u64>,
+    guardrails: GuardrailSet,
+}
+
+impl<'a> ReadingAgent<'a> {
+    pub fn new(llm: &'a dyn LlmProvider, prompt: PromptVersion) -> Self {
+        ReadingAgent {
+            llm,
+            prompt,
+            params: AskParams::default(),
+            memory: None,
+            detail: DetailLevel::default(),
+            topic: Topic::Other,
+            mood: Mood::Neutral,
+            max_prompt_tokens: None,
+            disclaimer: false,
+            explain: false,
+            language: None,
+            seed: None,
+            guardrails: GuardrailPolicy::default().reading,
+        }
+    }
+
+    /// The guardrails composed into every prompt (`READING_GUARDRAILS`).
+    pub fn with_guardrails(mut self, guardrails: GuardrailSet) -> Self {
+        self.guardrails = guardrails;
+        self
+    }
+
+    /// Frame readings with the template for `topic` (from QuestionAnalysis).
+    pub fn with_topic(mut self, topic: Topic) -> Self {
+        self.topic = topic;
+        self
+    }
+
+    /// Set the tone for the asker's `mood` (from QuestionAnalysis). Only
+    /// single-question readings use it; a session's questions can differ.
+    pub fn with_mood(mut self, mood: Mood) -> Self {
+        self.mood = mood;
+        self
+    }
+
+    /// Sampling parameters for this agent's calls.
+    pub fn with_params(mut self, params: AskParams) -> Self {
+        self.params = params;
+        self
+    }
+
+    /// Verbosity: sets the length guidance in the prompt and caps
+    /// `max_tokens` at the level's budget. Call after `with_params`.
+    pub fn with_detail(mut self, detail: DetailLevel) -> Self {
+        let budget = detail.max_tokens();
+        self.params.max_tokens = Some(self.params.max_tokens.map_or(budget, |m| m.min(budget)));
+        self.detail = detail;
+        self
+    }
+
+    pub fn detail(&self) -> DetailLevel {
+        self.detail
+    }
+
+    /// Condensed recent readings to include as context.
+    pub fn with_memory(mut self, memory: Option<String>) -> Self {
+        self.memory = memory;
+        self
+    }
+
+    /// Trim memory, then card keywords, until the system and user prompts
+    /// fit in `max` estimated tokens. `None` never trims.
+    pub fn with_max_prompt_tokens(mut self, max: Option<usize>) -> Self {
+        self.max_prompt_tokens = max;
+        self
+    }
+
+    /// Tell the model which language to write in (from `resolve_language`).
+    pub fn with_language(mut self, language: ResolvedLanguage) -> Self {
+        self.language = Some(language);
+        self
+    }
+
+    /// Send `seed` with every completion (`SEED_COMPLETIONS`), for
+    /// reproducible wording.
+    pub fn with_seed(mut self, seed: Option<u64>) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn seed(&self) -> Option<u64> {
+        self.seed
+    }
+
+    /// `ReadingMeta` with only the language fields set, for struct update.
+    pub fn language_meta(&self) -> ReadingMeta {
+        ReadingMeta {
+            language: self.language.map(|l| l.language.code().to_string()),
+            language_fallback: self.language.is_some_and(|l| l.fallback),
+            ..ReadingMeta::default()
+        }
+    }
+
+    /// Sensitive topic: ask for cautious wording and a `disclaimer`, and
+    /// fill in `DEFAULT_DISCLAIMER` if the model leaves it out.
+    pub fn with_disclaimer(mut self, required: bool) -> Self {
+        self.disclaimer = required;
+        self
+    }
+
+    /// Explain mode: ask for a `reasoning` per card and reject answers
+    /// without one. Off by default, since it costs tokens.
+    pub fn with_explain(mut self, explain: bool) -> Self {
+        self.explain = explain;
+        self
+    }
+
+    /// `system` plus the disclaimer and explain instructions when they apply.
+    fn safe_system_prompt(&self, system: String) -> String {
+        let mut system = system;
+        if self.disclaimer {
+            system = format!("{}\n\n{}", system, DISCLAIMER_INSTRUCTIONS);
+        }
+        if self.explain {
+            system = format!("{}\n\n{}", system, EXPLAIN_INSTRUCTIONS);
+        }
+        system
+    }
+
+    /// In explain mode every interpretation needs a `reasoning`; otherwise
+    /// any the model volunteers are dropped, so ordinary readings are
+    /// unchanged.
+    fn settle_reasoning(&self, output: ReadingOutput) -> Result<ReadingOutput, String> {
+        let mut output = output;
+        for interp in &mut output.interpretations {
+            let reasoning = interp
+                .reasoning
+                .take()
+                .map(|r| r.trim().to_string())
+                .filter(|r| !r.is_empty());
+            if self.explain && reasoning.is_none() {
+                return Err(format!(
+                    "interpretation for '{}' is missing its reasoning",
+                    interp.card
+                ));
+            }
+            interp.reasoning = reasoning.filter(|_| self.explain);
+        }
+        Ok(output)
+    }
+
+    /// The disclaimer to return: the model's (or the default) when required,
+    /// otherwise none, so ordinary readings are unchanged.
+    fn settle_disclaimer(&self, given: Option<String>) -> Option<String> {
+        if !self.disclaimer {
+            return None;
+        }
+        Some(
+            given
+                .map(|d| d.trim().to_string())
+                .filter(|d| !d.is_empty())
+                .unwrap_or_else(|| DEFAULT_DISCLAIMER.to_string()),
+        )
+    }
+
+    /// The user prompt listing the question and the cards in spread order.
+    pub fn build_prompt(
+        guardrails: &GuardrailSet,
+        question: &str,
+        cards: &[DrawnCard],
+        card_keywords: bool,
+    ) -> String {
+        format!(
+            "Question:\n{}\n\nDrawn cards:\n{}",
+            guardrails.user_input(question),
+            card_list(cards, card_keywords)
+        )
+    }
+
+    /// Add length and language guidance and any memory lines to a user prompt.
+    fn decorate_prompt(&self, prompt: String, memory: &[&str]) -> String {
+        let mut prompt = format!(
+            "{}\n\nLength: about {} words per card interpretation and {} words for the summary.",
+            prompt,
+            self.detail.words_per_card(),
+            self.detail.summary_words()
+        );
+        if let Some(name) = self.language.and_then(|l| l.language.name()) {
+            prompt = format!("{}\nLanguage: write the whole reading in {}.", prompt, name);
+        }
+        if memory.is_empty() {
+            return prompt;
+        }
+        format!(
+            "Recent readings with this user (context only, do not reinterpret):\n{}\n\n{}",
+            self.guardrails.user_input(&memory.join("\n")),
+            prompt
+        )
+    }
+
+    /// The full user prompt: `body` (given whether to include card
+    /// keywords) decorated with memory, trimmed to `max_prompt_tokens`.
+    fn compose_prompt(&self, system: &str, body: impl Fn(bool) -> String) -> String {
+        let context = PromptContext {
+            memory: self
+                .memory
+                .as_deref()
+                .map(|m| m.lines().collect())
+                .unwrap_or_default(),
+            card_keywords: true,
+        };
+        let render =
+            |ctx: &PromptContext| self.decorate_prompt(body(ctx.card_keywords), &ctx.memory);
+        match self.max_prompt_tokens {
+            Some(max) => fit_prompt(max, estimate_tokens(system), context, render),
+            None => render(&context),
+        }
+    }
+
+    /// Generate interpretations for `cards`, reprompting once if the first
+    /// answer doesn't match the draw and `budget` allows. Every LLM call
+    /// increments `attempts`; no call is made once `attempts` reaches
+    /// `max_attempts`.
+    pub async fn generate(
+        &self,
+        question: &str,
+        cards: &[DrawnCard],
+        attempts: &mut u32,
+        max_attempts: u32,
+        budget: &mut RetryBudget,
+    ) -> Result<Answered<ReadingOutput>, AgentFailure> {
+        let system = self.safe_system_prompt(self.prompt.system_prompt(
+            self.topic,
+            self.mood,
+            &self.guardrails,
+        ));
+        let base_prompt = self.compose_prompt(&system, |keywords| {
+            Self::build_prompt(&self.guardrails, question, cards, keywords)
+        });
+        self.ask_validated(
+            &system,
+            &base_prompt,
+            attempts,
+            max_attempts,
+            budget,
+            |content| {
+                parse_and_validate(content, cards).and_then(|output| self.settle_reasoning(output))
+            },
+            |_| {},
+        )
+        .await
+        .map(|mut answered| {
+            answered.output.disclaimer = self.settle_disclaimer(answered.output.disclaimer.take());
+            answered
+        })
+    }
+
+    /// The user prompt for a session: the numbered questions, then the shared cards.
+    pub fn build_session_prompt(
+        guardrails: &GuardrailSet,
+        questions: &[String],
+        cards: &[DrawnCard],
+        card_keywords: bool,
+    ) -> String {
+        let questions = questions
+            .iter()
+            .enumerate()
+            .map(|(i, q)| format!("{}. {}", i + 1, q))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let questions = guardrails.user_input(&questions);
+        format!(
+            "Questions:\n{}\n\nDrawn cards:\n{}",
+            questions,
+            card_list(cards, card_keywords)
+        )
+    }
+
+    /// Generate a session reading: every question interpreted against the
+    /// same `cards` in a single call, with the same reprompt rules as `generate`.
+    /// An invalid answer whose leading sections are usable (e.g. one cut off
+    /// mid-JSON) is kept in `partial`, for the caller to fall back on.
+    pub async fn generate_session(
+        &self,
+        questions: &[String],
+        cards: &[DrawnCard],
+        attempts: &mut u32,
+        max_attempts: u32,
+        budget: &mut RetryBudget,
+        partial: &mut Option<Answered<SessionOutput>>,
+    ) -> Result<Answered<SessionOutput>, AgentFailure> {
+        let system = self.safe_system_prompt(self.prompt.session_system_prompt(&self.guardrails));
+        let base_prompt = self.compose_prompt(&system, |keywords| {
+            Self::build_session_prompt(&self.guardrails, questions, cards, keywords)
+        });
+        self.ask_validated(
+            &system,
+            &base_prompt,
+            attempts,
+            max_attempts,
+            budget,
+            |content| parse_and_validate_session(content, questions.len(), cards),
+            |response| {
+                if let Some(mut output) = salvage_session(&response.content, questions.len(), cards)
+                {
+                    output.disclaimer = self.settle_disclaimer(output.disclaimer.take());
+                    *partial = Some(Answered {
+                        output,
+                        model: response.model.clone(),
+                        system_fingerprint: response.system_fingerprint.clone(),
+                        raw: response.raw.clone(),
+                    });
+                }
+            },
+        )
+        .await
+        .map(|mut answered| {
+            answered.output.disclaimer = self.settle_disclaimer(answered.output.disclaimer.take());
+            answered
+        })
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn ask_validated<T>(
+        &self,
+        system: &str,
+        base_prompt: &str,
+        attempts: &mut u32,
+        max_attempts: u32,
+        budget: &mut RetryBudget,
+        validate: impl Fn(&str) -> Result<T, String>,
+        mut on_invalid: impl FnMut(&LlmResponse),
+    ) -> Result<Answered<T>, AgentFailure> {
+        let mut prompt = base_prompt.to_string();
+        let mut params = self.params.clone();
+        params.seed = self.seed.or(params.seed);
+        let mut last_reason: Option<String> = None;
+
+        for _ in 0..2 {
+            if *attempts >= max_attempts {
+                break;
+            }
+            if let Some(reason) = &last_reason
+                && !budget.try_consume()
+            {
+                return Err(AgentFailure::RetryBudgetExhausted(
+                    budget.exhausted_reason(reason.clone()),
+                ));
+            }
+            *attempts += 1;
+            let response = self
+                .llm
+                .ask(system, &prompt, &params)
+                .await
+                .map_err(AgentFailure::Llm)?;
+            if response.finish_reason == Some(FinishReason::ContentFilter) {
+                return Err(AgentFailure::Llm(LlmError::ContentFiltered));
+            }
+            match validate(&response.content) {
+                Ok(output) => {
+                    return Ok(Answered {
+                        output,
+                        model: response.model,
+                        system_fingerprint: response.system_fingerprint,
+                        raw: response.raw,
+                    });
+                }
+                Err(reason) => {
+                    log::warn!("reading validation failed: {}", reason);
+                    on_invalid(&response);
+                    // A cut-off answer is usually why it didn't parse; give
+                    // the retry more room.
+                    if response.finish_reason == Some(FinishReason::Length)
+                        && let Some(max_tokens) = params.max_tokens
+                    {
+                        params.max_tokens = Some(max_tokens.saturating_mul(2));
+                    }
+                    prompt = format!(
+                        "{}\n\nYour previous answer was invalid: {}. \
+                         Interpret exactly these cards, in this order.",
+                        base_prompt, reason
+                    );
+                    last_reason = Some(reason);
+                }
+            }
+        }
+        Err(AgentFailure::Validation(
+            last_reason.unwrap_or_else(|| "no attempts left".to_string()),
+        ))
+    }
+}
+
+/// The cards in spread order, one numbered line each with the card's id,
+/// optionally with the deck's keywords for each card.
+fn card_list(cards: &[DrawnCard], keywords: bool) -> String {
+    cards
+        .iter()
+        .enumerate()
+        .map(|(i, c)| {
+            let orientation = if c.reversed { "reversed" } else { "upright" };
+            let line = format!(
+                "{}. {} — {} ({}, id {})",
+                i + 1,
+                c.position,
+                c.name,
+                orientation,
+                c.card_id
+            );
+            match card_by_id(c.card_id).filter(|_| keywords) {
+                Some(card) => format!("{} [keywords: {}]", line, card.keywords.join(", ")),
+                None => line,
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Parse the model's JSON and check it covers exactly the drawn cards in order.
+pub fn parse_and_validate(content: &str, cards: &[DrawnCard]) -> Result<ReadingOutput, String> {
+    let json = extract_json(content).ok_or("response contained no JSON object")?;
+    let payload: ReadingPayload =
+        serde_json::from_str(json).map_err(|e| format!("malformed reading JSON: {}", e))?;
+
+    Ok(ReadingOutput {
+        interpretations: order_interpretations(payload.interpretations, cards)?,
+        summary: payload.summary,
+        disclaimer: payload.disclaimer,
+    })
+}
+
+/// Parse a session answer and check every section covers the drawn cards;
+/// each section's interpretations come back in spread order.
+pub fn parse_and_validate_session(
+    content: &str,
+    question_count: usize,
+    cards: &[DrawnCard],
+) -> Result<SessionOutput, String> {
+    let json = extract_json(content).ok_or("response contained no JSON object")?;
+    let payload: SessionPayload =
+        serde_json::from_str(json).map_err(|e| format!("malformed session JSON: {}", e))?;
+
+    if payload.sections.len() != question_count {
+        return Err(format!(
+            "expected {} sections, got {}",
+            question_count,
+            payload.sections.len()
+        ));
+    }
+    let sections = payload
+        .sections
+        .into_iter()
+        .map(|s| order_interpretations(s.interpretations, cards))
+        .collect::<Result<_, _>>()?;
+    Ok(SessionOutput {
+        sections,
+        synthesis: payload.synthesis,
+        disclaimer: payload.disclaimer,
+    })
+}
+
+/// The usable part of an invalid session answer: the leading sections that
+/// match the draw, read from a complete or cut-off JSON answer. `None` if
+/// not even the first section is usable. The synthesis is empty unless the
+/// JSON got that far.
+pub fn salvage_session(
+    content: &str,
+    question_count: usize,
+    cards: &[DrawnCard],
+) -> Option<SessionOutput> {
+    let start = content.find('{')?;
+    let (sections, synthesis, disclaimer) = match extract_json(content)
+        .and_then(|json| serde_json::from_str::<SessionPayload>(json).ok())
+    {
+        Some(payload) => (payload.sections, payload.synthesis, payload.disclaimer),
+        None => (leading_sections(&content[start..]), String::new(), None),
+    };
+    let sections: Vec<_> = sections
+        .into_iter()
+        .take(question_count)
+        .map_while(|s| order_interpretations(s.interpretations, cards).ok())
+        .collect();
+    if sections.is_empty() {
+        return None;
+    }
+    Some(SessionOutput {
+        sections,
+        synthesis,
+        disclaimer,
+    })
+}
+
+/// The complete section objects at the start of a truncated
+/// `{"sections":[...` answer.
+fn leading_sections(json: &str) -> Vec<SessionSectionPayload> {
+    let mut sections = Vec::new();
+    let Some(rest) = json
+        .find("\"sections\"")
+        .and_then(|at| json[at..].find('[').map(|open| &json[at + open + 1..]))
+    else {
+        return sections;
+    };
+    let mut rest = rest;
+    loop {
+        rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == ',');
+        let mut stream =
+            serde_json::Deserializer::from_str(rest).into_iter::<SessionSectionPayload>();
+        match stream.next() {
+            Some(Ok(section)) => {
+                sections.push(section);
+                rest = &rest[stream.byte_offset()..];
+            }
+            _ => return sections,
+        }
+    }
+}
+
+/// `interpretations` rearranged into the spread order of `cards`, matched
+/// by `card_id`, since the model's ordering can't be relied on. Every drawn
+/// card needs exactly one interpretation, named as drawn.
+fn order_interpretations(
+    interpretations: Vec<CardInterpretation>,
+    cards: &[DrawnCard],
+) -> Result<Vec<CardInterpretation>, String> {
+    if interpretations.len() != cards.len() {
+        return Err(format!(
+            "expected {} interpretations, got {}",
+            cards.len(),
+            interpretations.len()
+        ));
+    }
+    let mut ordered: Vec<Option<CardInterpretation>> = vec![None; cards.len()];
+    for interp in interpretations {
+        let card_id = interp
+            .card_id
+            .ok_or_else(|| format!("interpretation for '{}' has no card_id", interp.card))?;
+        let index = cards
+            .iter()
+            .position(|c| c.card_id == card_id)
+            .ok_or_else(|| format!("card_id {} is not one of the drawn cards", card_id))?;
+        let card = &cards[index];
+        if !interp.card.trim().eq_ignore_ascii_case(&card.name) {
+            return Err(format!(
+                "interpretation for '{}' does not match drawn card '{}' (card_id {})",
+                interp.card, card.name, card_id
+            ));
+        }
+        if ordered[index].replace(interp).is_some() {
+            return Err(format!("card_id {} is interpreted more than once", card_id));
+        }
+    }
+    Ok(ordered.into_iter().flatten().collect())
+}
+
+/// Slice out the outermost `{...}`, tolerating markdown code fences.
+fn extract_json(content: &str) -> Option<&str> {
+    let start = content.find('{')?;
+    let end = content.rfind('}')?;
+    (end > start).then(|| &content[start..=end])
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn drawn() -> Vec<DrawnCard> {
+        [(0, "past"), (21, "present"), (77, "future")]
+            .into_iter()
+            .map(|(card_id, position)| DrawnCard {
+                card_id,
+                name: card_by_id(card_id).unwrap().name,
+                position: position.to_string(),
+                reversed: false,
+            })
+            .collect()
+    }
+
+    fn answer(interpretations: serde_json::Value) -> String {
+        json!({ "interpretations": interpretations, "summary": "All is well." }).to_string()
+    }
+
+    fn interp(card: &DrawnCard) -> serde_json::Value {
+        json!({
+            "card_id": card.card_id,
+            "card": card.name,
+            "position": card.position,
+            "interpretation": "...",
+        })
+    }
+
+    #[test]
+    fn shuffled_interpretations_come_back_in_spread_order() {
+        let cards = drawn();
+        let shuffled = json!([interp(&cards[2]), interp(&cards[0]), interp(&cards[1])]);
+        let output = parse_and_validate(&answer(shuffled), &cards).unwrap();
+        let ids: Vec<_> = output.interpretations.iter().map(|i| i.card_id).collect();
+        assert_eq!(ids, [Some(0), Some(21), Some(77)]);
+        assert_eq!(output.interpretations[2].position, "future");
+    }
+
+    #[test]
+    fn interpretations_must_match_the_drawn_set() {
+        let cards = drawn();
+        let mut missing_id = interp(&cards[1]);
+        missing_id.as_object_mut().unwrap().remove("card_id");
+        let mut foreign = interp(&cards[1]);
+        foreign["card_id"] = json!(5);
+        let mut misnamed = interp(&cards[1]);
+        misnamed["card"] = json!("The Tower");
+        for (interpretations, expected) in [
+            (
+                json!([interp(&cards[0]), missing_id, interp(&cards[2])]),
+                "has no card_id",
+            ),
+            (
+                json!([interp(&cards[0]), foreign, interp(&cards[2])]),
+                "not one of the drawn cards",
+            ),
+            (
+                json!([interp(&cards[0]), misnamed, interp(&cards[2])]),
+                "does not match drawn card",
+            ),
+            (
+                json!([interp(&cards[0]), interp(&cards[0]), interp(&cards[2])]),
+                "more than once",
+            ),
+            (
+                json!([interp(&cards[0]), interp(&cards[1])]),
+                "expected 3 interpretations",
+            ),
+        ] {
+            let err = parse_and_validate(&answer(interpretations), &cards).unwrap_err();
+            assert!(err.contains(expected), "{}", err);
+        }
+    }
+}
@@ -0,0 +1,94 @@
+//! A cap on retries shared by every stage of one reading, so per-stage
+//! retries can't multiply into unbounded latency and cost.
+//!
+//! The cap is both a count and, optionally, a wall-clock deadline: a retry
+//! is refused once too little time is left for it to finish.
+
+use std::time::{Duration, Instant};
+
+/// Least time worth starting another LLM attempt with.
+pub const MIN_ATTEMPT_WINDOW: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone)]
+pub struct RetryBudget {
+    remaining: u32,
+    deadline: Option<Instant>,
+    deadline_hit: bool,
+}
+
+impl RetryBudget {
+    pub fn new(retries: u32) -> Self {
+        RetryBudget {
+            remaining: retries,
+            deadline: None,
+            deadline_hit: false,
+        }
+    }
+
+    /// Also refuse retries once less than `MIN_ATTEMPT_WINDOW` of `total`
+    /// (counted from now) is left.
+    pub fn with_deadline(mut self, total: Duration) -> Self {
+        self.deadline = Some(Instant::now() + total);
+        self
+    }
+
+    /// Spend one retry. Returns `false` once the budget is used up.
+    pub fn try_consume(&mut self) -> bool {
+        if self.remaining == 0 {
+            return false;
+        }
+        if let Some(deadline) = self.deadline
+            && deadline.saturating_duration_since(Instant::now()) < MIN_ATTEMPT_WINDOW
+        {
+            self.deadline_hit = true;
+            return false;
+        }
+        self.remaining -= 1;
+        true
+    }
+
+    pub fn remaining(&self) -> u32 {
+        self.remaining
+    }
+
+    /// Whether a retry was refused because the deadline was near.
+    pub fn deadline_hit(&self) -> bool {
+        self.deadline_hit
+    }
+
+    /// `reason` for a refused retry, noting when the deadline refused it.
+    pub fn exhausted_reason(&self, reason: String) -> String {
+        if self.deadline_hit {
+            format!("{} (total deadline reached)", reason)
+        } else {
+            reason
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stops_after_the_allowed_retries() {
+        let mut budget = RetryBudget::new(2);
+        assert!(budget.try_consume());
+        assert!(budget.try_consume());
+        assert!(!budget.try_consume());
+        assert_eq!(budget.remaining(), 0);
+        assert_eq!(budget.exhausted_reason("bad".to_string()), "bad");
+    }
+
+    #[test]
+    fn refuses_retries_near_the_deadline() {
+        let mut budget = RetryBudget::new(3).with_deadline(MIN_ATTEMPT_WINDOW / 2);
+        assert!(!budget.try_consume());
+        assert!(budget.deadline_hit());
+        assert_eq!(budget.remaining(), 3);
+        assert_eq!(
+            budget.exhausted_reason("bad".to_string()),
+            "bad (total deadline reached)"
+        );
+    }
+}
@@ -0,0 +1,62 @@
+//! Per-user reading stats for `GET /users/me/stats`.
+//!
+//! Stats are cached in Redis under `user_stats:<user_id>` for
+//! `STATS_CACHE_SECS`; they are not invalidated, so a new reading can take
+//! that long to show up.
+
+use chrono::{DateTime, Datelike, Duration, Utc};
+use redis::AsyncCommands;
+use redis::aio::ConnectionManager;
+
+use super::daily_card::{DAY_OFFSET_SECS, today};
+use crate::db::{Datastore, DbError};
+use crate::models::UserStats;
+
+const STATS_CACHE_SECS: u64 = 300;
+
+fn stats_key(user_id: i64) -> String {
+    format!("user_stats:{}", user_id)
+}
+
+/// Midnight on the 1st of the current month, Thai time.
+fn month_start() -> DateTime<Utc> {
+    let first = today().with_day(1).expect("every month has a 1st");
+    first.and_hms_opt(0, 0, 0).expect("midnight").and_utc()
+        - Duration::seconds(DAY_OFFSET_SECS.into())
+}
+
+/// The user's stats, from the cache when fresh. `None` if there is no such
+/// user. Redis errors fall back to the datastore.
+pub async fn load_user_stats(
+    store: &dyn Datastore,
+    redis: Option<&ConnectionManager>,
+    user_id: i64,
+) -> Result<Option<UserStats>, DbError> {
+    if let Some(redis) = redis {
+        let mut conn = redis.clone();
+        match conn.get::<_, Option<String>>(stats_key(user_id)).await {
+            Ok(Some(cached)) => {
+                if let Ok(stats) = serde_json::from_str(&cached) {
+                    return Ok(Some(stats));
+                }
+            }
+            Ok(None) => {}
+            Err(e) => log::warn!("stats cache read failed for user {}: {}", user_id, e),
+        }
+    }
+    let Some(stats) = store.user_stats(user_id, month_start()).await? else {
+        return Ok(None);
+    };
+    if let Some(redis) = redis
+        && let Ok(json) = serde_json::to_string(&stats)
+    {
+        let mut conn = redis.clone();
+        let written: Result<(), _> = conn
+            .set_ex(stats_key(user_id), json, STATS_CACHE_SECS)
+            .await;
+        if let Err(e) = written {
+            log::warn!("stats cache write failed for user {}: {}", user_id, e);
+        }
+    }
+    Ok(Some(stats))
+}
@@ -0,0 +1,91 @@
+//! Topic-biased draws: a love question can lean toward Cups without ruling
+//! out any card. Weights only nudge the shuffle (see `DRAW_PROCEDURE`).
+//!
+//! `TOPIC_SUIT_WEIGHTS` is comma-separated `topic:suit=weight` entries,
+//! e.g. `love:cups=2,career:pentacles=1.5`. Unlisted suits weigh 1, and
+//! topics with no entry draw uniformly, which is the default.
+
+use std::collections::HashMap;
+
+use crate::config::Config;
+use crate::models::{Suit, SuitWeights, Topic};
+
+/// Topic → suit weights, parsed once at startup.
+#[derive(Debug, Clone, Default)]
+pub struct TopicSuitWeights {
+    weights: HashMap<Topic, SuitWeights>,
+}
+
+impl TopicSuitWeights {
+    pub fn from_config(config: &Config) -> Self {
+        let mut weights: HashMap<Topic, SuitWeights> = HashMap::new();
+        for entry in &config.topic_suit_weights {
+            let parsed = entry.split_once(':').and_then(|(topic, rest)| {
+                let (suit, weight) = rest.split_once('=')?;
+                let weight: f64 = weight.trim().parse().ok()?;
+                // Zero or negative weights would make cards impossible.
+                (weight.is_finite() && weight > 0.0).then_some((
+                    Topic::from_name(&topic.trim().to_lowercase()),
+                    Suit::from_name(suit.trim())?,
+                    weight,
+                ))
+            });
+            match parsed {
+                Some((topic, suit, weight)) => weights.entry(topic).or_default().set(suit, weight),
+                None => log::warn!("ignoring malformed TOPIC_SUIT_WEIGHTS entry '{}'", entry),
+            }
+        }
+        TopicSuitWeights { weights }
+    }
+
+    /// The weights for `topic`, or `None` for a uniform draw.
+    pub fn for_topic(&self, topic: Topic) -> Option<SuitWeights> {
+        self.weights.get(&topic).copied()
+    }
+
+    /// The weights for a session: only when every question shares a topic.
+    pub fn for_topics(&self, topics: impl IntoIterator<Item = Topic>) -> Option<SuitWeights> {
+        let mut topics = topics.into_iter();
+        let first = topics.next()?;
+        topics
+            .all(|topic| topic == first)
+            .then(|| self.for_topic(first))
+            .flatten()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn weights(entries: &[&str]) -> TopicSuitWeights {
+        let mut config = Config::from_vars(|key| (key == "JWT_SECRET").then(|| "s".to_string()))
+            .expect("config");
+        config.topic_suit_weights = entries.iter().map(|e| e.to_string()).collect();
+        TopicSuitWeights::from_config(&config)
+    }
+
+    #[test]
+    fn parses_weights_per_topic() {
+        let weights = weights(&["love:cups=2", "Love:swords=0.5", "career:pentacles=1.5"]);
+        let love = weights.for_topic(Topic::Love).unwrap();
+        assert_eq!((love.cups, love.swords, love.wands), (2.0, 0.5, 1.0));
+        assert_eq!(weights.for_topic(Topic::Career).unwrap().pentacles, 1.5);
+        assert_eq!(weights.for_topic(Topic::Health), None);
+    }
+
+    #[test]
+    fn defaults_to_uniform_and_skips_bad_entries() {
+        assert_eq!(weights(&[]).for_topic(Topic::Love), None);
+        let weights = weights(&["love:cups=0", "love:cups=-1", "love=2", "love:coins=2"]);
+        assert_eq!(weights.for_topic(Topic::Love), None);
+    }
+
+    #[test]
+    fn sessions_are_biased_only_when_the_topics_agree() {
+        let weights = weights(&["love:cups=2"]);
+        assert!(weights.for_topics([Topic::Love, Topic::Love]).is_some());
+        assert_eq!(weights.for_topics([Topic::Love, Topic::Career]), None);
+        assert_eq!(weights.for_topics([]), None);
+    }
+}
@@ -0,0 +1,198 @@
+//! Auto-suspension of users who keep sending questions the QuestionFilter
+//! rejects. Rejections are counted per user over a fixed window starting at
+//! the first one (`ABUSE_WINDOW_SECS`), in Redis when it is available so
+//! every instance shares the count, otherwise in process. Reaching
+//! `ABUSE_REJECTION_THRESHOLD` suspends the user until an admin lifts it.
+//!
+//! Whether a user is suspended is cached in Redis under
+//! `user_suspended:<user_id>` for `SUSPENSION_CACHE_SECS`; suspending and
+//! unsuspending overwrite the entry.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use redis::AsyncCommands;
+use redis::aio::ConnectionManager;
+
+use crate::config::Config;
+use crate::db::{Datastore, DbError};
+
+const SUSPENSION_CACHE_SECS: u64 = 60;
+
+/// Recorded as the suspension reason.
+const AUTO_SUSPENSION_REASON: &str = "Too many rejected questions";
+
+fn rejections_key(user_id: i64) -> String {
+    format!("filter_rejections:{}", user_id)
+}
+
+fn suspended_key(user_id: i64) -> String {
+    format!("user_suspended:{}", user_id)
+}
+
+/// The threshold and window, plus the in-process rejection counts.
+pub struct AbuseTracker {
+    threshold: Option<u32>,
+    window: Duration,
+    /// user id → (window start, rejections in it).
+    local: Mutex<HashMap<i64, (Instant, u32)>>,
+}
+
+impl AbuseTracker {
+    pub fn new(threshold: Option<u32>, window: Duration) -> Self {
+        AbuseTracker {
+            threshold,
+            window: window.max(Duration::from_secs(1)),
+            local: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn from_config(config: &Config) -> Self {
+        AbuseTracker::new(
+            Some(config.abuse_rejection_threshold).filter(|&n| n > 0),
+            Duration::from_secs(config.abuse_window_secs),
+        )
+    }
+
+    /// Rejections that suspend a user; `None` when auto-suspension is off.
+    pub fn threshold(&self) -> Option<u32> {
+        self.threshold
+    }
+
+    fn count_local(&self, user_id: i64) -> u32 {
+        let now = Instant::now();
+        let mut local = self.local.lock().unwrap();
+        let (started, count) = local.entry(user_id).or_insert((now, 0));
+        if now.duration_since(*started) >= self.window {
+            *started = now;
+            *count = 0;
+        }
+        *count += 1;
+        *count
+    }
+
+    fn clear_local(&self, user_id: i64) {
+        self.local.lock().unwrap().remove(&user_id);
+    }
+}
+
+/// Count one rejection for `user_id`, suspending them once the window's
+/// count reaches the threshold. Returns whether the user is now suspended.
+/// Redis errors fall back to the in-process counts.
+pub async fn record_rejection(
+    tracker: &AbuseTracker,
+    store: &dyn Datastore,
+    redis: Option<&ConnectionManager>,
+    user_id: i64,
+) -> Result<bool, DbError> {
+    let Some(threshold) = tracker.threshold else {
+        return Ok(false);
+    };
+    let mut count = None;
+    if let Some(redis) = redis {
+        match count_in_redis(redis, user_id, tracker.window).await {
+            Ok(n) => count = Some(n),
+            Err(e) => log::warn!("rejection count failed for user {}: {}", user_id, e),
+        }
+    }
+    let count = match count {
+        Some(count) => count,
+        None => tracker.count_local(user_id),
+    };
+    if count < threshold {
+        return Ok(false);
+    }
+    store.suspend_user(user_id, AUTO_SUSPENSION_REASON).await?;
+    if let Some(redis) = redis {
+        cache_suspension(redis, user_id, true).await;
+    }
+    log::warn!(
+        "suspended user {} after {} rejected question(s)",
+        user_id,
+        count
+    );
+    Ok(true)
+}
+
+/// Count a rejection in Redis; the window starts with the first one.
+async fn count_in_redis(
+    redis: &ConnectionManager,
+    user_id: i64,
+    window: Duration,
+) -> redis::RedisResult<u32> {
+    let key = rejections_key(user_id);
+    let mut conn = redis.clone();
+    // INCR keeps the TTL set when the counter was created.
+    let (count,): (u32,) = redis::pipe()
+        .atomic()
+        .cmd("SET")
+        .arg(&key)
+        .arg(0)
+        .arg("EX")
+        .arg(window.as_secs())
+        .arg("NX")
+        .ignore()
+        .incr(&key, 1)
+        .query_async(&mut conn)
+        .await?;
+    Ok(count)
+}
+
+/// Lift `user_id`'s suspension and reset their rejection count, so they
+/// start over rather than tripping again on the next rejection. Returns
+/// whether they were suspended.
+pub async fn lift_suspension(
+    tracker: &AbuseTracker,
+    store: &dyn Datastore,
+    redis: Option<&ConnectionManager>,
+    user_id: i64,
+) -> Result<bool, DbError> {
+    let lifted = store.unsuspend_user(user_id).await?;
+    tracker.clear_local(user_id);
+    if let Some(redis) = redis {
+        let mut conn = redis.clone();
+        let deleted: Result<(), _> = conn.del(rejections_key(user_id)).await;
+        if let Err(e) = deleted {
+            log::warn!("failed to reset rejections for user {}: {}", user_id, e);
+        }
+        cache_suspension(redis, user_id, false).await;
+    }
+    Ok(lifted)
+}
+
+/// Whether the caller is suspended; admins never are. Redis errors fall
+/// back to the datastore.
+pub async fn is_suspended(
+    user_id: i64,
+    is_admin: bool,
+    store: &dyn Datastore,
+    redis: Option<&ConnectionManager>,
+) -> Result<bool, DbError> {
+    if is_admin {
+        return Ok(false);
+    }
+    if let Some(redis) = redis {
+        let mut conn = redis.clone();
+        match conn.get::<_, Option<bool>>(suspended_key(user_id)).await {
+            Ok(Some(suspended)) => return Ok(suspended),
+            Ok(None) => {}
+            Err(e) => log::warn!("suspension cache read failed for user {}: {}", user_id, e),
+        }
+    }
+    let suspended = store.is_suspended(user_id).await?;
+    if let Some(redis) = redis {
+        cache_suspension(redis, user_id, suspended).await;
+    }
+    Ok(suspended)
+}
+
+async fn cache_suspension(redis: &ConnectionManager, user_id: i64, suspended: bool) {
+    let mut conn = redis.clone();
+    let written: Result<(), _> = conn
+        .set_ex(suspended_key(user_id), suspended, SUSPENSION_CACHE_SECS)
+        .await;
+    if let Err(e) = written {
+        log::warn!("suspension cache write failed for user {}: {}", user_id, e);
+    }
+}
@@ -0,0 +1,64 @@
+//! Resolving a user's `Tier`: admins from their token role, paid users from
+//! a settled payment, everyone else free.
+//!
+//! Lookups are cached in Redis under `user_tier:<user_id>` for
+//! `TIER_CACHE_SECS`; settling a payment drops the entry.
+
+use crate::db::{Datastore, DbError};
+use crate::models::Tier;
+use redis::AsyncCommands;
+use redis::aio::ConnectionManager;
+
+const TIER_CACHE_SECS: u64 = 60;
+
+fn tier_key(user_id: i64) -> String {
+    format!("user_tier:{}", user_id)
+}
+
+/// The caller's tier. Redis errors fall back to the datastore; without one
+/// every non-admin is `Free`.
+pub async fn resolve_tier(
+    user_id: i64,
+    is_admin: bool,
+    store: Option<&dyn Datastore>,
+    redis: Option<&ConnectionManager>,
+) -> Result<Tier, DbError> {
+    if is_admin {
+        return Ok(Tier::Admin);
+    }
+    if let Some(redis) = redis {
+        let mut conn = redis.clone();
+        match conn.get::<_, Option<String>>(tier_key(user_id)).await {
+            Ok(Some(cached)) => {
+                if let Ok(tier) = cached.parse() {
+                    return Ok(tier);
+                }
+            }
+            Ok(None) => {}
+            Err(e) => log::warn!("tier cache read failed for user {}: {}", user_id, e),
+        }
+    }
+    let tier = match store {
+        Some(store) if store.has_settled_payment(user_id).await? => Tier::Paid,
+        _ => Tier::Free,
+    };
+    if let Some(redis) = redis {
+        let mut conn = redis.clone();
+        let written: Result<(), _> = conn
+            .set_ex(tier_key(user_id), tier.as_str(), TIER_CACHE_SECS)
+            .await;
+        if let Err(e) = written {
+            log::warn!("tier cache write failed for user {}: {}", user_id, e);
+        }
+    }
+    Ok(tier)
+}
+
+/// Drop the cached tier so the next request re-resolves it.
+pub async fn invalidate_tier(redis: &ConnectionManager, user_id: i64) {
+    let mut conn = redis.clone();
+    let deleted: Result<(), _> = conn.del(tier_key(user_id)).await;
+    if let Err(e) = deleted {
+        log::warn!("tier cache invalidation failed for user {}: {}", user_id, e);
+    }
+}
@@ -0,0 +1,178 @@
+//! Per-model output ceilings. A completion's `max_tokens` is clamped to the
+//! model's output limit and to what is left of its context window after the
+//! (estimated) prompt, so an oversized request can't fail with a
+//! context-length error.
+//!
+//! `MODEL_TOKEN_LIMITS` is comma-separated `model=context/max_output`
+//! entries in tokens; entries override `BUILTIN_TOKEN_LIMITS`. Models in
+//! neither are left unclamped.
+
+use std::collections::HashMap;
+
+use super::prompt_budget::estimate_tokens;
+use crate::config::Config;
+
+/// Context window and output limit of the default and common models.
+const BUILTIN_TOKEN_LIMITS: [(&str, u32, u32); 4] = [
+    ("gpt-4o-mini", 128_000, 16_384),
+    ("gpt-4o", 128_000, 16_384),
+    ("gpt-4", 8_192, 8_192),
+    ("gpt-3.5-turbo", 16_385, 4_096),
+];
+
+/// Chat framing (roles, separators) on top of the message text.
+const MESSAGE_OVERHEAD_TOKENS: u32 = 16;
+
+/// `estimate_tokens` is approximate; leave this share of the prompt
+/// estimate again as headroom.
+const ESTIMATE_HEADROOM_PERCENT: u32 = 10;
+
+/// Token limits of one model.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ModelLimits {
+    /// Prompt plus completion.
+    pub context_window: u32,
+    /// Largest completion the model will produce.
+    pub max_output: u32,
+}
+
+/// Model id → limits, parsed once at startup.
+#[derive(Debug, Clone)]
+pub struct TokenLimits {
+    limits: HashMap<String, ModelLimits>,
+}
+
+impl TokenLimits {
+    pub fn from_config(config: &Config) -> Self {
+        let mut limits: HashMap<String, ModelLimits> = BUILTIN_TOKEN_LIMITS
+            .iter()
+            .map(|(model, context_window, max_output)| {
+                (
+                    model.to_string(),
+                    ModelLimits {
+                        context_window: *context_window,
+                        max_output: *max_output,
+                    },
+                )
+            })
+            .collect();
+        for entry in &config.model_token_limits {
+            let parsed = entry.split_once('=').and_then(|(model, limit)| {
+                let (context_window, max_output) = limit.split_once('/')?;
+                Some((
+                    model.trim(),
+                    ModelLimits {
+                        context_window: context_window.trim().parse().ok()?,
+                        max_output: max_output.trim().parse().ok()?,
+                    },
+                ))
+            });
+            match parsed {
+                Some((model, limit)) => {
+                    limits.insert(model.to_string(), limit);
+                }
+                None => log::warn!("ignoring malformed MODEL_TOKEN_LIMITS entry '{}'", entry),
+            }
+        }
+        TokenLimits { limits }
+    }
+
+    pub fn get(&self, model: &str) -> Option<ModelLimits> {
+        self.limits.get(model).copied()
+    }
+
+    /// `requested` capped at what `model` can still produce after a prompt
+    /// of `system` and `user`. Unknown models and unset `max_tokens` pass
+    /// through unchanged.
+    pub fn clamp_max_tokens(
+        &self,
+        model: &str,
+        requested: Option<u32>,
+        system: &str,
+        user: &str,
+    ) -> Option<u32> {
+        let (Some(limits), Some(requested)) = (self.get(model), requested) else {
+            return requested;
+        };
+        let prompt = u32::try_from(estimate_tokens(system) + estimate_tokens(user))
+            .unwrap_or(u32::MAX)
+            .saturating_mul(100 + ESTIMATE_HEADROOM_PERCENT)
+            / 100
+            + MESSAGE_OVERHEAD_TOKENS;
+        let ceiling = limits
+            .max_output
+            .min(limits.context_window.saturating_sub(prompt))
+            .max(1);
+        if requested > ceiling {
+            log::info!(
+                "clamping max_tokens {} to {} for {} (~{} prompt tokens)",
+                requested,
+                ceiling,
+                model,
+                prompt
+            );
+        }
+        Some(requested.min(ceiling))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits(entries: &[&str]) -> TokenLimits {
+        let mut config = Config::from_vars(|key| (key == "JWT_SECRET").then(|| "s".to_string()))
+            .expect("config");
+        config.model_token_limits = entries.iter().map(|e| e.to_string()).collect();
+        TokenLimits::from_config(&config)
+    }
+
+    #[test]
+    fn a_large_request_is_clamped_to_a_small_context() {
+        let limits = limits(&["tiny=2000/4000"]);
+        // 3600 ASCII chars ≈ 900 tokens, +10% headroom, +16 framing = 1006.
+        let system = "a".repeat(3600);
+        assert_eq!(
+            limits.clamp_max_tokens("tiny", Some(4000), &system, ""),
+            Some(994)
+        );
+        assert_eq!(
+            limits.clamp_max_tokens("tiny", Some(500), &system, ""),
+            Some(500)
+        );
+    }
+
+    #[test]
+    fn requests_are_held_to_the_output_limit() {
+        let limits = limits(&[]);
+        assert_eq!(
+            limits.clamp_max_tokens("gpt-3.5-turbo", Some(8000), "system", "user"),
+            Some(4096)
+        );
+    }
+
+    #[test]
+    fn unknown_models_and_unset_max_tokens_pass_through() {
+        let limits = limits(&[]);
+        assert_eq!(
+            limits.clamp_max_tokens("someone-elses-model", Some(100_000), "s", "u"),
+            Some(100_000)
+        );
+        assert_eq!(limits.clamp_max_tokens("gpt-4", None, "s", "u"), None);
+    }
+
+    #[test]
+    fn configured_entries_override_the_builtins_and_bad_ones_are_skipped() {
+        let limits = limits(&["gpt-4=4000/1000", "broken", "half=100"]);
+        assert_eq!(
+            limits.get("gpt-4"),
+            Some(ModelLimits {
+                context_window: 4000,
+                max_output: 1000,
+            })
+        );
+        assert_eq!(limits.get("broken"), None);
+        assert_eq!(limits.get("half"), None);
+        assert!(limits.get("gpt-4o-mini").is_some());
+    }
+}
@@ -0,0 +1,157 @@
+//! `AppState`: every shared dependency, built once at startup and
+//! registered as a single `web::Data<AppState>`. Handlers and middleware
+//! take what they need through its accessors.
+//!
+//! `AppState::new` builds everything from config; `with_llm`, `with_store`
+//! and `with_payments` swap in other implementations, e.g. mocks.
+
+use std::sync::Arc;
+
+use redis::aio::ConnectionManager;
+use sqlx::PgPool;
+
+use crate::config::Config;
+use crate::db::{Datastore, datastore_from_config};
+use crate::middleware::{ConcurrencyLimiter, RateLimiter, RouteTimeouts, TrustedProxies};
+use crate::services::{
+    AbuseTracker, CassetteLlm, FeatureFlags, FilterMetrics, LlmProvider, ModelCatalog,
+    OpenAiClient, PaymentProvider, PricingTable, payment_provider_from_config,
+};
+
+pub struct AppState {
+    config: Config,
+    pool: Option<PgPool>,
+    redis: Option<ConnectionManager>,
+    store: Arc<dyn Datastore>,
+    payments: Arc<dyn PaymentProvider>,
+    /// The configured OpenAI client, for the health probe and warm-up.
+    openai: Arc<OpenAiClient>,
+    /// What readings and asks are sent to: `openai` (through cassettes under
+    /// `LLM_RECORD`/`LLM_REPLAY`) unless replaced.
+    llm: Arc<dyn LlmProvider>,
+    flags: FeatureFlags,
+    models: ModelCatalog,
+    pricing: PricingTable,
+    filter_metrics: FilterMetrics,
+    timeouts: RouteTimeouts,
+    rate_limiter: RateLimiter,
+    /// Shared with the slots it hands out.
+    concurrency_limiter: Arc<ConcurrencyLimiter>,
+    abuse_tracker: AbuseTracker,
+    trusted_proxies: TrustedProxies,
+}
+
+impl AppState {
+    pub fn new(config: Config, pool: Option<PgPool>, redis: Option<ConnectionManager>) -> Self {
+        // Under `MOCK_DB` nothing may reach Postgres, not just readings.
+        let pool = pool.filter(|_| !config.mock_db);
+        let openai = Arc::new(OpenAiClient::new(&config));
+        AppState {
+            store: datastore_from_config(&config, pool.as_ref()),
+            payments: payment_provider_from_config(&config),
+            llm: CassetteLlm::from_config(&config, openai.clone()),
+            openai,
+            flags: FeatureFlags::new(redis.clone(), FeatureFlags::default_rules()),
+            models: ModelCatalog::from_config(&config),
+            pricing: PricingTable::from_config(&config),
+            filter_metrics: FilterMetrics::new(),
+            timeouts: RouteTimeouts::from_config(&config),
+            rate_limiter: RateLimiter::from_config(&config),
+            concurrency_limiter: Arc::new(ConcurrencyLimiter::from_config(&config)),
+            abuse_tracker: AbuseTracker::from_config(&config),
+            trusted_proxies: TrustedProxies::from_config(&config),
+            config,
+            pool,
+            redis,
+        }
+    }
+
+    /// Send readings and asks to `llm` instead of the OpenAI client.
+    pub fn with_llm(mut self, llm: Arc<dyn LlmProvider>) -> Self {
+        self.llm = llm;
+        self
+    }
+
+    pub fn with_store(mut self, store: Arc<dyn Datastore>) -> Self {
+        self.store = store;
+        self
+    }
+
+    pub fn with_payments(mut self, payments: Arc<dyn PaymentProvider>) -> Self {
+        self.payments = payments;
+        self
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// `None` when `DATABASE_URL` is unset or `MOCK_DB` is on, so credits,
+    /// payments and shares are unavailable (or free) rather than written to
+    /// a database the readings aren't in.
+    pub fn pool(&self) -> Option<&PgPool> {
+        self.pool.as_ref()
+    }
+
+    /// `None` when Redis is not configured or unreachable at startup.
+    pub fn redis(&self) -> Option<&ConnectionManager> {
+        self.redis.as_ref()
+    }
+
+    pub fn store(&self) -> &dyn Datastore {
+        self.store.as_ref()
+    }
+
+    pub fn payments(&self) -> &dyn PaymentProvider {
+        self.payments.as_ref()
+    }
+
+    pub fn openai(&self) -> &OpenAiClient {
+        &self.openai
+    }
+
+    pub fn llm(&self) -> &dyn LlmProvider {
+        self.llm.as_ref()
+    }
+
+    /// A handle to `llm` for work that outlives a request (the queue worker).
+    pub fn llm_handle(&self) -> Arc<dyn LlmProvider> {
+        self.llm.clone()
+    }
+
+    pub fn flags(&self) -> &FeatureFlags {
+        &self.flags
+    }
+
+    pub fn models(&self) -> &ModelCatalog {
+        &self.models
+    }
+
+    pub fn pricing(&self) -> &PricingTable {
+        &self.pricing
+    }
+
+    pub fn filter_metrics(&self) -> &FilterMetrics {
+        &self.filter_metrics
+    }
+
+    pub fn timeouts(&self) -> &RouteTimeouts {
+        &self.timeouts
+    }
+
+    pub fn rate_limiter(&self) -> &RateLimiter {
+        &self.rate_limiter
+    }
+
+    pub fn concurrency_limiter(&self) -> &Arc<ConcurrencyLimiter> {
+        &self.concurrency_limiter
+    }
+
+    pub fn abuse_tracker(&self) -> &AbuseTracker {
+        &self.abuse_tracker
+    }
+
+    pub fn trusted_proxies(&self) -> &TrustedProxies {
+        &self.trusted_proxies
+    }
+}
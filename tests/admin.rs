@@ -0,0 +1,246 @@
+//! Admin reading and user endpoints against the in-memory datastore and
+//! `EchoLlm`; the import rollback also needs `DATABASE_URL`.
+
+mod common;
+
+use std::sync::Arc;
+
+use actix_web::{test, web};
+use serde_json::{Value, json};
+
+use mimi_backend::app::build_app;
+use mimi_backend::db::MemoryDatastore;
+use mimi_backend::models::Spread;
+use mimi_backend::services::{
+    CardPicker, PipelineSettings, PromptVersion, ReadingAgent, generate_reading,
+};
+
+fn remoderate(token: String, reading_id: i64) -> test::TestRequest {
+    test::TestRequest::post()
+        .uri(&format!("/admin/readings/{}/remoderate", reading_id))
+        .insert_header(("Authorization", token))
+}
+
+#[actix_web::test]
+async fn a_question_failing_the_current_filter_is_flagged_on_remoderation() {
+    let store = Arc::new(MemoryDatastore::new());
+    let state = common::state(common::config(&[])).with_store(store.clone());
+    let app = test::init_service(build_app(web::Data::new(state))).await;
+    let resp = test::call_service(
+        &app,
+        test::TestRequest::post()
+            .uri("/readings")
+            .insert_header(("Authorization", common::token(1)))
+            .set_json(json!({ "question": "Will I get the job?" }))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), 200);
+    let reading: Value = test::read_body_json(resp).await;
+    let reading_id = reading["id"].as_i64().unwrap();
+
+    // Still allowed under the policy it was read under.
+    let resp = test::call_service(
+        &app,
+        remoderate(common::admin_token(99), reading_id).to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), 200);
+    let verdict: Value = test::read_body_json(resp).await;
+    assert_eq!(verdict["flagged"], false);
+
+    // EchoLlm analyses with confidence 0.9; the tightened policy wants more.
+    let tightened = common::state(common::config(&[("ANALYSIS_MIN_CONFIDENCE", "0.95")]))
+        .with_store(store.clone());
+    let app = test::init_service(build_app(web::Data::new(tightened))).await;
+    let resp = test::call_service(
+        &app,
+        remoderate(common::admin_token(99), reading_id).to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), 200);
+    let verdict: Value = test::read_body_json(resp).await;
+    assert_eq!(verdict["flagged"], true);
+    assert_eq!(verdict["moderated_by"], 99);
+    assert!(verdict["reason"].is_string(), "{}", verdict);
+
+    let stored = store
+        .reading_moderation(reading_id)
+        .expect("verdict stored");
+    assert!(stored.flagged);
+    assert_eq!(stored.moderated_by, 99);
+    // Flagged, not deleted.
+    let resp = test::call_service(
+        &app,
+        test::TestRequest::get()
+            .uri("/readings")
+            .insert_header(("Authorization", common::token(1)))
+            .to_request(),
+    )
+    .await;
+    let history: Value = test::read_body_json(resp).await;
+    assert_eq!(history["items"][0]["id"], reading_id);
+}
+
+#[actix_web::test]
+async fn remoderation_is_admin_only_and_needs_a_reading() {
+    let app = test::init_service(build_app(web::Data::new(common::state(
+        common::config(&[]),
+    ))))
+    .await;
+    let resp = test::call_service(&app, remoderate(common::token(1), 1).to_request()).await;
+    assert_eq!(resp.status(), 403);
+    let resp =
+        test::call_service(&app, remoderate(common::admin_token(99), 404).to_request()).await;
+    assert_eq!(resp.status(), 404);
+}
+
+#[actix_web::test]
+async fn repeated_rejections_suspend_a_user_until_an_admin_lifts_it() {
+    let state = common::state(common::config(&[("ABUSE_REJECTION_THRESHOLD", "2")]));
+    let app = test::init_service(build_app(web::Data::new(state))).await;
+    let ask = |user_id: i64, question: &str| {
+        test::TestRequest::post()
+            .uri("/readings")
+            .insert_header(("Authorization", common::token(user_id)))
+            .set_json(json!({ "question": question }))
+            .to_request()
+    };
+    // Refused by the language filter.
+    let rejected = "我会得到这份工作吗？";
+
+    let resp = test::call_service(&app, ask(1, rejected)).await;
+    assert_eq!(resp.status(), 422);
+    let resp = test::call_service(&app, ask(1, "Will I get the job?")).await;
+    assert_eq!(resp.status(), 200);
+    let resp = test::call_service(&app, ask(1, rejected)).await;
+    assert_eq!(resp.status(), 422);
+
+    // Suspension is enforced in middleware, so the 403 is an `Err`.
+    let err = test::try_call_service(&app, ask(1, "Will I get the job?"))
+        .await
+        .err()
+        .expect("suspended");
+    assert_eq!(err.error_response().status(), 403);
+    let resp = test::call_service(&app, ask(2, "Will I get the job?")).await;
+    assert_eq!(resp.status(), 200);
+
+    let unsuspend = |token: String| {
+        test::TestRequest::post()
+            .uri("/admin/users/1/unsuspend")
+            .insert_header(("Authorization", token))
+            .to_request()
+    };
+    let resp = test::call_service(&app, unsuspend(common::token(2))).await;
+    assert_eq!(resp.status(), 403);
+    let resp = test::call_service(&app, unsuspend(common::admin_token(99))).await;
+    assert_eq!(resp.status(), 200);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["was_suspended"], true);
+
+    let resp = test::call_service(&app, ask(1, "Will I get the job?")).await;
+    assert_eq!(resp.status(), 200);
+    // The count starts over, so one more rejection doesn't trip it again.
+    let resp = test::call_service(&app, ask(1, rejected)).await;
+    assert_eq!(resp.status(), 422);
+    let resp = test::call_service(&app, ask(1, "Will I get the job?")).await;
+    assert_eq!(resp.status(), 200);
+}
+
+fn import(token: String, readings: &[Value]) -> test::TestRequest {
+    test::TestRequest::post()
+        .uri("/admin/readings/import")
+        .insert_header(("Authorization", token))
+        .set_json(json!({ "readings": readings }))
+}
+
+/// Import entries for `user_id`, one per question, a day apart.
+async fn legacy_entries(user_id: i64, questions: &[&str]) -> Vec<Value> {
+    let llm = common::EchoLlm::default();
+    let settings = PipelineSettings::from_config(&common::config(&[]));
+    let mut entries = Vec::new();
+    for (day, question) in questions.iter().enumerate() {
+        let reading = generate_reading(
+            &ReadingAgent::new(&llm, PromptVersion::Stable),
+            question,
+            None,
+            Spread::ThreeCard,
+            CardPicker::new(day as u64),
+            &settings,
+            &mut settings.budget(),
+        )
+        .await
+        .expect("reading");
+        entries.push(json!({
+            "user_id": user_id,
+            "created_at": format!("2023-01-{:02}T09:00:00Z", day + 1),
+            "reading": reading,
+        }));
+    }
+    entries
+}
+
+#[actix_web::test]
+async fn imported_readings_keep_their_order_and_times() {
+    let app = test::init_service(build_app(web::Data::new(common::state(
+        common::config(&[]),
+    ))))
+    .await;
+    let questions = ["First legacy?", "Second legacy?", "Third legacy?"];
+    let entries = legacy_entries(5, &questions).await;
+
+    let resp = test::call_service(&app, import(common::token(1), &entries).to_request()).await;
+    assert_eq!(resp.status(), 403);
+
+    let resp =
+        test::call_service(&app, import(common::admin_token(1), &entries).to_request()).await;
+    assert_eq!(resp.status(), 200);
+    let body: Value = test::read_body_json(resp).await;
+    let ids = body["ids"].as_array().unwrap();
+    assert_eq!(ids.len(), 3);
+
+    let history: Value = test::call_and_read_body_json(
+        &app,
+        test::TestRequest::get()
+            .uri("/readings")
+            .insert_header(("Authorization", common::token(5)))
+            .to_request(),
+    )
+    .await;
+    // Newest first; each id is the reading sent in its place.
+    let items = history["items"].as_array().unwrap();
+    assert_eq!(items.len(), 3);
+    for (item, (id, question)) in items.iter().rev().zip(ids.iter().zip(questions)) {
+        assert_eq!(&item["id"], id);
+        assert_eq!(item["question"], question);
+    }
+    assert_eq!(items[2]["created_at"], "2023-01-01T09:00:00Z");
+
+    let resp = test::call_service(&app, import(common::admin_token(1), &[]).to_request()).await;
+    assert_eq!(resp.status(), 400);
+}
+
+#[actix_web::test]
+async fn an_import_with_an_unknown_user_stores_nothing() {
+    let Some(pool) = common::test_pool().await else {
+        return;
+    };
+    let app = test::init_service(build_app(web::Data::new(common::pg_state(
+        common::config(&[]),
+        pool.clone(),
+    ))))
+    .await;
+    let user_id = common::new_user(&pool, 0).await;
+    let mut entries = legacy_entries(user_id, &["First legacy?", "Second legacy?"]).await;
+    entries.extend(legacy_entries(-1, &["Nobody's?"]).await);
+
+    let resp =
+        test::call_service(&app, import(common::admin_token(1), &entries).to_request()).await;
+    assert_eq!(resp.status(), 422);
+    let stored: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM readings WHERE user_id = $1")
+        .bind(user_id)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(stored, 0);
+}
@@ -0,0 +1,76 @@
+//! `/ask` against `EchoLlm`.
+
+mod common;
+
+use actix_web::http::header::CONTENT_TYPE;
+use actix_web::{test, web};
+use serde_json::{Value, json};
+
+use mimi_backend::app::build_app;
+
+#[actix_web::test]
+async fn get_ask_returns_plain_text() {
+    let app = test::init_service(build_app(web::Data::new(common::state(
+        common::config(&[]),
+    ))))
+    .await;
+    let resp = test::call_service(
+        &app,
+        test::TestRequest::get()
+            .uri("/ask?q=Is%20today%20lucky%3F")
+            .insert_header(("Authorization", common::token(1)))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(
+        resp.headers().get(CONTENT_TYPE).unwrap(),
+        "text/plain; charset=utf-8"
+    );
+    let body = test::read_body(resp).await;
+    assert!(body.starts_with(b"{\"confidence\""), "{:?}", body);
+}
+
+#[actix_web::test]
+async fn post_ask_honours_accept_text_plain() {
+    let app = test::init_service(build_app(web::Data::new(common::state(
+        common::config(&[]),
+    ))))
+    .await;
+    let ask = |accept: &'static str| {
+        test::TestRequest::post()
+            .uri("/ask")
+            .insert_header(("Authorization", common::token(1)))
+            .insert_header(("Accept", accept))
+            .set_json(json!({ "question": "Is today lucky?" }))
+            .to_request()
+    };
+
+    let resp = test::call_service(&app, ask("text/plain")).await;
+    assert_eq!(
+        resp.headers().get(CONTENT_TYPE).unwrap(),
+        "text/plain; charset=utf-8"
+    );
+
+    let resp = test::call_service(&app, ask("application/json")).await;
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["model"], "echo");
+    assert!(body["answer"].is_string());
+}
+
+#[actix_web::test]
+async fn ask_rejects_a_blank_question() {
+    let app = test::init_service(build_app(web::Data::new(common::state(
+        common::config(&[]),
+    ))))
+    .await;
+    let resp = test::call_service(
+        &app,
+        test::TestRequest::get()
+            .uri("/ask?q=%20%20")
+            .insert_header(("Authorization", common::token(1)))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), 400);
+}
@@ -0,0 +1,42 @@
+//! Bearer token validation, including the clock-skew leeway on `exp`.
+
+mod common;
+
+use actix_web::{test, web};
+
+use mimi_backend::app::build_app;
+
+#[actix_web::test]
+async fn expired_tokens_are_accepted_only_within_the_leeway() {
+    for (leeway, expired_secs_ago, expected) in [
+        (None, 30, 200),
+        (None, 120, 401),
+        (Some("300"), 120, 200),
+        (Some("0"), 30, 401),
+        (Some("0"), -30, 200),
+    ] {
+        let vars: Vec<_> = leeway.map(|l| ("JWT_LEEWAY_SECS", l)).into_iter().collect();
+        let app = test::init_service(build_app(web::Data::new(common::state(common::config(
+            &vars,
+        )))))
+        .await;
+        let resp = test::call_service(
+            &app,
+            test::TestRequest::get()
+                .uri("/readings")
+                .insert_header((
+                    "Authorization",
+                    common::token_expiring(1, -expired_secs_ago),
+                ))
+                .to_request(),
+        )
+        .await;
+        assert_eq!(
+            resp.status(),
+            expected,
+            "leeway {:?}, expired {}s ago",
+            leeway,
+            expired_secs_ago
+        );
+    }
+}
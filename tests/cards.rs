@@ -0,0 +1,166 @@
+//! Deck reference and card of the day endpoints, and how drawn cards are
+//! stored.
+
+mod common;
+
+use actix_web::{test, web};
+use serde_json::{Value, json};
+
+use mimi_backend::app::build_app;
+use mimi_backend::models::{DrawnCard, StoredCard, card_by_id};
+use mimi_backend::services::{card_of_the_day, today};
+
+#[actix_web::test]
+async fn card_of_the_day_is_shared_or_personal() {
+    let app = test::init_service(build_app(web::Data::new(common::state(
+        common::config(&[]),
+    ))))
+    .await;
+    let shared: Value = test::call_and_read_body_json(
+        &app,
+        test::TestRequest::get()
+            .uri("/card-of-the-day")
+            .to_request(),
+    )
+    .await;
+    assert_eq!(
+        shared,
+        serde_json::to_value(card_of_the_day(today(), None)).unwrap()
+    );
+
+    let personal: Value = test::call_and_read_body_json(
+        &app,
+        test::TestRequest::get()
+            .uri("/card-of-the-day")
+            .insert_header(("Authorization", common::token(42)))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(
+        personal,
+        serde_json::to_value(card_of_the_day(today(), Some(42))).unwrap()
+    );
+}
+
+#[tokio::test]
+async fn drawn_cards_are_stored_by_id_and_named_from_the_deck() {
+    let drawn = DrawnCard {
+        card_id: 0,
+        name: "Renamed since".to_string(),
+        position: "Past".to_string(),
+        reversed: true,
+    };
+    let stored = StoredCard::from(&drawn);
+    assert_eq!(
+        serde_json::to_value(&stored).unwrap(),
+        json!({ "card_id": 0, "position": "Past", "reversed": true })
+    );
+
+    let read_back: StoredCard = serde_json::from_value(json!({
+        "card_id": 0,
+        "position": "Past",
+        "reversed": true,
+    }))
+    .unwrap();
+    assert_eq!(read_back, stored);
+    let resolved = read_back.resolve();
+    assert_eq!(resolved.name, card_by_id(0).unwrap().name);
+    assert_eq!(
+        (
+            resolved.card_id,
+            resolved.position.as_str(),
+            resolved.reversed
+        ),
+        (0, "Past", true)
+    );
+}
+
+#[tokio::test]
+async fn an_unknown_card_id_still_resolves() {
+    let named = StoredCard {
+        card_id: 200,
+        position: "Past".to_string(),
+        reversed: false,
+        name: Some("Retired card".to_string()),
+    };
+    assert_eq!(named.resolve().name, "Retired card");
+    let unnamed = StoredCard {
+        card_id: 200,
+        position: "Past".to_string(),
+        reversed: false,
+        name: None,
+    };
+    assert_eq!(unnamed.resolve().name, "Unknown card #200");
+}
+
+#[actix_web::test]
+async fn the_deck_reference_lists_every_card_in_either_language() {
+    let app = test::init_service(build_app(web::Data::new(common::state(
+        common::config(&[]),
+    ))))
+    .await;
+    let resp = test::call_service(
+        &app,
+        test::TestRequest::get().uri("/cards?lang=en").to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), 200);
+    let cache = resp
+        .headers()
+        .get("cache-control")
+        .unwrap()
+        .to_str()
+        .unwrap();
+    assert!(
+        cache.contains("public") && cache.contains("max-age"),
+        "{}",
+        cache
+    );
+    let body: Value = test::read_body_json(resp).await;
+    let items = body["items"].as_array().unwrap();
+    assert_eq!(items.len(), 78);
+    for (i, item) in items.iter().enumerate() {
+        assert_eq!(item["id"], i);
+        assert_eq!(item["name"], item["name_en"]);
+        assert!(!item["keywords"].as_array().unwrap().is_empty(), "{}", item);
+    }
+
+    let body: Value = test::call_and_read_body_json(
+        &app,
+        test::TestRequest::get().uri("/cards?lang=th").to_request(),
+    )
+    .await;
+    let fool = card_by_id(0).unwrap();
+    assert_eq!(body["items"][0]["name"], fool.name_th);
+    assert_eq!(body["items"][0]["name_en"], fool.name);
+    assert_eq!(body["items"][0]["keywords"], json!(fool.keywords_th));
+}
+
+#[actix_web::test]
+async fn a_single_card_is_served_by_id() {
+    let app = test::init_service(build_app(web::Data::new(common::state(
+        common::config(&[]),
+    ))))
+    .await;
+    let get = |uri: &str| test::TestRequest::get().uri(uri).to_request();
+
+    let resp = test::call_service(&app, get("/cards/77?lang=en")).await;
+    assert_eq!(resp.status(), 200);
+    let card: Value = test::read_body_json(resp).await;
+    let expected = card_by_id(77).unwrap();
+    assert_eq!(card["id"], 77);
+    assert_eq!(card["name"], expected.name);
+    assert_eq!(card["name_th"], expected.name_th);
+    assert!(card["suit"].is_string(), "{}", card);
+
+    let card: Value = test::call_and_read_body_json(&app, get("/cards/0?lang=th")).await;
+    assert_eq!(card["name"], card_by_id(0).unwrap().name_th);
+    assert_eq!(card["suit"], Value::Null);
+
+    for uri in ["/cards/78", "/cards/999", "/cards/fool"] {
+        let resp = test::call_service(&app, get(uri)).await;
+        assert_eq!(resp.status(), 404, "{}", uri);
+    }
+    let resp = test::call_service(&app, get("/cards/0?lang=fr")).await;
+    assert_eq!(resp.status(), 400);
+}
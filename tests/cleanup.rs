@@ -0,0 +1,80 @@
+//! The periodic purge of expired rows. Skipped without `DATABASE_URL`.
+
+mod common;
+
+use chrono::{Duration, Utc};
+use sqlx::PgPool;
+
+use mimi_backend::db::{insert_share, record_line_event};
+use mimi_backend::services::purge_expired;
+
+async fn share_exists(pool: &PgPool, token: &str) -> bool {
+    sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM reading_shares WHERE token = $1)")
+        .bind(token)
+        .fetch_one(pool)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn purges_only_expired_rows() {
+    let Some(pool) = common::test_pool().await else {
+        return;
+    };
+    let user_id = common::new_user(&pool, 0).await;
+    let reading_id = common::new_reading(&pool, user_id).await;
+    let expired = format!("expired-{}", uuid::Uuid::new_v4());
+    let fresh = format!("fresh-{}", uuid::Uuid::new_v4());
+    insert_share(
+        &pool,
+        &expired,
+        reading_id,
+        Utc::now() - Duration::seconds(1),
+    )
+    .await
+    .unwrap();
+    insert_share(&pool, &fresh, reading_id, Utc::now() + Duration::hours(1))
+        .await
+        .unwrap();
+
+    // Other tests may leave expired rows too, so only a lower bound holds.
+    assert!(purge_expired(&pool).await.unwrap() >= 1);
+    assert!(!share_exists(&pool, &expired).await);
+    assert!(share_exists(&pool, &fresh).await);
+
+    // A second pass (or another instance's) has nothing of ours to remove.
+    purge_expired(&pool).await.unwrap();
+    assert!(share_exists(&pool, &fresh).await);
+}
+
+async fn line_event_exists(pool: &PgPool, event_id: &str) -> bool {
+    sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM line_events WHERE event_id = $1)")
+        .bind(event_id)
+        .fetch_one(pool)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn purges_expired_line_events() {
+    let Some(pool) = common::test_pool().await else {
+        return;
+    };
+    let expired = format!("expired-{}", uuid::Uuid::new_v4());
+    let fresh = format!("fresh-{}", uuid::Uuid::new_v4());
+    for event_id in [&expired, &fresh] {
+        let mut conn = pool.acquire().await.unwrap();
+        assert!(record_line_event(&mut conn, event_id).await.unwrap());
+    }
+    sqlx::query(
+        "UPDATE line_events SET expires_at = NOW() - INTERVAL '1 second' WHERE event_id = $1",
+    )
+    .bind(&expired)
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    assert!(purge_expired(&pool).await.unwrap() >= 1);
+    assert!(!line_event_exists(&pool, &expired).await);
+    assert!(line_event_exists(&pool, &fresh).await);
+}
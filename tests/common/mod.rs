@@ -0,0 +1,506 @@
+//! Helpers shared by the HTTP and database tests.
+//!
+//! HTTP tests run `build_app` against an `AppState` with the in-memory
+//! datastore and `EchoLlm`. Database tests need Postgres and queue tests
+//! need Redis: they read `DATABASE_URL` / `UPSTASH_REDIS_URL` (or `.env`)
+//! and are skipped, with a note on stderr, when it is unset.
+
+#![allow(dead_code)]
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use async_trait::async_trait;
+use jsonwebtoken::{EncodingKey, Header, encode};
+use redis::aio::ConnectionManager;
+use regex::Regex;
+use sqlx::PgPool;
+
+use mimi_backend::config::Config;
+use mimi_backend::db::{MemoryDatastore, connect_redis, create_pool};
+use mimi_backend::middleware::Claims;
+use mimi_backend::services::{
+    AskParams, FinishReason, LlmError, LlmProvider, LlmResponse, MockPaymentProvider,
+};
+use mimi_backend::state::AppState;
+
+pub const SECRET: &str = "test-secret";
+
+/// Config from `vars` alone (the process environment is ignored), with
+/// `JWT_SECRET` set to `SECRET` unless given.
+pub fn config(vars: &[(&str, &str)]) -> Config {
+    let vars: Vec<(String, String)> = vars
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+    Config::from_vars(|key| {
+        vars.iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.clone())
+            .or_else(|| (key == "JWT_SECRET").then(|| SECRET.to_string()))
+    })
+    .expect("test config")
+}
+
+/// State with no Postgres or Redis, the in-memory datastore and `EchoLlm`.
+pub fn state(config: Config) -> AppState {
+    AppState::new(config, None, None)
+        .with_store(Arc::new(MemoryDatastore::new()))
+        .with_llm(Arc::new(EchoLlm::default()))
+}
+
+/// State on Postgres (and its datastore) with the mock payment provider
+/// and `EchoLlm`; no Redis.
+pub fn pg_state(config: Config, pool: PgPool) -> AppState {
+    AppState::new(config, Some(pool), None)
+        .with_payments(Arc::new(MockPaymentProvider))
+        .with_llm(Arc::new(EchoLlm::default()))
+}
+
+/// A bearer token for `user_id`.
+pub fn token(user_id: i64) -> String {
+    sign(user_id, None)
+}
+
+/// A bearer token for an admin.
+pub fn admin_token(user_id: i64) -> String {
+    sign(user_id, Some("admin"))
+}
+
+/// A bearer token for `user_id` whose `exp` is `exp_in_secs` from now;
+/// negative for one that has already expired.
+pub fn token_expiring(user_id: i64, exp_in_secs: i64) -> String {
+    sign_until(user_id, None, exp_in_secs)
+}
+
+fn sign(user_id: i64, role: Option<&str>) -> String {
+    sign_until(user_id, role, 3600)
+}
+
+fn sign_until(user_id: i64, role: Option<&str>, exp_in_secs: i64) -> String {
+    let claims = Claims {
+        sub: user_id,
+        exp: (chrono::Utc::now().timestamp() + exp_in_secs) as usize,
+        role: role.map(str::to_string),
+    };
+    let token = encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(SECRET.as_bytes()),
+    )
+    .expect("sign token");
+    format!("Bearer {}", token)
+}
+
+/// A well-formed model: question analysis for analysis prompts, one
+/// interpretation per drawn card for reading prompts (with a `reasoning`
+/// when explain mode asks for one), and one section per question for
+/// session prompts.
+#[derive(Default)]
+pub struct EchoLlm {
+    calls: AtomicUsize,
+    /// Reading prompts still to answer with invalid JSON.
+    failures: AtomicUsize,
+    /// Every call's (system prompt, user prompt, params), in order.
+    asked: std::sync::Mutex<Vec<(String, String, AskParams)>>,
+    /// Topic reported by question analysis; `career` when unset.
+    topic: Option<&'static str>,
+    /// How long each call takes.
+    delay: Option<std::time::Duration>,
+    /// Lists interpretations in reverse spread order.
+    out_of_order: bool,
+}
+
+impl EchoLlm {
+    /// Answers the first `n` reading prompts with text that isn't JSON.
+    pub fn failing(n: usize) -> Self {
+        EchoLlm {
+            failures: AtomicUsize::new(n),
+            ..EchoLlm::default()
+        }
+    }
+
+    /// Analyses every question as `topic`.
+    pub fn with_topic(topic: &'static str) -> Self {
+        EchoLlm {
+            topic: Some(topic),
+            ..EchoLlm::default()
+        }
+    }
+
+    /// Takes `delay` to answer each call.
+    pub fn slow(delay: std::time::Duration) -> Self {
+        EchoLlm {
+            delay: Some(delay),
+            ..EchoLlm::default()
+        }
+    }
+
+    /// Answers with the interpretations in reverse spread order.
+    pub fn out_of_order() -> Self {
+        EchoLlm {
+            out_of_order: true,
+            ..EchoLlm::default()
+        }
+    }
+
+    pub fn calls(&self) -> usize {
+        self.calls.load(Ordering::SeqCst)
+    }
+
+    pub fn asked(&self) -> Vec<(String, String, AskParams)> {
+        self.asked.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl LlmProvider for EchoLlm {
+    async fn ask(
+        &self,
+        system: &str,
+        user: &str,
+        params: &AskParams,
+    ) -> Result<LlmResponse, LlmError> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        if let Some(delay) = self.delay {
+            tokio::time::sleep(delay).await;
+        }
+        self.asked
+            .lock()
+            .unwrap()
+            .push((system.to_string(), user.to_string(), params.clone()));
+        let content = if user.contains("Drawn cards:")
+            && self
+                .failures
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+                .is_ok()
+        {
+            serde_json::json!("not json")
+        } else if let Some((head, cards)) = user.split_once("Drawn cards:") {
+            let explain = system.contains("Add a \"reasoning\" field");
+            let line =
+                Regex::new(r"(?m)^\d+\. (.+?) — (.+?) \((?:upright|reversed), id (\d+)\)").unwrap();
+            let mut interpretations: Vec<serde_json::Value> = line
+                .captures_iter(cards)
+                .map(|c| {
+                    let mut interp = serde_json::json!({
+                        "card_id": c[3].parse::<u32>().unwrap(),
+                        "card": &c[2],
+                        "position": &c[1],
+                        "interpretation": "A steady card for a steady path.",
+                    });
+                    if explain {
+                        interp["reasoning"] = format!("{} speaks to the question.", &c[2]).into();
+                    }
+                    interp
+                })
+                .collect();
+            if self.out_of_order {
+                interpretations.reverse();
+            }
+            if head.starts_with("Questions:") {
+                let questions = Regex::new(r"(?m)^\d+\. ").unwrap().find_iter(head).count();
+                let section = serde_json::json!({ "interpretations": interpretations });
+                serde_json::json!({
+                    "sections": vec![section; questions],
+                    "synthesis": "Together, all is well.",
+                })
+            } else {
+                serde_json::json!({ "interpretations": interpretations, "summary": "All is well." })
+            }
+        } else {
+            serde_json::json!({
+                "topic": self.topic.unwrap_or("career"),
+                "mood": "curious",
+                "confidence": 0.9,
+            })
+        };
+        Ok(LlmResponse {
+            content: match content {
+                serde_json::Value::String(text) => text,
+                json => json.to_string(),
+            },
+            model: "echo".to_string(),
+            usage: None,
+            finish_reason: Some(FinishReason::Stop),
+            system_fingerprint: None,
+            raw: serde_json::Value::Null,
+        })
+    }
+}
+
+/// `EchoLlm`, except that session answers run out of time: the first is
+/// cut off (`finish_reason: length`) after its first section, and the
+/// retry times out.
+#[derive(Default)]
+pub struct CutOffLlm {
+    echo: EchoLlm,
+    session_calls: AtomicUsize,
+}
+
+#[async_trait]
+impl LlmProvider for CutOffLlm {
+    async fn ask(
+        &self,
+        system: &str,
+        user: &str,
+        params: &AskParams,
+    ) -> Result<LlmResponse, LlmError> {
+        let mut response = self.echo.ask(system, user, params).await?;
+        if !user.starts_with("Questions:") {
+            return Ok(response);
+        }
+        if self.session_calls.fetch_add(1, Ordering::SeqCst) > 0 {
+            return Err(LlmError::Timeout);
+        }
+        let answer: serde_json::Value = serde_json::from_str(&response.content).unwrap();
+        response.content = format!(
+            r#"{{"sections":[{},{{"interpretations":[{{"card_id""#,
+            answer["sections"][0]
+        );
+        response.finish_reason = Some(FinishReason::Length);
+        Ok(response)
+    }
+}
+
+/// `DATABASE_URL`; `None` (and the test should return) when it is unset.
+pub fn database_url() -> Option<String> {
+    dotenv::dotenv().ok();
+    let url = std::env::var("DATABASE_URL").ok();
+    if url.is_none() {
+        eprintln!("DATABASE_URL not set; skipping database test");
+    }
+    url
+}
+
+/// A migrated pool on `DATABASE_URL`; `None` when it is unset.
+pub async fn test_pool() -> Option<PgPool> {
+    let url = database_url()?;
+    Some(
+        create_pool(&url, 5000)
+            .await
+            .expect("connect to DATABASE_URL"),
+    )
+}
+
+/// `user_id`'s credit balance.
+pub async fn credits(pool: &PgPool, user_id: i64) -> i64 {
+    sqlx::query_scalar("SELECT credits FROM users WHERE id = $1")
+        .bind(user_id)
+        .fetch_one(pool)
+        .await
+        .expect("read credits")
+}
+
+/// A fresh user with `credits`, so tests sharing a database don't collide.
+pub async fn new_user(pool: &PgPool, credits: i64) -> i64 {
+    sqlx::query_scalar("INSERT INTO users (line_id, credits) VALUES ($1, $2) RETURNING id")
+        .bind(format!("test-{}", uuid::Uuid::new_v4()))
+        .bind(credits)
+        .fetch_one(pool)
+        .await
+        .expect("insert test user")
+}
+
+/// A bare stored reading owned by `user_id`, for tests that only need a row
+/// to point at.
+pub async fn new_reading(pool: &PgPool, user_id: i64) -> i64 {
+    sqlx::query_scalar(
+        "INSERT INTO readings (user_id, question, spread, cards, result) \
+         VALUES ($1, 'Will I get the job?', 'three_card', '[]', '{}') RETURNING id",
+    )
+    .bind(user_id)
+    .fetch_one(pool)
+    .await
+    .expect("insert test reading")
+}
+
+/// A Redis connection on `UPSTASH_REDIS_URL`; `None` (and the test should
+/// return) when it is unset.
+pub async fn test_redis() -> Option<ConnectionManager> {
+    dotenv::dotenv().ok();
+    let Ok(url) = std::env::var("UPSTASH_REDIS_URL") else {
+        eprintln!("UPSTASH_REDIS_URL not set; skipping queue test");
+        return None;
+    };
+    Some(
+        connect_redis(Some(&url))
+            .await
+            .expect("connect to UPSTASH_REDIS_URL"),
+    )
+}
+
+/// One canned reply from `FakeOpenAi`.
+#[derive(Clone)]
+pub struct StubResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+    pub delay: std::time::Duration,
+    /// Send no `Content-Length`; the body ends when the connection closes.
+    pub unsized_body: bool,
+}
+
+impl StubResponse {
+    pub fn new(status: u16, body: impl Into<String>) -> Self {
+        StubResponse {
+            status,
+            headers: Vec::new(),
+            body: body.into(),
+            delay: std::time::Duration::ZERO,
+            unsized_body: false,
+        }
+    }
+
+    /// Leave out `Content-Length`, so the client only learns the size by
+    /// reading.
+    pub fn without_length(mut self) -> Self {
+        self.unsized_body = true;
+        self
+    }
+
+    /// A 200 chat completion whose first choice says `content`.
+    pub fn completion(content: &str) -> Self {
+        StubResponse::new(
+            200,
+            serde_json::json!({
+                "model": "stub-model",
+                "choices": [{
+                    "message": { "role": "assistant", "content": content },
+                    "finish_reason": "stop",
+                }],
+                "usage": { "prompt_tokens": 10, "completion_tokens": 5, "total_tokens": 15 },
+            })
+            .to_string(),
+        )
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn delay(mut self, delay: std::time::Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+}
+
+/// A request `FakeOpenAi` received.
+#[derive(Debug, Clone)]
+pub struct StubRequest {
+    pub method: String,
+    pub path: String,
+    /// Header names lowercased.
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl StubRequest {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k == &name.to_ascii_lowercase())
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn json(&self) -> serde_json::Value {
+        serde_json::from_str(&self.body).unwrap_or_default()
+    }
+}
+
+/// A local HTTP server standing in for the OpenAI API: answers requests
+/// with `responses` in order (repeating the last) and records them.
+pub struct FakeOpenAi {
+    pub base_url: String,
+    requests: Arc<std::sync::Mutex<Vec<StubRequest>>>,
+}
+
+impl FakeOpenAi {
+    pub async fn start(responses: Vec<StubResponse>) -> Self {
+        use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let queue = Arc::new(std::sync::Mutex::new(
+            responses
+                .into_iter()
+                .collect::<std::collections::VecDeque<_>>(),
+        ));
+        let recorded = requests.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let recorded = recorded.clone();
+                let queue = queue.clone();
+                tokio::spawn(async move {
+                    let mut reader = BufReader::new(stream);
+                    let mut line = String::new();
+                    if reader.read_line(&mut line).await.unwrap_or(0) == 0 {
+                        return;
+                    }
+                    let mut parts = line.split_whitespace();
+                    let method = parts.next().unwrap_or_default().to_string();
+                    let path = parts.next().unwrap_or_default().to_string();
+                    let mut headers = Vec::new();
+                    loop {
+                        let mut line = String::new();
+                        reader.read_line(&mut line).await.unwrap();
+                        let line = line.trim_end();
+                        if line.is_empty() {
+                            break;
+                        }
+                        if let Some((k, v)) = line.split_once(':') {
+                            headers.push((k.trim().to_ascii_lowercase(), v.trim().to_string()));
+                        }
+                    }
+                    let length = headers
+                        .iter()
+                        .find(|(k, _)| k == "content-length")
+                        .and_then(|(_, v)| v.parse().ok())
+                        .unwrap_or(0);
+                    let mut body = vec![0; length];
+                    reader.read_exact(&mut body).await.unwrap();
+                    recorded.lock().unwrap().push(StubRequest {
+                        method,
+                        path,
+                        headers,
+                        body: String::from_utf8_lossy(&body).into_owned(),
+                    });
+                    let response = {
+                        let mut queue = queue.lock().unwrap();
+                        if queue.len() > 1 {
+                            queue.pop_front()
+                        } else {
+                            queue.front().cloned()
+                        }
+                    };
+                    let Some(response) = response else {
+                        return;
+                    };
+                    tokio::time::sleep(response.delay).await;
+                    let mut head = format!(
+                        "HTTP/1.1 {} Stub\r\ncontent-type: application/json\r\nconnection: close\r\n",
+                        response.status
+                    );
+                    if !response.unsized_body {
+                        head.push_str(&format!("content-length: {}\r\n", response.body.len()));
+                    }
+                    for (k, v) in &response.headers {
+                        head.push_str(&format!("{}: {}\r\n", k, v));
+                    }
+                    head.push_str("\r\n");
+                    let mut stream = reader.into_inner();
+                    let _ = stream.write_all(head.as_bytes()).await;
+                    let _ = stream.write_all(response.body.as_bytes()).await;
+                    let _ = stream.shutdown().await;
+                });
+            }
+        });
+        FakeOpenAi { base_url, requests }
+    }
+
+    pub fn requests(&self) -> Vec<StubRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+}
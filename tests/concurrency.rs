@@ -0,0 +1,78 @@
+//! Per-user cap on readings in flight (`MAX_CONCURRENT_READINGS`).
+
+mod common;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use actix_web::{test, web};
+use serde_json::json;
+
+use mimi_backend::app::build_app;
+
+#[actix_web::test]
+async fn parallel_readings_past_the_cap_get_429() {
+    let llm = Arc::new(common::EchoLlm::slow(Duration::from_millis(200)));
+    let state = common::state(common::config(&[("MAX_CONCURRENT_READINGS", "2")])).with_llm(llm);
+    let app = test::init_service(build_app(web::Data::new(state))).await;
+    let status = |token: String| {
+        let req = test::TestRequest::post()
+            .uri("/readings")
+            .insert_header(("Authorization", token))
+            .set_json(json!({ "question": "Will I get the job?" }))
+            .to_request();
+        let app = &app;
+        async move {
+            // The cap is enforced in middleware, so a 429 is an `Err`.
+            match test::try_call_service(app, req).await {
+                Ok(resp) => resp.status().as_u16(),
+                Err(err) => err.error_response().status().as_u16(),
+            }
+        }
+    };
+
+    let mut statuses: [u16; 5] = tokio::join!(
+        status(common::token(1)),
+        status(common::token(1)),
+        status(common::token(1)),
+        status(common::token(1)),
+        status(common::token(1)),
+    )
+    .into();
+    statuses.sort();
+    assert_eq!(statuses, [200, 200, 429, 429, 429]);
+
+    // Finished readings give their slots back.
+    let again: [u16; 2] = tokio::join!(status(common::token(1)), status(common::token(1))).into();
+    assert_eq!(again, [200, 200]);
+}
+
+#[actix_web::test]
+async fn the_cap_is_per_user_and_admins_are_exempt() {
+    let llm = Arc::new(common::EchoLlm::slow(Duration::from_millis(200)));
+    let state = common::state(common::config(&[("MAX_CONCURRENT_READINGS", "1")])).with_llm(llm);
+    let app = test::init_service(build_app(web::Data::new(state))).await;
+    let status = |token: String| {
+        let req = test::TestRequest::post()
+            .uri("/readings")
+            .insert_header(("Authorization", token))
+            .set_json(json!({ "question": "Will I get the job?" }))
+            .to_request();
+        let app = &app;
+        async move {
+            match test::try_call_service(app, req).await {
+                Ok(resp) => resp.status().as_u16(),
+                Err(err) => err.error_response().status().as_u16(),
+            }
+        }
+    };
+
+    let statuses: [u16; 4] = tokio::join!(
+        status(common::token(1)),
+        status(common::token(2)),
+        status(common::admin_token(3)),
+        status(common::admin_token(3)),
+    )
+    .into();
+    assert_eq!(statuses, [200, 200, 200, 200]);
+}
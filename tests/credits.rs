@@ -0,0 +1,274 @@
+//! Credit balance changes and the admin adjustment endpoint. Skipped
+//! without `DATABASE_URL`.
+
+mod common;
+
+use std::sync::Arc;
+
+use actix_web::{test, web};
+use serde_json::{Value, json};
+use sqlx::PgPool;
+
+use mimi_backend::app::build_app;
+use mimi_backend::db::{Datastore, PgDatastore};
+use mimi_backend::services::{CreditError, credit_service};
+
+fn adjust(user_id: i64, delta: i64, reason: &str) -> test::TestRequest {
+    test::TestRequest::post()
+        .uri(&format!("/admin/users/{}/credits", user_id))
+        .insert_header(("Authorization", common::admin_token(1)))
+        .set_json(json!({ "delta": delta, "reason": reason }))
+}
+
+async fn ledger(pool: &PgPool, user_id: i64) -> Vec<(i64, String)> {
+    sqlx::query_as("SELECT delta, reason FROM credit_ledger WHERE user_id = $1 ORDER BY id")
+        .bind(user_id)
+        .fetch_all(pool)
+        .await
+        .unwrap()
+}
+
+#[actix_web::test]
+async fn admins_grant_and_deduct_credits_through_the_ledger() {
+    let Some(pool) = common::test_pool().await else {
+        return;
+    };
+    let user_id = common::new_user(&pool, 0).await;
+    let app = test::init_service(build_app(web::Data::new(common::pg_state(
+        common::config(&[]),
+        pool.clone(),
+    ))))
+    .await;
+
+    let resp = test::call_service(&app, adjust(user_id, 5, "goodwill").to_request()).await;
+    assert_eq!(resp.status(), 200);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body, json!({ "user_id": user_id, "balance": 5 }));
+
+    let body: Value =
+        test::call_and_read_body_json(&app, adjust(user_id, -3, "duplicate grant").to_request())
+            .await;
+    assert_eq!(body["balance"], 2);
+    assert_eq!(common::credits(&pool, user_id).await, 2);
+    assert_eq!(
+        ledger(&pool, user_id).await,
+        [
+            (5, "admin:1: goodwill".to_string()),
+            (-3, "admin:1: duplicate grant".to_string()),
+        ]
+    );
+}
+
+#[actix_web::test]
+async fn adjustments_that_cannot_apply_are_refused() {
+    let Some(pool) = common::test_pool().await else {
+        return;
+    };
+    let user_id = common::new_user(&pool, 2).await;
+    let app = test::init_service(build_app(web::Data::new(common::pg_state(
+        common::config(&[]),
+        pool.clone(),
+    ))))
+    .await;
+
+    // Over-deduction, and a delta with no positive counterpart.
+    for delta in [-3, i64::MIN] {
+        let resp = test::call_service(&app, adjust(user_id, delta, "oops").to_request()).await;
+        assert_eq!(resp.status(), 422, "delta {}", delta);
+    }
+    // A grant past the largest balance.
+    let resp = test::call_service(&app, adjust(user_id, i64::MAX, "oops").to_request()).await;
+    assert_eq!(resp.status(), 422);
+    assert_eq!(common::credits(&pool, user_id).await, 2);
+    assert!(ledger(&pool, user_id).await.is_empty());
+
+    let resp = test::call_service(&app, adjust(user_id, 1, "  ").to_request()).await;
+    assert_eq!(resp.status(), 400);
+    let resp = test::call_service(&app, adjust(-1, 1, "goodwill").to_request()).await;
+    assert_eq!(resp.status(), 404);
+    let resp = test::call_service(
+        &app,
+        adjust(user_id, 1, "goodwill")
+            .insert_header(("Authorization", common::token(user_id)))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), 403);
+}
+
+#[actix_web::test]
+async fn a_partial_session_is_charged_pro_rata() {
+    let Some(pool) = common::test_pool().await else {
+        return;
+    };
+    let user_id = common::new_user(&pool, 10).await;
+    let state = common::pg_state(
+        common::config(&[("SESSION_CREDIT_COST", "4")]),
+        pool.clone(),
+    )
+    .with_llm(Arc::new(common::CutOffLlm::default()));
+    let app = test::init_service(build_app(web::Data::new(state))).await;
+
+    let resp = test::call_service(
+        &app,
+        test::TestRequest::post()
+            .uri("/readings/session")
+            .insert_header(("Authorization", common::token(user_id)))
+            .set_json(json!({
+                "questions": ["Will I get the job?", "Should I move abroad?"],
+            }))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), 200);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["partial"], true);
+    assert_eq!(body["sections"].as_array().unwrap().len(), 1);
+    // One of two questions answered: half of the 4 credits.
+    assert_eq!(common::credits(&pool, user_id).await, 8);
+}
+
+#[actix_web::test]
+async fn regenerating_a_reading_is_charged() {
+    let Some(pool) = common::test_pool().await else {
+        return;
+    };
+    let user_id = common::new_user(&pool, 5).await;
+    let state = common::pg_state(
+        common::config(&[
+            ("READING_CREDIT_COST", "1"),
+            ("REGENERATE_CREDIT_COST", "2"),
+        ]),
+        pool.clone(),
+    );
+    let app = test::init_service(build_app(web::Data::new(state))).await;
+    let resp = test::call_service(
+        &app,
+        test::TestRequest::post()
+            .uri("/readings")
+            .insert_header(("Authorization", common::token(user_id)))
+            .set_json(json!({ "question": "Will I get the job?" }))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), 200);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(common::credits(&pool, user_id).await, 5);
+
+    let resp = test::call_service(
+        &app,
+        test::TestRequest::post()
+            .uri(&format!("/readings/{}/regenerate", body["id"]))
+            .insert_header(("Authorization", common::token(user_id)))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(common::credits(&pool, user_id).await, 3);
+}
+
+#[actix_web::test]
+async fn estimates_report_what_the_chosen_route_charges() {
+    let Some(pool) = common::test_pool().await else {
+        return;
+    };
+    let user_id = common::new_user(&pool, 5).await;
+    let state = common::pg_state(
+        common::config(&[("READING_CREDIT_COST", "2")]),
+        pool.clone(),
+    );
+    let app = test::init_service(build_app(web::Data::new(state))).await;
+    let mut required = Vec::new();
+    for queued in [false, true] {
+        let resp = test::call_service(
+            &app,
+            test::TestRequest::post()
+                .uri("/readings/estimate")
+                .insert_header(("Authorization", common::token(user_id)))
+                .set_json(json!({ "question": "Will I get the job?", "queued": queued }))
+                .to_request(),
+        )
+        .await;
+        assert_eq!(resp.status(), 200);
+        let body: Value = test::read_body_json(resp).await;
+        required.push(body["credits_required"].as_i64().unwrap());
+    }
+    assert_eq!(required, [0, 2]);
+    // Estimating is free.
+    assert_eq!(common::credits(&pool, user_id).await, 5);
+}
+
+#[actix_web::test]
+async fn queued_estimates_report_the_cost_without_a_database() {
+    let state = common::state(common::config(&[("READING_CREDIT_COST", "2")]));
+    let app = test::init_service(build_app(web::Data::new(state))).await;
+    let resp = test::call_service(
+        &app,
+        test::TestRequest::post()
+            .uri("/readings/estimate")
+            .insert_header(("Authorization", common::token(1)))
+            .set_json(json!({ "question": "Will I get the job?", "queued": true }))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), 200);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["credits_required"], 2);
+}
+
+#[tokio::test]
+async fn only_the_login_that_creates_a_user_grants_the_signup_bonus() {
+    let Some(pool) = common::test_pool().await else {
+        return;
+    };
+    let store = PgDatastore::new(pool.clone()).with_signup_bonus(3);
+    let line_id = format!("U{}", uuid::Uuid::new_v4().simple());
+    let user = store.upsert_user(&line_id, Some("Alice")).await.unwrap();
+    assert_eq!(common::credits(&pool, user.id).await, 3);
+
+    // The same LINE id logging in again is the same user, with no new bonus.
+    let again = store.upsert_user(&line_id, None).await.unwrap();
+    assert_eq!(again.id, user.id);
+    assert_eq!(again.name.as_deref(), Some("Alice"));
+    assert_eq!(common::credits(&pool, user.id).await, 3);
+    assert_eq!(
+        ledger(&pool, user.id).await,
+        [(3, "signup_bonus".to_string())]
+    );
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn simultaneous_deductions_cannot_overdraw() {
+    let Some(pool) = common::test_pool().await else {
+        return;
+    };
+    // Several rounds, so a read-then-write race would show up.
+    for _ in 0..10 {
+        let user_id = common::new_user(&pool, 1).await;
+        let deduct = || {
+            let pool = pool.clone();
+            tokio::spawn(async move { credit_service::deduct(&pool, user_id, 1, "reading").await })
+        };
+        let (first, second) = tokio::join!(deduct(), deduct());
+        let outcomes = [first.unwrap(), second.unwrap()];
+        assert_eq!(
+            outcomes.iter().filter(|outcome| outcome.is_ok()).count(),
+            1,
+            "{:?}",
+            outcomes
+        );
+        assert!(
+            outcomes
+                .iter()
+                .any(|outcome| matches!(outcome, Err(CreditError::Insufficient))),
+            "{:?}",
+            outcomes
+        );
+        assert_eq!(common::credits(&pool, user_id).await, 0);
+        assert_eq!(ledger(&pool, user_id).await, [(-1, "reading".to_string())]);
+    }
+    assert!(matches!(
+        credit_service::deduct(&pool, -1, 1, "reading").await,
+        Err(CreditError::UserNotFound)
+    ));
+}
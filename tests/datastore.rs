@@ -0,0 +1,454 @@
+//! The in-memory `Datastore`, when it replaces Postgres, and an `AppState`
+//! built from mocks; the Postgres side is skipped without `DATABASE_URL`.
+
+mod common;
+
+use std::sync::Arc;
+
+use actix_web::{test, web};
+use chrono::{Duration, Utc};
+use serde_json::{Value, json};
+use sqlx::postgres::PgPoolOptions;
+
+use mimi_backend::app::build_app;
+use mimi_backend::db::{
+    Datastore, MemoryDatastore, PgDatastore, RawLlmStorage, SIMILAR_FINGERPRINT_PREFIX,
+    reading_from_stored,
+};
+use mimi_backend::models::{Mood, QuestionAnalysis, Spread, TarotReading, Topic};
+use mimi_backend::services::{
+    CardPicker, Language, MockPaymentProvider, PipelineSettings, PromptVersion, ReadingAgent,
+    generate_reading,
+};
+use mimi_backend::state::AppState;
+
+async fn reading(question: &str) -> TarotReading {
+    let llm = common::EchoLlm::default();
+    let settings = PipelineSettings::from_config(&common::config(&[]));
+    generate_reading(
+        &ReadingAgent::new(&llm, PromptVersion::Stable),
+        question,
+        None,
+        Spread::ThreeCard,
+        CardPicker::new(7),
+        &settings,
+        &mut settings.budget(),
+    )
+    .await
+    .expect("reading")
+}
+
+#[tokio::test]
+async fn upserts_users_by_line_id() {
+    let store = MemoryDatastore::new();
+    let alice = store.upsert_user("line-alice", None).await.unwrap();
+    let bob = store.upsert_user("line-bob", Some("Bob")).await.unwrap();
+    assert_ne!(alice.id, bob.id);
+
+    let renamed = store
+        .upsert_user("line-alice", Some("Alice"))
+        .await
+        .unwrap();
+    assert_eq!(renamed.id, alice.id);
+    // A login without a name keeps the one on file.
+    let again = store.upsert_user("line-alice", None).await.unwrap();
+    assert_eq!(again.name.as_deref(), Some("Alice"));
+    let stored = store.get_user(alice.id).await.unwrap().unwrap();
+    assert_eq!(stored.name.as_deref(), Some("Alice"));
+    assert!(store.get_user(999).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn lists_a_users_readings_newest_first() {
+    let store = MemoryDatastore::new();
+    let mut ids = Vec::new();
+    for question in ["First?", "Second?", "Third?"] {
+        let reading = reading(question).await;
+        ids.push(
+            store
+                .insert_reading(1, &reading, Language::English, question)
+                .await
+                .unwrap(),
+        );
+    }
+    let other = store
+        .insert_reading(2, &reading("Not yours?").await, Language::English, "x")
+        .await
+        .unwrap();
+
+    let page = store.list_readings(1, None, 2).await.unwrap();
+    let questions: Vec<_> = page.iter().map(|r| r.question.as_str()).collect();
+    assert_eq!(questions, ["Third?", "Second?"]);
+    let rest = store.list_readings(1, Some(page[1].id), 2).await.unwrap();
+    assert_eq!(rest.len(), 1);
+    assert_eq!(rest[0].id, ids[0]);
+    assert_eq!(rest[0].spread, "three_card");
+
+    let (owner, result) = store.get_reading_result(other).await.unwrap().unwrap();
+    assert_eq!(owner, 2);
+    assert_eq!(result["question"], "Not yours?");
+}
+
+#[tokio::test]
+async fn mock_db_keeps_postgres_out_of_reach() {
+    // Never connects; any query through it would fail.
+    let pool = PgPoolOptions::new()
+        .connect_lazy("postgres://nobody@127.0.0.1:1/none")
+        .unwrap();
+    let state = AppState::new(common::config(&[("MOCK_DB", "true")]), Some(pool), None);
+    assert!(state.pool().is_none());
+    let user = state.store().upsert_user("line-mock", None).await.unwrap();
+    assert_eq!(
+        state.store().get_user(user.id).await.unwrap().unwrap().id,
+        user.id
+    );
+}
+
+#[tokio::test]
+async fn stats_aggregate_a_users_original_readings() {
+    let store = MemoryDatastore::new();
+    let user = store.upsert_user("line-stats", None).await.unwrap();
+    let other = store.upsert_user("line-other", None).await.unwrap();
+    let mut ids = Vec::new();
+    for (question, topic) in [
+        ("Does he love me?", Topic::Love),
+        ("Will I get the job?", Topic::Career),
+        ("Will we marry?", Topic::Love),
+    ] {
+        let mut reading = reading(question).await;
+        reading.analysis = Some(QuestionAnalysis {
+            topic,
+            mood: Mood::default(),
+            confidence: 0.9,
+        });
+        ids.push(
+            store
+                .insert_reading(user.id, &reading, Language::English, question)
+                .await
+                .unwrap(),
+        );
+    }
+    // Versions and other users' readings are not counted.
+    let version = reading("Will I get the job?").await;
+    store
+        .insert_reading_version(user.id, ids[1], &version, Language::English, "v")
+        .await
+        .unwrap();
+    store
+        .insert_reading(other.id, &reading("Mine?").await, Language::English, "x")
+        .await
+        .unwrap();
+
+    let month_start = Utc::now() - Duration::days(1);
+    let stats = store
+        .user_stats(user.id, month_start)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(stats.total_readings, 3);
+    assert_eq!(stats.readings_this_month, 3);
+    assert_eq!(stats.top_topic, Some(Topic::Love));
+    assert_eq!(stats.credits_spent, 0);
+
+    let next_month = Utc::now() + Duration::days(1);
+    let stats = store
+        .user_stats(user.id, next_month)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(stats.total_readings, 3);
+    assert_eq!(stats.readings_this_month, 0);
+
+    assert!(store.user_stats(999, month_start).await.unwrap().is_none());
+}
+
+async fn insert_with_topic(
+    store: &dyn Datastore,
+    user_id: i64,
+    question: &str,
+    fingerprint: &str,
+    topic: Topic,
+) -> i64 {
+    let mut reading = reading(question).await;
+    reading.analysis = Some(QuestionAnalysis {
+        topic,
+        mood: Mood::default(),
+        confidence: 0.9,
+    });
+    store
+        .insert_reading(user_id, &reading, Language::English, fingerprint)
+        .await
+        .unwrap()
+}
+
+/// Inserts related and unrelated readings and checks that only the related
+/// ones come back, the same question ahead of the same topic.
+async fn check_find_similar(store: &dyn Datastore, user_id: i64, other_id: i64) {
+    let job = "Will I get the job?";
+    let target = insert_with_topic(store, user_id, job, "fp-job", Topic::Career).await;
+    let same_topic = insert_with_topic(
+        store,
+        user_id,
+        "Should I ask for a raise?",
+        "fp-raise",
+        Topic::Career,
+    )
+    .await;
+    insert_with_topic(store, user_id, "Does she love me?", "fp-love", Topic::Love).await;
+    let same_question = insert_with_topic(store, user_id, job, "fp-job", Topic::Love).await;
+    // Neither the reading's own versions nor other users' readings count.
+    store
+        .insert_reading_version(
+            user_id,
+            target,
+            &reading(job).await,
+            Language::English,
+            "fp-job",
+        )
+        .await
+        .unwrap();
+    insert_with_topic(store, other_id, job, "fp-job", Topic::Career).await;
+
+    let related = store
+        .find_similar(user_id, target, "fp-job", Some(Topic::Career), 10)
+        .await
+        .unwrap();
+    let ids: Vec<_> = related.iter().map(|r| r.id).collect();
+    assert_eq!(ids, [same_question, same_topic]);
+    assert_eq!(related[0].question, job);
+
+    let first = store
+        .find_similar(user_id, target, "fp-job", Some(Topic::Career), 1)
+        .await
+        .unwrap();
+    assert_eq!(first.len(), 1);
+    assert_eq!(first[0].id, same_question);
+    let none = store
+        .find_similar(user_id, target, "fp-none", None, 10)
+        .await
+        .unwrap();
+    assert!(none.is_empty(), "{:?}", none.len());
+}
+
+#[tokio::test]
+async fn only_related_readings_are_found_similar() {
+    let store = MemoryDatastore::new();
+    let user = store.upsert_user("line-similar", None).await.unwrap();
+    let other = store.upsert_user("line-other", None).await.unwrap();
+    check_find_similar(&store, user.id, other.id).await;
+
+    let Some(pool) = common::test_pool().await else {
+        return;
+    };
+    let user_id = common::new_user(&pool, 0).await;
+    let other_id = common::new_user(&pool, 0).await;
+    check_find_similar(&PgDatastore::new(pool), user_id, other_id).await;
+}
+
+/// Readings whose fingerprints share the compared prefix count as the same
+/// question even when the full fingerprints differ.
+async fn check_fingerprint_prefix(store: &dyn Datastore, user_id: i64) {
+    let shared = "a".repeat(SIMILAR_FINGERPRINT_PREFIX);
+    let target = format!("{}-target", shared);
+    let job = "Will I get the job?";
+    let target_id = insert_with_topic(store, user_id, job, &target, Topic::Career).await;
+    let same_prefix = insert_with_topic(
+        store,
+        user_id,
+        job,
+        &format!("{}-other", shared),
+        Topic::Love,
+    )
+    .await;
+    let mut differs = shared.clone();
+    differs.replace_range(..1, "b");
+    insert_with_topic(store, user_id, job, &differs, Topic::Love).await;
+
+    let related = store
+        .find_similar(user_id, target_id, &target, None, 10)
+        .await
+        .unwrap();
+    let ids: Vec<_> = related.iter().map(|r| r.id).collect();
+    assert_eq!(ids, [same_prefix]);
+}
+
+#[tokio::test]
+async fn fingerprints_sharing_a_prefix_are_found_similar() {
+    let store = MemoryDatastore::new();
+    let user = store.upsert_user("line-prefix", None).await.unwrap();
+    check_fingerprint_prefix(&store, user.id).await;
+
+    let Some(pool) = common::test_pool().await else {
+        return;
+    };
+    let user_id = common::new_user(&pool, 0).await;
+    check_fingerprint_prefix(&PgDatastore::new(pool), user_id).await;
+}
+
+/// Follow-ups link to the first reading of their conversation and are
+/// counted from any reading in it.
+async fn check_followups(store: &dyn Datastore, user_id: i64) {
+    let job = reading("Will I get the job?").await;
+    let first = store
+        .insert_reading(user_id, &job, Language::English, "fp-job")
+        .await
+        .unwrap();
+    let other = store
+        .insert_reading(user_id, &job, Language::English, "fp-job")
+        .await
+        .unwrap();
+    assert_eq!(store.count_followups(first).await.unwrap(), 0);
+
+    let team = reading("Will I like the new team?").await;
+    let (followup, linked) = store
+        .insert_followup(user_id, first, &team, Language::English, "fp-team")
+        .await
+        .unwrap();
+    assert_eq!(linked, first);
+    let (_, linked) = store
+        .insert_followup(user_id, followup, &team, Language::English, "fp-team")
+        .await
+        .unwrap();
+    assert_eq!(linked, first);
+
+    for reading_id in [first, followup] {
+        assert_eq!(store.count_followups(reading_id).await.unwrap(), 2);
+    }
+    assert_eq!(store.count_followups(other).await.unwrap(), 0);
+    // Follow-ups are not regenerated versions.
+    assert_eq!(store.count_reading_versions(first).await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn follow_ups_are_counted_per_conversation() {
+    let store = MemoryDatastore::new();
+    let user = store.upsert_user("line-followups", None).await.unwrap();
+    check_followups(&store, user.id).await;
+
+    let Some(pool) = common::test_pool().await else {
+        return;
+    };
+    let user_id = common::new_user(&pool, 0).await;
+    check_followups(&PgDatastore::new(pool), user_id).await;
+}
+
+#[tokio::test]
+async fn postgres_stores_cards_by_id_and_reads_names_from_the_deck() {
+    let Some(pool) = common::test_pool().await else {
+        return;
+    };
+    let store = PgDatastore::new(pool.clone());
+    let user_id = common::new_user(&pool, 0).await;
+    let reading = reading("Will I get the job?").await;
+    let id = store
+        .insert_reading(user_id, &reading, Language::English, "fp")
+        .await
+        .unwrap();
+
+    let (cards, result): (serde_json::Value, serde_json::Value) =
+        sqlx::query_as("SELECT cards, result FROM readings WHERE id = $1")
+            .bind(id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    for stored in [&cards, &result["cards"]] {
+        let stored = stored.as_array().unwrap();
+        assert_eq!(stored.len(), 3);
+        assert!(
+            stored.iter().all(|card| card.get("name").is_none()),
+            "{:?}",
+            stored
+        );
+    }
+    let (_, result) = store.get_reading_result(id).await.unwrap().unwrap();
+    assert_eq!(reading_from_stored(result).unwrap().cards, reading.cards);
+}
+
+#[tokio::test]
+async fn postgres_stores_the_raw_completion_as_configured() {
+    let Some(pool) = common::test_pool().await else {
+        return;
+    };
+    let user_id = common::new_user(&pool, 0).await;
+    let raw = json!({
+        "model": "gpt-4o-mini",
+        "choices": [{"message": {"content": "A long reading..."}, "finish_reason": "stop"}],
+        "usage": {"prompt_tokens": 120, "completion_tokens": 80}
+    });
+    let mut reading = reading("Will I get the job?").await;
+    reading.meta.raw_llm = Some(raw.clone());
+    for (mode, expected) in [
+        (RawLlmStorage::Full, raw.clone()),
+        (
+            RawLlmStorage::Trimmed,
+            json!({
+                "usage": {"prompt_tokens": 120, "completion_tokens": 80},
+                "finish_reason": "stop"
+            }),
+        ),
+        (RawLlmStorage::None, Value::Null),
+    ] {
+        let store = PgDatastore::new(pool.clone()).with_raw_llm(mode);
+        let id = store
+            .insert_reading(user_id, &reading, Language::English, "fp")
+            .await
+            .unwrap();
+        let (version, _) = store
+            .insert_reading_version(user_id, id, &reading, Language::English, "fp")
+            .await
+            .unwrap();
+        for id in [id, version] {
+            let (stored,): (Option<Value>,) =
+                sqlx::query_as("SELECT raw_llm FROM readings WHERE id = $1")
+                    .bind(id)
+                    .fetch_one(&pool)
+                    .await
+                    .unwrap();
+            assert_eq!(stored.unwrap_or(Value::Null), expected, "{:?}", mode);
+        }
+    }
+}
+
+#[actix_web::test]
+async fn handlers_run_on_an_app_state_built_from_mocks() {
+    let llm = Arc::new(common::EchoLlm::default());
+    let store = Arc::new(MemoryDatastore::new());
+    let state = AppState::new(common::config(&[]), None, None)
+        .with_store(store.clone())
+        .with_llm(llm.clone())
+        .with_payments(Arc::new(MockPaymentProvider));
+    assert!(state.pool().is_none() && state.redis().is_none());
+    let app = test::init_service(build_app(web::Data::new(state))).await;
+
+    let resp = test::call_service(
+        &app,
+        test::TestRequest::post()
+            .uri("/ask")
+            .insert_header(("Authorization", common::token(1)))
+            .set_json(json!({ "question": "Is today lucky?" }))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), 200);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["model"], "echo");
+    assert_eq!(llm.calls(), 1);
+
+    let resp = test::call_service(
+        &app,
+        test::TestRequest::post()
+            .uri("/readings")
+            .insert_header(("Authorization", common::token(1)))
+            .set_json(json!({ "question": "Will I get the job?" }))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), 200);
+    let reading: Value = test::read_body_json(resp).await;
+    let (owner, _) = store
+        .get_reading_result(reading["id"].as_i64().unwrap())
+        .await
+        .unwrap()
+        .expect("stored in the injected datastore");
+    assert_eq!(owner, 1);
+}
@@ -0,0 +1,154 @@
+//! Postgres-backed queries, batch imports among them, and their error
+//! mapping. Skipped without `DATABASE_URL`.
+
+mod common;
+
+use actix_web::ResponseError;
+use chrono::{Duration, TimeZone, Utc};
+
+use mimi_backend::db::{
+    DbError, MAX_INSERT_BATCH, NewReading, create_pool, insert_many, reading_from_stored,
+    record_line_event,
+};
+use mimi_backend::middleware::ApiError;
+use mimi_backend::models::{Spread, TarotReading};
+use mimi_backend::services::{
+    CardPicker, Language, PipelineSettings, PromptVersion, ReadingAgent, generate_reading,
+};
+
+async fn reading() -> TarotReading {
+    let llm = common::EchoLlm::default();
+    let settings = PipelineSettings::from_config(&common::config(&[]));
+    generate_reading(
+        &ReadingAgent::new(&llm, PromptVersion::Stable),
+        "Will I get the job?",
+        None,
+        Spread::ThreeCard,
+        CardPicker::new(7),
+        &settings,
+        &mut settings.budget(),
+    )
+    .await
+    .expect("reading")
+}
+
+/// `count` legacy readings for `user_id`, told apart by question and time.
+fn legacy_rows(template: &TarotReading, user_id: i64, count: usize) -> Vec<NewReading> {
+    let start = Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap();
+    (0..count)
+        .map(|i| {
+            let mut reading = template.clone();
+            reading.question = format!("Legacy question {}?", i);
+            NewReading {
+                user_id,
+                reading,
+                language: Language::English,
+                fingerprint: format!("legacy-{}", i),
+                created_at: start + Duration::minutes(i as i64),
+            }
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn statement_timeout_cancels_slow_queries() {
+    let Some(url) = common::database_url() else {
+        return;
+    };
+    let pool = create_pool(&url, 100).await.unwrap();
+
+    let err = sqlx::query("SELECT pg_sleep(2)")
+        .execute(&pool)
+        .await
+        .map_err(DbError::from)
+        .unwrap_err();
+    assert!(matches!(err, DbError::Timeout), "{:?}", err);
+    assert_eq!(ApiError::from(err).status_code(), 504);
+
+    // The connection is still usable after the cancel.
+    let one: i32 = sqlx::query_scalar("SELECT 1")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(one, 1);
+}
+
+#[tokio::test]
+async fn a_line_event_is_claimed_once() {
+    let Some(pool) = common::test_pool().await else {
+        return;
+    };
+    let event_id = format!("line-{}", uuid::Uuid::new_v4());
+
+    // A claim rolled back with its transaction leaves the event unclaimed.
+    let mut tx = pool.begin().await.unwrap();
+    assert!(record_line_event(&mut tx, &event_id).await.unwrap());
+    tx.rollback().await.unwrap();
+
+    let mut tx = pool.begin().await.unwrap();
+    assert!(record_line_event(&mut tx, &event_id).await.unwrap());
+    tx.commit().await.unwrap();
+
+    // The redelivery is seen as a duplicate.
+    let mut conn = pool.acquire().await.unwrap();
+    assert!(!record_line_event(&mut conn, &event_id).await.unwrap());
+}
+
+#[tokio::test]
+async fn a_batch_spanning_several_statements_keeps_its_order() {
+    let Some(pool) = common::test_pool().await else {
+        return;
+    };
+    let user_id = common::new_user(&pool, 0).await;
+    let rows = legacy_rows(&reading().await, user_id, MAX_INSERT_BATCH + 3);
+
+    let ids = insert_many(&pool, &rows).await.unwrap();
+    assert_eq!(ids.len(), rows.len());
+    assert!(ids.windows(2).all(|w| w[0] < w[1]), "ids ascend with input");
+    let stored: Vec<(i64, String, chrono::DateTime<Utc>, serde_json::Value)> = sqlx::query_as(
+        "SELECT id, question, created_at, result FROM readings WHERE user_id = $1 ORDER BY id",
+    )
+    .bind(user_id)
+    .fetch_all(&pool)
+    .await
+    .unwrap();
+    assert_eq!(stored.len(), rows.len());
+    for ((id, question, created_at, result), (expected_id, row)) in
+        stored.into_iter().zip(ids.iter().zip(&rows))
+    {
+        assert_eq!(id, *expected_id);
+        assert_eq!(question, row.reading.question);
+        assert_eq!(created_at, row.created_at);
+        assert_eq!(
+            reading_from_stored(result).unwrap().cards,
+            row.reading.cards
+        );
+    }
+
+    assert_eq!(insert_many(&pool, &[]).await.unwrap(), Vec::<i64>::new());
+}
+
+#[tokio::test]
+async fn a_failing_row_rolls_back_the_whole_batch() {
+    let Some(pool) = common::test_pool().await else {
+        return;
+    };
+    let user_id = common::new_user(&pool, 0).await;
+    let mut rows = legacy_rows(&reading().await, user_id, MAX_INSERT_BATCH + 3);
+    // In the second statement, after a whole batch has gone in.
+    rows[MAX_INSERT_BATCH + 1].user_id = -1;
+
+    let err = insert_many(&pool, &rows).await.unwrap_err();
+    assert!(
+        matches!(err, DbError::ForeignKeyViolation { .. }),
+        "{:?}",
+        err
+    );
+    assert_eq!(ApiError::from(err).status_code(), 422);
+    let stored: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM readings WHERE user_id = $1")
+        .bind(user_id)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(stored, 0);
+}
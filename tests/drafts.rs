@@ -0,0 +1,110 @@
+//! `/drafts` endpoints against the in-memory datastore and `EchoLlm`.
+
+mod common;
+
+use std::sync::Arc;
+
+use actix_web::{test, web};
+use serde_json::{Value, json};
+
+use mimi_backend::app::build_app;
+
+fn save(user_id: i64, question: &str) -> test::TestRequest {
+    test::TestRequest::post()
+        .uri("/drafts")
+        .insert_header(("Authorization", common::token(user_id)))
+        .set_json(json!({ "question": question, "spread": "three_card" }))
+}
+
+fn list(user_id: i64) -> test::TestRequest {
+    test::TestRequest::get()
+        .uri("/drafts")
+        .insert_header(("Authorization", common::token(user_id)))
+}
+
+fn submit(user_id: i64, draft_id: i64) -> test::TestRequest {
+    test::TestRequest::post()
+        .uri(&format!("/drafts/{}/submit", draft_id))
+        .insert_header(("Authorization", common::token(user_id)))
+}
+
+#[actix_web::test]
+async fn a_saved_draft_is_listed_and_read_on_submit() {
+    let llm = Arc::new(common::EchoLlm::default());
+    let state = common::state(common::config(&[])).with_llm(llm.clone());
+    let app = test::init_service(build_app(web::Data::new(state))).await;
+
+    let resp = test::call_service(&app, save(1, "Will I get the job?").to_request()).await;
+    assert_eq!(resp.status(), 201);
+    let draft: Value = test::read_body_json(resp).await;
+    let draft_id = draft["id"].as_i64().unwrap();
+    assert_eq!(draft["spread"], "three_card");
+    // Saving reads nothing.
+    assert_eq!(llm.calls(), 0);
+
+    let resp = test::call_service(&app, list(1).to_request()).await;
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["items"].as_array().map(Vec::len), Some(1));
+    assert_eq!(body["items"][0]["question"], "Will I get the job?");
+    let resp = test::call_service(&app, list(2).to_request()).await;
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["items"], json!([]));
+
+    let resp = test::call_service(&app, submit(2, draft_id).to_request()).await;
+    assert_eq!(resp.status(), 404);
+
+    let resp = test::call_service(&app, submit(1, draft_id).to_request()).await;
+    assert_eq!(resp.status(), 200);
+    let reading: Value = test::read_body_json(resp).await;
+    assert_eq!(reading["question"], "Will I get the job?");
+    assert_eq!(reading["cards"].as_array().map(Vec::len), Some(3));
+
+    let resp = test::call_service(
+        &app,
+        test::TestRequest::get()
+            .uri("/readings")
+            .insert_header(("Authorization", common::token(1)))
+            .to_request(),
+    )
+    .await;
+    let history: Value = test::read_body_json(resp).await;
+    assert_eq!(history["items"][0]["id"], reading["id"]);
+
+    let resp = test::call_service(&app, list(1).to_request()).await;
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["items"], json!([]));
+    let resp = test::call_service(&app, submit(1, draft_id).to_request()).await;
+    assert_eq!(resp.status(), 404);
+}
+
+#[actix_web::test]
+async fn drafts_are_capped_per_user() {
+    let app = test::init_service(build_app(web::Data::new(common::state(common::config(&[
+        ("MAX_DRAFTS", "2"),
+    ])))))
+    .await;
+    for question in ["First?", "Second?"] {
+        let resp = test::call_service(&app, save(1, question).to_request()).await;
+        assert_eq!(resp.status(), 201);
+    }
+    let resp = test::call_service(&app, save(1, "Third?").to_request()).await;
+    assert_eq!(resp.status(), 409);
+    let resp = test::call_service(&app, save(2, "Someone else's?").to_request()).await;
+    assert_eq!(resp.status(), 201);
+}
+
+#[actix_web::test]
+async fn a_draft_is_kept_when_its_reading_fails() {
+    let llm = Arc::new(common::EchoLlm::failing(usize::MAX));
+    let state = common::state(common::config(&[])).with_llm(llm);
+    let app = test::init_service(build_app(web::Data::new(state))).await;
+    let resp = test::call_service(&app, save(1, "Will I get the job?").to_request()).await;
+    let draft: Value = test::read_body_json(resp).await;
+
+    let resp =
+        test::call_service(&app, submit(1, draft["id"].as_i64().unwrap()).to_request()).await;
+    assert!(resp.status().is_server_error(), "{}", resp.status());
+    let resp = test::call_service(&app, list(1).to_request()).await;
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["items"].as_array().map(Vec::len), Some(1));
+}
@@ -0,0 +1,202 @@
+//! Error responses: JSON shape, localization, request ids and unknown routes.
+
+mod common;
+
+use actix_web::{test, web};
+use serde_json::{Value, json};
+
+use mimi_backend::app::build_app;
+
+#[actix_web::test]
+async fn errors_are_thai_by_default_and_english_on_request() {
+    let app = test::init_service(build_app(web::Data::new(common::state(
+        common::config(&[]),
+    ))))
+    .await;
+
+    let resp =
+        test::call_service(&app, test::TestRequest::get().uri("/readings").to_request()).await;
+    assert_eq!(resp.status(), 401);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["error"]["code"], "unauthorized");
+    assert_eq!(body["error"]["message"], "กรุณาเข้าสู่ระบบใหม่อีกครั้ง");
+
+    let resp = test::call_service(
+        &app,
+        test::TestRequest::get()
+            .uri("/readings")
+            .insert_header(("Accept-Language", "en-GB,en;q=0.9"))
+            .to_request(),
+    )
+    .await;
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["error"]["code"], "unauthorized");
+    assert_eq!(body["error"]["message"], "missing or invalid token");
+}
+
+fn request_id_header(headers: &actix_web::http::header::HeaderMap) -> String {
+    headers
+        .get("x-request-id")
+        .expect("X-Request-Id header")
+        .to_str()
+        .unwrap()
+        .to_string()
+}
+
+#[actix_web::test]
+async fn every_error_body_carries_the_request_id_header() {
+    let app = test::init_service(build_app(web::Data::new(common::state(
+        common::config(&[]),
+    ))))
+    .await;
+    let requests = [
+        // Auth
+        test::TestRequest::get().uri("/readings").to_request(),
+        // Validation
+        test::TestRequest::post()
+            .uri("/readings")
+            .insert_header(("Authorization", common::token(1)))
+            .insert_header(("Content-Type", "application/json"))
+            .set_payload("{")
+            .to_request(),
+        // Unknown route
+        test::TestRequest::get().uri("/no-such-route").to_request(),
+    ];
+    for req in requests {
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_client_error(), "{}", resp.status());
+        let header = request_id_header(resp.headers());
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["error"]["request_id"], header, "{}", body);
+    }
+}
+
+#[actix_web::test]
+async fn a_client_supplied_request_id_is_reported_back() {
+    let app = test::init_service(build_app(web::Data::new(common::state(
+        common::config(&[]),
+    ))))
+    .await;
+    let resp = test::call_service(
+        &app,
+        test::TestRequest::get()
+            .uri("/readings")
+            .insert_header(("X-Request-Id", "support-ticket-42"))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(request_id_header(resp.headers()), "support-ticket-42");
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["error"]["request_id"], "support-ticket-42");
+}
+
+#[actix_web::test]
+async fn unknown_routes_get_a_json_not_found() {
+    let app = test::init_service(build_app(web::Data::new(common::state(
+        common::config(&[]),
+    ))))
+    .await;
+    for req in [
+        test::TestRequest::get().uri("/no-such-route"),
+        test::TestRequest::post().uri("/readings/1/no-such-action"),
+    ] {
+        let resp = test::call_service(
+            &app,
+            req.insert_header(("Accept-Language", "en")).to_request(),
+        )
+        .await;
+        assert_eq!(resp.status(), 404);
+        assert_eq!(
+            resp.headers().get("content-type").unwrap(),
+            "application/json"
+        );
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["error"]["code"], "not_found");
+        let message = body["error"]["message"].as_str().unwrap();
+        assert!(
+            message.ends_with("/no-such-route") || message.ends_with("/no-such-action"),
+            "{}",
+            message
+        );
+    }
+}
+
+#[actix_web::test]
+async fn unknown_body_fields_are_named_in_a_400() {
+    let app = test::init_service(build_app(web::Data::new(common::state(
+        common::config(&[]),
+    ))))
+    .await;
+    for (uri, body) in [
+        ("/ask", json!({ "questoin": "Will I get the job?" })),
+        (
+            "/readings",
+            json!({ "question": "Will I get the job?", "questoin": "typo" }),
+        ),
+        (
+            "/payments",
+            json!({ "amount_baht": 100, "idempotency_key": "k", "questoin": "typo" }),
+        ),
+        (
+            "/drafts",
+            json!({ "question": "Later?", "questoin": "typo" }),
+        ),
+    ] {
+        let resp = test::call_service(
+            &app,
+            test::TestRequest::post()
+                .uri(uri)
+                .insert_header(("Authorization", common::token(1)))
+                .insert_header(("Accept-Language", "en"))
+                .set_json(body)
+                .to_request(),
+        )
+        .await;
+        assert_eq!(resp.status(), 400, "{}", uri);
+        let body: Value = test::read_body_json(resp).await;
+        let message = body["error"]["message"].as_str().unwrap();
+        assert!(
+            message.starts_with("Invalid request body: unknown field `questoin`"),
+            "{}: {}",
+            uri,
+            message
+        );
+    }
+}
+
+#[actix_web::test]
+async fn a_json_body_sent_as_plain_text_is_unsupported_media_type() {
+    let app = test::init_service(build_app(web::Data::new(common::state(
+        common::config(&[]),
+    ))))
+    .await;
+    let request = |content_type: &'static str, language: &'static str| {
+        test::TestRequest::post()
+            .uri("/ask")
+            .insert_header(("Authorization", common::token(1)))
+            .insert_header(("Accept-Language", language))
+            .insert_header(("Content-Type", content_type))
+            .set_payload(r#"{"question": "Will I get the job?"}"#)
+            .to_request()
+    };
+
+    let resp = test::call_service(&app, request("text/plain", "en")).await;
+    assert_eq!(resp.status(), 415);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["error"]["code"], "unsupported_media_type");
+    assert_eq!(
+        body["error"]["message"],
+        "Content-Type must be application/json"
+    );
+    assert!(body["error"]["request_id"].is_string(), "{}", body);
+
+    let resp = test::call_service(&app, request("text/plain", "th")).await;
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(
+        body["error"]["message"],
+        "Content-Type ต้องเป็น application/json"
+    );
+
+    let resp = test::call_service(&app, request("application/json", "en")).await;
+    assert_eq!(resp.status(), 200);
+}
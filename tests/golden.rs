@@ -0,0 +1,104 @@
+//! Golden reading snapshots: every case in `tests/golden/` must reproduce
+//! its `.snap.json`. Run with `UPDATE_GOLDEN=1` to (re)record the
+//! snapshots instead. See `mimi_backend::services::golden`.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use mimi_backend::services::{GoldenCase, GoldenFailure, run_golden_case};
+
+fn case_files(dir: &Path) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)
+        .unwrap_or_else(|e| panic!("cannot read {}: {}", dir.display(), e))
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| {
+            path.extension().is_some_and(|ext| ext == "json")
+                && !path.to_string_lossy().ends_with(".snap.json")
+        })
+        .collect();
+    files.sort();
+    files
+}
+
+#[tokio::test]
+async fn golden_readings_match_snapshots() {
+    let update = std::env::var("UPDATE_GOLDEN").is_ok_and(|v| v == "1" || v == "true");
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+    let files = case_files(&dir);
+    assert!(!files.is_empty(), "no golden cases in {}", dir.display());
+
+    let mut failures = Vec::new();
+    for path in &files {
+        let name = path.file_stem().unwrap_or_default().to_string_lossy();
+        let snap_path = path.with_extension("snap.json");
+        let case: GoldenCase = match fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|text| serde_json::from_str(&text).map_err(|e| e.to_string()))
+        {
+            Ok(case) => case,
+            Err(e) => {
+                failures.push(format!("{}: unreadable case: {}", name, e));
+                continue;
+            }
+        };
+        let actual = match run_golden_case(&case).await {
+            Ok(actual) => actual,
+            Err(e) => {
+                failures.push(format!("{}: {}", name, e));
+                continue;
+            }
+        };
+        let rendered = serde_json::to_string_pretty(&actual).unwrap() + "\n";
+        if update {
+            fs::write(&snap_path, rendered)
+                .unwrap_or_else(|e| panic!("cannot write {}: {}", snap_path.display(), e));
+            continue;
+        }
+        let expected: Option<serde_json::Value> = fs::read_to_string(&snap_path)
+            .ok()
+            .and_then(|text| serde_json::from_str(&text).ok());
+        match expected {
+            Some(expected) if expected == actual => {}
+            Some(_) => failures.push(format!(
+                "{}: output differs from snapshot; got:\n{}",
+                name, rendered
+            )),
+            None => failures.push(format!(
+                "{}: no snapshot; run with UPDATE_GOLDEN=1 to record one",
+                name
+            )),
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "{} of {} golden case(s) failed:\n{}",
+        failures.len(),
+        files.len(),
+        failures.join("\n")
+    );
+}
+
+fn career_case() -> GoldenCase {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden/career_single.json");
+    serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap()
+}
+
+#[tokio::test]
+async fn a_golden_case_reproduces_on_every_run() {
+    let case = career_case();
+    let first = run_golden_case(&case).await.unwrap();
+    let second = run_golden_case(&case).await.unwrap();
+    assert_eq!(first, second);
+}
+
+#[tokio::test]
+async fn a_prompt_missing_its_expected_text_fails_the_case() {
+    let mut case = career_case();
+    case.expect_in_system_prompt = vec!["text no prompt contains".to_string()];
+    let err = run_golden_case(&case).await.unwrap_err();
+    assert!(
+        matches!(err, GoldenFailure::MissingFromPrompt { index: 0, .. }),
+        "{}",
+        err
+    );
+}
@@ -0,0 +1,145 @@
+//! Guest readings and their per-device quota; skipped without
+//! `UPSTASH_REDIS_URL`.
+
+mod common;
+
+use std::sync::Arc;
+
+use actix_web::{test, web};
+use serde_json::{Value, json};
+
+use mimi_backend::app::build_app;
+use mimi_backend::db::MemoryDatastore;
+use mimi_backend::services::LlmProvider;
+use mimi_backend::state::AppState;
+
+/// A device id no earlier run has used up.
+fn new_device() -> String {
+    format!("device-{}", uuid::Uuid::new_v4().simple())
+}
+
+fn guest_reading(device_id: &str) -> test::TestRequest {
+    test::TestRequest::post()
+        .uri("/readings/guest")
+        .insert_header(("X-Device-Id", device_id))
+        .insert_header(("Accept-Language", "en"))
+        .set_json(json!({ "question": "Will I get the job?" }))
+}
+
+async fn guest_state(llm: Arc<dyn LlmProvider>) -> Option<AppState> {
+    let redis = common::test_redis().await?;
+    Some(
+        AppState::new(common::config(&[]), None, Some(redis))
+            .with_store(Arc::new(MemoryDatastore::new()))
+            .with_llm(llm),
+    )
+}
+
+#[actix_web::test]
+async fn a_device_gets_one_free_reading() {
+    let Some(state) = guest_state(Arc::new(common::EchoLlm::default())).await else {
+        return;
+    };
+    let app = test::init_service(build_app(web::Data::new(state))).await;
+    let device = new_device();
+
+    let resp = test::call_service(&app, guest_reading(&device).to_request()).await;
+    assert_eq!(resp.status(), 200);
+    let reading: Value = test::read_body_json(resp).await;
+    assert_eq!(reading["id"], Value::Null);
+    assert_eq!(reading["cards"].as_array().map(Vec::len), Some(3));
+
+    let resp = test::call_service(&app, guest_reading(&device).to_request()).await;
+    assert_eq!(resp.status(), 429);
+    let body: Value = test::read_body_json(resp).await;
+    assert!(
+        body["error"]["message"]
+            .as_str()
+            .unwrap()
+            .contains("sign in"),
+        "{}",
+        body
+    );
+
+    // The quota is per device.
+    let resp = test::call_service(&app, guest_reading(&new_device()).to_request()).await;
+    assert_eq!(resp.status(), 200);
+}
+
+#[actix_web::test]
+async fn a_failed_guest_reading_does_not_use_the_quota() {
+    let Some(state) = guest_state(Arc::new(common::EchoLlm::failing(2))).await else {
+        return;
+    };
+    let app = test::init_service(build_app(web::Data::new(state))).await;
+    let device = new_device();
+    let resp = test::call_service(&app, guest_reading(&device).to_request()).await;
+    assert!(resp.status().is_server_error(), "{}", resp.status());
+    let resp = test::call_service(&app, guest_reading(&device).to_request()).await;
+    assert_eq!(resp.status(), 200);
+}
+
+#[actix_web::test]
+async fn an_account_claims_its_devices_guest_readings() {
+    let Some(state) = guest_state(Arc::new(common::EchoLlm::default())).await else {
+        return;
+    };
+    let app = test::init_service(build_app(web::Data::new(state))).await;
+    let device = new_device();
+    let resp = test::call_service(&app, guest_reading(&device).to_request()).await;
+    assert_eq!(resp.status(), 200);
+
+    let claim = || {
+        test::TestRequest::post()
+            .uri("/readings/guest/claim")
+            .insert_header(("Authorization", common::token(7)))
+            .insert_header(("X-Device-Id", device.as_str()))
+            .to_request()
+    };
+    let body: Value = test::call_and_read_body_json(&app, claim()).await;
+    let claimed = body["claimed"].as_array().unwrap().clone();
+    assert_eq!(claimed.len(), 1);
+
+    let history: Value = test::call_and_read_body_json(
+        &app,
+        test::TestRequest::get()
+            .uri("/readings")
+            .insert_header(("Authorization", common::token(7)))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(history["items"][0]["id"], claimed[0]);
+    assert_eq!(history["items"][0]["question"], "Will I get the job?");
+
+    // Claimed once only.
+    let body: Value = test::call_and_read_body_json(&app, claim()).await;
+    assert_eq!(body["claimed"], json!([]));
+}
+
+#[actix_web::test]
+async fn guest_readings_need_a_device_id_and_redis() {
+    let Some(state) = guest_state(Arc::new(common::EchoLlm::default())).await else {
+        return;
+    };
+    let app = test::init_service(build_app(web::Data::new(state))).await;
+    for device in ["short", "has spaces in it", "semi;colons;here"] {
+        let resp = test::call_service(&app, guest_reading(device).to_request()).await;
+        assert_eq!(resp.status(), 400, "{}", device);
+    }
+    let resp = test::call_service(
+        &app,
+        test::TestRequest::post()
+            .uri("/readings/guest")
+            .set_json(json!({ "question": "Will I get the job?" }))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), 400);
+
+    let app = test::init_service(build_app(web::Data::new(common::state(
+        common::config(&[]),
+    ))))
+    .await;
+    let resp = test::call_service(&app, guest_reading(&new_device()).to_request()).await;
+    assert_eq!(resp.status(), 503);
+}
@@ -0,0 +1,166 @@
+//! `/health/*` endpoints; `/health/full` also checks Postgres when
+//! `DATABASE_URL` is set.
+
+mod common;
+
+use actix_web::{test, web};
+use serde_json::Value;
+
+use common::{FakeOpenAi, StubResponse};
+use mimi_backend::app::build_app;
+
+#[actix_web::test]
+async fn llm_health_reports_a_reachable_provider() {
+    let openai = FakeOpenAi::start(vec![StubResponse::new(200, r#"{"data": []}"#)]).await;
+    let config = common::config(&[
+        ("OPENAI_API_KEY", "sk-test"),
+        ("OPENAI_BASE_URL", &openai.base_url),
+    ]);
+    let app = test::init_service(build_app(web::Data::new(common::state(config)))).await;
+    let resp = test::call_service(
+        &app,
+        test::TestRequest::get()
+            .uri("/health/llm")
+            .insert_header(("Authorization", common::admin_token(99)))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), 200);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["status"], "ok");
+    let requests = openai.requests();
+    assert_eq!(requests[0].path, "/models");
+    assert_eq!(requests[0].header("authorization"), Some("Bearer sk-test"));
+}
+
+#[actix_web::test]
+async fn llm_health_is_unavailable_when_the_key_is_refused() {
+    let openai = FakeOpenAi::start(vec![StubResponse::new(
+        401,
+        r#"{"error": {"message": "Incorrect API key provided", "type": "invalid_request_error", "code": "invalid_api_key"}}"#,
+    )])
+    .await;
+    let config = common::config(&[
+        ("OPENAI_API_KEY", "sk-test"),
+        ("OPENAI_BASE_URL", &openai.base_url),
+    ]);
+    let app = test::init_service(build_app(web::Data::new(common::state(config)))).await;
+    let resp = test::call_service(
+        &app,
+        test::TestRequest::get()
+            .uri("/health/llm")
+            .insert_header(("Authorization", common::admin_token(99)))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), 503);
+    let body: Value = test::read_body_json(resp).await;
+    assert!(
+        body["error"]
+            .as_str()
+            .unwrap()
+            .contains("Incorrect API key"),
+        "{}",
+        body
+    );
+}
+
+#[actix_web::test]
+async fn llm_health_is_admin_only() {
+    let app = test::init_service(build_app(web::Data::new(common::state(
+        common::config(&[]),
+    ))))
+    .await;
+    let resp = test::call_service(
+        &app,
+        test::TestRequest::get()
+            .uri("/health/llm")
+            .insert_header(("Authorization", common::token(1)))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), 403);
+}
+
+fn full_health() -> test::TestRequest {
+    test::TestRequest::get()
+        .uri("/health/full")
+        .insert_header(("Authorization", common::admin_token(99)))
+}
+
+#[actix_web::test]
+async fn full_health_reports_each_subsystem() {
+    let openai = FakeOpenAi::start(vec![StubResponse::new(200, r#"{"data": []}"#)]).await;
+    let config = common::config(&[
+        ("OPENAI_API_KEY", "sk-test"),
+        ("OPENAI_BASE_URL", &openai.base_url),
+    ]);
+    let app = test::init_service(build_app(web::Data::new(common::state(config)))).await;
+    let resp = test::call_service(&app, full_health().to_request()).await;
+    // Subsystems this deployment doesn't have don't fail the check.
+    assert_eq!(resp.status(), 200);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["status"], "ok");
+    let checks = &body["checks"];
+    assert_eq!(checks["llm"]["status"], "ok");
+    assert_eq!(checks["llm"]["critical"], true);
+    assert!(checks["llm"]["latency_ms"].is_u64(), "{}", checks);
+    assert_eq!(checks["database"]["status"], "not_configured");
+    assert_eq!(checks["redis"]["status"], "not_configured");
+    assert_eq!(checks["queue"]["status"], "not_configured");
+    assert_eq!(checks["queue"]["critical"], false);
+}
+
+#[actix_web::test]
+async fn full_health_is_degraded_when_a_critical_subsystem_is_down() {
+    let openai = FakeOpenAi::start(vec![StubResponse::new(
+        401,
+        r#"{"error": {"message": "Incorrect API key provided", "type": "invalid_request_error", "code": "invalid_api_key"}}"#,
+    )])
+    .await;
+    let config = common::config(&[
+        ("OPENAI_API_KEY", "sk-test"),
+        ("OPENAI_BASE_URL", &openai.base_url),
+    ]);
+    let state = match common::test_pool().await {
+        Some(pool) => common::pg_state(config, pool),
+        None => common::state(config),
+    };
+    let has_pool = state.pool().is_some();
+    let app = test::init_service(build_app(web::Data::new(state))).await;
+    let resp = test::call_service(&app, full_health().to_request()).await;
+    assert_eq!(resp.status(), 503);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["status"], "degraded");
+    let checks = &body["checks"];
+    assert_eq!(checks["llm"]["status"], "unavailable");
+    assert!(
+        checks["llm"]["error"]
+            .as_str()
+            .unwrap()
+            .contains("Incorrect API key"),
+        "{}",
+        checks
+    );
+    if has_pool {
+        assert_eq!(checks["database"]["status"], "ok");
+        assert!(checks["database"]["pool_idle"].is_u64(), "{}", checks);
+    }
+}
+
+#[actix_web::test]
+async fn full_health_is_admin_only() {
+    let app = test::init_service(build_app(web::Data::new(common::state(
+        common::config(&[]),
+    ))))
+    .await;
+    let resp = test::call_service(
+        &app,
+        test::TestRequest::get()
+            .uri("/health/full")
+            .insert_header(("Authorization", common::token(1)))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), 403);
+}
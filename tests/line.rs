@@ -0,0 +1,139 @@
+//! The LINE webhook: signature checks, and redelivered events queueing a
+//! reading once; the queueing test is skipped without `DATABASE_URL` and
+//! `UPSTASH_REDIS_URL`.
+
+mod common;
+
+use std::sync::Arc;
+
+use actix_web::{test, web};
+use redis::AsyncCommands;
+use serde_json::{Value, json};
+
+use mimi_backend::app::build_app;
+use mimi_backend::services::{QUEUE_KEY, get_job, sign_line_body};
+use mimi_backend::state::AppState;
+
+const CHANNEL_SECRET: &str = "line-channel-secret";
+
+/// A webhook delivery of one text message, as LINE sends it.
+fn text_event(event_id: &str, line_id: &str, text: &str, redelivery: bool) -> Value {
+    json!({
+        "destination": "Ubot",
+        "events": [{
+            "type": "message",
+            "mode": "active",
+            "timestamp": 1_700_000_000_000_i64,
+            "webhookEventId": event_id,
+            "deliveryContext": { "isRedelivery": redelivery },
+            "replyToken": "reply-token",
+            "source": { "type": "user", "userId": line_id },
+            "message": { "type": "text", "id": "1", "text": text },
+        }],
+    })
+}
+
+fn delivery(body: &Value, signature: Option<String>) -> test::TestRequest {
+    let body = body.to_string();
+    let mut req = test::TestRequest::post()
+        .uri("/line/webhook")
+        .insert_header(("Content-Type", "application/json"));
+    if let Some(signature) = signature {
+        req = req.insert_header(("X-Line-Signature", signature));
+    }
+    req.set_payload(body)
+}
+
+fn signed(body: &Value) -> test::TestRequest {
+    delivery(
+        body,
+        Some(sign_line_body(CHANNEL_SECRET, body.to_string().as_bytes())),
+    )
+}
+
+#[actix_web::test]
+async fn a_redelivered_event_queues_one_reading() {
+    let Some(pool) = common::test_pool().await else {
+        return;
+    };
+    let Some(redis) = common::test_redis().await else {
+        return;
+    };
+    let line_id = format!("U{}", uuid::Uuid::new_v4().simple());
+    let user_id: i64 =
+        sqlx::query_scalar("INSERT INTO users (line_id, credits) VALUES ($1, 5) RETURNING id")
+            .bind(&line_id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    let state = AppState::new(
+        common::config(&[("LINE_CHANNEL_SECRET", CHANNEL_SECRET)]),
+        Some(pool.clone()),
+        Some(redis.clone()),
+    )
+    .with_llm(Arc::new(common::EchoLlm::default()));
+    let app = test::init_service(build_app(web::Data::new(state))).await;
+    let event_id = uuid::Uuid::new_v4().to_string();
+
+    let resp = test::call_service(
+        &app,
+        signed(&text_event(
+            &event_id,
+            &line_id,
+            "Will I get the job?",
+            false,
+        ))
+        .to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), 200);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["duplicates"], 0);
+    let jobs = body["jobs"].as_array().unwrap();
+    assert_eq!(jobs.len(), 1);
+    let job = get_job(&redis, jobs[0].as_str().unwrap()).await.unwrap();
+    assert_eq!(job.user_id, user_id);
+    assert_eq!(job.question, "Will I get the job?");
+    assert_eq!(job.credits_charged, 1);
+
+    // LINE redelivers the same event: answered 200, nothing queued or charged.
+    let resp = test::call_service(
+        &app,
+        signed(&text_event(
+            &event_id,
+            &line_id,
+            "Will I get the job?",
+            true,
+        ))
+        .to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), 200);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["jobs"], json!([]));
+    assert_eq!(body["duplicates"], 1);
+    assert_eq!(common::credits(&pool, user_id).await, 4);
+    let _: i64 = redis.clone().lrem(QUEUE_KEY, 0, &job.id).await.unwrap();
+}
+
+#[actix_web::test]
+async fn deliveries_need_the_channel_signature() {
+    let app = test::init_service(build_app(web::Data::new(common::state(common::config(&[
+        ("LINE_CHANNEL_SECRET", CHANNEL_SECRET),
+    ])))))
+    .await;
+    let body = text_event("event-1", "U1", "Will I get the job?", false);
+    let resp = test::call_service(&app, delivery(&body, None).to_request()).await;
+    assert_eq!(resp.status(), 400);
+    let forged = sign_line_body("not-the-secret", body.to_string().as_bytes());
+    let resp = test::call_service(&app, delivery(&body, Some(forged)).to_request()).await;
+    assert_eq!(resp.status(), 401);
+
+    // Without a channel secret the webhook is off.
+    let app = test::init_service(build_app(web::Data::new(common::state(
+        common::config(&[]),
+    ))))
+    .await;
+    let resp = test::call_service(&app, signed(&body).to_request()).await;
+    assert_eq!(resp.status(), 503);
+}
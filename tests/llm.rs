@@ -0,0 +1,455 @@
+//! `OpenAiClient`, and the cassette layer around it, against a local
+//! stand-in for the OpenAI API.
+
+mod common;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use actix_web::{ResponseError, test, web};
+use serde_json::Value;
+
+use common::{FakeOpenAi, StubResponse};
+use mimi_backend::app::build_app;
+use mimi_backend::middleware::ApiError;
+use mimi_backend::services::{
+    AskParams, CassetteLlm, FinishReason, LlmError, LlmProvider, OpenAiClient,
+};
+
+fn client(base_url: &str, extra: &[(&str, &str)]) -> OpenAiClient {
+    let mut vars = vec![("OPENAI_API_KEY", "sk-test"), ("OPENAI_BASE_URL", base_url)];
+    vars.extend_from_slice(extra);
+    OpenAiClient::new(&common::config(&vars))
+}
+
+#[tokio::test]
+async fn sends_sampling_parameters_only_when_set() {
+    let openai = FakeOpenAi::start(vec![StubResponse::completion("hello")]).await;
+    let client = client(&openai.base_url, &[]);
+
+    let params = AskParams {
+        temperature: Some(0.25),
+        max_tokens: Some(300),
+        top_p: Some(0.5),
+        seed: None,
+    };
+    let response = client.ask("system", "user", &params).await.unwrap();
+    assert_eq!(response.content, "hello");
+    client
+        .ask("system", "user", &AskParams::default())
+        .await
+        .unwrap();
+
+    let requests = openai.requests();
+    let body = requests[0].json();
+    assert_eq!(requests[0].path, "/chat/completions");
+    assert_eq!(body["temperature"], 0.25);
+    assert_eq!(body["max_tokens"], 300);
+    assert_eq!(body["top_p"], 0.5);
+    let body = requests[1].json();
+    assert!(body.get("temperature").is_none(), "{}", body);
+    assert!(body.get("top_p").is_none(), "{}", body);
+}
+
+#[tokio::test]
+async fn an_unreachable_provider_is_a_network_error() {
+    // Bind then drop a listener so the port is (almost certainly) closed.
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let err = client(&format!("http://127.0.0.1:{}", port), &[])
+        .ask("system", "user", &AskParams::default())
+        .await
+        .unwrap_err();
+    assert!(matches!(err, LlmError::Network(_)), "{:?}", err);
+    assert_eq!(ApiError::from(err).status_code(), 502);
+}
+
+#[tokio::test]
+async fn a_slow_provider_is_a_timeout() {
+    let openai = FakeOpenAi::start(vec![
+        StubResponse::completion("late").delay(Duration::from_millis(1500)),
+    ])
+    .await;
+    let err = client(&openai.base_url, &[("OPENAI_TIMEOUT_SECS", "1")])
+        .ask("system", "user", &AskParams::default())
+        .await
+        .unwrap_err();
+    assert!(err.is_timeout(), "{:?}", err);
+    assert_eq!(ApiError::from(err).status_code(), 504);
+}
+
+fn upstream_error(status: u16, code: &str) -> StubResponse {
+    StubResponse::new(
+        status,
+        serde_json::json!({
+            "error": { "message": format!("{} from upstream", code), "code": code },
+        })
+        .to_string(),
+    )
+}
+
+#[tokio::test]
+async fn falls_back_when_the_model_is_unavailable() {
+    let openai = FakeOpenAi::start(vec![
+        upstream_error(404, "model_not_found"),
+        upstream_error(503, "overloaded"),
+        StubResponse::completion("from the fallback"),
+    ])
+    .await;
+    let client = client(
+        &openai.base_url,
+        &[
+            ("OPENAI_MODEL", "primary"),
+            ("OPENAI_MODEL_FALLBACKS", "second,third"),
+        ],
+    );
+    let response = client
+        .ask("system", "user", &AskParams::default())
+        .await
+        .unwrap();
+    assert_eq!(response.content, "from the fallback");
+    assert_eq!(response.model, "third");
+    let models: Vec<_> = openai
+        .requests()
+        .iter()
+        .map(|r| r.json()["model"].as_str().unwrap().to_string())
+        .collect();
+    assert_eq!(models, ["primary", "second", "third"]);
+}
+
+#[tokio::test]
+async fn client_errors_do_not_fall_back() {
+    for (status, code) in [(400, "invalid_request_error"), (429, "insufficient_quota")] {
+        let openai = FakeOpenAi::start(vec![
+            upstream_error(status, code),
+            StubResponse::completion("unreachable"),
+        ])
+        .await;
+        let err = client(&openai.base_url, &[("OPENAI_MODEL_FALLBACKS", "second")])
+            .ask("system", "user", &AskParams::default())
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(err, LlmError::Upstream { status: s, .. } if s == status),
+            "{:?}",
+            err
+        );
+        assert_eq!(openai.requests().len(), 1);
+    }
+}
+
+#[actix_web::test]
+async fn responses_name_the_model_that_answered() {
+    let openai = FakeOpenAi::start(vec![
+        upstream_error(404, "model_not_found"),
+        StubResponse::completion("Yes, today is lucky."),
+    ])
+    .await;
+    let llm = client(
+        &openai.base_url,
+        &[
+            ("OPENAI_MODEL", "primary"),
+            ("OPENAI_MODEL_FALLBACKS", "backup"),
+        ],
+    );
+    let state = common::state(common::config(&[])).with_llm(Arc::new(llm));
+    let app = test::init_service(build_app(web::Data::new(state))).await;
+    let body: Value = test::call_and_read_body_json(
+        &app,
+        test::TestRequest::post()
+            .uri("/ask")
+            .insert_header(("Authorization", common::token(1)))
+            .set_json(serde_json::json!({ "question": "Is today lucky?" }))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(body["model"], "backup");
+}
+
+#[tokio::test]
+async fn warmup_opens_a_connection_and_never_fails() {
+    let openai = FakeOpenAi::start(vec![StubResponse::new(200, r#"{"data":[]}"#)]).await;
+    client(&openai.base_url, &[]).warm_up().await;
+    let requests = openai.requests();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].path, "/models");
+
+    // Failures are only logged.
+    let openai = FakeOpenAi::start(vec![upstream_error(401, "invalid_api_key")]).await;
+    client(&openai.base_url, &[]).warm_up().await;
+    OpenAiClient::new(&common::config(&[])).warm_up().await;
+}
+
+#[tokio::test]
+async fn warmup_is_skipped_in_mock_mode() {
+    assert!(!OpenAiClient::warmup_enabled(&common::config(&[])));
+    assert!(OpenAiClient::warmup_enabled(&common::config(&[(
+        "WARMUP_LLM",
+        "true"
+    )])));
+    assert!(!OpenAiClient::warmup_enabled(&common::config(&[
+        ("WARMUP_LLM", "true"),
+        ("LLM_MOCK", "true"),
+    ])));
+}
+
+#[tokio::test]
+async fn fallbacks_stop_at_the_total_deadline() {
+    let openai = FakeOpenAi::start(vec![
+        upstream_error(503, "overloaded").delay(Duration::from_millis(1500)),
+    ])
+    .await;
+    let err = client(
+        &openai.base_url,
+        &[
+            ("OPENAI_MODEL_FALLBACKS", "second,third,fourth"),
+            ("LLM_TOTAL_DEADLINE_SECS", "6"),
+        ],
+    )
+    .ask("system", "user", &AskParams::default())
+    .await
+    .unwrap_err();
+    // 4.5s left is less than one attempt window, so no fallback is tried.
+    assert!(
+        matches!(&err, LlmError::DeadlineExceeded(last) if matches!(**last, LlmError::Upstream { status: 503, .. })),
+        "{:?}",
+        err
+    );
+    assert!(err.to_string().contains("total LLM deadline"), "{}", err);
+    assert_eq!(openai.requests().len(), 1);
+    assert_eq!(ApiError::from(err).status_code(), 504);
+}
+
+fn finishing(reason: Value, content: Value) -> StubResponse {
+    StubResponse::new(
+        200,
+        serde_json::json!({
+            "model": "stub-model",
+            "choices": [{
+                "message": { "role": "assistant", "content": content },
+                "finish_reason": reason,
+            }],
+        })
+        .to_string(),
+    )
+}
+
+#[tokio::test]
+async fn finish_reasons_are_parsed_from_the_completion() {
+    let cases = [
+        (serde_json::json!("stop"), Some(FinishReason::Stop)),
+        (serde_json::json!("length"), Some(FinishReason::Length)),
+        (
+            serde_json::json!("tool_calls"),
+            Some(FinishReason::ToolCalls),
+        ),
+        (
+            serde_json::json!("content_filter"),
+            Some(FinishReason::ContentFilter),
+        ),
+        (
+            serde_json::json!("something_new"),
+            Some(FinishReason::Other),
+        ),
+        (Value::Null, None),
+    ];
+    let openai = FakeOpenAi::start(
+        cases
+            .iter()
+            .map(|(reason, _)| finishing(reason.clone(), serde_json::json!("text")))
+            .collect(),
+    )
+    .await;
+    let client = client(&openai.base_url, &[]);
+    for (reason, expected) in cases {
+        let response = client
+            .ask("system", "user", &AskParams::default())
+            .await
+            .unwrap();
+        assert_eq!(response.finish_reason, expected, "{}", reason);
+    }
+}
+
+#[tokio::test]
+async fn a_filtered_completion_without_content_is_content_filtered() {
+    let openai = FakeOpenAi::start(vec![finishing(
+        serde_json::json!("content_filter"),
+        Value::Null,
+    )])
+    .await;
+    let err = client(&openai.base_url, &[])
+        .ask("system", "user", &AskParams::default())
+        .await
+        .unwrap_err();
+    assert!(matches!(err, LlmError::ContentFiltered), "{:?}", err);
+}
+
+#[tokio::test]
+async fn sends_the_seed_and_reads_back_the_system_fingerprint() {
+    let openai = FakeOpenAi::start(vec![StubResponse::new(
+        200,
+        serde_json::json!({
+            "model": "stub-model",
+            "system_fingerprint": "fp_44709d6fcb",
+            "choices": [{
+                "message": { "role": "assistant", "content": "hello" },
+                "finish_reason": "stop",
+            }],
+        })
+        .to_string(),
+    )])
+    .await;
+    let params = AskParams {
+        seed: Some(20251015),
+        ..AskParams::default()
+    };
+    let response = client(&openai.base_url, &[])
+        .ask("system", "user", &params)
+        .await
+        .unwrap();
+    assert_eq!(
+        response.system_fingerprint.as_deref(),
+        Some("fp_44709d6fcb")
+    );
+    assert_eq!(openai.requests()[0].json()["seed"], 20251015);
+}
+
+#[tokio::test]
+async fn max_tokens_is_clamped_for_a_small_context_model() {
+    let openai = FakeOpenAi::start(vec![StubResponse::completion("ok")]).await;
+    let client = client(
+        &openai.base_url,
+        &[
+            ("OPENAI_MODEL", "tiny-model"),
+            ("MODEL_TOKEN_LIMITS", "tiny-model=2000/4000"),
+        ],
+    );
+    let params = AskParams {
+        max_tokens: Some(4000),
+        ..AskParams::default()
+    };
+    client
+        .ask(&"a".repeat(3600), "user", &params)
+        .await
+        .unwrap();
+    let sent = openai.requests()[0].json()["max_tokens"].as_u64().unwrap();
+    assert!(sent < 1000, "{}", sent);
+}
+
+#[tokio::test]
+async fn an_oversized_response_is_refused() {
+    let huge = format!(
+        r#"{{"choices": [{{"message": {{"content": "{}"}}}}]}}"#,
+        "x".repeat(4096)
+    );
+    // Refused from `Content-Length`, and while reading when there is none.
+    for stub in [
+        StubResponse::new(200, huge.clone()),
+        StubResponse::new(200, huge.clone()).without_length(),
+    ] {
+        let openai = FakeOpenAi::start(vec![stub]).await;
+        let client = client(&openai.base_url, &[("LLM_MAX_RESPONSE_BYTES", "1024")]);
+        let err = client
+            .ask("system", "user", &AskParams::default())
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(err, LlmError::ResponseTooLarge { limit: 1024 }),
+            "{:?}",
+            err
+        );
+    }
+}
+
+#[tokio::test]
+async fn a_response_within_the_limit_is_read() {
+    for stub in [
+        StubResponse::completion("hello"),
+        StubResponse::completion("hello").without_length(),
+    ] {
+        let openai = FakeOpenAi::start(vec![stub]).await;
+        let client = client(&openai.base_url, &[("LLM_MAX_RESPONSE_BYTES", "1024")]);
+        let response = client
+            .ask("system", "user", &AskParams::default())
+            .await
+            .unwrap();
+        assert_eq!(response.content, "hello");
+    }
+}
+
+#[tokio::test]
+async fn rate_limit_headers_update_the_throttle() {
+    let openai = FakeOpenAi::start(vec![
+        StubResponse::completion("hello")
+            .header("x-ratelimit-limit-requests", "500")
+            .header("x-ratelimit-remaining-requests", "2")
+            .header("x-ratelimit-reset-requests", "1m")
+            .header("x-ratelimit-limit-tokens", "30000")
+            .header("x-ratelimit-remaining-tokens", "29000")
+            .header("x-ratelimit-reset-tokens", "2s"),
+    ])
+    .await;
+    let client = client(&openai.base_url, &[("LLM_THROTTLE_MAX_DELAY_MS", "250")]);
+    assert_eq!(client.rate_limits(), None);
+    assert!(client.throttle().delay().is_zero());
+
+    client
+        .ask("system", "user", &AskParams::default())
+        .await
+        .unwrap();
+    let limits = client.rate_limits().expect("limits from the response");
+    assert_eq!(limits.requests.limit, Some(500));
+    assert_eq!(limits.requests.remaining, Some(2));
+    assert_eq!(limits.tokens.remaining, Some(29000));
+    assert_eq!(limits.tokens.reset, Some(Duration::from_secs(2)));
+    // 2 of 500 requests left is well under the 10% default threshold.
+    assert_eq!(client.throttle().delay(), Duration::from_millis(250));
+}
+
+#[tokio::test]
+async fn a_recorded_completion_is_replayed_for_the_same_request() {
+    let openai = FakeOpenAi::start(vec![
+        StubResponse::completion("recorded answer"),
+        StubResponse::completion("a live answer"),
+    ])
+    .await;
+    let live: Arc<dyn LlmProvider> = Arc::new(client(&openai.base_url, &[]));
+    let dir = std::env::temp_dir().join(format!("cassettes-{}", uuid::Uuid::new_v4()));
+    let params = AskParams {
+        temperature: Some(0.2),
+        ..AskParams::default()
+    };
+
+    let recorder = CassetteLlm::new(live.clone(), &dir, true, false);
+    let recorded = recorder.ask("system", "user", &params).await.unwrap();
+    assert_eq!(recorded.content, "recorded answer");
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+    let player = CassetteLlm::new(live.clone(), &dir, false, true);
+    let replayed = player.ask("system", "user", &params).await.unwrap();
+    assert_eq!(replayed.content, recorded.content);
+    assert_eq!(replayed.model, recorded.model);
+    assert_eq!(replayed.raw, recorded.raw);
+    assert_eq!(openai.requests().len(), 1);
+
+    // A different request has no cassette: replay alone refuses it...
+    let other = AskParams::default();
+    let err = player.ask("system", "user", &other).await.unwrap_err();
+    assert!(
+        matches!(&err, LlmError::Request(message) if message.contains("No recorded")),
+        "{:?}",
+        err
+    );
+    assert_eq!(openai.requests().len(), 1);
+    // ...and replay with record sends and records it.
+    let both = CassetteLlm::new(live, &dir, true, true);
+    let fresh = both.ask("system", "user", &other).await.unwrap();
+    assert_eq!(fresh.content, "a live answer");
+    assert_eq!(openai.requests().len(), 2);
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 2);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
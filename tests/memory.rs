@@ -0,0 +1,85 @@
+//! Per-user conversation memory in Redis. Skipped without
+//! `UPSTASH_REDIS_URL`.
+
+mod common;
+
+use actix_web::{test, web};
+
+use mimi_backend::app::build_app;
+use mimi_backend::services::{MemorySettings, MemoryTurn, clear_memory, recent_turns, record_turn};
+use mimi_backend::state::AppState;
+
+fn settings() -> MemorySettings {
+    MemorySettings {
+        max_turns: 2,
+        ttl_secs: 60,
+        max_context_chars: 1000,
+    }
+}
+
+/// A user id no other test run is using.
+fn fresh_user() -> i64 {
+    (uuid::Uuid::new_v4().as_u128() % 1_000_000_000) as i64 + 1_000_000_000
+}
+
+#[tokio::test]
+async fn keeps_the_newest_turns_up_to_the_limit() {
+    let Some(redis) = common::test_redis().await else {
+        return;
+    };
+    let user_id = fresh_user();
+    for question in ["first", "second", "third"] {
+        record_turn(
+            &redis,
+            user_id,
+            &MemoryTurn::new(question, "ok"),
+            &settings(),
+        )
+        .await
+        .unwrap();
+    }
+    let turns = recent_turns(&redis, user_id, &settings()).await.unwrap();
+    let questions: Vec<&str> = turns.iter().map(|t| t.question.as_str()).collect();
+    assert_eq!(questions, ["third", "second"]);
+
+    clear_memory(&redis, user_id).await.unwrap();
+    assert!(
+        recent_turns(&redis, user_id, &settings())
+            .await
+            .unwrap()
+            .is_empty()
+    );
+}
+
+#[actix_web::test]
+async fn users_can_clear_their_memory() {
+    let Some(redis) = common::test_redis().await else {
+        return;
+    };
+    let user_id = fresh_user();
+    record_turn(
+        &redis,
+        user_id,
+        &MemoryTurn::new("first", "ok"),
+        &settings(),
+    )
+    .await
+    .unwrap();
+    let state = AppState::new(common::config(&[]), None, Some(redis.clone()));
+    let app = test::init_service(build_app(web::Data::new(state))).await;
+    let resp = test::call_service(
+        &app,
+        test::TestRequest::delete()
+            .uri("/users/me/memory")
+            .insert_header(("Authorization", common::token(user_id)))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), 204);
+    assert!(
+        recent_turns(&redis, user_id, &settings())
+            .await
+            .unwrap()
+            .is_empty()
+    );
+}
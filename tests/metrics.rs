@@ -0,0 +1,62 @@
+//! `GET /metrics`.
+
+mod common;
+
+use actix_web::{test, web};
+use serde_json::json;
+
+use mimi_backend::app::build_app;
+
+#[actix_web::test]
+async fn a_rejected_question_increments_its_labeled_counter() {
+    let app = test::init_service(build_app(web::Data::new(common::state(common::config(&[
+        ("SUPPORTED_LANGUAGES", "th,en"),
+    ])))))
+    .await;
+    let resp = test::call_service(
+        &app,
+        test::TestRequest::post()
+            .uri("/readings")
+            .insert_header(("Authorization", common::token(1)))
+            .set_json(json!({ "question": "我会得到这份工作吗？" }))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), 422);
+
+    let resp = test::call_service(
+        &app,
+        test::TestRequest::get()
+            .uri("/metrics")
+            .insert_header(("Authorization", common::admin_token(99)))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), 200);
+    let text = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+    let lines: Vec<&str> = text.lines().collect();
+    assert!(lines.contains(&"mimi_questions_screened_total{language=\"other\"} 1"));
+    assert!(lines.contains(
+        &"mimi_filter_rejections_total{reason=\"unsupported_language\",language=\"other\"} 1"
+    ));
+    assert!(
+        lines.contains(&"mimi_filter_rejections_total{reason=\"invalid\",language=\"other\"} 0")
+    );
+}
+
+#[actix_web::test]
+async fn metrics_are_for_admins_only() {
+    let app = test::init_service(build_app(web::Data::new(common::state(
+        common::config(&[]),
+    ))))
+    .await;
+    let resp = test::call_service(
+        &app,
+        test::TestRequest::get()
+            .uri("/metrics")
+            .insert_header(("Authorization", common::token(1)))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), 403);
+}
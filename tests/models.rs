@@ -0,0 +1,63 @@
+//! `GET /models`: the allow-listed model picker data.
+
+mod common;
+
+use actix_web::http::header::CACHE_CONTROL;
+use actix_web::{test, web};
+use serde_json::{Value, json};
+
+use mimi_backend::app::build_app;
+
+#[actix_web::test]
+async fn lists_the_allow_list_without_secrets() {
+    let app = test::init_service(build_app(web::Data::new(common::state(common::config(&[
+        ("OPENAI_API_KEY", "sk-very-secret"),
+        ("OPENAI_BASE_URL", "https://llm.internal.example/v1"),
+        ("OPENAI_ORG_ID", "org-hidden"),
+        ("OPENAI_MODEL", "gpt-4o-mini"),
+        (
+            "OPENAI_ALLOWED_MODELS",
+            "gpt-4o-mini|GPT-4o mini, gpt-4o|GPT-4o|paid, o1||admin, bad|Bad|gold",
+        ),
+    ])))))
+    .await;
+    let resp = test::call_service(&app, test::TestRequest::get().uri("/models").to_request()).await;
+    assert_eq!(resp.status(), 200);
+    let cache = resp.headers().get(CACHE_CONTROL).unwrap().to_str().unwrap();
+    assert!(cache.contains("max-age"), "{}", cache);
+
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(
+        body,
+        json!({
+            "provider": "openai",
+            "default_model": "gpt-4o-mini",
+            "models": [
+                { "id": "gpt-4o-mini", "display_name": "GPT-4o mini", "tier": "free" },
+                { "id": "gpt-4o", "display_name": "GPT-4o", "tier": "paid" },
+                { "id": "o1", "display_name": "o1", "tier": "admin" },
+            ],
+        })
+    );
+    let text = body.to_string();
+    for secret in ["sk-very-secret", "llm.internal", "org-hidden"] {
+        assert!(!text.contains(secret), "{} leaked", secret);
+    }
+}
+
+#[actix_web::test]
+async fn defaults_to_the_configured_model() {
+    let app = test::init_service(build_app(web::Data::new(common::state(common::config(&[
+        ("OPENAI_MODEL", "gpt-4o-mini"),
+        ("LLM_MOCK", "true"),
+    ])))))
+    .await;
+    let body: Value =
+        test::call_and_read_body_json(&app, test::TestRequest::get().uri("/models").to_request())
+            .await;
+    assert_eq!(body["provider"], "mock");
+    assert_eq!(
+        body["models"],
+        json!([{ "id": "gpt-4o-mini", "display_name": "gpt-4o-mini", "tier": "free" }])
+    );
+}
@@ -0,0 +1,333 @@
+//! Credit purchases through the mock payment provider. Skipped without
+//! `DATABASE_URL`.
+
+mod common;
+
+use std::sync::Arc;
+
+use actix_web::{test, web};
+use async_trait::async_trait;
+use serde_json::{Value, json};
+use sqlx::PgPool;
+
+use mimi_backend::app::build_app;
+use mimi_backend::db::{insert_payment, settle_payment};
+use mimi_backend::models::Payment;
+use mimi_backend::services::{
+    Charge, ChargeStatus, MOCK_WEBHOOK_SIGNATURE, MockPaymentProvider, PaymentError, PaymentEvent,
+    PaymentProvider, credit_service, payment_service,
+};
+
+/// A settled payment for `user_id` that bought `credits`, granted as the
+/// webhook would.
+async fn paid(pool: &PgPool, user_id: i64, credits: i64) -> Payment {
+    let charge = Charge {
+        id: format!("mock_ch_{}", uuid::Uuid::new_v4().simple()),
+        amount_baht: 100,
+        status: ChargeStatus::Pending,
+        client_secret: None,
+    };
+    let payment = insert_payment(pool, user_id, "mock", &charge, credits)
+        .await
+        .unwrap();
+    let mut tx = pool.begin().await.unwrap();
+    settle_payment(&mut tx, &charge.id, ChargeStatus::Succeeded)
+        .await
+        .unwrap();
+    credit_service::grant_in(&mut tx, user_id, credits, "purchase")
+        .await
+        .unwrap();
+    tx.commit().await.unwrap();
+    Payment {
+        status: "succeeded".to_string(),
+        ..payment
+    }
+}
+
+async fn status(pool: &PgPool, payment_id: i64) -> String {
+    sqlx::query_scalar("SELECT status FROM payments WHERE id = $1")
+        .bind(payment_id)
+        .fetch_one(pool)
+        .await
+        .unwrap()
+}
+
+/// The mock provider, except that the provider refuses every refund.
+struct RefusingRefunds;
+
+#[async_trait]
+impl PaymentProvider for RefusingRefunds {
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+
+    fn signature_header(&self) -> &'static str {
+        MockPaymentProvider.signature_header()
+    }
+
+    async fn create_charge(
+        &self,
+        user_id: i64,
+        amount_baht: u32,
+        idempotency_key: &str,
+    ) -> Result<Charge, PaymentError> {
+        MockPaymentProvider
+            .create_charge(user_id, amount_baht, idempotency_key)
+            .await
+    }
+
+    fn verify_webhook(
+        &self,
+        payload: &[u8],
+        signature: &str,
+    ) -> Result<Option<PaymentEvent>, PaymentError> {
+        MockPaymentProvider.verify_webhook(payload, signature)
+    }
+
+    async fn refund(&self, _charge_id: &str, _amount_baht: u32) -> Result<Charge, PaymentError> {
+        Err(PaymentError::Provider("card issuer declined".to_string()))
+    }
+}
+
+fn buy(user_id: i64, amount_baht: u32, key: &str) -> test::TestRequest {
+    test::TestRequest::post()
+        .uri("/payments")
+        .insert_header(("Authorization", common::token(user_id)))
+        .set_json(json!({ "amount_baht": amount_baht, "idempotency_key": key }))
+}
+
+fn webhook(event_id: &str, charge_id: &str, status: &str, signature: &str) -> test::TestRequest {
+    test::TestRequest::post()
+        .uri("/payments/webhook")
+        .insert_header(("X-Mock-Signature", signature))
+        .set_json(json!({ "event_id": event_id, "charge_id": charge_id, "status": status }))
+}
+
+fn key() -> String {
+    uuid::Uuid::new_v4().to_string()
+}
+
+#[actix_web::test]
+async fn a_retried_purchase_returns_the_same_payment() {
+    let Some(pool) = common::test_pool().await else {
+        return;
+    };
+    let user_id = common::new_user(&pool, 0).await;
+    let app = test::init_service(build_app(web::Data::new(common::pg_state(
+        common::config(&[]),
+        pool.clone(),
+    ))))
+    .await;
+    let key = key();
+
+    let resp = test::call_service(&app, buy(user_id, 100, &key).to_request()).await;
+    assert_eq!(resp.status(), 201);
+    let first: Value = test::read_body_json(resp).await;
+    let resp = test::call_service(&app, buy(user_id, 100, &key).to_request()).await;
+    let retry: Value = test::read_body_json(resp).await;
+    assert_eq!(first["payment"]["id"], retry["payment"]["id"]);
+    assert_eq!(first["payment"]["status"], "pending");
+    assert!(
+        first["client_secret"]
+            .as_str()
+            .unwrap()
+            .starts_with("mock_secret_")
+    );
+}
+
+#[actix_web::test]
+async fn a_succeeded_charge_grants_its_credits() {
+    let Some(pool) = common::test_pool().await else {
+        return;
+    };
+    let user_id = common::new_user(&pool, 0).await;
+    let app = test::init_service(build_app(web::Data::new(common::pg_state(
+        common::config(&[("BAHT_PER_CREDIT", "10")]),
+        pool.clone(),
+    ))))
+    .await;
+    let resp = test::call_service(&app, buy(user_id, 100, &key()).to_request()).await;
+    let body: Value = test::read_body_json(resp).await;
+    let charge_id = body["payment"]["charge_id"].as_str().unwrap().to_string();
+
+    let resp = test::call_service(
+        &app,
+        webhook(&key(), &charge_id, "succeeded", "forged").to_request(),
+    )
+    .await;
+    assert!(resp.status().is_client_error());
+    assert_eq!(common::credits(&pool, user_id).await, 0);
+
+    let resp = test::call_service(
+        &app,
+        webhook(&key(), &charge_id, "succeeded", MOCK_WEBHOOK_SIGNATURE).to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(common::credits(&pool, user_id).await, 10);
+}
+
+#[actix_web::test]
+async fn a_redelivered_paid_webhook_grants_credits_once() {
+    let Some(pool) = common::test_pool().await else {
+        return;
+    };
+    let user_id = common::new_user(&pool, 0).await;
+    let app = test::init_service(build_app(web::Data::new(common::pg_state(
+        common::config(&[("BAHT_PER_CREDIT", "25")]),
+        pool.clone(),
+    ))))
+    .await;
+    let resp = test::call_service(&app, buy(user_id, 100, &key()).to_request()).await;
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["payment"]["credits"], 4);
+    let charge_id = body["payment"]["charge_id"].as_str().unwrap().to_string();
+
+    let event_id = key();
+    for _ in 0..2 {
+        let resp = test::call_service(
+            &app,
+            webhook(&event_id, &charge_id, "succeeded", MOCK_WEBHOOK_SIGNATURE).to_request(),
+        )
+        .await;
+        assert_eq!(resp.status(), 200);
+    }
+    assert_eq!(common::credits(&pool, user_id).await, 4);
+
+    // A second event for the same charge finds it already paid.
+    let resp = test::call_service(
+        &app,
+        webhook(&key(), &charge_id, "succeeded", MOCK_WEBHOOK_SIGNATURE).to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(common::credits(&pool, user_id).await, 4);
+    assert_eq!(
+        status(&pool, body["payment"]["id"].as_i64().unwrap()).await,
+        "succeeded"
+    );
+}
+
+#[tokio::test]
+async fn a_refund_takes_back_the_credits_once() {
+    let Some(pool) = common::test_pool().await else {
+        return;
+    };
+    let user_id = common::new_user(&pool, 0).await;
+    let payment = paid(&pool, user_id, 5).await;
+    assert_eq!(common::credits(&pool, user_id).await, 5);
+
+    let refunded = payment_service::refund(&MockPaymentProvider, &pool, payment.id, "test")
+        .await
+        .unwrap();
+    assert_eq!(refunded.status, "refunded");
+    assert_eq!(status(&pool, payment.id).await, "refunded");
+    assert_eq!(common::credits(&pool, user_id).await, 0);
+
+    let again = payment_service::refund(&MockPaymentProvider, &pool, payment.id, "test").await;
+    assert!(
+        matches!(again, Err(PaymentError::NotRefundable(ref s)) if s == "refunded"),
+        "{:?}",
+        again
+    );
+    assert_eq!(common::credits(&pool, user_id).await, 0);
+}
+
+#[tokio::test]
+async fn spent_credits_block_the_refund() {
+    let Some(pool) = common::test_pool().await else {
+        return;
+    };
+    let user_id = common::new_user(&pool, 0).await;
+    let payment = paid(&pool, user_id, 5).await;
+    credit_service::deduct(&pool, user_id, 3, "reading")
+        .await
+        .unwrap();
+
+    let result = payment_service::refund(&MockPaymentProvider, &pool, payment.id, "test").await;
+    assert!(
+        matches!(result, Err(PaymentError::CreditsSpent)),
+        "{:?}",
+        result
+    );
+    assert_eq!(status(&pool, payment.id).await, "succeeded");
+    assert_eq!(common::credits(&pool, user_id).await, 2);
+}
+
+#[tokio::test]
+async fn a_refused_provider_refund_gives_the_credits_back() {
+    let Some(pool) = common::test_pool().await else {
+        return;
+    };
+    let user_id = common::new_user(&pool, 0).await;
+    let payment = paid(&pool, user_id, 5).await;
+
+    let result = payment_service::refund(&RefusingRefunds, &pool, payment.id, "test").await;
+    assert!(
+        matches!(result, Err(PaymentError::Provider(_))),
+        "{:?}",
+        result
+    );
+    assert_eq!(status(&pool, payment.id).await, "succeeded");
+    assert_eq!(common::credits(&pool, user_id).await, 5);
+}
+
+#[actix_web::test]
+async fn a_failed_paid_reading_refunds_the_payment() {
+    let Some(pool) = common::test_pool().await else {
+        return;
+    };
+    let user_id = common::new_user(&pool, 0).await;
+    let payment = paid(&pool, user_id, 5).await;
+    let state = common::pg_state(common::config(&[]), pool.clone())
+        .with_llm(Arc::new(common::EchoLlm::failing(usize::MAX)));
+    let app = test::init_service(build_app(web::Data::new(state))).await;
+
+    let resp = test::call_service(
+        &app,
+        test::TestRequest::post()
+            .uri("/readings")
+            .insert_header(("Authorization", common::token(user_id)))
+            .set_json(json!({ "question": "Will I get the job?", "payment_id": payment.id }))
+            .to_request(),
+    )
+    .await;
+    assert!(resp.status().is_server_error(), "{}", resp.status());
+    assert_eq!(status(&pool, payment.id).await, "refunded");
+    assert_eq!(common::credits(&pool, user_id).await, 0);
+}
+
+#[actix_web::test]
+async fn a_payment_pays_for_one_reading() {
+    let Some(pool) = common::test_pool().await else {
+        return;
+    };
+    let user_id = common::new_user(&pool, 0).await;
+    let payment = paid(&pool, user_id, 5).await;
+    let app = test::init_service(build_app(web::Data::new(common::pg_state(
+        common::config(&[]),
+        pool.clone(),
+    ))))
+    .await;
+    let read = || {
+        test::TestRequest::post()
+            .uri("/readings")
+            .insert_header(("Authorization", common::token(user_id)))
+            .set_json(json!({ "question": "Will I get the job?", "payment_id": payment.id }))
+            .to_request()
+    };
+
+    let resp = test::call_service(&app, read()).await;
+    assert_eq!(resp.status(), 200);
+    let body: Value = test::read_body_json(resp).await;
+    let reading_id: Option<i64> =
+        sqlx::query_scalar("SELECT reading_id FROM payments WHERE id = $1")
+            .bind(payment.id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!(reading_id, body["id"].as_i64());
+
+    let resp = test::call_service(&app, read()).await;
+    assert_eq!(resp.status(), 409);
+}
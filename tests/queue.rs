@@ -0,0 +1,362 @@
+//! The Redis reading queue: enqueueing, cancelling, expiring, replaying and
+//! stats.
+
+mod common;
+
+use actix_web::{test, web};
+use chrono::Utc;
+use redis::AsyncCommands;
+
+use mimi_backend::app::build_app;
+use mimi_backend::models::{JobStatus, ReadingJob, Spread};
+use mimi_backend::services::{
+    PipelineSettings, QUEUE_KEY, QueueError, cancel_job, enqueue_job, get_job, process_job,
+    queue_stats, replay_job, save_job, summarize_queue,
+};
+use mimi_backend::state::AppState;
+
+fn queued_job(user_id: i64) -> ReadingJob {
+    ReadingJob {
+        id: uuid::Uuid::new_v4().to_string(),
+        user_id,
+        question: "Will I get the job?".to_string(),
+        analysis: None,
+        spread: Spread::Single,
+        detail_level: Default::default(),
+        seed: 7,
+        explain: false,
+        status: JobStatus::Queued,
+        credits_charged: 0,
+        created_at: Utc::now().timestamp(),
+        deadline: None,
+        result: None,
+        error: None,
+        replay_count: 0,
+        replayed_by: None,
+    }
+}
+
+#[tokio::test]
+async fn only_the_owner_cancels_a_queued_job_once() {
+    let Some(redis) = common::test_redis().await else {
+        return;
+    };
+    let job = queued_job(1);
+    enqueue_job(&redis, &job, None).await.unwrap();
+
+    assert!(matches!(
+        cancel_job(&redis, &job.id, 2).await,
+        Err(QueueError::NotOwner)
+    ));
+    let cancelled = cancel_job(&redis, &job.id, 1).await.unwrap();
+    assert_eq!(cancelled.status, JobStatus::Cancelled);
+    assert_eq!(
+        get_job(&redis, &job.id).await.unwrap().status,
+        JobStatus::Cancelled
+    );
+    assert!(matches!(
+        cancel_job(&redis, &job.id, 1).await,
+        Err(QueueError::Conflict(JobStatus::Cancelled))
+    ));
+}
+
+#[tokio::test]
+async fn a_job_the_worker_already_took_cannot_be_cancelled() {
+    let Some(redis) = common::test_redis().await else {
+        return;
+    };
+    let job = queued_job(1);
+    enqueue_job(&redis, &job, None).await.unwrap();
+    let mut conn = redis.clone();
+    let _: i64 = conn.lrem(QUEUE_KEY, 0, &job.id).await.unwrap();
+
+    assert!(matches!(
+        cancel_job(&redis, &job.id, 1).await,
+        Err(QueueError::Conflict(JobStatus::Running))
+    ));
+    assert_eq!(
+        get_job(&redis, &job.id).await.unwrap().status,
+        JobStatus::Queued
+    );
+}
+
+#[actix_web::test]
+async fn cancel_endpoint_returns_conflict_the_second_time() {
+    let Some(redis) = common::test_redis().await else {
+        return;
+    };
+    let job = queued_job(1);
+    enqueue_job(&redis, &job, None).await.unwrap();
+    let state = AppState::new(common::config(&[]), None, Some(redis))
+        .with_llm(std::sync::Arc::new(common::EchoLlm::default()));
+    let app = test::init_service(build_app(web::Data::new(state))).await;
+    let cancel = || {
+        test::TestRequest::delete()
+            .uri(&format!("/readings/jobs/{}", job.id))
+            .insert_header(("Authorization", common::token(1)))
+            .to_request()
+    };
+
+    let resp = test::call_service(&app, cancel()).await;
+    assert_eq!(resp.status(), 200);
+    let resp = test::call_service(&app, cancel()).await;
+    assert_eq!(resp.status(), 409);
+}
+
+#[actix_web::test]
+async fn another_users_job_is_hidden_or_forbidden_per_config() {
+    let Some(redis) = common::test_redis().await else {
+        return;
+    };
+    for (hide, theirs) in [(None, 404), (Some("true"), 404), (Some("false"), 403)] {
+        let vars: Vec<_> = hide.map(|h| ("HIDE_OWNERSHIP", h)).into_iter().collect();
+        let state = AppState::new(common::config(&vars), None, Some(redis.clone()))
+            .with_llm(std::sync::Arc::new(common::EchoLlm::default()));
+        let app = test::init_service(build_app(web::Data::new(state))).await;
+        let job = queued_job(1);
+        enqueue_job(&redis, &job, None).await.unwrap();
+
+        for (request, method) in [
+            (test::TestRequest::get(), "GET"),
+            (test::TestRequest::delete(), "DELETE"),
+        ] {
+            let resp = test::call_service(
+                &app,
+                request
+                    .uri(&format!("/readings/jobs/{}", job.id))
+                    .insert_header(("Authorization", common::token(2)))
+                    .to_request(),
+            )
+            .await;
+            assert_eq!(
+                resp.status(),
+                theirs,
+                "HIDE_OWNERSHIP={:?} {} by user 2",
+                hide,
+                method
+            );
+        }
+        // The job is untouched and still the owner's to read.
+        let resp = test::call_service(
+            &app,
+            test::TestRequest::get()
+                .uri(&format!("/readings/jobs/{}", job.id))
+                .insert_header(("Authorization", common::token(1)))
+                .to_request(),
+        )
+        .await;
+        assert_eq!(resp.status(), 200);
+        assert_eq!(
+            get_job(&redis, &job.id).await.unwrap().status,
+            JobStatus::Queued
+        );
+        let _: i64 = redis.clone().lrem(QUEUE_KEY, 0, &job.id).await.unwrap();
+    }
+}
+
+#[tokio::test]
+async fn replaying_a_failed_job_requeues_it_under_its_id() {
+    let Some(redis) = common::test_redis().await else {
+        return;
+    };
+    let mut job = queued_job(1);
+    job.status = JobStatus::Failed;
+    job.error = Some("upstream timeout".to_string());
+    job.created_at -= 600;
+    job.deadline = Some(job.created_at + 60);
+    save_job(&redis, &job).await.unwrap();
+
+    let replayed = replay_job(&redis, &job.id, 99).await.unwrap();
+    assert_eq!(replayed.id, job.id);
+    assert_eq!(replayed.status, JobStatus::Queued);
+    assert_eq!(replayed.error, None);
+    assert_eq!(replayed.replay_count, 1);
+    assert_eq!(replayed.replayed_by, Some(99));
+    assert_eq!(replayed.seed, job.seed);
+    let deadline = replayed.deadline.unwrap();
+    assert!(
+        deadline > Utc::now().timestamp(),
+        "deadline restarts from now"
+    );
+
+    let mut conn = redis.clone();
+    let queued: Vec<String> = conn.lrange(QUEUE_KEY, 0, -1).await.unwrap();
+    assert!(queued.contains(&job.id));
+    assert!(matches!(
+        replay_job(&redis, &job.id, 99).await,
+        Err(QueueError::Conflict(JobStatus::Queued))
+    ));
+}
+
+#[actix_web::test]
+async fn only_admins_replay_jobs() {
+    let Some(redis) = common::test_redis().await else {
+        return;
+    };
+    let mut job = queued_job(1);
+    job.status = JobStatus::Failed;
+    save_job(&redis, &job).await.unwrap();
+    let state = AppState::new(common::config(&[]), None, Some(redis));
+    let app = test::init_service(build_app(web::Data::new(state))).await;
+    let replay = |token: String| {
+        test::TestRequest::post()
+            .uri(&format!("/admin/readings/jobs/{}/replay", job.id))
+            .insert_header(("Authorization", token))
+            .to_request()
+    };
+
+    let resp = test::call_service(&app, replay(common::token(1))).await;
+    assert_eq!(resp.status(), 403);
+    let resp = test::call_service(&app, replay(common::admin_token(99))).await;
+    assert_eq!(resp.status(), 202);
+}
+
+#[tokio::test]
+async fn a_job_past_its_deadline_is_expired_and_refunded() {
+    let (Some(redis), Some(pool)) = (common::test_redis().await, common::test_pool().await) else {
+        return;
+    };
+    let user_id = common::new_user(&pool, 3).await;
+    let llm = common::EchoLlm::default();
+    let settings = PipelineSettings::from_config(&common::config(&[]));
+
+    let mut stale = queued_job(user_id);
+    stale.credits_charged = 2;
+    stale.deadline = Some(Utc::now().timestamp() - 1);
+    enqueue_job(&redis, &stale, None).await.unwrap();
+    process_job(&redis, Some(&pool), &llm, &settings, &stale.id)
+        .await
+        .unwrap();
+    let expired = get_job(&redis, &stale.id).await.unwrap();
+    assert_eq!(expired.status, JobStatus::Expired);
+    assert!(expired.result.is_none());
+    assert_eq!(llm.calls(), 0);
+    assert_eq!(common::credits(&pool, user_id).await, 5);
+
+    // Seen again (a redelivery, another worker), it isn't refunded twice.
+    process_job(&redis, Some(&pool), &llm, &settings, &stale.id)
+        .await
+        .unwrap();
+    assert_eq!(common::credits(&pool, user_id).await, 5);
+
+    let mut fresh = queued_job(user_id);
+    fresh.credits_charged = 2;
+    fresh.deadline = Some(Utc::now().timestamp() + 60);
+    enqueue_job(&redis, &fresh, None).await.unwrap();
+    process_job(&redis, Some(&pool), &llm, &settings, &fresh.id)
+        .await
+        .unwrap();
+    assert_eq!(
+        get_job(&redis, &fresh.id).await.unwrap().status,
+        JobStatus::Done
+    );
+    assert_eq!(common::credits(&pool, user_id).await, 5);
+}
+
+#[tokio::test]
+async fn queue_stats_report_the_oldest_waiting_job_and_status_counts() {
+    let now = 1_700_000_000;
+    let job = |status: JobStatus, age: i64| ReadingJob {
+        status,
+        created_at: now - age,
+        ..queued_job(1)
+    };
+    let jobs = [
+        job(JobStatus::Queued, 30),
+        job(JobStatus::Queued, 300),
+        job(JobStatus::Running, 900),
+        job(JobStatus::Done, 3600),
+        job(JobStatus::Failed, 7200),
+    ];
+    let stats = summarize_queue(2, &jobs, now);
+    assert_eq!(stats.depth, 2);
+    // Only jobs still waiting count toward the oldest age.
+    assert_eq!(stats.oldest_age_secs, Some(300));
+    assert_eq!(stats.by_status.get(&JobStatus::Queued), Some(&2));
+    assert_eq!(stats.by_status.get(&JobStatus::Running), Some(&1));
+    assert_eq!(stats.by_status.get(&JobStatus::Cancelled), None);
+
+    let idle = summarize_queue(0, &jobs[2..], now);
+    assert_eq!(idle.oldest_age_secs, None);
+}
+
+#[tokio::test]
+async fn queue_stats_read_depth_and_ages_from_redis() {
+    let Some(redis) = common::test_redis().await else {
+        return;
+    };
+    let now = Utc::now().timestamp();
+    let mut job = queued_job(1);
+    // Older than anything another test leaves queued.
+    job.created_at = now - 10 * 365 * 86_400;
+    enqueue_job(&redis, &job, None).await.unwrap();
+
+    let stats = queue_stats(&redis, now).await.unwrap();
+    assert!(stats.depth >= 1, "{:?}", stats);
+    assert!(
+        stats.oldest_age_secs >= Some(10 * 365 * 86_400),
+        "{:?}",
+        stats
+    );
+    assert!(stats.by_status[&JobStatus::Queued] >= 1, "{:?}", stats);
+
+    let mut conn = redis.clone();
+    let _: () = conn.lrem(QUEUE_KEY, 0, &job.id).await.unwrap();
+    let _: () = conn.del(format!("reading_job:{}", job.id)).await.unwrap();
+}
+
+#[actix_web::test]
+async fn queue_stats_are_admin_only() {
+    let app = test::init_service(build_app(web::Data::new(common::state(
+        common::config(&[]),
+    ))))
+    .await;
+    let resp = test::call_service(
+        &app,
+        test::TestRequest::get()
+            .uri("/admin/queue")
+            .insert_header(("Authorization", common::token(1)))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), 403);
+}
+
+#[actix_web::test]
+async fn a_saturated_queue_refuses_new_jobs_with_retry_after() {
+    let Some(redis) = common::test_redis().await else {
+        return;
+    };
+    // At least one job is waiting, so a depth limit of 1 is reached.
+    let waiting = queued_job(1);
+    enqueue_job(&redis, &waiting, None).await.unwrap();
+
+    let refused = queued_job(1);
+    assert!(matches!(
+        enqueue_job(&redis, &refused, Some(1)).await,
+        Err(QueueError::Saturated { depth }) if depth >= 1
+    ));
+    assert!(get_job(&redis, &refused.id).await.is_err());
+
+    let state = AppState::new(
+        common::config(&[("MAX_QUEUE_DEPTH", "1")]),
+        None,
+        Some(redis.clone()),
+    )
+    .with_llm(std::sync::Arc::new(common::EchoLlm::default()));
+    let app = test::init_service(build_app(web::Data::new(state))).await;
+    let resp = test::call_service(
+        &app,
+        test::TestRequest::post()
+            .uri("/readings/async")
+            .insert_header(("Authorization", common::token(1)))
+            .set_json(serde_json::json!({ "question": "Will I get the job?" }))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), 503);
+    assert!(resp.headers().contains_key("Retry-After"));
+
+    let mut conn = redis.clone();
+    let _: () = conn.lrem(QUEUE_KEY, 0, &waiting.id).await.unwrap();
+}
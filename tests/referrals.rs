@@ -0,0 +1,90 @@
+//! `GET /referrals/me`. Skipped without `DATABASE_URL`.
+
+mod common;
+
+use actix_web::{test, web};
+use serde_json::Value;
+use sqlx::PgPool;
+
+use mimi_backend::app::build_app;
+
+async fn refer(pool: &PgPool, referrer_id: i64, status: &str, credits_awarded: i64) {
+    let referred_id = common::new_user(pool, 0).await;
+    sqlx::query(
+        "INSERT INTO referrals (referrer_id, referred_id, status, credits_awarded) \
+         VALUES ($1, $2, $3, $4)",
+    )
+    .bind(referrer_id)
+    .bind(referred_id)
+    .bind(status)
+    .bind(credits_awarded)
+    .execute(pool)
+    .await
+    .unwrap();
+}
+
+#[actix_web::test]
+async fn counts_referrals_by_state_and_keeps_the_code() {
+    let Some(pool) = common::test_pool().await else {
+        return;
+    };
+    let user_id = common::new_user(&pool, 0).await;
+    refer(&pool, user_id, "rewarded", 5).await;
+    refer(&pool, user_id, "rewarded", 3).await;
+    refer(&pool, user_id, "pending", 0).await;
+    // Someone else's referral.
+    let other = common::new_user(&pool, 0).await;
+    refer(&pool, other, "rewarded", 10).await;
+
+    let app = test::init_service(build_app(web::Data::new(common::pg_state(
+        common::config(&[]),
+        pool.clone(),
+    ))))
+    .await;
+    let stats = || {
+        test::TestRequest::get()
+            .uri("/referrals/me")
+            .insert_header(("Authorization", common::token(user_id)))
+            .to_request()
+    };
+    let resp = test::call_service(&app, stats()).await;
+    assert_eq!(resp.status(), 200);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["successful_referrals"], 2);
+    assert_eq!(body["pending_referrals"], 1);
+    assert_eq!(body["credits_earned"], 8);
+    let code = body["referral_code"].as_str().unwrap().to_string();
+    assert_eq!(code.len(), 8);
+
+    // The code is generated once and then kept.
+    let resp = test::call_service(&app, stats()).await;
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["referral_code"], code);
+}
+
+#[actix_web::test]
+async fn a_user_without_referrals_gets_zeroes() {
+    let Some(pool) = common::test_pool().await else {
+        return;
+    };
+    let user_id = common::new_user(&pool, 0).await;
+    let app = test::init_service(build_app(web::Data::new(common::pg_state(
+        common::config(&[]),
+        pool.clone(),
+    ))))
+    .await;
+    let resp = test::call_service(
+        &app,
+        test::TestRequest::get()
+            .uri("/referrals/me")
+            .insert_header(("Authorization", common::token(user_id)))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), 200);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["successful_referrals"], 0);
+    assert_eq!(body["pending_referrals"], 0);
+    assert_eq!(body["credits_earned"], 0);
+    assert!(body["referral_code"].is_string());
+}
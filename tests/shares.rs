@@ -0,0 +1,187 @@
+//! Public share links and their absolute URLs. Skipped without
+//! `DATABASE_URL`.
+
+mod common;
+
+use actix_web::{test, web};
+use serde_json::{Value, json};
+
+use mimi_backend::app::build_app;
+
+fn create_reading(user_id: i64) -> test::TestRequest {
+    test::TestRequest::post()
+        .uri("/readings")
+        .insert_header(("Authorization", common::token(user_id)))
+        .set_json(json!({ "question": "Will I get the job?" }))
+}
+
+fn share(method: test::TestRequest, reading_id: i64, user_id: i64) -> test::TestRequest {
+    method
+        .uri(&format!("/readings/{}/share", reading_id))
+        .insert_header(("Authorization", common::token(user_id)))
+}
+
+fn shared(path: &str) -> test::TestRequest {
+    test::TestRequest::get().uri(path)
+}
+
+#[actix_web::test]
+async fn a_shared_reading_is_public_until_revoked() {
+    let Some(pool) = common::test_pool().await else {
+        return;
+    };
+    let app = test::init_service(build_app(web::Data::new(common::pg_state(
+        common::config(&[]),
+        pool.clone(),
+    ))))
+    .await;
+    let owner = common::new_user(&pool, 0).await;
+    let stranger = common::new_user(&pool, 0).await;
+    let reading: Value =
+        test::call_and_read_body_json(&app, create_reading(owner).to_request()).await;
+    let reading_id = reading["id"].as_i64().unwrap();
+
+    let resp = test::call_service(
+        &app,
+        share(test::TestRequest::post(), reading_id, stranger).to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), 404);
+
+    let resp = test::call_service(
+        &app,
+        share(test::TestRequest::post(), reading_id, owner).to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), 201);
+    let link: Value = test::read_body_json(resp).await;
+    let path = link["path"].as_str().unwrap().to_string();
+    assert!(link["url"].as_str().unwrap().ends_with(&path));
+
+    let resp = test::call_service(&app, shared(&path).to_request()).await;
+    assert_eq!(resp.status(), 200);
+    let view: Value = test::read_body_json(resp).await;
+    assert_eq!(view["question"], reading["question"]);
+    assert_eq!(view["cards"], reading["cards"]);
+    assert_eq!(view["summary"], reading["summary"]);
+    for private in ["id", "user_id", "analysis", "meta", "raw_llm"] {
+        assert!(view.get(private).is_none(), "{} leaked: {}", private, view);
+    }
+    assert!(!view.to_string().contains(&format!("\"{}\"", owner)));
+
+    let resp = test::call_service(
+        &app,
+        share(test::TestRequest::delete(), reading_id, owner).to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), 200);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["revoked"], 1);
+    let resp = test::call_service(&app, shared(&path).to_request()).await;
+    assert_eq!(resp.status(), 404);
+}
+
+#[actix_web::test]
+async fn an_expired_link_is_not_found() {
+    let Some(pool) = common::test_pool().await else {
+        return;
+    };
+    let app = test::init_service(build_app(web::Data::new(common::pg_state(
+        common::config(&[("SHARE_TTL_SECS", "60")]),
+        pool.clone(),
+    ))))
+    .await;
+    let owner = common::new_user(&pool, 0).await;
+    let reading: Value =
+        test::call_and_read_body_json(&app, create_reading(owner).to_request()).await;
+    let link: Value = test::call_and_read_body_json(
+        &app,
+        share(
+            test::TestRequest::post(),
+            reading["id"].as_i64().unwrap(),
+            owner,
+        )
+        .to_request(),
+    )
+    .await;
+    let path = link["path"].as_str().unwrap();
+    let resp = test::call_service(&app, shared(path).to_request()).await;
+    assert_eq!(resp.status(), 200);
+
+    sqlx::query(
+        "UPDATE reading_shares SET expires_at = NOW() - INTERVAL '1 second' WHERE token = $1",
+    )
+    .bind(link["token"].as_str().unwrap())
+    .execute(&pool)
+    .await
+    .unwrap();
+    let resp = test::call_service(&app, shared(path).to_request()).await;
+    assert_eq!(resp.status(), 404);
+
+    let resp = test::call_service(&app, shared("/shared/not-a-real-token").to_request()).await;
+    assert_eq!(resp.status(), 404);
+}
+
+#[actix_web::test]
+async fn share_urls_use_the_scheme_only_a_trusted_proxy_reports() {
+    let Some(pool) = common::test_pool().await else {
+        return;
+    };
+    let app = test::init_service(build_app(web::Data::new(common::pg_state(
+        common::config(&[("TRUSTED_PROXIES", "10.0.0.0/8")]),
+        pool.clone(),
+    ))))
+    .await;
+    let owner = common::new_user(&pool, 0).await;
+    let reading: Value =
+        test::call_and_read_body_json(&app, create_reading(owner).to_request()).await;
+    let reading_id = reading["id"].as_i64().unwrap();
+
+    for (peer, expected) in [
+        ("10.1.2.3:443", "https://mimi.example/shared/"),
+        ("203.0.113.9:443", "http://internal:8080/shared/"),
+    ] {
+        let req = share(test::TestRequest::post(), reading_id, owner)
+            .peer_addr(peer.parse().unwrap())
+            .insert_header(("Host", "internal:8080"))
+            .insert_header(("X-Forwarded-Proto", "https"))
+            .insert_header(("X-Forwarded-Host", "mimi.example"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 201);
+        let link: Value = test::read_body_json(resp).await;
+        let url = link["url"].as_str().unwrap();
+        assert!(url.starts_with(expected), "{}: {}", peer, url);
+        assert!(url.ends_with(link["path"].as_str().unwrap()));
+    }
+}
+
+#[actix_web::test]
+async fn sharing_another_users_reading_is_forbidden_when_ownership_is_shown() {
+    let Some(pool) = common::test_pool().await else {
+        return;
+    };
+    let app = test::init_service(build_app(web::Data::new(common::pg_state(
+        common::config(&[("HIDE_OWNERSHIP", "false")]),
+        pool.clone(),
+    ))))
+    .await;
+    let owner = common::new_user(&pool, 0).await;
+    let stranger = common::new_user(&pool, 0).await;
+    let reading: Value =
+        test::call_and_read_body_json(&app, create_reading(owner).to_request()).await;
+    let reading_id = reading["id"].as_i64().unwrap();
+
+    for method in [test::TestRequest::post, test::TestRequest::delete] {
+        let resp =
+            test::call_service(&app, share(method(), reading_id, stranger).to_request()).await;
+        assert_eq!(resp.status(), 403);
+        // A missing reading is still just not found.
+        let resp = test::call_service(
+            &app,
+            share(method(), reading_id + 1_000_000, stranger).to_request(),
+        )
+        .await;
+        assert_eq!(resp.status(), 404);
+    }
+}
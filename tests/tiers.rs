@@ -0,0 +1,131 @@
+//! Per-request tier resolution and per-tier limits against the in-memory
+//! datastore; caching also needs `UPSTASH_REDIS_URL`.
+
+mod common;
+
+use std::sync::Arc;
+
+use actix_web::{test, web};
+use serde_json::{Value, json};
+
+use mimi_backend::app::build_app;
+use mimi_backend::db::MemoryDatastore;
+use mimi_backend::models::{Payment, Tier};
+use mimi_backend::services::{invalidate_tier, resolve_tier};
+
+fn payment(id: i64, user_id: i64, status: &str) -> Payment {
+    Payment {
+        id,
+        user_id,
+        amount_baht: 100,
+        charge_id: format!("mock_ch_{}", id),
+        credits: 10,
+        status: status.to_string(),
+    }
+}
+
+#[tokio::test]
+async fn resolves_free_paid_and_admin_users() {
+    let store = MemoryDatastore::new();
+    store.insert_payment(payment(1, 10, "pending"));
+    store.insert_payment(payment(2, 20, "succeeded"));
+
+    let free = resolve_tier(10, false, Some(&store), None).await.unwrap();
+    let paid = resolve_tier(20, false, Some(&store), None).await.unwrap();
+    let admin = resolve_tier(30, true, Some(&store), None).await.unwrap();
+    assert_eq!((free, paid, admin), (Tier::Free, Tier::Paid, Tier::Admin));
+    assert_eq!(
+        resolve_tier(20, false, None, None).await.unwrap(),
+        Tier::Free
+    );
+}
+
+#[tokio::test]
+async fn caches_the_tier_until_invalidated() {
+    let Some(redis) = common::test_redis().await else {
+        return;
+    };
+    let user_id = rand::random::<u32>() as i64;
+    let store = MemoryDatastore::new();
+    let tier = resolve_tier(user_id, false, Some(&store), Some(&redis))
+        .await
+        .unwrap();
+    assert_eq!(tier, Tier::Free);
+
+    // A payment settling isn't seen until the cache entry is dropped.
+    store.insert_payment(payment(1, user_id, "succeeded"));
+    let cached = resolve_tier(user_id, false, Some(&store), Some(&redis))
+        .await
+        .unwrap();
+    assert_eq!(cached, Tier::Free);
+    invalidate_tier(&redis, user_id).await;
+    let fresh = resolve_tier(user_id, false, Some(&store), Some(&redis))
+        .await
+        .unwrap();
+    assert_eq!(fresh, Tier::Paid);
+}
+
+#[actix_web::test]
+async fn free_users_hit_their_rate_limit_before_paid_users() {
+    let store = Arc::new(MemoryDatastore::new());
+    store.insert_payment(payment(1, 20, "succeeded"));
+    let config = common::config(&[("RATE_LIMIT_FREE", "2/min"), ("RATE_LIMIT_PAID", "4/min")]);
+    let state = common::state(config).with_store(store);
+    let app = test::init_service(build_app(web::Data::new(state))).await;
+    let history = |token: String| {
+        test::TestRequest::get()
+            .uri("/readings")
+            .insert_header(("Authorization", token))
+            .to_request()
+    };
+
+    let mut statuses = Vec::new();
+    for _ in 0..3 {
+        let mut round = Vec::new();
+        for token in [
+            common::token(10),
+            common::token(20),
+            common::admin_token(30),
+        ] {
+            // The limit is enforced in middleware, so a 429 is an `Err`.
+            let status = match test::try_call_service(&app, history(token)).await {
+                Ok(resp) => resp.status(),
+                Err(err) => err.error_response().status(),
+            };
+            round.push(status.as_u16());
+        }
+        statuses.push(round);
+    }
+    assert_eq!(
+        statuses,
+        [[200, 200, 200], [200, 200, 200], [429, 200, 200]]
+    );
+}
+
+#[actix_web::test]
+async fn only_paid_users_may_draw_five_cards() {
+    let store = Arc::new(MemoryDatastore::new());
+    store.insert_payment(payment(1, 20, "succeeded"));
+    let state = common::state(common::config(&[])).with_store(store);
+    let app = test::init_service(build_app(web::Data::new(state))).await;
+    let five_cards = |token: String| {
+        test::TestRequest::post()
+            .uri("/readings")
+            .insert_header(("Authorization", token))
+            .insert_header(("Accept-Language", "en"))
+            .set_json(json!({ "question": "Will I get the job?", "spread": "five_card" }))
+            .to_request()
+    };
+
+    let resp = test::call_service(&app, five_cards(common::token(10))).await;
+    assert_eq!(resp.status(), 402);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(
+        body["error"]["message"],
+        "Upgrade your plan to use this spread"
+    );
+    let resp = test::call_service(&app, five_cards(common::token(20))).await;
+    assert_eq!(resp.status(), 200);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["cards"].as_array().map(Vec::len), Some(5));
+}
@@ -0,0 +1,52 @@
+//! Per-route request deadlines (`enforce_timeouts`).
+
+mod common;
+
+use std::time::{Duration, Instant};
+
+use actix_web::middleware::from_fn;
+use actix_web::{App, HttpResponse, test, web};
+use serde_json::Value;
+
+use mimi_backend::middleware::enforce_timeouts;
+
+async fn slow() -> HttpResponse {
+    tokio::time::sleep(Duration::from_secs(3)).await;
+    HttpResponse::Ok().finish()
+}
+
+#[actix_web::test]
+async fn a_slow_route_times_out_with_a_json_504() {
+    let state = common::state(common::config(&[
+        ("ROUTE_TIMEOUTS", "/slow/{id}=1"),
+        ("DEFAULT_ROUTE_TIMEOUT_SECS", "5"),
+    ]));
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .wrap(from_fn(enforce_timeouts))
+            .route("/slow/{id}", web::get().to(slow))
+            .route("/fine", web::get().to(slow)),
+    )
+    .await;
+
+    let started = Instant::now();
+    // The middleware fails the call; the server renders the error.
+    let err = test::try_call_service(&app, test::TestRequest::get().uri("/slow/7").to_request())
+        .await
+        .err()
+        .expect("timed out");
+    let waited = started.elapsed();
+    assert!(waited >= Duration::from_secs(1), "{:?}", waited);
+    assert!(waited < Duration::from_secs(3), "{:?}", waited);
+    let resp = err.error_response();
+    assert_eq!(resp.status(), 504);
+    let body: Value =
+        serde_json::from_slice(&actix_web::body::to_bytes(resp.into_body()).await.unwrap())
+            .unwrap();
+    assert_eq!(body["error"]["code"], "timeout");
+
+    // Routes without an entry get the longer default.
+    let resp = test::call_service(&app, test::TestRequest::get().uri("/fine").to_request()).await;
+    assert_eq!(resp.status(), 200);
+}